@@ -0,0 +1,269 @@
+//! The same mixed workload against each [`DbBackend`], side by side.
+//!
+//! ```shell
+//! cargo bench --features rusqlite-backend --bench compare -- --writers 4 --readers 8 --secs 10
+//! ```
+//!
+//! Writers insert single rows or ten at a time in a transaction, with
+//! bodies from 64B to 16KB. Readers look up a row by id or scan a slice
+//! of the loc ring. The backends run one after the other, and peak
+//! memory is the process's resident high-water mark, reset in between.
+//!
+//! Each backend brings its own concurrency: sqlx a pool of
+//! connections, rusqlite one connection taken in turns.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use spike_sqlx::*;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const PREFILL: i64 = 10_000;
+const BODY_SIZES: &[usize] = &[64, 1024, 16 * 1024];
+const BATCH: usize = 10;
+/// A scan covers this fraction of the ring.
+const SCAN_FRACTION: i64 = 1024;
+
+struct Args {
+    writers: usize,
+    readers: usize,
+    duration: Duration,
+}
+
+impl Args {
+    /// `cargo bench` adds a `--bench` of its own, anything unknown is skipped.
+    fn parse() -> Self {
+        let mut args = Self {
+            writers: 2,
+            readers: 4,
+            duration: Duration::from_secs(5),
+        };
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            let mut value = || -> u64 {
+                iter.next()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(|| panic!("{} takes a number", arg))
+            };
+            match arg.as_str() {
+                "--writers" => args.writers = value() as usize,
+                "--readers" => args.readers = value() as usize,
+                "--secs" => args.duration = Duration::from_secs(value()),
+                _ => {}
+            }
+        }
+        args
+    }
+}
+
+/// What one backend managed.
+struct Report {
+    name: &'static str,
+    writes: Vec<Duration>,
+    reads: Vec<Duration>,
+    elapsed: Duration,
+    peak_rss_kb: Option<u64>,
+}
+
+impl Report {
+    fn row(&self) -> String {
+        let per_sec = |n: usize| n as f64 / self.elapsed.as_secs_f64();
+        format!(
+            "{:<10} {:>10.0} {:>10} {:>10} {:>10.0} {:>10} {:>10} {:>12}",
+            self.name,
+            per_sec(self.writes.len()),
+            micros(percentile(&self.writes, 0.50)),
+            micros(percentile(&self.writes, 0.99)),
+            per_sec(self.reads.len()),
+            micros(percentile(&self.reads, 0.50)),
+            micros(percentile(&self.reads, 0.99)),
+            self.peak_rss_kb
+                .map(|kb| format!("{} MB", kb / 1024))
+                .unwrap_or_else(|| "n/a".to_string()),
+        )
+    }
+}
+
+fn header() -> String {
+    format!(
+        "{:<10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>12}",
+        "backend", "writes/s", "w p50", "w p99", "reads/s", "r p50", "r p99", "peak rss"
+    )
+}
+
+fn micros(d: Option<Duration>) -> String {
+    d.map(|d| format!("{}us", d.as_micros()))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// `latencies` must be sorted.
+fn percentile(latencies: &[Duration], p: f64) -> Option<Duration> {
+    if latencies.is_empty() {
+        return None;
+    }
+    let i = ((latencies.len() - 1) as f64 * p).round() as usize;
+    Some(latencies[i])
+}
+
+/// Forget the resident high-water mark, so the next run's is its own.
+fn reset_peak_rss() {
+    let _ = std::fs::write("/proc/self/clear_refs", "5");
+}
+
+fn peak_rss_kb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn row(rng: &mut StdRng) -> Vec<Value> {
+    let size = BODY_SIZES[rng.gen_range(0, BODY_SIZES.len())];
+    let mut body = vec![0; size];
+    rng.fill(&mut body[..]);
+    vec![Value::Integer(rng.gen::<u32>() as i64), Value::Blob(body)]
+}
+
+const INSERT: &str = "INSERT INTO ops (loc, body) VALUES (?, ?)";
+
+async fn setup<B: DbBackend>(backend: &B) -> anyhow::Result<()> {
+    // journal_mode answers with a row, which rusqlite won't execute
+    backend.query("PRAGMA journal_mode = WAL", &[]).await?;
+    backend
+        .execute(
+            "CREATE TABLE ops (
+                id      INTEGER PRIMARY KEY,
+                loc     INTEGER NOT NULL,
+                body    BLOB NOT NULL
+            )",
+            &[],
+        )
+        .await?;
+    backend
+        .execute("CREATE INDEX ops_loc_idx ON ops (loc)", &[])
+        .await?;
+    let mut rng = StdRng::from_entropy();
+    for _ in 0..PREFILL / 1000 {
+        let statements = (0..1000)
+            .map(|_| Statement::new(INSERT, row(&mut rng)))
+            .collect();
+        backend.transaction(statements).await?;
+    }
+    Ok(())
+}
+
+async fn writer<B: DbBackend>(
+    backend: Arc<B>,
+    rows: Arc<AtomicI64>,
+    until: Instant,
+) -> anyhow::Result<Vec<Duration>> {
+    let mut rng = StdRng::from_entropy();
+    let mut latencies = Vec::new();
+    while Instant::now() < until {
+        let start = Instant::now();
+        let written = if rng.gen_bool(0.5) {
+            backend.execute(INSERT, &row(&mut rng)).await?
+        } else {
+            let statements = (0..BATCH)
+                .map(|_| Statement::new(INSERT, row(&mut rng)))
+                .collect();
+            backend.transaction(statements).await?
+        };
+        latencies.push(start.elapsed());
+        rows.fetch_add(written as i64, Ordering::Relaxed);
+    }
+    Ok(latencies)
+}
+
+async fn reader<B: DbBackend>(
+    backend: Arc<B>,
+    rows: Arc<AtomicI64>,
+    until: Instant,
+) -> anyhow::Result<Vec<Duration>> {
+    let mut rng = StdRng::from_entropy();
+    let width = (u32::MAX as i64) / SCAN_FRACTION;
+    let mut latencies = Vec::new();
+    while Instant::now() < until {
+        let start = Instant::now();
+        if rng.gen_bool(0.8) {
+            let id = rng.gen_range(1, rows.load(Ordering::Relaxed) + 1);
+            backend
+                .query("SELECT * FROM ops WHERE id = ?", &[Value::Integer(id)])
+                .await?;
+        } else {
+            let from = rng.gen_range(0, u32::MAX as i64 - width);
+            backend
+                .query(
+                    "SELECT * FROM ops WHERE loc >= ? AND loc <= ?",
+                    &[Value::Integer(from), Value::Integer(from + width)],
+                )
+                .await?;
+        }
+        latencies.push(start.elapsed());
+    }
+    Ok(latencies)
+}
+
+async fn run<B: DbBackend + 'static>(name: &'static str, args: &Args) -> anyhow::Result<Report> {
+    let dir = std::env::temp_dir().join(format!(
+        "spike-sqlx-compare-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    reset_peak_rss();
+
+    let backend = Arc::new(B::open(&dir.join("db.sqlite3")).await?);
+    setup(&*backend).await?;
+    let rows = Arc::new(AtomicI64::new(PREFILL));
+    let start = Instant::now();
+    let until = start + args.duration;
+    let writers: Vec<_> = (0..args.writers)
+        .map(|_| tokio::spawn(writer(backend.clone(), rows.clone(), until)))
+        .collect();
+    let readers: Vec<_> = (0..args.readers)
+        .map(|_| tokio::spawn(reader(backend.clone(), rows.clone(), until)))
+        .collect();
+    let mut writes = Vec::new();
+    for task in writers {
+        writes.extend(task.await??);
+    }
+    let mut reads = Vec::new();
+    for task in readers {
+        reads.extend(task.await??);
+    }
+    let elapsed = start.elapsed();
+    writes.sort();
+    reads.sort();
+
+    drop(backend);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(Report {
+        name,
+        writes,
+        reads,
+        elapsed,
+        peak_rss_kb: peak_rss_kb(),
+    })
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    println!(
+        "{} writers, {} readers, {}s each",
+        args.writers,
+        args.readers,
+        args.duration.as_secs()
+    );
+    let reports = vec![
+        run::<SqlxBackend>("sqlx", &args).await?,
+        run::<RusqliteBackend>("rusqlite", &args).await?,
+    ];
+    println!("{}", header());
+    for report in &reports {
+        println!("{}", report.row());
+    }
+    Ok(())
+}
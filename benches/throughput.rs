@@ -0,0 +1,225 @@
+//! Insert and read throughput across journal mode and encryption.
+//!
+//! ```shell
+//! cargo bench --bench throughput
+//! cargo bench --bench throughput -- point_lookup/wal+cipher
+//! ```
+//!
+//! Stock sqlite ignores the key, so the `cipher` rows only measure
+//! SQLCipher when built against it (see Cargo.toml).
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use spike_sqlx::*;
+use std::path::PathBuf;
+use tokio::runtime::Runtime;
+
+const BATCH_SIZES: &[usize] = &[100, 1_000, 10_000];
+const TABLE_SIZES: &[usize] = &[1_000, 10_000, 100_000];
+/// A scan covers this fraction of the ring, so of the table.
+const SCAN_FRACTION: u32 = 256;
+const CONTENT_BYTES: usize = 256;
+
+/// One point on the journal mode × encryption grid.
+#[derive(Clone, Copy)]
+struct Setup {
+    wal: bool,
+    cipher: bool,
+}
+
+const SETUPS: &[Setup] = &[
+    Setup {
+        wal: true,
+        cipher: false,
+    },
+    Setup {
+        wal: true,
+        cipher: true,
+    },
+    Setup {
+        wal: false,
+        cipher: false,
+    },
+    Setup {
+        wal: false,
+        cipher: true,
+    },
+];
+
+impl Setup {
+    fn label(&self) -> String {
+        format!(
+            "{}+{}",
+            if self.wal { "wal" } else { "delete" },
+            if self.cipher { "cipher" } else { "plain" }
+        )
+    }
+
+    fn config(&self) -> DbConfig {
+        DbConfig::new()
+            .journal_mode(if self.wal {
+                SqliteJournalMode::Wal
+            } else {
+                SqliteJournalMode::Delete
+            })
+            .encryption(if self.cipher {
+                Encryption::SqlCipher(KeySource::Raw(SecretKey::new([0; 32])))
+            } else {
+                Encryption::None
+            })
+    }
+
+    /// A fresh database on disk, WAL needs a real file.
+    fn open(&self, rt: &Runtime, name: &str) -> (Db, PathBuf) {
+        let dir = std::env::temp_dir().join(format!(
+            "spike-sqlx-bench-{}-{}-{}",
+            name,
+            self.label(),
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = rt
+            .block_on(Db::open_with(dir.join("db.sqlite3"), self.config()))
+            .unwrap();
+        (db, dir)
+    }
+}
+
+fn close(rt: &Runtime, db: Db, dir: PathBuf) {
+    rt.block_on(db.close()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn entry() -> Entry {
+    let mut content = vec![0; CONTENT_BYTES];
+    rand::thread_rng().fill(&mut content[..]);
+    Entry::from_content(content)
+}
+
+fn entries(n: usize) -> Vec<Entry> {
+    (0..n).map(|_| entry()).collect()
+}
+
+/// A database already holding `n` entries, and their hashes.
+fn filled(rt: &Runtime, setup: &Setup, name: &str, n: usize) -> (Db, PathBuf, Vec<EntryHash>) {
+    let (db, dir) = setup.open(rt, name);
+    let held = entries(n);
+    for chunk in held.chunks(10_000) {
+        rt.block_on(db.insert_entries(chunk)).unwrap();
+    }
+    // plan from real statistics, as a long-running node would
+    rt.block_on(db.analyze()).unwrap();
+    let hashes = held.into_iter().map(|entry| entry.hash).collect();
+    (db, dir, hashes)
+}
+
+fn insert_single(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("insert_single");
+    group.throughput(Throughput::Elements(1));
+    for setup in SETUPS {
+        let (db, dir) = setup.open(&rt, "single");
+        group.bench_function(setup.label(), |b| {
+            b.to_async(&rt).iter_batched(
+                entry,
+                |entry| {
+                    let db = db.clone();
+                    async move { db.insert_entry(&entry).await.unwrap() }
+                },
+                BatchSize::SmallInput,
+            )
+        });
+        close(&rt, db, dir);
+    }
+    group.finish();
+}
+
+fn insert_batch(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("insert_batch");
+    // a 10k batch is a long iteration
+    group.sample_size(10);
+    for setup in SETUPS {
+        let (db, dir) = setup.open(&rt, "batch");
+        for &size in BATCH_SIZES {
+            group.throughput(Throughput::Elements(size as u64));
+            group.bench_with_input(BenchmarkId::new(setup.label(), size), &size, |b, &size| {
+                b.to_async(&rt).iter_batched(
+                    || entries(size),
+                    |batch| {
+                        let db = db.clone();
+                        async move { db.insert_entries(&batch).await.unwrap() }
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+        close(&rt, db, dir);
+    }
+    group.finish();
+}
+
+fn point_lookup(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("point_lookup");
+    group.throughput(Throughput::Elements(1));
+    for setup in SETUPS {
+        for &size in TABLE_SIZES {
+            let (db, dir, hashes) = filled(&rt, setup, "lookup", size);
+            group.bench_with_input(BenchmarkId::new(setup.label(), size), &size, |b, _| {
+                b.to_async(&rt).iter_batched(
+                    || hashes.choose(&mut rand::thread_rng()).unwrap().clone(),
+                    |hash| {
+                        let db = db.clone();
+                        async move { db.get_entry(&hash).await.unwrap().unwrap() }
+                    },
+                    BatchSize::SmallInput,
+                )
+            });
+            close(&rt, db, dir);
+        }
+    }
+    group.finish();
+}
+
+fn loc_range_scan(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("loc_range_scan");
+    let width = u32::MAX / SCAN_FRACTION;
+    for setup in SETUPS {
+        for &size in TABLE_SIZES {
+            let (db, dir, _) = filled(&rt, setup, "scan", size);
+            // counted per entry returned, hashes spread evenly over the ring
+            group.throughput(Throughput::Elements(size as u64 / SCAN_FRACTION as u64));
+            group.bench_with_input(BenchmarkId::new(setup.label(), size), &size, |b, _| {
+                b.to_async(&rt).iter_batched(
+                    || rand::thread_rng().gen::<u32>(),
+                    |start| {
+                        let db = db.clone();
+                        // wraps past the top of the ring like a real arc
+                        let end = start.wrapping_add(width);
+                        async move {
+                            db.query_entries(start, end, Timestamp(0), Timestamp::now())
+                                .await
+                                .unwrap()
+                        }
+                    },
+                    BatchSize::SmallInput,
+                )
+            });
+            close(&rt, db, dir);
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    insert_single,
+    insert_batch,
+    point_lookup,
+    loc_range_scan
+);
+criterion_main!(benches);
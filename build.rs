@@ -0,0 +1,4 @@
+fn main() {
+    // sqlx::migrate! embeds the files but doesn't track them itself
+    println!("cargo:rerun-if-changed=migrations");
+}
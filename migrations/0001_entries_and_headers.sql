@@ -0,0 +1,31 @@
+-- IF NOT EXISTS so files created before versioning are adopted as is
+
+-- create entries table
+CREATE TABLE IF NOT EXISTS entries (
+    hash            BLOB PRIMARY KEY,
+    dht_loc         INT NOT NULL,
+    created_at      TEXT NOT NULL
+);
+
+-- create dht_loc + created_at index
+-- we can have as many indexes as we want
+-- i.e. we could have separate dht_loc only index
+-- if we want queries that don't care about created_at, etc.
+CREATE INDEX IF NOT EXISTS entries_query_idx ON entries (
+    dht_loc, created_at
+);
+
+-- create headers table
+-- headers without an entry (e.g. Dna) are valid, so entry_hash is
+-- nullable - but when present it must reference a stored entry,
+-- and purging the entry purges the headers pointing at it
+CREATE TABLE IF NOT EXISTS headers (
+    hash            BLOB PRIMARY KEY,
+    entry_hash      BLOB NULL
+        REFERENCES entries(hash) ON DELETE CASCADE
+);
+
+-- sqlite doesn't index the child side of a foreign key for us
+CREATE INDEX IF NOT EXISTS headers_entry_hash_idx ON headers (
+    entry_hash
+);
@@ -0,0 +1,43 @@
+-- give headers the fields holochain actually stores
+-- sqlite can't add NOT NULL columns without a default, so rebuild
+-- the table - nothing references headers yet, so this is safe with
+-- foreign keys on
+
+CREATE TABLE headers_new (
+    hash            BLOB PRIMARY KEY,
+    author          BLOB NOT NULL,
+    -- position in the author's source chain, Dna is 0
+    seq             INT NOT NULL,
+    -- only the Dna header has no previous header
+    prev_hash       BLOB NULL,
+    entry_hash      BLOB NULL
+        REFERENCES entries(hash) ON DELETE CASCADE,
+    type            TEXT NOT NULL,
+    timestamp       TEXT NOT NULL
+);
+
+-- headers written before this migration carry no authorship,
+-- keep them under an empty author so they stay queryable
+INSERT INTO headers_new (hash, author, seq, prev_hash, entry_hash, type, timestamp)
+SELECT
+    hash,
+    x'',
+    0,
+    NULL,
+    entry_hash,
+    CASE WHEN entry_hash IS NULL THEN 'Dna' ELSE 'Create' END,
+    strftime('%Y-%m-%d %H:%M:%f', 'now')
+FROM headers;
+
+DROP TABLE headers;
+ALTER TABLE headers_new RENAME TO headers;
+
+-- dropped along with the old table
+CREATE INDEX headers_entry_hash_idx ON headers (
+    entry_hash
+);
+
+-- walk an author's chain in order
+CREATE INDEX headers_author_seq_idx ON headers (
+    author, seq
+);
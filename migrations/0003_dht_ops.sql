@@ -0,0 +1,25 @@
+-- create dht_ops table
+-- ops are what gossip and integration actually work on,
+-- each one is stored at the dht location of its basis hash
+CREATE TABLE dht_ops (
+    op_hash             BLOB PRIMARY KEY,
+    op_type             TEXT NOT NULL,
+    basis_loc           INT NOT NULL,
+    authored_timestamp  TEXT NOT NULL,
+    -- NULL until the integration workflow has processed the op
+    when_integrated     TEXT NULL,
+    -- NULL until validated
+    validation_status   TEXT NULL,
+    -- the op that must be integrated before this one can be, if any
+    dependency          BLOB NULL
+);
+
+-- gossip: which ops do we hold in this arc / time window
+CREATE INDEX dht_ops_gossip_idx ON dht_ops (
+    basis_loc, authored_timestamp
+);
+
+-- integration: which ops are still waiting
+CREATE INDEX dht_ops_integration_idx ON dht_ops (
+    when_integrated
+);
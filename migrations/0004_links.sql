@@ -0,0 +1,17 @@
+-- create links table
+-- a link is identified by the CreateLink header that made it,
+-- deleting it records the DeleteLink header rather than removing the row
+CREATE TABLE links (
+    create_header   BLOB PRIMARY KEY,
+    base_hash       BLOB NOT NULL,
+    target_hash     BLOB NOT NULL,
+    tag             BLOB NOT NULL,
+    zome_index      INT NOT NULL,
+    link_type       INT NOT NULL,
+    delete_header   BLOB NULL
+);
+
+-- blobs compare bytewise, so a tag prefix is a range scan on this
+CREATE INDEX links_base_tag_idx ON links (
+    base_hash, tag
+);
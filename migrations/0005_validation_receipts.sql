@@ -0,0 +1,9 @@
+-- create validation_receipts table
+-- one receipt per validator per op, dropped along with the op
+CREATE TABLE validation_receipts (
+    op_hash         BLOB NOT NULL
+        REFERENCES dht_ops(op_hash) ON DELETE CASCADE,
+    signer          BLOB NOT NULL,
+    timestamp       TEXT NOT NULL,
+    PRIMARY KEY (op_hash, signer)
+);
@@ -0,0 +1,15 @@
+-- create agent_store table
+-- the latest signed agent info we've seen for each agent,
+-- the arc is inclusive and wraps past u32::MAX when start > end
+CREATE TABLE agent_store (
+    agent               BLOB PRIMARY KEY,
+    agent_info          BLOB NOT NULL,
+    storage_arc_start   INT NOT NULL,
+    storage_arc_end     INT NOT NULL,
+    expires_at          TEXT NOT NULL
+);
+
+-- pruning scans by expiry
+CREATE INDEX agent_store_expires_at_idx ON agent_store (
+    expires_at
+);
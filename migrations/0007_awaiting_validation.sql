@@ -0,0 +1,5 @@
+-- app validation: which ops still have no outcome, oldest first.
+-- partial, so it only ever holds the backlog rather than every op
+CREATE INDEX dht_ops_awaiting_validation_idx ON dht_ops (
+    authored_timestamp
+) WHERE validation_status IS NULL;
@@ -0,0 +1,3 @@
+-- add the entry itself, MessagePack encoded by the app.
+-- entries stored before this have none
+ALTER TABLE entries ADD COLUMN content BLOB NOT NULL DEFAULT x'';
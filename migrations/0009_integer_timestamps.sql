@@ -0,0 +1,63 @@
+-- store entries.created_at as integer microseconds since the unix
+-- epoch rather than TEXT, which only sorts right while every writer
+-- formats it the same way
+-- sqlite can't change a column's type, so rebuild the table - and
+-- dropping entries with foreign keys on would cascade into headers,
+-- so headers are moved onto the new table first
+
+CREATE TABLE entries_new (
+    hash            BLOB PRIMARY KEY,
+    dht_loc         INT NOT NULL,
+    -- micros since the unix epoch
+    created_at      INTEGER NOT NULL,
+    content         BLOB NOT NULL DEFAULT x''
+);
+
+-- sqlx wrote `YYYY-MM-DD HH:MM:SS[.fraction]`, strftime only keeps
+-- milliseconds so the fraction is read separately
+INSERT INTO entries_new (hash, dht_loc, created_at, content)
+SELECT
+    hash,
+    dht_loc,
+    CAST(strftime('%s', created_at) AS INTEGER) * 1000000
+        + CASE WHEN instr(created_at, '.') > 0
+            THEN CAST(substr(substr(created_at, instr(created_at, '.') + 1) || '000000', 1, 6) AS INTEGER)
+            ELSE 0
+        END,
+    content
+FROM entries;
+
+CREATE TABLE headers_new (
+    hash            BLOB PRIMARY KEY,
+    author          BLOB NOT NULL,
+    -- position in the author's source chain, Dna is 0
+    seq             INT NOT NULL,
+    -- only the Dna header has no previous header
+    prev_hash       BLOB NULL,
+    entry_hash      BLOB NULL
+        REFERENCES entries_new(hash) ON DELETE CASCADE,
+    type            TEXT NOT NULL,
+    timestamp       TEXT NOT NULL
+);
+
+INSERT INTO headers_new (hash, author, seq, prev_hash, entry_hash, type, timestamp)
+SELECT hash, author, seq, prev_hash, entry_hash, type, timestamp FROM headers;
+
+DROP TABLE headers;
+DROP TABLE entries;
+-- also points headers_new's foreign key at the renamed table
+ALTER TABLE entries_new RENAME TO entries;
+ALTER TABLE headers_new RENAME TO headers;
+
+-- dropped along with the old tables
+CREATE INDEX entries_query_idx ON entries (
+    dht_loc, created_at
+);
+
+CREATE INDEX headers_entry_hash_idx ON headers (
+    entry_hash
+);
+
+CREATE INDEX headers_author_seq_idx ON headers (
+    author, seq
+);
@@ -0,0 +1,65 @@
+-- store dht_ops.validation_status as a small integer, 0 while pending,
+-- rather than the variant name or NULL
+-- sqlite can't change a column's type, so rebuild the table - and
+-- dropping dht_ops with foreign keys on would cascade into
+-- validation_receipts, so receipts are moved onto the new table first
+
+CREATE TABLE dht_ops_new (
+    op_hash             BLOB PRIMARY KEY,
+    op_type             TEXT NOT NULL,
+    basis_loc           INT NOT NULL,
+    authored_timestamp  TEXT NOT NULL,
+    -- NULL until the integration workflow has processed the op
+    when_integrated     TEXT NULL,
+    -- 0 pending, 1 valid, 2 rejected, 3 abandoned
+    validation_status   INTEGER NOT NULL DEFAULT 0,
+    -- the op that must be integrated before this one can be, if any
+    dependency          BLOB NULL
+);
+
+INSERT INTO dht_ops_new (op_hash, op_type, basis_loc, authored_timestamp,
+    when_integrated, validation_status, dependency)
+SELECT
+    op_hash,
+    op_type,
+    basis_loc,
+    authored_timestamp,
+    when_integrated,
+    CASE validation_status
+        WHEN 'Valid' THEN 1
+        WHEN 'Rejected' THEN 2
+        WHEN 'Abandoned' THEN 3
+        ELSE 0
+    END,
+    dependency
+FROM dht_ops;
+
+CREATE TABLE validation_receipts_new (
+    op_hash         BLOB NOT NULL
+        REFERENCES dht_ops_new(op_hash) ON DELETE CASCADE,
+    signer          BLOB NOT NULL,
+    timestamp       TEXT NOT NULL,
+    PRIMARY KEY (op_hash, signer)
+);
+
+INSERT INTO validation_receipts_new (op_hash, signer, timestamp)
+SELECT op_hash, signer, timestamp FROM validation_receipts;
+
+DROP TABLE validation_receipts;
+DROP TABLE dht_ops;
+-- also points validation_receipts_new's foreign key at the renamed table
+ALTER TABLE dht_ops_new RENAME TO dht_ops;
+ALTER TABLE validation_receipts_new RENAME TO validation_receipts;
+
+-- dropped along with the old table
+CREATE INDEX dht_ops_gossip_idx ON dht_ops (
+    basis_loc, authored_timestamp
+);
+
+CREATE INDEX dht_ops_integration_idx ON dht_ops (
+    when_integrated
+);
+
+CREATE INDEX dht_ops_awaiting_validation_idx ON dht_ops (
+    authored_timestamp
+) WHERE validation_status = 0;
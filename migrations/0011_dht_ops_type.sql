@@ -0,0 +1,5 @@
+-- gossip and integration per op type, e.g. only links or only
+-- agent activity, within an arc / time window
+CREATE INDEX dht_ops_type_idx ON dht_ops (
+    op_type, basis_loc, authored_timestamp
+);
@@ -0,0 +1,11 @@
+-- an author can't have two headers at the same chain position,
+-- so forks are refused on write rather than found on read
+
+-- headers from before 0002 all sit at seq 0 under the empty author,
+-- spread them out so they don't collide
+UPDATE headers SET seq = rowid WHERE author = x'';
+
+DROP INDEX headers_author_seq_idx;
+CREATE UNIQUE INDEX headers_author_seq_idx ON headers (
+    author, seq
+);
@@ -0,0 +1,14 @@
+-- what each entry is, and how big, so holdings can be filtered by
+-- type and totted up without reading the content
+-- no covering index for sizes: sqlite would prefer it to
+-- entries_query_idx for every plain range query
+-- entries stored before this are taken to be app entries
+ALTER TABLE entries ADD COLUMN entry_type TEXT NOT NULL DEFAULT 'App:0:0';
+ALTER TABLE entries ADD COLUMN size_bytes INT NOT NULL DEFAULT 0;
+
+UPDATE entries SET size_bytes = length(content);
+
+-- entries of one type, by location
+CREATE INDEX entries_type_idx ON entries (
+    entry_type, dht_loc
+);
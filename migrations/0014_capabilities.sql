@@ -0,0 +1,44 @@
+-- capability grants made from our chain and claims we hold on others',
+-- so a call can be checked against the grants without walking the chain
+-- a grant is identified by the header that created it, deleting the
+-- row revokes it
+CREATE TABLE cap_grants (
+    header_hash     BLOB PRIMARY KEY,
+    tag             TEXT NOT NULL,
+    -- Unrestricted, Transferable or Assigned
+    access          TEXT NOT NULL,
+    -- NULL only for Unrestricted
+    secret          BLOB NULL
+);
+
+-- the zome functions each grant covers
+CREATE TABLE cap_grant_functions (
+    zome            TEXT NOT NULL,
+    function        TEXT NOT NULL,
+    header_hash     BLOB NOT NULL
+        REFERENCES cap_grants(header_hash) ON DELETE CASCADE,
+    PRIMARY KEY (zome, function, header_hash)
+);
+
+-- for the cascade from cap_grants
+CREATE INDEX cap_grant_functions_header_idx ON cap_grant_functions (
+    header_hash
+);
+
+-- the only agents an Assigned grant lets in
+CREATE TABLE cap_grant_assignees (
+    header_hash     BLOB NOT NULL
+        REFERENCES cap_grants(header_hash) ON DELETE CASCADE,
+    agent           BLOB NOT NULL,
+    PRIMARY KEY (header_hash, agent)
+);
+
+CREATE TABLE cap_claims (
+    secret          BLOB PRIMARY KEY,
+    tag             TEXT NOT NULL,
+    grantor         BLOB NOT NULL
+);
+
+CREATE INDEX cap_claims_grantor_idx ON cap_claims (
+    grantor, tag
+);
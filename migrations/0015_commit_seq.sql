@@ -0,0 +1,8 @@
+-- bumped by every DbWrite::commit_and_notify, so a reader can tell
+-- whether its snapshot includes a given commit
+CREATE TABLE commit_seq (
+    id              INTEGER PRIMARY KEY CHECK (id = 0),
+    seq             INTEGER NOT NULL
+);
+
+INSERT INTO commit_seq (id, seq) VALUES (0, 0);
@@ -0,0 +1,9 @@
+-- every snapshot Db::snapshot has taken and not yet rotated away,
+-- the files themselves live outside the database
+CREATE TABLE snapshots (
+    file            TEXT PRIMARY KEY,
+    -- micros since the unix epoch
+    taken_at        INTEGER NOT NULL,
+    size_bytes      INTEGER NOT NULL,
+    schema_version  INTEGER NOT NULL
+);
@@ -0,0 +1,41 @@
+-- full-text search over entry content, read as text
+-- contentless, so the text isn't stored twice, and keyed by a rowid of
+-- its own: entries has no INTEGER PRIMARY KEY, so a VACUUM may
+-- renumber its rowids
+CREATE TABLE entry_search_ids (
+    id              INTEGER PRIMARY KEY,
+    hash            BLOB NOT NULL UNIQUE
+);
+
+CREATE VIRTUAL TABLE entry_search USING fts5(
+    text,
+    content = '',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+-- a contentless table only forgets a row when handed back the exact
+-- text it was indexed with
+CREATE TRIGGER entry_search_insert AFTER INSERT ON entries BEGIN
+    INSERT INTO entry_search_ids (hash) VALUES (new.hash);
+    INSERT INTO entry_search (rowid, text)
+    SELECT id, CAST(new.content AS TEXT) FROM entry_search_ids WHERE hash = new.hash;
+END;
+
+CREATE TRIGGER entry_search_update AFTER UPDATE OF content ON entries BEGIN
+    INSERT INTO entry_search (entry_search, rowid, text)
+    SELECT 'delete', id, CAST(old.content AS TEXT) FROM entry_search_ids WHERE hash = old.hash;
+    INSERT INTO entry_search (rowid, text)
+    SELECT id, CAST(new.content AS TEXT) FROM entry_search_ids WHERE hash = new.hash;
+END;
+
+CREATE TRIGGER entry_search_delete AFTER DELETE ON entries BEGIN
+    INSERT INTO entry_search (entry_search, rowid, text)
+    SELECT 'delete', id, CAST(old.content AS TEXT) FROM entry_search_ids WHERE hash = old.hash;
+    DELETE FROM entry_search_ids WHERE hash = old.hash;
+END;
+
+INSERT INTO entry_search_ids (hash) SELECT hash FROM entries;
+INSERT INTO entry_search (rowid, text)
+SELECT ids.id, CAST(entries.content AS TEXT)
+FROM entry_search_ids AS ids
+JOIN entries ON entries.hash = ids.hash;
@@ -0,0 +1,151 @@
+-- the schema sqlite reaches after migrations/0015, in one step:
+-- BYTEA for blobs, BIGINT for anything holding a u32 (there are no
+-- unsigned types), TIMESTAMPTZ where sqlite stores chrono's text
+
+CREATE TABLE entries (
+    hash            BYTEA PRIMARY KEY,
+    dht_loc         BIGINT NOT NULL,
+    -- micros since the unix epoch
+    created_at      BIGINT NOT NULL,
+    content         BYTEA NOT NULL DEFAULT '',
+    entry_type      TEXT NOT NULL DEFAULT 'App:0:0',
+    size_bytes      BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE INDEX entries_query_idx ON entries (
+    dht_loc, created_at
+);
+
+CREATE INDEX entries_type_idx ON entries (
+    entry_type, dht_loc
+);
+
+CREATE TABLE headers (
+    hash            BYTEA PRIMARY KEY,
+    author          BYTEA NOT NULL,
+    -- position in the author's source chain, Dna is 0
+    seq             BIGINT NOT NULL,
+    -- only the Dna header has no previous header
+    prev_hash       BYTEA NULL,
+    entry_hash      BYTEA NULL
+        REFERENCES entries(hash) ON DELETE CASCADE,
+    type            TEXT NOT NULL,
+    timestamp       TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX headers_entry_hash_idx ON headers (
+    entry_hash
+);
+
+CREATE UNIQUE INDEX headers_author_seq_idx ON headers (
+    author, seq
+);
+
+CREATE TABLE dht_ops (
+    op_hash             BYTEA PRIMARY KEY,
+    op_type             TEXT NOT NULL,
+    basis_loc           BIGINT NOT NULL,
+    authored_timestamp  TIMESTAMPTZ NOT NULL,
+    -- NULL until the integration workflow has processed the op
+    when_integrated     TIMESTAMPTZ NULL,
+    -- 0 pending, 1 valid, 2 rejected, 3 abandoned
+    validation_status   SMALLINT NOT NULL DEFAULT 0,
+    -- the op that must be integrated before this one can be, if any
+    dependency          BYTEA NULL
+);
+
+CREATE INDEX dht_ops_gossip_idx ON dht_ops (
+    basis_loc, authored_timestamp
+);
+
+CREATE INDEX dht_ops_integration_idx ON dht_ops (
+    when_integrated
+);
+
+CREATE INDEX dht_ops_awaiting_validation_idx ON dht_ops (
+    authored_timestamp
+) WHERE validation_status = 0;
+
+CREATE INDEX dht_ops_type_idx ON dht_ops (
+    op_type, basis_loc, authored_timestamp
+);
+
+CREATE TABLE validation_receipts (
+    op_hash         BYTEA NOT NULL
+        REFERENCES dht_ops(op_hash) ON DELETE CASCADE,
+    signer          BYTEA NOT NULL,
+    timestamp       TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (op_hash, signer)
+);
+
+CREATE TABLE links (
+    create_header   BYTEA PRIMARY KEY,
+    base_hash       BYTEA NOT NULL,
+    target_hash     BYTEA NOT NULL,
+    tag             BYTEA NOT NULL,
+    zome_index      INTEGER NOT NULL,
+    link_type       INTEGER NOT NULL,
+    delete_header   BYTEA NULL
+);
+
+CREATE INDEX links_base_tag_idx ON links (
+    base_hash, tag
+);
+
+CREATE TABLE agent_store (
+    agent               BYTEA PRIMARY KEY,
+    agent_info          BYTEA NOT NULL,
+    storage_arc_start   BIGINT NOT NULL,
+    storage_arc_end     BIGINT NOT NULL,
+    expires_at          TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX agent_store_expires_at_idx ON agent_store (
+    expires_at
+);
+
+CREATE TABLE cap_grants (
+    header_hash     BYTEA PRIMARY KEY,
+    tag             TEXT NOT NULL,
+    -- Unrestricted, Transferable or Assigned
+    access          TEXT NOT NULL,
+    -- NULL only for Unrestricted
+    secret          BYTEA NULL
+);
+
+CREATE TABLE cap_grant_functions (
+    zome            TEXT NOT NULL,
+    function        TEXT NOT NULL,
+    header_hash     BYTEA NOT NULL
+        REFERENCES cap_grants(header_hash) ON DELETE CASCADE,
+    PRIMARY KEY (zome, function, header_hash)
+);
+
+CREATE INDEX cap_grant_functions_header_idx ON cap_grant_functions (
+    header_hash
+);
+
+CREATE TABLE cap_grant_assignees (
+    header_hash     BYTEA NOT NULL
+        REFERENCES cap_grants(header_hash) ON DELETE CASCADE,
+    agent           BYTEA NOT NULL,
+    PRIMARY KEY (header_hash, agent)
+);
+
+CREATE TABLE cap_claims (
+    secret          BYTEA PRIMARY KEY,
+    tag             TEXT NOT NULL,
+    grantor         BYTEA NOT NULL
+);
+
+CREATE INDEX cap_claims_grantor_idx ON cap_claims (
+    grantor, tag
+);
+
+-- bumped on every commit marker, as in sqlite
+CREATE TABLE commit_seq (
+    id              INTEGER PRIMARY KEY CHECK (id = 0),
+    seq             BIGINT NOT NULL
+);
+
+INSERT INTO commit_seq (id, seq) VALUES (0, 0);
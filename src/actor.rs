@@ -0,0 +1,177 @@
+use crate::{Db, Entry, Timestamp, WriteOutcome};
+use futures::channel::{mpsc, oneshot};
+use futures::{SinkExt, StreamExt};
+
+/// Commands not yet taken by the actor before senders have to wait.
+const CHANNEL_BOUND: usize = 64;
+
+type Reply<T> = oneshot::Sender<anyhow::Result<T>>;
+
+enum Command {
+    Insert(Entry, Reply<WriteOutcome>),
+    Query {
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+        reply: Reply<Vec<Entry>>,
+    },
+    Count {
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+        reply: Reply<u64>,
+    },
+}
+
+/// Send handle to a task owning the database, for callers that expect
+/// to talk to it ghost-actor style over a channel rather than hold a
+/// [`Db`] themselves. Cheap to clone, see [`Db::spawn_actor`].
+///
+/// Commands are handled one at a time in the order they arrive.
+/// The task stops once every handle is dropped.
+#[derive(Clone)]
+pub struct DbActor {
+    tx: mpsc::Sender<Command>,
+}
+
+impl DbActor {
+    async fn call<T>(&self, command: impl FnOnce(Reply<T>) -> Command) -> anyhow::Result<T> {
+        let (reply, done) = oneshot::channel();
+        self.tx
+            .clone()
+            .send(command(reply))
+            .await
+            .map_err(|_| anyhow::anyhow!("db actor stopped"))?;
+        done.await
+            .map_err(|_| anyhow::anyhow!("db actor stopped"))?
+    }
+
+    /// Insert a single entry in its own transaction.
+    pub async fn insert(&self, entry: Entry) -> anyhow::Result<WriteOutcome> {
+        self.call(|reply| Command::Insert(entry, reply)).await
+    }
+
+    /// Fetch all entries within the given (inclusive) location
+    /// and creation time ranges, as [`Db::query_entries`] does.
+    pub async fn query(
+        &self,
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+    ) -> anyhow::Result<Vec<Entry>> {
+        self.call(|reply| Command::Query {
+            dht_loc_start,
+            dht_loc_end,
+            created_at_start,
+            created_at_end,
+            reply,
+        })
+        .await
+    }
+
+    /// Count the entries within the given (inclusive) location
+    /// and creation time ranges, as [`Db::count_entries`] does.
+    pub async fn count(
+        &self,
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+    ) -> anyhow::Result<u64> {
+        self.call(|reply| Command::Count {
+            dht_loc_start,
+            dht_loc_end,
+            created_at_start,
+            created_at_end,
+            reply,
+        })
+        .await
+    }
+}
+
+/// What the actor task runs each command against.
+struct DbActorHandler {
+    db: Db,
+}
+
+impl DbActorHandler {
+    async fn handle_insert(&mut self, entry: Entry) -> anyhow::Result<WriteOutcome> {
+        self.db.insert_entry(&entry).await
+    }
+
+    async fn handle_query(
+        &mut self,
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+    ) -> anyhow::Result<Vec<Entry>> {
+        self.db
+            .query_entries(dht_loc_start, dht_loc_end, created_at_start, created_at_end)
+            .await
+    }
+
+    async fn handle_count(
+        &mut self,
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+    ) -> anyhow::Result<u64> {
+        self.db
+            .count_entries(dht_loc_start, dht_loc_end, created_at_start, created_at_end)
+            .await
+    }
+}
+
+async fn actor_task(mut handler: DbActorHandler, mut rx: mpsc::Receiver<Command>) {
+    // the caller may have given up waiting, so replies can go nowhere
+    while let Some(command) = rx.next().await {
+        match command {
+            Command::Insert(entry, reply) => {
+                let _ = reply.send(handler.handle_insert(entry).await);
+            }
+            Command::Query {
+                dht_loc_start,
+                dht_loc_end,
+                created_at_start,
+                created_at_end,
+                reply,
+            } => {
+                let res = handler
+                    .handle_query(dht_loc_start, dht_loc_end, created_at_start, created_at_end)
+                    .await;
+                let _ = reply.send(res);
+            }
+            Command::Count {
+                dht_loc_start,
+                dht_loc_end,
+                created_at_start,
+                created_at_end,
+                reply,
+            } => {
+                let res = handler
+                    .handle_count(dht_loc_start, dht_loc_end, created_at_start, created_at_end)
+                    .await;
+                let _ = reply.send(res);
+            }
+        }
+    }
+}
+
+impl Db {
+    /// Spawn a task serving this database over a channel,
+    /// returning the handle to send it commands.
+    ///
+    /// The task holds a clone of the `Db`, so the database stays open
+    /// while any handle is alive unless closed explicitly.
+    pub fn spawn_actor(&self) -> DbActor {
+        let (tx, rx) = mpsc::channel(CHANNEL_BOUND);
+        let handler = DbActorHandler { db: self.clone() };
+        tokio::task::spawn(actor_task(handler, rx));
+        DbActor { tx }
+    }
+}
@@ -0,0 +1,192 @@
+use crate::retry::with_retry;
+use crate::{AgentPubKey, Db, DbRead, DbWrite, DhtLocation, WriteOutcome};
+use chrono::prelude::*;
+use futures::TryStreamExt;
+use rand::Rng;
+use sqlx::{Connection, Execute};
+
+/// Demo peer info type for database, as gossiped by kitsune.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct AgentInfo {
+    /// The agent's public key.
+    pub agent: AgentPubKey,
+    /// The signed agent info, opaque to the database.
+    pub agent_info: Vec<u8>,
+    /// First location the agent stores, inclusive.
+    pub storage_arc_start: u32,
+    /// Last location the agent stores, inclusive.
+    /// Less than the start if the arc wraps past `u32::MAX`.
+    pub storage_arc_end: u32,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl AgentInfo {
+    /// Generate a random agent storing the whole space for an hour.
+    pub fn rand() -> Self {
+        let mut agent_info = vec![0; 16];
+        rand::thread_rng().fill(&mut agent_info[..]);
+
+        Self {
+            agent: AgentPubKey::rand(),
+            agent_info,
+            storage_arc_start: 0,
+            storage_arc_end: u32::MAX,
+            expires_at: Utc::now() + chrono::Duration::hours(1),
+        }
+    }
+}
+
+impl Db {
+    /// Store `info`, replacing whatever we held for that agent.
+    pub async fn put_agent_info(&self, info: &AgentInfo) -> anyhow::Result<WriteOutcome> {
+        self.write.put_agent_info(info).await
+    }
+
+    /// Remove agent info that expired before `now`.
+    pub async fn prune_agent_info(&self, now: DateTime<Utc>) -> anyhow::Result<WriteOutcome> {
+        self.write.prune_agent_info(now).await
+    }
+
+    /// Fetch the info we hold for `agent`.
+    pub async fn get_agent_info(&self, agent: &AgentPubKey) -> anyhow::Result<Option<AgentInfo>> {
+        self.reader().get_agent_info(agent).await
+    }
+
+    /// Fetch every agent whose storage arc covers `dht_loc`.
+    pub async fn agents_covering(&self, dht_loc: u32) -> anyhow::Result<Vec<AgentInfo>> {
+        self.reader().agents_covering(dht_loc).await
+    }
+}
+
+impl DbWrite {
+    /// Store `info`, replacing whatever we held for that agent.
+    pub async fn put_agent_info(&self, info: &AgentInfo) -> anyhow::Result<WriteOutcome> {
+        let outcome = with_retry(
+            self.trace.op("put_agent_info"),
+            &self.retry,
+            &self.permits,
+            || async {
+                let info = info.clone();
+                let mut con = self.pool.acquire().await?;
+                // replacing an agent's info changes one row just as adding
+                // it does, so check whether the agent is held beforehand
+                con.transaction(move |tx| {
+                    Box::pin(async move {
+                        let existed = sqlx::query!(
+                            "SELECT 1 AS found FROM agent_store WHERE agent = ?1",
+                            info.agent
+                        )
+                        .fetch_optional(&mut *tx)
+                        .await?
+                        .is_some();
+                        let arc_start = DhtLocation(info.storage_arc_start);
+                        let arc_end = DhtLocation(info.storage_arc_end);
+                        let res = sqlx::query!(
+                            "INSERT INTO agent_store
+                        (agent, agent_info, storage_arc_start, storage_arc_end, expires_at)
+                        VALUES (?1, ?2, ?3, ?4, ?5)
+                        ON CONFLICT (agent) DO UPDATE SET
+                            agent_info = excluded.agent_info,
+                            storage_arc_start = excluded.storage_arc_start,
+                            storage_arc_end = excluded.storage_arc_end,
+                            expires_at = excluded.expires_at",
+                            info.agent,
+                            info.agent_info,
+                            arc_start,
+                            arc_end,
+                            info.expires_at,
+                        )
+                        .execute(&mut *tx)
+                        .await?;
+                        Ok(WriteOutcome::upserted(existed, res.rows_affected()))
+                    })
+                })
+                .await
+            },
+        )
+        .await?;
+        Ok(outcome)
+    }
+
+    /// Remove agent info that expired before `now`.
+    pub async fn prune_agent_info(&self, now: DateTime<Utc>) -> anyhow::Result<WriteOutcome> {
+        let query = || sqlx::query!("DELETE FROM agent_store WHERE expires_at < ?1", now);
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let res = with_retry(
+            self.trace.op("prune_agent_info").statement(sql, &[&now]),
+            &self.retry,
+            &self.permits,
+            || async { query().execute(&self.pool).await },
+        )
+        .await?;
+        let deleted = res.rows_affected();
+        Ok(WriteOutcome::deleted(deleted, deleted))
+    }
+}
+
+impl DbRead {
+    /// Fetch the info we hold for `agent`.
+    pub async fn get_agent_info(&self, agent: &AgentPubKey) -> anyhow::Result<Option<AgentInfo>> {
+        let query = || {
+            sqlx::query_as!(
+                AgentInfo,
+                r#"SELECT
+                    agent AS "agent!: AgentPubKey",
+                    agent_info,
+                    storage_arc_start AS "storage_arc_start: u32",
+                    storage_arc_end AS "storage_arc_end: u32",
+                    expires_at AS "expires_at: DateTime<Utc>"
+                FROM agent_store WHERE agent = ?1;"#,
+                agent
+            )
+        };
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let out = with_retry(
+            self.trace.op("get_agent_info").statement(sql, &[&agent]),
+            &self.retry,
+            &self.permits,
+            || async { query().fetch_optional(&self.pool).await },
+        )
+        .await?;
+        Ok(out)
+    }
+
+    /// Fetch every agent whose storage arc covers `dht_loc`.
+    pub async fn agents_covering(&self, dht_loc: u32) -> anyhow::Result<Vec<AgentInfo>> {
+        let loc = DhtLocation(dht_loc);
+        let query = || {
+            sqlx::query_as!(
+                AgentInfo,
+                r#"SELECT
+                    agent AS "agent!: AgentPubKey",
+                    agent_info,
+                    storage_arc_start AS "storage_arc_start: u32",
+                    storage_arc_end AS "storage_arc_end: u32",
+                    expires_at AS "expires_at: DateTime<Utc>"
+                FROM agent_store
+                WHERE (
+                    storage_arc_start <= storage_arc_end
+                    AND ?1 >= storage_arc_start
+                    AND ?1 <= storage_arc_end
+                ) OR (
+                    storage_arc_start > storage_arc_end
+                    AND (?1 >= storage_arc_start OR ?1 <= storage_arc_end)
+                )
+                ;"#,
+                loc
+            )
+        };
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let out = with_retry(
+            self.trace.op("agents_covering").statement(sql, &[&loc]),
+            &self.retry,
+            &self.permits,
+            || async { query().fetch(&self.pool).try_collect::<Vec<_>>().await },
+        )
+        .await?;
+        Ok(out)
+    }
+}
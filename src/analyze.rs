@@ -0,0 +1,50 @@
+use crate::{Db, DbWrite};
+use std::time::Duration;
+
+impl Db {
+    /// Gather fresh query planner statistics for every table and index.
+    ///
+    /// The background option [`crate::DbConfig::analyze_every`] runs this
+    /// on a timer, call it directly to get stable plans before a
+    /// benchmark or after a large import.
+    pub async fn analyze(&self) -> anyhow::Result<()> {
+        self.write.analyze().await
+    }
+
+    /// Run `PRAGMA optimize`, which analyzes only the tables the writer's
+    /// recent queries suggest have stale statistics.
+    /// [`Db::close`] does this unless [`crate::DbConfig::optimize_on_close`]
+    /// is turned off.
+    pub async fn optimize(&self) -> anyhow::Result<()> {
+        self.write.optimize().await
+    }
+}
+
+impl DbWrite {
+    /// Run `ANALYZE` through the writer, see [`Db::analyze`].
+    pub async fn analyze(&self) -> anyhow::Result<()> {
+        let _permit = self.permits.acquire().await?;
+        sqlx::query("ANALYZE;").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Run `PRAGMA optimize` through the writer, see [`Db::optimize`].
+    pub async fn optimize(&self) -> anyhow::Result<()> {
+        let _permit = self.permits.acquire().await?;
+        sqlx::query("PRAGMA optimize;").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+/// Periodically analyze until the task is aborted.
+pub(crate) async fn analyze_task(write: DbWrite, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // the first tick completes immediately, the tables were just opened
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if let Err(err) = write.analyze().await {
+            tracing::warn!(?err, "background analyze failed");
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Other databases attached to every connection, so one statement can
+//! join across them, e.g. the cache of a DNA against its DHT database.
+
+use crate::backup::main_file;
+use crate::db::{
+    cipher_pragmas, connect_keyed, exec_secret, health_check, init_connection, is_sqlcipher,
+};
+use crate::export::quote_path;
+use crate::key_provider::DbKey;
+use crate::{Db, DbConfig, DbKind, DbRead, Encryption, EntryHash};
+use sqlx::{Connection, Executor, SqliteConnection};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A database attached with [`Db::attach`].
+#[derive(Clone)]
+pub(crate) struct Attachment {
+    alias: String,
+    path: PathBuf,
+    key: Option<DbKey>,
+}
+
+/// The databases every connection should have attached, shared by both
+/// pools so connections opened after an attach pick it up.
+/// `changed` is only there so connections of a database that never
+/// attached anything skip the check on acquire.
+#[derive(Default)]
+pub(crate) struct Attachments {
+    changed: bool,
+    list: Vec<Attachment>,
+}
+
+pub(crate) type SharedAttachments = Arc<std::sync::RwLock<Attachments>>;
+
+/// Attach `attachment` to `con`, keyed with its own key if SQLCipher is
+/// linked.
+async fn attach_one(
+    con: &mut SqliteConnection,
+    config: &DbConfig,
+    attachment: &Attachment,
+    sqlcipher: bool,
+) -> sqlx::Result<()> {
+    let prefix = format!(
+        "ATTACH DATABASE {} AS \"{}\"",
+        quote_path(&attachment.path),
+        attachment.alias
+    );
+    match &attachment.key {
+        Some(key) if sqlcipher => {
+            let cmd = key.statement(&format!("{} KEY ", prefix), ";");
+            exec_secret(con, "ATTACH DATABASE", &cmd)?;
+        }
+        _ => {
+            con.execute(&*format!("{};", prefix)).await?;
+        }
+    }
+    if attachment.key.is_some() && sqlcipher {
+        // the attached file has to be read with the same settings
+        let scoped = format!("PRAGMA \"{}\".", attachment.alias);
+        for pragma in cipher_pragmas(config) {
+            con.execute(&*pragma.replacen("PRAGMA ", &scoped, 1))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Attach everything in `attachments` to a fresh connection.
+pub(crate) async fn attach_all(
+    con: &mut SqliteConnection,
+    config: &DbConfig,
+    attachments: &SharedAttachments,
+) -> sqlx::Result<()> {
+    let list = attachments.read().unwrap().list.clone();
+    if list.is_empty() {
+        return Ok(());
+    }
+    let sqlcipher = is_sqlcipher(con).await?;
+    for attachment in &list {
+        attach_one(con, config, attachment, sqlcipher).await?;
+    }
+    Ok(())
+}
+
+/// Whether `con` has exactly the databases of `attachments` attached.
+/// A pooled connection that doesn't is dropped and replaced on acquire,
+/// the new one attaching them in `after_connect`.
+pub(crate) async fn attachments_current(
+    con: &mut SqliteConnection,
+    attachments: &SharedAttachments,
+) -> sqlx::Result<bool> {
+    let mut want: Vec<String> = {
+        let attachments = attachments.read().unwrap();
+        if !attachments.changed {
+            return Ok(true);
+        }
+        attachments.list.iter().map(|a| a.alias.clone()).collect()
+    };
+    let mut have: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM pragma_database_list WHERE name NOT IN ('main', 'temp');",
+    )
+    .fetch_all(con)
+    .await?;
+    want.sort();
+    have.sort();
+    Ok(want == have)
+}
+
+/// Aliases are spliced into sql, so keep them to plain identifiers.
+fn check_alias(alias: &str) -> anyhow::Result<()> {
+    let valid = alias
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid || alias.eq_ignore_ascii_case("main") || alias.eq_ignore_ascii_case("temp") {
+        anyhow::bail!("invalid alias {:?} for an attached database", alias);
+    }
+    Ok(())
+}
+
+impl Db {
+    /// Attach the database of `other_kind`, next to this one under the
+    /// same data root, to every connection as `alias`, so its tables can
+    /// be queried as `alias.entries` and joined against these.
+    ///
+    /// It's keyed with its own key from the configured key source, with
+    /// SQLCipher's `ATTACH ... KEY`, and must exist already.
+    /// Pooled connections pick it up as they're next acquired.
+    pub async fn attach(&self, other_kind: DbKind, alias: &str) -> anyhow::Result<()> {
+        check_alias(alias)?;
+        if self.kind.is_none() {
+            anyhow::bail!("attach needs a database opened with Db::open_kind");
+        }
+        let main = match main_file(self).await? {
+            Some(file) => file,
+            None => anyhow::bail!("an in-memory database has no data root to attach from"),
+        };
+        let data_root = main
+            .parent()
+            .and_then(|dir| dir.parent())
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        let path = other_kind.path(data_root);
+        if tokio::fs::metadata(&path).await.is_err() {
+            anyhow::bail!("no {} database at {}", other_kind.name(), path.display());
+        }
+        if self
+            .attachments
+            .read()
+            .unwrap()
+            .list
+            .iter()
+            .any(|a| a.alias == alias)
+        {
+            anyhow::bail!("a database is already attached as {}", alias);
+        }
+        let key = match &self.config.encryption {
+            Some(Encryption::SqlCipher(source)) => Some(source.db_key(Some(&other_kind)).await?),
+            _ => None,
+        };
+        let attachment = Attachment {
+            alias: alias.to_string(),
+            path,
+            key,
+        };
+
+        // try it on a connection of its own first, so a wrong key or a
+        // broken file fails here rather than on every acquire
+        let key = self.key.read().unwrap().clone();
+        let mut check = connect_keyed(
+            self.options.clone(),
+            Some(&main),
+            &self.config,
+            key.as_ref(),
+        )
+        .await?;
+        let res = async {
+            init_connection(&mut check, &self.config, key.as_ref()).await?;
+            let sqlcipher = is_sqlcipher(&mut check).await?;
+            attach_one(&mut check, &self.config, &attachment, sqlcipher).await?;
+            sqlx::query(&format!(
+                "SELECT count(*) FROM \"{}\".sqlite_master;",
+                alias
+            ))
+            .fetch_one(&mut check)
+            .await?;
+            health_check(&mut check).await
+        }
+        .await;
+        check.close().await?;
+        res?;
+
+        let mut attachments = self.attachments.write().unwrap();
+        attachments.changed = true;
+        attachments.list.push(attachment);
+        Ok(())
+    }
+
+    /// Detach the database attached as `alias`.
+    /// Pooled connections drop it as they're next acquired.
+    pub fn detach(&self, alias: &str) -> anyhow::Result<()> {
+        let mut attachments = self.attachments.write().unwrap();
+        let before = attachments.list.len();
+        attachments.list.retain(|a| a.alias != alias);
+        if attachments.list.len() == before {
+            anyhow::bail!("no database attached as {}", alias);
+        }
+        Ok(())
+    }
+
+    /// The aliases of the attached databases, in the order attached.
+    pub fn attached(&self) -> Vec<String> {
+        self.attachments
+            .read()
+            .unwrap()
+            .list
+            .iter()
+            .map(|a| a.alias.clone())
+            .collect()
+    }
+
+    /// Hashes of the entries held in the database attached as `alias`
+    /// but not in this one, see [`DbRead::entries_missing_from_main`].
+    pub async fn entries_missing_from_main(&self, alias: &str) -> anyhow::Result<Vec<EntryHash>> {
+        self.reader().entries_missing_from_main(alias).await
+    }
+}
+
+impl DbRead {
+    /// Hashes of the entries held in the database attached as `alias`
+    /// but not in this one, e.g. with the cache attached to a DHT
+    /// database, the cached entries that haven't been integrated yet.
+    /// One statement over both files, so one consistent read.
+    pub async fn entries_missing_from_main(&self, alias: &str) -> anyhow::Result<Vec<EntryHash>> {
+        check_alias(alias)?;
+        let _permit = self.permits.acquire().await?;
+        let sql = format!(
+            "SELECT other.hash FROM \"{}\".entries AS other
+            WHERE NOT EXISTS (SELECT 1 FROM main.entries AS held WHERE held.hash = other.hash)
+            ORDER BY other.hash;",
+            alias
+        );
+        Ok(sqlx::query_scalar(&sql).fetch_all(&self.pool).await?)
+    }
+}
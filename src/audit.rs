@@ -0,0 +1,100 @@
+//! Hooks run after every committed write, for audit logs and cache
+//! invalidation, with a summary of what it changed.
+
+use crate::{Db, DbWrite, OpHash};
+use std::sync::Arc;
+
+/// The rows one table had written by a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableChanges {
+    /// As named in the schema, `alias.table` for an attached database.
+    pub table: String,
+    pub inserted: u64,
+    pub updated: u64,
+    pub deleted: u64,
+}
+
+impl TableChanges {
+    fn new(table: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            inserted: 0,
+            updated: 0,
+            deleted: 0,
+        }
+    }
+
+    /// Every row written, however.
+    pub fn rows(&self) -> u64 {
+        self.inserted + self.updated + self.deleted
+    }
+}
+
+/// What a committed transaction wrote, handed to the hooks given to
+/// [`Db::on_commit`].
+///
+/// Counts are of rows as sqlite wrote them, so they include rows
+/// changed by triggers and foreign key actions. That takes in the
+/// search index's own tables, `entry_search_ids` and the FTS5 shadow
+/// tables such as `entry_search_data` and `entry_search_docsize`, the
+/// same tables [`Db::stats`] lists, but never `entry_search` itself.
+/// A write undone by rolling back to a savepoint isn't counted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitSummary {
+    /// Every table written to, by name.
+    pub tables: Vec<TableChanges>,
+    /// The ops inserted, updated or deleted, each once, in the order
+    /// first written.
+    pub op_hashes: Vec<OpHash>,
+}
+
+impl CommitSummary {
+    /// The changes to `table`, if it was written to.
+    pub fn table(&self, table: &str) -> Option<&TableChanges> {
+        self.tables.iter().find(|changes| changes.table == table)
+    }
+
+    /// Every row written, across all tables.
+    pub fn rows(&self) -> u64 {
+        self.tables.iter().map(TableChanges::rows).sum()
+    }
+
+    pub(crate) fn table_mut(&mut self, table: &str) -> &mut TableChanges {
+        match self
+            .tables
+            .iter()
+            .position(|changes| changes.table == table)
+        {
+            Some(i) => &mut self.tables[i],
+            None => {
+                self.tables.push(TableChanges::new(table));
+                self.tables.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+impl Db {
+    /// See [`DbWrite::on_commit`].
+    pub fn on_commit<F>(&self, hook: F)
+    where
+        F: Fn(&CommitSummary) + Send + Sync + 'static,
+    {
+        self.writer().on_commit(hook)
+    }
+}
+
+impl DbWrite {
+    /// Call `hook` with a summary of every transaction committed from
+    /// here on that wrote something, however it was written.
+    ///
+    /// Hooks run in commit order, once the transaction is visible to
+    /// readers, as the write connection is handed back, so a slow one
+    /// holds up the next write. Each is run until the database closes.
+    pub fn on_commit<F>(&self, hook: F)
+    where
+        F: Fn(&CommitSummary) + Send + Sync + 'static,
+    {
+        self.changes.on_commit(Arc::new(hook))
+    }
+}
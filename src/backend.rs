@@ -0,0 +1,186 @@
+//! The bare sqlite operations, behind one trait so the sqlx and
+//! rusqlite drivers can be swapped for each other and compared.
+
+use futures::future::BoxFuture;
+use futures::TryStreamExt;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqliteRow};
+use sqlx::{Row, ValueRef};
+use std::path::Path;
+
+/// A value bound to or read from sqlite, one per storage class.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    /// The bytes of a blob, or of text as UTF-8.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::Blob(bytes) => Some(bytes),
+            Self::Text(text) => Some(text.as_bytes()),
+            _ => None,
+        }
+    }
+
+    /// An integer, or a real with no fractional part.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Integer(i) => Some(*i),
+            Self::Real(r) if r.fract() == 0.0 => Some(*r as i64),
+            _ => None,
+        }
+    }
+}
+
+/// One statement of a [`DbBackend::transaction`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub sql: String,
+    pub params: Vec<Value>,
+}
+
+impl Statement {
+    pub fn new(sql: &str, params: Vec<Value>) -> Self {
+        Self {
+            sql: sql.to_string(),
+            params,
+        }
+    }
+}
+
+/// A sqlite driver.
+///
+/// Only the plain file is opened: no key, no migrations, so a
+/// benchmark sets up its own schema the same way on either driver.
+/// Parameters bind to `?` placeholders in order.
+pub trait DbBackend: Send + Sync + Sized {
+    /// Open (or create) the database file at `path`.
+    fn open(path: &Path) -> BoxFuture<'static, anyhow::Result<Self>>;
+
+    /// Run one statement, returning the rows it changed.
+    fn execute<'a>(
+        &'a self,
+        sql: &'a str,
+        params: &'a [Value],
+    ) -> BoxFuture<'a, anyhow::Result<u64>>;
+
+    /// Run one statement, returning every row it produced.
+    fn query<'a>(
+        &'a self,
+        sql: &'a str,
+        params: &'a [Value],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<Vec<Value>>>>;
+
+    /// Run `statements` in one transaction, committing only if they all
+    /// succeed, returning the rows they changed between them.
+    fn transaction(&self, statements: Vec<Statement>) -> BoxFuture<'_, anyhow::Result<u64>>;
+}
+
+/// The [`DbBackend`] the `rusqlite-backend` feature selects.
+#[cfg(not(feature = "rusqlite-backend"))]
+pub type DefaultBackend = SqlxBackend;
+
+/// The [`DbBackend`] the `rusqlite-backend` feature selects.
+#[cfg(feature = "rusqlite-backend")]
+pub type DefaultBackend = crate::RusqliteBackend;
+
+/// [`DbBackend`] over a sqlx pool, the driver [`crate::Db`] uses.
+#[derive(Clone)]
+pub struct SqlxBackend {
+    pool: SqlitePool,
+}
+
+pub(crate) fn bind_values<'q>(
+    mut query: sqlx::query::Query<'q, sqlx::Sqlite, sqlx::sqlite::SqliteArguments<'q>>,
+    params: &'q [Value],
+) -> sqlx::query::Query<'q, sqlx::Sqlite, sqlx::sqlite::SqliteArguments<'q>> {
+    for param in params {
+        query = match param {
+            Value::Null => query.bind(None::<i64>),
+            Value::Integer(v) => query.bind(v),
+            Value::Real(v) => query.bind(v),
+            Value::Text(v) => query.bind(v.as_str()),
+            Value::Blob(v) => query.bind(v.as_slice()),
+        };
+    }
+    query
+}
+
+/// Read every column of `row` by the storage class of its value.
+pub(crate) fn row_values(row: &SqliteRow) -> sqlx::Result<Vec<Value>> {
+    (0..row.len())
+        .map(|i| {
+            let raw = row.try_get_raw(i)?;
+            if raw.is_null() {
+                return Ok(Value::Null);
+            }
+            Ok(match raw.type_info().to_string().as_str() {
+                "INTEGER" | "BOOLEAN" => Value::Integer(row.try_get(i)?),
+                "REAL" => Value::Real(row.try_get(i)?),
+                "TEXT" | "DATETIME" => Value::Text(row.try_get(i)?),
+                _ => Value::Blob(row.try_get(i)?),
+            })
+        })
+        .collect()
+}
+
+impl DbBackend for SqlxBackend {
+    fn open(path: &Path) -> BoxFuture<'static, anyhow::Result<Self>> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        Box::pin(async move {
+            Ok(Self {
+                pool: SqlitePool::connect_with(options).await?,
+            })
+        })
+    }
+
+    fn execute<'a>(
+        &'a self,
+        sql: &'a str,
+        params: &'a [Value],
+    ) -> BoxFuture<'a, anyhow::Result<u64>> {
+        Box::pin(async move {
+            let res = bind_values(sqlx::query(sql), params)
+                .execute(&self.pool)
+                .await?;
+            Ok(res.rows_affected())
+        })
+    }
+
+    fn query<'a>(
+        &'a self,
+        sql: &'a str,
+        params: &'a [Value],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<Vec<Value>>>> {
+        Box::pin(async move {
+            let rows: Vec<SqliteRow> = bind_values(sqlx::query(sql), params)
+                .fetch(&self.pool)
+                .try_collect()
+                .await?;
+            Ok(rows.iter().map(row_values).collect::<sqlx::Result<_>>()?)
+        })
+    }
+
+    fn transaction(&self, statements: Vec<Statement>) -> BoxFuture<'_, anyhow::Result<u64>> {
+        Box::pin(async move {
+            // rolled back on drop if a statement fails
+            let mut tx = self.pool.begin().await?;
+            let mut changed = 0;
+            for statement in &statements {
+                changed += bind_values(sqlx::query(&statement.sql), &statement.params)
+                    .execute(&mut tx)
+                    .await?
+                    .rows_affected();
+            }
+            tx.commit().await?;
+            Ok(changed)
+        })
+    }
+}
@@ -0,0 +1,160 @@
+use crate::db::{cipher_pragmas, connect_keyed, exec_secret, init_connection, is_sqlcipher};
+use crate::export::quote_path;
+use crate::key_provider::DbKey;
+use crate::migrations::{applied, validate_schema, MIGRATOR};
+use crate::{Db, DbConfig, SecretKey};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{Connection, Executor};
+use std::path::{Path, PathBuf};
+
+/// The file behind the main database, `None` if it's in memory.
+pub(crate) async fn main_file(db: &Db) -> anyhow::Result<Option<PathBuf>> {
+    let file: String =
+        sqlx::query_scalar("SELECT file FROM pragma_database_list WHERE name = 'main';")
+            .fetch_one(&db.write.pool)
+            .await?;
+    Ok(if file.is_empty() {
+        None
+    } else {
+        Some(file.into())
+    })
+}
+
+/// Open `path` the way the database itself would be, and check it's
+/// something we can switch to: keyed with `key`, intact, and at a
+/// schema version this build knows.
+async fn check_backup(path: &Path, config: &DbConfig, key: Option<&DbKey>) -> anyhow::Result<()> {
+    let mut con = connect_keyed(SqliteConnectOptions::new(), Some(path), config, key).await?;
+    let res = async {
+        init_connection(&mut con, config, key).await?;
+        let check: String = sqlx::query_scalar("PRAGMA quick_check;")
+            .fetch_one(&mut con)
+            .await?;
+        if check != "ok" {
+            anyhow::bail!("backup failed its integrity check: {}", check);
+        }
+        let current = applied(&mut con).await?.map_or(0, |(_, version)| version);
+        let latest = MIGRATOR.iter().last().map_or(0, |m| m.version);
+        if current > latest {
+            anyhow::bail!(
+                "backup is at schema version {} but this build only knows up to {}",
+                current,
+                latest
+            );
+        }
+        // an older backup is migrated on open, so only check a current one
+        if current == latest {
+            validate_schema(&mut con).await?;
+        }
+        Ok(())
+    }
+    .await;
+    // closing the last connection folds the WAL back into the file
+    con.close().await?;
+    res
+}
+
+pub(crate) async fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+impl Db {
+    /// Write a consistent copy of the live database to `path`,
+    /// encrypted under `key`, or under the database's own key if `None`.
+    /// `path` must not exist yet.
+    ///
+    /// Writes wait until the copy is done, reads carry on.
+    /// Without SQLCipher linked, or for a plaintext database without a
+    /// `key`, this is a plain `VACUUM INTO` copy.
+    pub async fn backup_to<P: AsRef<Path>>(
+        &self,
+        path: P,
+        key: Option<[u8; 32]>,
+    ) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if tokio::fs::metadata(path).await.is_ok() {
+            anyhow::bail!("{} already exists", path.display());
+        }
+        let key = match key {
+            Some(key) => Some(DbKey::Raw(SecretKey::new(key))),
+            None => self.key.read().unwrap().clone(),
+        };
+        let _permit = self.write.permits.acquire().await?;
+        let mut con = self.write.pool.acquire().await?;
+
+        let key = match key {
+            Some(key) if is_sqlcipher(&mut con).await? => key,
+            _ => {
+                sqlx::query("VACUUM INTO ?1;")
+                    .bind(&*path.to_string_lossy())
+                    .execute(&mut con)
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        let cmd = key.statement(
+            &format!("ATTACH DATABASE {} AS backup KEY ", quote_path(path)),
+            ";",
+        );
+        exec_secret(&mut con, "ATTACH DATABASE", &cmd)?;
+        let res = async {
+            // the copy has to be readable with the same settings
+            for pragma in cipher_pragmas(&self.config) {
+                con.execute(&*pragma.replacen("PRAGMA ", "PRAGMA backup.", 1))
+                    .await?;
+            }
+            con.execute("SELECT sqlcipher_export('backup');").await
+        }
+        .await;
+        con.execute("DETACH DATABASE backup;").await?;
+        if let Err(err) = res {
+            remove_if_exists(path).await?;
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    /// Replace the database with the backup at `backup`, taken by
+    /// [`Db::backup_to`] under the database's own key, and reopen it.
+    ///
+    /// The backup is copied next to the database file and checked
+    /// first: it must open with the key, pass `PRAGMA quick_check`, and
+    /// not be at a newer schema version than this build knows. Only then
+    /// is this handle closed and the copy renamed over the file. An older
+    /// backup is migrated on reopening.
+    ///
+    /// If the check fails the database file is left as it was, but this
+    /// handle is gone all the same, reopen it to carry on.
+    pub async fn restore_from<P: AsRef<Path>>(self, backup: P) -> anyhow::Result<Self> {
+        let file = match main_file(&self).await? {
+            Some(file) => file,
+            None => anyhow::bail!("an in-memory database has no file to restore over"),
+        };
+        let staged = with_suffix(&file, "-restore");
+        tokio::fs::copy(backup.as_ref(), &staged).await?;
+        let key = self.key.read().unwrap().clone();
+        if let Err(err) = check_backup(&staged, &self.config, key.as_ref()).await {
+            remove_if_exists(&staged).await?;
+            return Err(err);
+        }
+
+        let (options, kind, config) =
+            (self.options.clone(), self.kind.clone(), self.config.clone());
+        self.close().await?;
+        for suffix in ["-wal", "-shm"].iter() {
+            remove_if_exists(&with_suffix(&file, suffix)).await?;
+        }
+        tokio::fs::rename(&staged, &file).await?;
+        Self::open_options(options, Some(&file), kind, config).await
+    }
+}
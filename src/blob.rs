@@ -0,0 +1,255 @@
+//! Entry content in chunks, for entries too big to hold in memory whole.
+//!
+//! sqlx only reads and binds blobs whole, so this goes through sqlite's
+//! incremental blob I/O on the raw handle instead: content is written
+//! into a zero-filled blob of the final size, and read back a slice at
+//! a time, without sqlite materializing the whole value either.
+
+use crate::{Db, DbRead, DbWrite, DhtLocation, EntryHash, EntryType, Timestamp, WriteOutcome};
+use futures::channel::mpsc;
+use futures::{SinkExt, Stream, StreamExt};
+use libsqlite3_sys::{
+    sqlite3, sqlite3_blob, sqlite3_blob_bytes, sqlite3_blob_close, sqlite3_blob_open,
+    sqlite3_blob_read, sqlite3_blob_write, sqlite3_errmsg, SQLITE_OK,
+};
+use sqlx::{Connection, SqliteConnection};
+use std::ffi::CStr;
+use std::marker::PhantomData;
+use std::os::raw::c_int;
+
+/// Bytes per chunk read out of a blob.
+pub const CONTENT_CHUNK: usize = 64 * 1024;
+
+/// Chunks read ahead of the consumer.
+const STREAM_BUFFER: usize = 4;
+
+/// An open handle on one entry's content, closed on drop.
+/// Borrows the connection it was opened on so it can't outlive it.
+struct Blob<'c> {
+    handle: *mut sqlite3,
+    blob: *mut sqlite3_blob,
+    _con: PhantomData<&'c mut SqliteConnection>,
+}
+
+// safety: only used through the connection it borrows, which is only
+// ever used from one thread at a time
+unsafe impl Send for Blob<'_> {}
+
+fn check(handle: *mut sqlite3, rc: c_int, what: &str) -> anyhow::Result<()> {
+    if rc != SQLITE_OK {
+        // safety: sqlite keeps the message until the next call on `handle`
+        let msg = unsafe { CStr::from_ptr(sqlite3_errmsg(handle)) }
+            .to_string_lossy()
+            .into_owned();
+        anyhow::bail!("{} failed ({}): {}", what, rc, msg);
+    }
+    Ok(())
+}
+
+impl<'c> Blob<'c> {
+    /// Open the content of the entry at `rowid`.
+    fn open(con: &'c mut SqliteConnection, rowid: i64, write: bool) -> anyhow::Result<Self> {
+        let handle = con.as_raw_handle();
+        let mut blob = std::ptr::null_mut();
+        // safety: we hold the connection and nothing is stepping on it
+        let rc = unsafe {
+            sqlite3_blob_open(
+                handle,
+                b"main\0".as_ptr() as *const _,
+                b"entries\0".as_ptr() as *const _,
+                b"content\0".as_ptr() as *const _,
+                rowid,
+                write as c_int,
+                &mut blob,
+            )
+        };
+        // a failed open may still hand back a handle to close
+        let blob = Self {
+            handle,
+            blob,
+            _con: PhantomData,
+        };
+        check(handle, rc, "opening entry content")?;
+        Ok(blob)
+    }
+
+    fn len(&self) -> usize {
+        // safety: the blob is open
+        unsafe { sqlite3_blob_bytes(self.blob) as usize }
+    }
+
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> anyhow::Result<()> {
+        // safety: the blob is open and `buf` is as long as we say
+        let rc = unsafe {
+            sqlite3_blob_read(
+                self.blob,
+                buf.as_mut_ptr() as *mut _,
+                buf.len() as c_int,
+                offset as c_int,
+            )
+        };
+        check(self.handle, rc, "reading entry content")
+    }
+
+    fn write(&mut self, offset: usize, buf: &[u8]) -> anyhow::Result<()> {
+        // safety: the blob is open and `buf` is as long as we say
+        let rc = unsafe {
+            sqlite3_blob_write(
+                self.blob,
+                buf.as_ptr() as *const _,
+                buf.len() as c_int,
+                offset as c_int,
+            )
+        };
+        check(self.handle, rc, "writing entry content")
+    }
+}
+
+impl Drop for Blob<'_> {
+    fn drop(&mut self) {
+        // safety: closing NULL is a no-op, and nothing uses it after
+        unsafe { sqlite3_blob_close(self.blob) };
+    }
+}
+
+impl Db {
+    /// The content of the entry with `hash`, in chunks of up to
+    /// [`CONTENT_CHUNK`] bytes, see [`DbRead::read_content_stream`].
+    pub fn read_content_stream(
+        &self,
+        hash: &EntryHash,
+    ) -> impl Stream<Item = anyhow::Result<Vec<u8>>> {
+        self.reader().read_content_stream(hash)
+    }
+
+    /// Insert an entry whose content arrives in chunks, see
+    /// [`DbWrite::insert_content_stream`].
+    pub async fn insert_content_stream<S>(
+        &self,
+        hash: &EntryHash,
+        entry_type: EntryType,
+        created_at: Timestamp,
+        size: u32,
+        content: S,
+    ) -> anyhow::Result<WriteOutcome>
+    where
+        S: Stream<Item = anyhow::Result<Vec<u8>>> + Unpin,
+    {
+        self.write
+            .insert_content_stream(hash, entry_type, created_at, size, content)
+            .await
+    }
+}
+
+impl DbRead {
+    /// The content of the entry with `hash`, in chunks of up to
+    /// [`CONTENT_CHUNK`] bytes, so only a few chunks are ever in memory.
+    /// Fails straight away if the entry isn't held.
+    ///
+    /// The chunks come from one read transaction, which holds a reader
+    /// connection until the stream ends or is dropped.
+    pub fn read_content_stream(
+        &self,
+        hash: &EntryHash,
+    ) -> impl Stream<Item = anyhow::Result<Vec<u8>>> {
+        let hash = hash.clone();
+        let (mut send, recv) = mpsc::channel(STREAM_BUFFER);
+        let pool = self.pool.clone();
+        let permits = self.permits.clone();
+        // blob handles borrow their connection, so read on a task
+        // that owns it, as for entry streams
+        tokio::task::spawn(async move {
+            let res: anyhow::Result<()> = async {
+                let _permit = permits.acquire().await?;
+                let mut tx = pool.begin().await?;
+                let rowid: Option<i64> =
+                    sqlx::query_scalar("SELECT rowid FROM entries WHERE hash = ?1;")
+                        .bind(&hash)
+                        .fetch_optional(&mut tx)
+                        .await?;
+                let rowid = match rowid {
+                    Some(rowid) => rowid,
+                    None => anyhow::bail!("no such entry"),
+                };
+                let mut blob = Blob::open(&mut tx, rowid, false)?;
+                let len = blob.len();
+                let mut offset = 0;
+                while offset < len {
+                    let mut chunk = vec![0; CONTENT_CHUNK.min(len - offset)];
+                    blob.read(offset, &mut chunk)?;
+                    offset += chunk.len();
+                    if send.send(Ok(chunk)).await.is_err() {
+                        // the consumer went away
+                        break;
+                    }
+                }
+                Ok(())
+            }
+            .await;
+            if let Err(err) = res {
+                let _ = send.send(Err(err)).await;
+            }
+        });
+        recv
+    }
+}
+
+impl DbWrite {
+    /// Insert an entry whose `size` bytes of content arrive in chunks
+    /// from `content`, so only one chunk is ever in memory.
+    ///
+    /// Everything goes in one transaction: if the stream fails or
+    /// doesn't add up to `size` exactly, nothing is inserted.
+    /// The hash isn't checked against the content.
+    ///
+    /// The write permit is held while the stream is read, so a slow
+    /// stream holds up every other write.
+    pub async fn insert_content_stream<S>(
+        &self,
+        hash: &EntryHash,
+        entry_type: EntryType,
+        created_at: Timestamp,
+        size: u32,
+        mut content: S,
+    ) -> anyhow::Result<WriteOutcome>
+    where
+        S: Stream<Item = anyhow::Result<Vec<u8>>> + Unpin,
+    {
+        let _permit = self.permits.acquire().await?;
+        let mut con = self.pool.acquire().await?;
+        let mut tx = con.begin().await?;
+        let res = sqlx::query(
+            "INSERT INTO entries
+            (hash, dht_loc, created_at, entry_type, size_bytes, content)
+            VALUES (?1, ?2, ?3, ?4, ?5, zeroblob(?5))",
+        )
+        .bind(hash)
+        .bind(DhtLocation(hash.get_loc()))
+        .bind(created_at)
+        .bind(entry_type)
+        .bind(size)
+        .execute(&mut tx)
+        .await?;
+
+        let mut blob = Blob::open(&mut tx, res.last_insert_rowid(), true)?;
+        let mut offset = 0;
+        while let Some(chunk) = content.next().await {
+            let chunk = chunk?;
+            if offset + chunk.len() > size as usize {
+                anyhow::bail!("entry content is longer than the {} bytes given", size);
+            }
+            blob.write(offset, &chunk)?;
+            offset += chunk.len();
+        }
+        if offset != size as usize {
+            anyhow::bail!(
+                "entry content ended at {} of the {} bytes given",
+                offset,
+                size
+            );
+        }
+        drop(blob);
+        tx.commit().await?;
+        Ok(WriteOutcome::inserted(res.rows_affected(), 1))
+    }
+}
@@ -0,0 +1,328 @@
+use crate::retry::with_retry;
+use crate::{AgentPubKey, Db, DbRead, DbWrite, HeaderHash, WriteOutcome};
+use futures::TryStreamExt;
+use rand::Rng;
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Connection, Decode, Encode, Execute, Sqlite, Type};
+use std::borrow::Cow;
+use std::convert::TryInto;
+
+/// The secret a caller presents to use a grant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CapSecret(pub [u8; 64]);
+
+impl CapSecret {
+    /// A random secret.
+    pub fn rand() -> Self {
+        let mut secret = [0; 64];
+        rand::thread_rng().fill(&mut secret[..]);
+        Self(secret)
+    }
+}
+
+impl Type<Sqlite> for CapSecret {
+    fn type_info() -> SqliteTypeInfo {
+        <Vec<u8> as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <Vec<u8> as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for CapSecret {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        args.push(SqliteArgumentValue::Blob(Cow::Owned(self.0.to_vec())));
+        IsNull::No
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for CapSecret {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let bytes = <&[u8] as Decode<Sqlite>>::decode(value)?;
+        Ok(Self(bytes.try_into()?))
+    }
+}
+
+/// A zome function a grant lets callers use.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GrantedFunction {
+    pub zome: String,
+    pub function: String,
+}
+
+impl GrantedFunction {
+    pub fn new(zome: &str, function: &str) -> Self {
+        Self {
+            zome: zome.to_string(),
+            function: function.to_string(),
+        }
+    }
+}
+
+/// Who may use a grant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CapAccess {
+    /// Anyone.
+    Unrestricted,
+    /// Anyone with the secret.
+    Transferable { secret: CapSecret },
+    /// Only these agents, and only with the secret.
+    Assigned {
+        secret: CapSecret,
+        assignees: Vec<AgentPubKey>,
+    },
+}
+
+impl CapAccess {
+    /// As stored in `cap_grants.access`.
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Self::Unrestricted => "Unrestricted",
+            Self::Transferable { .. } => "Transferable",
+            Self::Assigned { .. } => "Assigned",
+        }
+    }
+
+    fn secret(&self) -> Option<&CapSecret> {
+        match self {
+            Self::Unrestricted => None,
+            Self::Transferable { secret } | Self::Assigned { secret, .. } => Some(secret),
+        }
+    }
+}
+
+/// A capability grant made from our source chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapGrant {
+    /// The header that created the grant.
+    pub header_hash: HeaderHash,
+    pub tag: String,
+    pub access: CapAccess,
+    pub functions: Vec<GrantedFunction>,
+}
+
+/// A secret someone else granted us, to call them with.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct CapClaim {
+    pub secret: CapSecret,
+    pub tag: String,
+    /// The agent who made the grant.
+    pub grantor: AgentPubKey,
+}
+
+impl Db {
+    /// Store a grant, failing if one from the same header is already held.
+    pub async fn insert_cap_grant(&self, grant: &CapGrant) -> anyhow::Result<WriteOutcome> {
+        self.write.insert_cap_grant(grant).await
+    }
+
+    /// Revoke the grant created by `header_hash`,
+    /// returning whether there was one.
+    pub async fn delete_cap_grant(&self, header_hash: &HeaderHash) -> anyhow::Result<bool> {
+        self.write.delete_cap_grant(header_hash).await
+    }
+
+    /// The header of a grant that lets `agent` call `function`,
+    /// presenting `secret` if they have one.
+    pub async fn valid_grant_for(
+        &self,
+        function: &GrantedFunction,
+        agent: &AgentPubKey,
+        secret: Option<&CapSecret>,
+    ) -> anyhow::Result<Option<HeaderHash>> {
+        self.reader().valid_grant_for(function, agent, secret).await
+    }
+
+    /// Store a claim, ignoring one with the same secret.
+    pub async fn insert_cap_claim(&self, claim: &CapClaim) -> anyhow::Result<WriteOutcome> {
+        self.write.insert_cap_claim(claim).await
+    }
+
+    /// The claims we hold on `grantor`, ordered by tag.
+    pub async fn cap_claims(&self, grantor: &AgentPubKey) -> anyhow::Result<Vec<CapClaim>> {
+        self.reader().cap_claims(grantor).await
+    }
+}
+
+impl DbWrite {
+    /// Store a grant with its functions and assignees in one transaction,
+    /// failing if one from the same header is already held.
+    pub async fn insert_cap_grant(&self, grant: &CapGrant) -> anyhow::Result<WriteOutcome> {
+        let inserted = with_retry(
+            self.trace.op("insert_cap_grant"),
+            &self.retry,
+            &self.permits,
+            || async {
+                let grant = grant.clone();
+                let mut con = self.pool.acquire().await?;
+                con.transaction(move |tx| {
+                    Box::pin(async move {
+                        let access = grant.access.as_sql();
+                        let secret = grant.access.secret();
+                        let res = sqlx::query!(
+                            "INSERT INTO cap_grants (header_hash, tag, access, secret)
+                        VALUES (?1, ?2, ?3, ?4)",
+                            grant.header_hash,
+                            grant.tag,
+                            access,
+                            secret,
+                        )
+                        .execute(&mut *tx)
+                        .await?;
+                        for function in &grant.functions {
+                            // listing a function twice grants nothing more
+                            sqlx::query!(
+                                "INSERT INTO cap_grant_functions (zome, function, header_hash)
+                            VALUES (?1, ?2, ?3)
+                            ON CONFLICT DO NOTHING",
+                                function.zome,
+                                function.function,
+                                grant.header_hash,
+                            )
+                            .execute(&mut *tx)
+                            .await?;
+                        }
+                        if let CapAccess::Assigned { assignees, .. } = &grant.access {
+                            for agent in assignees {
+                                sqlx::query!(
+                                    "INSERT INTO cap_grant_assignees (header_hash, agent)
+                                VALUES (?1, ?2)
+                                ON CONFLICT DO NOTHING",
+                                    grant.header_hash,
+                                    agent,
+                                )
+                                .execute(&mut *tx)
+                                .await?;
+                            }
+                        }
+                        Ok(res.rows_affected())
+                    })
+                })
+                .await
+            },
+        )
+        .await?;
+        Ok(WriteOutcome::inserted(inserted, 1))
+    }
+
+    /// Revoke the grant created by `header_hash`, along with its
+    /// functions and assignees, returning whether there was one.
+    pub async fn delete_cap_grant(&self, header_hash: &HeaderHash) -> anyhow::Result<bool> {
+        let query = || sqlx::query!("DELETE FROM cap_grants WHERE header_hash = ?1", header_hash);
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let res = with_retry(
+            self.trace
+                .op("delete_cap_grant")
+                .statement(sql, &[&header_hash]),
+            &self.retry,
+            &self.permits,
+            || async { query().execute(&self.pool).await },
+        )
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Store a claim, ignoring one with the same secret.
+    pub async fn insert_cap_claim(&self, claim: &CapClaim) -> anyhow::Result<WriteOutcome> {
+        let res = with_retry(
+            self.trace.op("insert_cap_claim"),
+            &self.retry,
+            &self.permits,
+            || async {
+                sqlx::query!(
+                    "INSERT INTO cap_claims (secret, tag, grantor)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (secret) DO NOTHING",
+                    claim.secret,
+                    claim.tag,
+                    claim.grantor,
+                )
+                .execute(&self.pool)
+                .await
+            },
+        )
+        .await?;
+        Ok(WriteOutcome::inserted(res.rows_affected(), 1))
+    }
+}
+
+impl DbRead {
+    /// The header of a grant that lets `agent` call `function`,
+    /// presenting `secret` if they have one.
+    ///
+    /// Unrestricted grants need no secret, Transferable ones need theirs,
+    /// and Assigned ones need theirs and `agent` among the assignees.
+    /// Where several match, which one is returned is unspecified.
+    pub async fn valid_grant_for(
+        &self,
+        function: &GrantedFunction,
+        agent: &AgentPubKey,
+        secret: Option<&CapSecret>,
+    ) -> anyhow::Result<Option<HeaderHash>> {
+        // the function's grants come off cap_grant_functions' key,
+        // a NULL secret matches no stored one
+        let query = || {
+            sqlx::query_scalar!(
+                r#"SELECT cap_grants.header_hash AS "header_hash!: HeaderHash"
+            FROM cap_grant_functions
+            JOIN cap_grants ON cap_grants.header_hash = cap_grant_functions.header_hash
+            WHERE cap_grant_functions.zome = ?1
+            AND cap_grant_functions.function = ?2
+            AND (
+                cap_grants.access = 'Unrestricted'
+                OR (cap_grants.access = 'Transferable' AND cap_grants.secret = ?4)
+                OR (cap_grants.access = 'Assigned' AND cap_grants.secret = ?4
+                    AND EXISTS (SELECT 1 FROM cap_grant_assignees
+                        WHERE cap_grant_assignees.header_hash = cap_grants.header_hash
+                        AND cap_grant_assignees.agent = ?3))
+            )
+            LIMIT 1;"#,
+                function.zome,
+                function.function,
+                agent,
+                secret
+            )
+        };
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let found = with_retry(
+            self.trace
+                .op("valid_grant_for")
+                .statement(sql, &[&function.zome, &function.function, &agent, &secret]),
+            &self.retry,
+            &self.permits,
+            || async { query().fetch_optional(&self.pool).await },
+        )
+        .await?;
+        Ok(found)
+    }
+
+    /// The claims we hold on `grantor`, ordered by tag.
+    pub async fn cap_claims(&self, grantor: &AgentPubKey) -> anyhow::Result<Vec<CapClaim>> {
+        let query = || {
+            sqlx::query_as!(
+                CapClaim,
+                r#"SELECT
+                    secret AS "secret!: CapSecret",
+                    tag,
+                    grantor AS "grantor: AgentPubKey"
+                FROM cap_claims WHERE grantor = ?1 ORDER BY tag;"#,
+                grantor
+            )
+        };
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let out = with_retry(
+            self.trace.op("cap_claims").statement(sql, &[&grantor]),
+            &self.retry,
+            &self.permits,
+            || async { query().fetch(&self.pool).try_collect::<Vec<_>>().await },
+        )
+        .await?;
+        Ok(out)
+    }
+}
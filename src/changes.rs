@@ -0,0 +1,411 @@
+//! Telling subscribers about new entries, and commit hooks about
+//! everything written, as it's committed, so gossip, signals, audit
+//! logs and caches can react without rescanning.
+//!
+//! Hooks on the writer's connection note every row it writes, and temp
+//! triggers hand inserted entries and written ops to
+//! `spike_sqlx_written`. All of it is buffered until sqlite reports the
+//! transaction committed or rolled back. What committed is sent once
+//! the write is done with the connection, after `COMMIT` has returned,
+//! so a subscriber reading on the event sees the entry.
+
+use crate::functions::read_value;
+use crate::migrations::{applied, MIGRATOR};
+use crate::{
+    CommitSummary, Db, DbWrite, EntryFilter, EntryHash, EntryType, OpHash, Timestamp, Value,
+};
+use libsqlite3_sys::{
+    sqlite3, sqlite3_commit_hook, sqlite3_context, sqlite3_create_function_v2,
+    sqlite3_rollback_hook, sqlite3_update_hook, sqlite3_user_data, sqlite3_value, SQLITE_DELETE,
+    SQLITE_INSERT, SQLITE_OK, SQLITE_UPDATE, SQLITE_UTF8,
+};
+use sqlx::{Executor, SqliteConnection};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// An entry committed since subscribing, from [`Db::subscribe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    pub hash: EntryHash,
+    pub dht_loc: u32,
+    pub entry_type: EntryType,
+    pub created_at: Timestamp,
+}
+
+impl ChangeEvent {
+    /// From the trigger's `NEW.hash, NEW.dht_loc, NEW.entry_type,
+    /// NEW.created_at`.
+    fn from_values(values: &[Value]) -> Option<Self> {
+        match values {
+            [Value::Blob(hash), Value::Integer(dht_loc), Value::Text(entry_type), Value::Integer(created_at)] => {
+                Some(Self {
+                    hash: EntryHash::from_raw_39(hash).ok()?,
+                    dht_loc: *dht_loc as u32,
+                    entry_type: entry_type.parse().ok()?,
+                    created_at: Timestamp(*created_at),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl EntryFilter {
+    /// Whether `event` is within the location, time and type
+    /// conditions.
+    fn matches(&self, event: &ChangeEvent) -> bool {
+        let in_arc = |(start, end): (u32, u32)| match start <= end {
+            true => start <= event.dht_loc && event.dht_loc <= end,
+            false => event.dht_loc >= start || event.dht_loc <= end,
+        };
+        self.loc_range.is_none_or(in_arc)
+            && self
+                .time_range
+                .is_none_or(|(start, end)| start <= event.created_at && event.created_at <= end)
+            && self
+                .entry_type
+                .is_none_or(|entry_type| entry_type == event.entry_type)
+    }
+}
+
+/// Called with what each transaction wrote, see [`Db::on_commit`].
+pub(crate) type CommitHook = Arc<dyn Fn(&CommitSummary) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy)]
+enum RowChange {
+    Inserted,
+    Updated,
+    Deleted,
+}
+
+/// Something the open transaction wrote.
+#[derive(Debug)]
+enum Written {
+    /// A row of the table at that index of [`Inner::tables`].
+    Row(usize, RowChange),
+    Entry(ChangeEvent),
+    Op(OpHash),
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Written by the open transaction.
+    pending: Vec<Written>,
+    /// How much of `pending` each open savepoint started after.
+    savepoints: Vec<(String, usize)>,
+    /// Every table written to so far, each once.
+    tables: Vec<String>,
+    /// Committed, not yet sent.
+    committed: Vec<(Vec<ChangeEvent>, CommitSummary)>,
+    subscribers: Vec<(EntryFilter, broadcast::Sender<ChangeEvent>)>,
+    hooks: Vec<CommitHook>,
+}
+
+/// What the writer has written, and who wants to hear of it.
+/// Clones share the same subscribers and hooks.
+#[derive(Clone)]
+pub(crate) struct Changes {
+    capacity: usize,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl std::fmt::Debug for Changes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Changes")
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+impl Changes {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Arc::default(),
+        }
+    }
+
+    fn subscribe(&self, filter: EntryFilter) -> broadcast::Receiver<ChangeEvent> {
+        let (tx, rx) = broadcast::channel(self.capacity);
+        self.inner.lock().unwrap().subscribers.push((filter, tx));
+        rx
+    }
+
+    pub(crate) fn on_commit(&self, hook: CommitHook) {
+        self.inner.lock().unwrap().hooks.push(hook);
+    }
+
+    /// Send what's committed to whoever it matches, dropping the
+    /// subscribers that have gone, then run the commit hooks.
+    pub(crate) fn publish(&self) {
+        let (committed, hooks) = {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.committed.is_empty() {
+                return;
+            }
+            let committed = std::mem::take(&mut inner.committed);
+            inner.subscribers.retain(|(_, tx)| tx.receiver_count() > 0);
+            for event in committed.iter().flat_map(|(events, _)| events) {
+                for (filter, tx) in &inner.subscribers {
+                    if filter.matches(event) {
+                        // a receiver dropped since is cleaned up next time
+                        let _ = tx.send(event.clone());
+                    }
+                }
+            }
+            (committed, inner.hooks.clone())
+        };
+        // unlocked, so a hook may subscribe or add another
+        for (_, summary) in &committed {
+            for hook in &hooks {
+                hook(summary);
+            }
+        }
+    }
+
+    /// See [`crate::Writer::savepoint`].
+    pub(crate) fn savepoint(&self, name: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let len = inner.pending.len();
+        inner.savepoints.push((name.to_string(), len));
+    }
+
+    /// See [`crate::Writer::release`], `rolled_back` if what was
+    /// written since is undone too.
+    pub(crate) fn close_savepoint(&self, name: &str, rolled_back: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        // the innermost of that name, as sqlite picks
+        if let Some(i) = inner.savepoints.iter().rposition(|(open, _)| open == name) {
+            let (_, len) = inner.savepoints[i];
+            inner.savepoints.truncate(i);
+            if rolled_back {
+                inner.pending.truncate(len);
+            }
+        }
+    }
+
+    fn row_written(&self, table: &str, change: RowChange) {
+        let mut inner = self.inner.lock().unwrap();
+        let i = match inner.tables.iter().position(|known| known == table) {
+            Some(i) => i,
+            None => {
+                inner.tables.push(table.to_string());
+                inner.tables.len() - 1
+            }
+        };
+        inner.pending.push(Written::Row(i, change));
+    }
+
+    fn committed(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.savepoints.clear();
+        let pending = std::mem::take(&mut inner.pending);
+        // a read, or a write that changed nothing
+        if pending.is_empty() {
+            return;
+        }
+        let mut events = Vec::new();
+        let mut summary = CommitSummary::default();
+        for written in pending {
+            match written {
+                Written::Row(i, change) => {
+                    let counts = summary.table_mut(&inner.tables[i]);
+                    match change {
+                        RowChange::Inserted => counts.inserted += 1,
+                        RowChange::Updated => counts.updated += 1,
+                        RowChange::Deleted => counts.deleted += 1,
+                    }
+                }
+                Written::Entry(event) => events.push(event),
+                Written::Op(hash) => {
+                    if !summary.op_hashes.contains(&hash) {
+                        summary.op_hashes.push(hash);
+                    }
+                }
+            }
+        }
+        summary.tables.sort_by(|a, b| a.table.cmp(&b.table));
+        inner.committed.push((events, summary));
+    }
+
+    fn rolled_back(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.pending.clear();
+        inner.savepoints.clear();
+    }
+}
+
+/// `spike_sqlx_written('entry', hash, dht_loc, entry_type, created_at)`
+/// or `spike_sqlx_written('op', op_hash)`: buffers what the triggers
+/// saw in the [`Changes`] registered as the function's user data.
+unsafe extern "C" fn written(
+    ctx: *mut sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    let changes = &*(sqlite3_user_data(ctx) as *const Changes);
+    let values: Vec<Value> = (0..argc as usize)
+        .map(|i| read_value(*argv.add(i)))
+        .collect();
+    // nothing to report a panic to, the write goes ahead regardless
+    let _ = catch_unwind(AssertUnwindSafe(|| {
+        let written = match values.split_first() {
+            Some((Value::Text(kind), values)) if kind == "entry" => {
+                ChangeEvent::from_values(values).map(Written::Entry)
+            }
+            Some((Value::Text(kind), [Value::Blob(hash)])) if kind == "op" => {
+                OpHash::from_raw_39(hash).ok().map(Written::Op)
+            }
+            _ => None,
+        };
+        if let Some(written) = written {
+            changes.inner.lock().unwrap().pending.push(written);
+        }
+    }));
+}
+
+/// Counts every row written outside the temp schema, naming those of
+/// attached databases `alias.table`.
+unsafe extern "C" fn on_update(
+    changes: *mut c_void,
+    op: c_int,
+    db: *const c_char,
+    table: *const c_char,
+    _rowid: i64,
+) {
+    let changes = &*(changes as *const Changes);
+    let change = match op {
+        SQLITE_INSERT => RowChange::Inserted,
+        SQLITE_UPDATE => RowChange::Updated,
+        SQLITE_DELETE => RowChange::Deleted,
+        _ => return,
+    };
+    let db = CStr::from_ptr(db).to_string_lossy();
+    let table = CStr::from_ptr(table).to_string_lossy();
+    let _ = catch_unwind(AssertUnwindSafe(|| match &*db {
+        "temp" => {}
+        "main" => changes.row_written(&table, change),
+        alias => changes.row_written(&format!("{}.{}", alias, table), change),
+    }));
+}
+
+/// Returning 0 lets the commit go ahead.
+unsafe extern "C" fn on_commit(changes: *mut c_void) -> c_int {
+    let changes = &*(changes as *const Changes);
+    let _ = catch_unwind(AssertUnwindSafe(|| changes.committed()));
+    0
+}
+
+unsafe extern "C" fn on_rollback(changes: *mut c_void) {
+    let changes = &*(changes as *const Changes);
+    let _ = catch_unwind(AssertUnwindSafe(|| changes.rolled_back()));
+}
+
+/// Frees the [`Changes`] once the connection closes, after its last
+/// commit or rollback hook.
+unsafe extern "C" fn drop_changes(changes: *mut c_void) {
+    drop(Box::from_raw(changes as *mut Changes));
+}
+
+/// Buffer what's written on the connection `handle` in `changes`, on
+/// either driver. The triggers in [`WATCH_TRIGGERS`] are added
+/// separately, once the schema is current.
+///
+/// # Safety
+///
+/// `handle` must be an open connection, and this the only thing
+/// setting its commit, rollback and update hooks.
+pub(crate) unsafe fn track_changes(handle: *mut sqlite3, changes: &Changes) -> Result<(), String> {
+    // lives until sqlite drops the function as it closes the
+    // connection, by when the hooks are done with it too. Registering
+    // the name again would drop it early, so the spike_sqlx_ prefix is
+    // refused for application functions.
+    let changes = Box::into_raw(Box::new(changes.clone())) as *mut c_void;
+    let rc = sqlite3_create_function_v2(
+        handle,
+        b"spike_sqlx_written\0".as_ptr() as *const _,
+        -1,
+        SQLITE_UTF8,
+        changes,
+        Some(written),
+        None,
+        None,
+        Some(drop_changes),
+    );
+    if rc != SQLITE_OK {
+        return Err(format!("registering spike_sqlx_written failed ({})", rc));
+    }
+    sqlite3_update_hook(handle, Some(on_update), changes);
+    sqlite3_commit_hook(handle, Some(on_commit), changes);
+    sqlite3_rollback_hook(handle, Some(on_rollback), changes);
+    Ok(())
+}
+
+/// Hand every entry inserted and op written to `spike_sqlx_written`.
+/// Temp, so they're only on the writer's connection and never in the
+/// file.
+pub(crate) const WATCH_TRIGGERS: &str = "
+    CREATE TEMP TRIGGER IF NOT EXISTS entry_changes AFTER INSERT ON main.entries
+    BEGIN
+        SELECT spike_sqlx_written('entry', NEW.hash, NEW.dht_loc, NEW.entry_type, NEW.created_at);
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS op_inserted AFTER INSERT ON main.dht_ops
+    BEGIN
+        SELECT spike_sqlx_written('op', NEW.op_hash);
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS op_updated AFTER UPDATE ON main.dht_ops
+    BEGIN
+        SELECT spike_sqlx_written('op', NEW.op_hash);
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS op_deleted AFTER DELETE ON main.dht_ops
+    BEGIN
+        SELECT spike_sqlx_written('op', OLD.op_hash);
+    END;";
+
+/// Buffer what's written on `con` in `changes`, and add the triggers if
+/// the schema is current.
+pub(crate) async fn watch_changes(
+    con: &mut SqliteConnection,
+    changes: &Changes,
+) -> sqlx::Result<()> {
+    // safety: the handle is live for the duration of `con`, which is
+    // the writer's own
+    unsafe { track_changes(con.as_raw_handle(), changes) }.map_err(sqlx::Error::Protocol)?;
+    // a file still to be migrated is watched once it has been
+    let latest = MIGRATOR.iter().last().map(|m| m.version);
+    if applied(con).await?.map(|(_, version)| version) == latest {
+        watch_entries(con).await?;
+    }
+    Ok(())
+}
+
+/// Add the [`WATCH_TRIGGERS`] to `con`.
+pub(crate) async fn watch_entries(con: &mut SqliteConnection) -> sqlx::Result<()> {
+    con.execute(WATCH_TRIGGERS).await?;
+    Ok(())
+}
+
+impl Db {
+    /// See [`DbWrite::subscribe`].
+    pub fn subscribe(&self, filter: EntryFilter) -> broadcast::Receiver<ChangeEvent> {
+        self.writer().subscribe(filter)
+    }
+}
+
+impl DbWrite {
+    /// Hear of every entry matching `filter`'s location, time and type
+    /// conditions as it's committed, however it was written. Its
+    /// author, limit and order don't apply.
+    ///
+    /// Events arrive in commit order once the transaction is visible
+    /// to readers. One that falls more than
+    /// [`crate::DbConfig::subscription_capacity`] events behind misses
+    /// the oldest, and is told so by
+    /// [`broadcast::error::RecvError::Lagged`].
+    pub fn subscribe(&self, filter: EntryFilter) -> broadcast::Receiver<ChangeEvent> {
+        self.changes.subscribe(filter)
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{Db, DbWrite};
+use std::time::Duration;
+
+/// Mode for `PRAGMA wal_checkpoint`.
+/// See <https://www.sqlite.org/pragma.html#pragma_wal_checkpoint>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckpointMode {
+    /// Checkpoint as much as possible without waiting on readers or writers.
+    Passive,
+    /// Wait for writers, then checkpoint everything.
+    Full,
+    /// Like `Full`, then wait for readers so the log restarts from the beginning.
+    Restart,
+    /// Like `Restart`, then truncate the WAL file to zero bytes.
+    Truncate,
+}
+
+impl CheckpointMode {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Passive => "PASSIVE",
+            Self::Full => "FULL",
+            Self::Restart => "RESTART",
+            Self::Truncate => "TRUNCATE",
+        }
+    }
+}
+
+/// Outcome of a WAL checkpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckpointResult {
+    /// True if the checkpoint could not complete because of a lock.
+    pub busy: bool,
+    /// Frames in the WAL, -1 if the database is not in WAL mode.
+    pub log_frames: i64,
+    /// Frames moved back into the database, -1 if not in WAL mode.
+    pub checkpointed_frames: i64,
+}
+
+/// Checkpoints run through [`Db::checkpoint`] or a background task,
+/// since the database was opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CheckpointMetrics {
+    pub checkpoints: u64,
+    /// Checkpoints that couldn't complete because of a lock.
+    pub busy: u64,
+    /// WAL pages written back into the database, summed.
+    pub pages_checkpointed: u64,
+    pub last: Option<CheckpointResult>,
+}
+
+impl CheckpointMetrics {
+    fn record(&mut self, result: CheckpointResult) {
+        self.checkpoints += 1;
+        self.busy += result.busy as u64;
+        self.pages_checkpointed += result.checkpointed_frames.max(0) as u64;
+        self.last = Some(result);
+    }
+}
+
+impl Db {
+    /// What checkpointing has done so far.
+    pub fn checkpoint_metrics(&self) -> CheckpointMetrics {
+        self.write.checkpoint_metrics()
+    }
+}
+
+impl DbWrite {
+    /// Run a WAL checkpoint through the writer connection.
+    pub async fn checkpoint(&self, mode: CheckpointMode) -> anyhow::Result<CheckpointResult> {
+        let _permit = self.permits.acquire().await?;
+        let (busy, log_frames, checkpointed_frames): (i64, i64, i64) =
+            sqlx::query_as(&format!("PRAGMA wal_checkpoint({});", mode.as_str()))
+                .fetch_one(&self.pool)
+                .await?;
+        let result = CheckpointResult {
+            busy: busy != 0,
+            log_frames,
+            checkpointed_frames,
+        };
+        self.checkpoints.lock().unwrap().record(result);
+        Ok(result)
+    }
+
+    /// What checkpointing has done so far.
+    pub fn checkpoint_metrics(&self) -> CheckpointMetrics {
+        *self.checkpoints.lock().unwrap()
+    }
+}
+
+/// Periodically checkpoint until the task is aborted.
+pub(crate) async fn checkpoint_task(
+    write: DbWrite,
+    interval: std::time::Duration,
+    mode: CheckpointMode,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    // the first tick completes immediately, there is nothing to flush yet
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if let Err(err) = write.checkpoint(mode).await {
+            tracing::warn!(?err, "background wal checkpoint failed");
+        }
+    }
+}
+
+/// Truncate the WAL whenever it's grown past `max_bytes`, checking
+/// every `poll` until the task is aborted.
+pub(crate) async fn wal_size_task(write: DbWrite, max_bytes: u64, poll: Duration) {
+    let file: String =
+        match sqlx::query_scalar("SELECT file FROM pragma_database_list WHERE name = 'main';")
+            .fetch_one(&write.pool)
+            .await
+        {
+            Ok(file) => file,
+            Err(err) => {
+                tracing::error!(?err, "wal size checkpoints disabled, no database path");
+                return;
+            }
+        };
+    // an in-memory database has no file, and no WAL
+    if file.is_empty() {
+        return;
+    }
+    let wal = format!("{}-wal", file);
+    let mut ticker = tokio::time::interval(poll);
+    loop {
+        ticker.tick().await;
+        let size = match tokio::fs::metadata(&wal).await {
+            Ok(meta) => meta.len(),
+            // not created until the first write
+            Err(_) => continue,
+        };
+        if size <= max_bytes {
+            continue;
+        }
+        // a truncate reports nothing once it has reset the log, so the
+        // pages written back are counted by a passive pass first
+        let res = match write.checkpoint(CheckpointMode::Passive).await {
+            Ok(_) => write.checkpoint(CheckpointMode::Truncate).await,
+            err => err,
+        };
+        // a busy result leaves it for the next tick
+        if let Err(err) = res {
+            tracing::warn!(?err, "wal size checkpoint failed");
+        }
+    }
+}
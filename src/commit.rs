@@ -0,0 +1,74 @@
+use crate::{Db, DbRead, DbWrite, Reader};
+use futures::future::BoxFuture;
+use sqlx::{Sqlite, Transaction};
+
+/// A point in the database's commit history, from
+/// [`DbWrite::commit_and_notify`].
+///
+/// Markers only compare between handles on the same file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CommitMarker(pub u64);
+
+/// The commit the transaction's snapshot is at.
+pub(crate) async fn commit_seq(tx: &mut Transaction<'static, Sqlite>) -> sqlx::Result<u64> {
+    // stepped to the end, so it isn't left running in a reader
+    let seq = sqlx::query_scalar!("SELECT seq FROM commit_seq WHERE id = 0;")
+        .fetch_all(tx)
+        .await?;
+    Ok(seq.first().map_or(0, |seq| *seq as u64))
+}
+
+impl Db {
+    /// See [`DbWrite::commit_and_notify`].
+    pub async fn commit_and_notify(&self) -> anyhow::Result<CommitMarker> {
+        self.writer().commit_and_notify().await
+    }
+
+    /// See [`DbRead::read_after`].
+    pub async fn read_after<F, R>(&self, marker: CommitMarker, f: F) -> anyhow::Result<R>
+    where
+        F: for<'r> FnMut(&'r mut Reader) -> BoxFuture<'r, anyhow::Result<R>> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.reader().read_after(marker, f).await
+    }
+}
+
+impl DbWrite {
+    /// Mark everything committed so far, returning a marker that
+    /// [`DbRead::read_after`] waits on, and wake readers waiting on
+    /// an earlier one.
+    ///
+    /// Commits in a transaction of its own, after whatever the caller
+    /// has already written.
+    pub async fn commit_and_notify(&self) -> anyhow::Result<CommitMarker> {
+        // held until sent, so markers go out in commit order
+        let _permit = self.permits.acquire().await?;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!("UPDATE commit_seq SET seq = seq + 1 WHERE id = 0;")
+            .execute(&mut tx)
+            .await?;
+        let marker = CommitMarker(commit_seq(&mut tx).await?);
+        tx.commit().await?;
+        // no readers left to wake is fine
+        let _ = self.commits.send(marker);
+        Ok(marker)
+    }
+}
+
+impl DbRead {
+    /// Like [`DbRead::read`], but on a snapshot that includes the commit
+    /// `marker` came from, even if a pooled reader is lagging behind.
+    ///
+    /// A snapshot that's behind is dropped and a fresh one taken once
+    /// the next commit is announced, or after the retry policy's
+    /// backoff, failing with [`DbError::StaleSnapshot`](crate::DbError)
+    /// when the policy runs out.
+    pub async fn read_after<F, R>(&self, marker: CommitMarker, f: F) -> anyhow::Result<R>
+    where
+        F: for<'r> FnMut(&'r mut Reader) -> BoxFuture<'r, anyhow::Result<R>> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.read_since(Some(marker), f).await
+    }
+}
@@ -0,0 +1,381 @@
+use crate::integrity::IntegritySchedule;
+use crate::{
+    AutoVacuum, CheckpointMode, Encryption, IntegrityFailure, RetryPolicy, SnapshotPolicy,
+    SqlFunction, TraceVerbosity,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use sqlx::sqlite::{SqliteJournalMode, SqliteSynchronous};
+
+/// `PRAGMA cipher_hmac_algorithm` values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HmacAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl HmacAlgorithm {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sha1 => "HMAC_SHA1",
+            Self::Sha256 => "HMAC_SHA256",
+            Self::Sha512 => "HMAC_SHA512",
+        }
+    }
+}
+
+/// Tunables applied when opening a [`crate::Db`].
+///
+/// ```no_run
+/// # use spike_sqlx::*;
+/// let config = DbConfig::new()
+///     .journal_mode(SqliteJournalMode::Wal)
+///     .synchronous(SqliteSynchronous::Normal)
+///     .cache_size(-64_000);
+/// ```
+#[derive(Debug, Clone)]
+pub struct DbConfig {
+    pub(crate) journal_mode: SqliteJournalMode,
+    pub(crate) synchronous: SqliteSynchronous,
+    pub(crate) cache_size: Option<i64>,
+    pub(crate) statement_cache_capacity: usize,
+    pub(crate) mmap_size: Option<u64>,
+    pub(crate) busy_timeout: Duration,
+    pub(crate) foreign_keys: bool,
+    pub(crate) encryption: Option<Encryption>,
+    pub(crate) cipher_page_size: Option<u32>,
+    pub(crate) kdf_iter: Option<u32>,
+    pub(crate) cipher_hmac_algorithm: Option<HmacAlgorithm>,
+    pub(crate) cipher_plaintext_header_size: Option<u32>,
+    pub(crate) min_read_connections: u32,
+    pub(crate) max_read_connections: u32,
+    pub(crate) background_checkpoint: Option<(Duration, CheckpointMode)>,
+    pub(crate) wal_autocheckpoint: Option<u32>,
+    pub(crate) checkpoint_wal_above: Option<(u64, Duration)>,
+    pub(crate) auto_vacuum: Option<AutoVacuum>,
+    pub(crate) analyze_every: Option<Duration>,
+    pub(crate) integrity_check_every: Option<IntegritySchedule>,
+    pub(crate) count_table_rows_every: Option<Duration>,
+    pub(crate) optimize_on_close: bool,
+    pub(crate) snapshots: Option<SnapshotPolicy>,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) health_check_on_acquire: bool,
+    pub(crate) explain_queries: bool,
+    pub(crate) write_coalescing: Option<(Duration, usize)>,
+    pub(crate) permit_timeout: Duration,
+    pub(crate) functions: Vec<SqlFunction>,
+    pub(crate) tracing: TraceVerbosity,
+    pub(crate) slow_query_threshold: Option<Duration>,
+    pub(crate) slow_acquire_threshold: Option<Duration>,
+    pub(crate) subscription_capacity: usize,
+}
+
+impl Default for DbConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DbConfig {
+    /// The default configuration.
+    pub fn new() -> Self {
+        Self {
+            journal_mode: SqliteJournalMode::Wal,
+            synchronous: SqliteSynchronous::Full,
+            cache_size: None,
+            statement_cache_capacity: 100,
+            mmap_size: None,
+            busy_timeout: Duration::from_secs(5),
+            foreign_keys: true,
+            #[cfg(feature = "test-keys")]
+            encryption: Some(Encryption::SqlCipher(crate::KeySource::Shim)),
+            #[cfg(not(feature = "test-keys"))]
+            encryption: None,
+            cipher_page_size: None,
+            kdf_iter: None,
+            cipher_hmac_algorithm: None,
+            cipher_plaintext_header_size: None,
+            min_read_connections: 1,
+            max_read_connections: 4,
+            background_checkpoint: None,
+            wal_autocheckpoint: None,
+            checkpoint_wal_above: None,
+            auto_vacuum: None,
+            analyze_every: None,
+            integrity_check_every: None,
+            count_table_rows_every: None,
+            optimize_on_close: true,
+            snapshots: None,
+            retry_policy: RetryPolicy::default(),
+            health_check_on_acquire: true,
+            explain_queries: false,
+            write_coalescing: None,
+            permit_timeout: Duration::from_secs(30),
+            functions: Vec::new(),
+            tracing: TraceVerbosity::Operations,
+            slow_query_threshold: Some(Duration::from_secs(1)),
+            slow_acquire_threshold: Some(Duration::from_millis(500)),
+            subscription_capacity: 1024,
+        }
+    }
+
+    /// Set `PRAGMA journal_mode`, defaults to WAL.
+    pub fn journal_mode(mut self, mode: SqliteJournalMode) -> Self {
+        self.journal_mode = mode;
+        self
+    }
+
+    /// Set `PRAGMA synchronous`.
+    pub fn synchronous(mut self, synchronous: SqliteSynchronous) -> Self {
+        self.synchronous = synchronous;
+        self
+    }
+
+    /// Set `PRAGMA cache_size`.
+    /// Positive values are pages, negative values are KiB.
+    pub fn cache_size(mut self, cache_size: i64) -> Self {
+        self.cache_size = Some(cache_size);
+        self
+    }
+
+    /// How many prepared statements each connection keeps for reuse,
+    /// least recently used dropped first. Defaults to 100, sqlx's own
+    /// default, 0 prepares every statement afresh each time.
+    /// See [`crate::Db::statement_cache_metrics`] for how well it's doing.
+    pub fn statement_cache_capacity(mut self, capacity: usize) -> Self {
+        self.statement_cache_capacity = capacity;
+        self
+    }
+
+    /// Set `PRAGMA mmap_size` in bytes, 0 disables memory mapping.
+    pub fn mmap_size(mut self, mmap_size: u64) -> Self {
+        self.mmap_size = Some(mmap_size);
+        self
+    }
+
+    /// How long a connection waits on a locked database before erroring.
+    pub fn busy_timeout(mut self, busy_timeout: Duration) -> Self {
+        self.busy_timeout = busy_timeout;
+        self
+    }
+
+    /// Set `PRAGMA foreign_keys`, enforced by default.
+    pub fn foreign_keys(mut self, foreign_keys: bool) -> Self {
+        self.foreign_keys = foreign_keys;
+        self
+    }
+
+    /// Whether to encrypt, and where to get the key.
+    /// Required unless the `test-keys` feature provides the shim default,
+    /// plaintext has to be asked for explicitly.
+    pub fn encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    /// Set SQLCipher's `PRAGMA cipher_page_size`.
+    /// Must match whatever the database was created with.
+    pub fn cipher_page_size(mut self, cipher_page_size: u32) -> Self {
+        self.cipher_page_size = Some(cipher_page_size);
+        self
+    }
+
+    /// Set SQLCipher's `PRAGMA kdf_iter`.
+    /// Only affects passphrase keys, raw keys skip the KDF entirely.
+    pub fn kdf_iter(mut self, kdf_iter: u32) -> Self {
+        self.kdf_iter = Some(kdf_iter);
+        self
+    }
+
+    /// Set SQLCipher's `PRAGMA cipher_hmac_algorithm`.
+    pub fn cipher_hmac_algorithm(mut self, cipher_hmac_algorithm: HmacAlgorithm) -> Self {
+        self.cipher_hmac_algorithm = Some(cipher_hmac_algorithm);
+        self
+    }
+
+    /// Set SQLCipher's `PRAGMA cipher_plaintext_header_size`,
+    /// e.g. 32 so iOS can recognize WAL databases.
+    pub fn cipher_plaintext_header_size(mut self, cipher_plaintext_header_size: u32) -> Self {
+        self.cipher_plaintext_header_size = Some(cipher_plaintext_header_size);
+        self
+    }
+
+    /// Read pool sizing, `max` is also how many reads may run at once.
+    /// Writes always go through a single dedicated connection.
+    pub fn read_connections(mut self, min: u32, max: u32) -> Self {
+        self.min_read_connections = min;
+        self.max_read_connections = max;
+        self
+    }
+
+    /// Checkpoint the WAL from a background task every `interval`,
+    /// so it doesn't grow unbounded under sustained write load.
+    pub fn background_checkpoint(mut self, interval: Duration, mode: CheckpointMode) -> Self {
+        self.background_checkpoint = Some((interval, mode));
+        self
+    }
+
+    /// Set `PRAGMA wal_autocheckpoint`, the WAL size in pages at which
+    /// a committing connection checkpoints on its own.
+    /// sqlite's default is 1000, 0 leaves checkpoints to
+    /// [`crate::Db::checkpoint`] and the background options.
+    pub fn wal_autocheckpoint(mut self, pages: u32) -> Self {
+        self.wal_autocheckpoint = Some(pages);
+        self
+    }
+
+    /// Check the WAL file's size every `poll` from a background task,
+    /// truncating it with a [`CheckpointMode::Truncate`] checkpoint
+    /// once it's over `max_bytes`.
+    ///
+    /// Autocheckpoints only ever run passive, so a long read can leave
+    /// the WAL file large indefinitely. Truncating waits for readers up
+    /// to the busy timeout, holding the write permit meanwhile.
+    pub fn checkpoint_wal_above(mut self, max_bytes: u64, poll: Duration) -> Self {
+        self.checkpoint_wal_above = Some((max_bytes, poll));
+        self
+    }
+
+    /// Set `PRAGMA auto_vacuum`, so space freed by deletes and purges
+    /// goes back to the filesystem rather than staying in the file.
+    ///
+    /// An existing database in another mode is converted on open by
+    /// rebuilding it with a `VACUUM`, which can take a while on a
+    /// large file. Left alone by default.
+    pub fn auto_vacuum(mut self, mode: AutoVacuum) -> Self {
+        self.auto_vacuum = Some(mode);
+        self
+    }
+
+    /// Run `ANALYZE` from a background task every `interval`, so the
+    /// query planner's statistics keep up as tables grow.
+    /// Each run reads every table and index with the write permit held.
+    pub fn analyze_every(mut self, interval: Duration) -> Self {
+        self.analyze_every = Some(interval);
+        self
+    }
+
+    /// Run [`crate::Db::integrity_check`] from a background task every
+    /// `interval`, quick or full, calling `on_failure` whenever it finds
+    /// a problem or can't run at all.
+    /// Each run reads the whole file while holding a read permit.
+    pub fn integrity_check_every<F>(
+        mut self,
+        interval: Duration,
+        quick: bool,
+        on_failure: F,
+    ) -> Self
+    where
+        F: Fn(IntegrityFailure) + Send + Sync + 'static,
+    {
+        self.integrity_check_every = Some(IntegritySchedule {
+            interval,
+            quick,
+            on_failure: Arc::new(on_failure),
+        });
+        self
+    }
+
+    /// Run [`crate::Db::count_table_rows`] from a background task as
+    /// soon as the database opens and every `interval` after, so
+    /// [`crate::MetricsSnapshot::tables`] has counts to keep up to date.
+    /// Each run scans every table while holding a read permit.
+    pub fn count_table_rows_every(mut self, interval: Duration) -> Self {
+        self.count_table_rows_every = Some(interval);
+        self
+    }
+
+    /// Run `PRAGMA optimize` when the database is closed, on by default.
+    pub fn optimize_on_close(mut self, optimize_on_close: bool) -> Self {
+        self.optimize_on_close = optimize_on_close;
+        self
+    }
+
+    /// Where [`crate::Db::snapshot`] keeps snapshots and how many, and
+    /// whether a corrupt file falls back to one at open.
+    pub fn snapshots(mut self, policy: SnapshotPolicy) -> Self {
+        self.snapshots = Some(policy);
+        self
+    }
+
+    /// How transactions are retried on `database is locked` errors.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Check connections, the writer's included, are still usable each
+    /// time one is handed out, replacing broken ones with a freshly keyed
+    /// connection.
+    pub fn health_check_on_acquire(mut self, health_check_on_acquire: bool) -> Self {
+        self.health_check_on_acquire = health_check_on_acquire;
+        self
+    }
+
+    /// Run `EXPLAIN QUERY PLAN` on each built-in statement the first
+    /// time it's used, logging the plan and warning on full table scans.
+    /// For debugging, see [`crate::Db::explained_queries`].
+    pub fn explain_queries(mut self, explain_queries: bool) -> Self {
+        self.explain_queries = explain_queries;
+        self
+    }
+
+    /// Hold writes on the [`crate::Db::write_queue`] back for up to
+    /// `flush_interval`, or until `max_rows` have queued up,
+    /// and commit them together in one transaction.
+    pub fn write_coalescing(mut self, flush_interval: Duration, max_rows: usize) -> Self {
+        self.write_coalescing = Some((flush_interval, max_rows));
+        self
+    }
+
+    /// How long a read or write waits for its turn on the database
+    /// before failing with [`crate::DbError::PermitTimeout`].
+    pub fn permit_timeout(mut self, permit_timeout: Duration) -> Self {
+        self.permit_timeout = permit_timeout;
+        self
+    }
+
+    /// Register `function` on every connection, alongside the built-in
+    /// `holo_dht_loc(hash)`, `blake2b(blob)` and
+    /// `arc_contains(loc, start, end)`. One with the name of a built-in
+    /// replaces it. Names starting `spike_sqlx_` are reserved, opening
+    /// fails with one.
+    pub fn function(mut self, function: SqlFunction) -> Self {
+        self.functions.push(function);
+        self
+    }
+
+    /// How much to report to `tracing`, defaults to a span per
+    /// operation. See [`TraceVerbosity`].
+    pub fn tracing(mut self, verbosity: TraceVerbosity) -> Self {
+        self.tracing = verbosity;
+        self
+    }
+
+    /// Log operations taking at least `threshold` at WARN, with their
+    /// statement, a redacted summary of its parameters and its query
+    /// plan, and count them in [`crate::OpMetrics::slow`].
+    /// Defaults to 1s, `None` turns it off.
+    pub fn slow_query_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_query_threshold = threshold;
+        self
+    }
+
+    /// Log callers that waited at least `threshold` for their turn on
+    /// the database at WARN, and count them in
+    /// [`crate::PermitMetrics::slow`].
+    /// Defaults to 500ms, `None` turns it off.
+    pub fn slow_acquire_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_acquire_threshold = threshold;
+        self
+    }
+
+    /// How many events each [`crate::Db::subscribe`] receiver may fall
+    /// behind by before it misses some, defaults to 1024.
+    pub fn subscription_capacity(mut self, capacity: usize) -> Self {
+        self.subscription_capacity = capacity;
+        self
+    }
+}
@@ -0,0 +1,145 @@
+//! Running SQL typed by a person, for poking at an encrypted file the
+//! `sqlite3` shell can't open.
+
+use crate::backend::row_values;
+use crate::{Db, DbWrite, Value};
+use futures::TryStreamExt;
+use libsqlite3_sys::sqlite3_total_changes;
+use sqlx::{Column, Executor, Row};
+use std::fmt;
+
+/// Longest a cell is shown before it's cut short, room for a 39 byte
+/// hash.
+const MAX_CELL_CHARS: usize = 96;
+
+/// What one statement did, from [`Db::run_sql`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SqlOutput {
+    /// Names of the columns returned, empty if no rows were.
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    /// Rows the statement itself inserted, updated or deleted, not
+    /// counting those changed by triggers.
+    pub rows_changed: u64,
+}
+
+/// As a sqlite literal, blobs in hex.
+pub(crate) fn cell(value: &Value) -> String {
+    let text = match value {
+        Value::Null => "NULL".to_string(),
+        Value::Integer(v) => v.to_string(),
+        Value::Real(v) => v.to_string(),
+        Value::Text(v) => v.clone(),
+        Value::Blob(v) => {
+            let hex: String = v.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("x'{}'", hex)
+        }
+    };
+    match text.char_indices().nth(MAX_CELL_CHARS) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+/// A table of the rows, or how many were changed if none were
+/// returned.
+impl fmt::Display for SqlOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.columns.is_empty(), self.rows_changed) {
+            (true, 0) => return writeln!(f, "(0 rows)"),
+            (true, changed) => return writeln!(f, "{} rows changed", changed),
+            (false, _) => {}
+        }
+        let cells: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(cell).collect())
+            .collect();
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                cells
+                    .iter()
+                    .filter_map(|row| row.get(i))
+                    .chain(Some(name))
+                    .map(|text| text.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let line = |f: &mut fmt::Formatter<'_>, texts: &[String]| {
+            let padded: Vec<String> = texts
+                .iter()
+                .zip(&widths)
+                .map(|(text, width)| format!("{:<width$}", text, width = width))
+                .collect();
+            writeln!(f, "{}", padded.join(" | ").trim_end())
+        };
+        line(f, &self.columns)?;
+        let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+        writeln!(f, "{}", rule.join("-+-"))?;
+        for row in &cells {
+            line(f, row)?;
+        }
+        match self.rows.len() {
+            1 => writeln!(f, "(1 row)"),
+            n => writeln!(f, "({} rows)", n),
+        }
+    }
+}
+
+impl Db {
+    /// See [`DbWrite::run_sql`].
+    pub async fn run_sql(&self, sql: &str) -> anyhow::Result<Vec<SqlOutput>> {
+        self.write.run_sql(sql).await
+    }
+}
+
+impl DbWrite {
+    /// Run every statement in `sql` on the writer as they come, each in
+    /// its own transaction unless `sql` begins one, returning what each
+    /// did.
+    ///
+    /// Nothing is checked or retried, so this is for people debugging,
+    /// never for the conductor. A failing statement fails the lot,
+    /// though those before it have run.
+    pub async fn run_sql(&self, sql: &str) -> anyhow::Result<Vec<SqlOutput>> {
+        let _permit = self.permits.acquire().await?;
+        let mut con = self.pool.acquire().await?;
+        let handle = con.as_raw_handle();
+        // safety: the handle is live for as long as `con` is
+        let total_changes = || unsafe { sqlite3_total_changes(handle) };
+        let mut outputs = Vec::new();
+        let mut output = SqlOutput::default();
+        let mut changes_before = total_changes();
+        let mut results = con.fetch_many(sql);
+        while let Some(result) = results.try_next().await? {
+            // each row, then the end of its statement
+            match result.either(Err, Ok) {
+                Ok(row) => {
+                    if output.columns.is_empty() {
+                        output.columns = row
+                            .columns()
+                            .iter()
+                            .map(|column| column.name().to_string())
+                            .collect();
+                    }
+                    output.rows.push(row_values(&row)?);
+                }
+                Err(done) => {
+                    // sqlite3_changes is left over from the last write,
+                    // so only believe it if this statement wrote
+                    let changes_after = total_changes();
+                    if changes_after != changes_before {
+                        output.rows_changed = done.rows_affected();
+                    }
+                    changes_before = changes_after;
+                    outputs.push(std::mem::take(&mut output));
+                }
+            }
+        }
+        Ok(outputs)
+    }
+}
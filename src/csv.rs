@@ -0,0 +1,156 @@
+//! CSV dumps of single tables, for spreadsheets and dataframes.
+
+use crate::{Db, DbRead};
+use futures::TryStreamExt;
+use sqlx::Row;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// A table [`Db::export_csv`] can dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvTable {
+    Entries,
+    Headers,
+    DhtOps,
+    ValidationReceipts,
+    Links,
+    AgentStore,
+    CapGrants,
+    CapClaims,
+}
+
+impl CsvTable {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Entries => "entries",
+            Self::Headers => "headers",
+            Self::DhtOps => "dht_ops",
+            Self::ValidationReceipts => "validation_receipts",
+            Self::Links => "links",
+            Self::AgentStore => "agent_store",
+            Self::CapGrants => "cap_grants",
+            Self::CapClaims => "cap_claims",
+        }
+    }
+
+    /// Dump only `columns` of this table, in that order.
+    pub fn columns<I, S>(self, columns: I) -> CsvExport
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        CsvExport {
+            table: self,
+            columns: Some(columns.into_iter().map(Into::into).collect()),
+        }
+    }
+}
+
+/// What [`Db::export_csv`] dumps: a table, every column unless
+/// narrowed down with [`CsvTable::columns`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvExport {
+    table: CsvTable,
+    columns: Option<Vec<String>>,
+}
+
+impl From<CsvTable> for CsvExport {
+    fn from(table: CsvTable) -> Self {
+        Self {
+            table,
+            columns: None,
+        }
+    }
+}
+
+/// Quote `field` if it needs it, as RFC 4180 has it.
+fn escape(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains(&[',', '"', '\r', '\n'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+fn record<'a, I: IntoIterator<Item = &'a str>>(fields: I) -> String {
+    let mut line = fields.into_iter().map(escape).collect::<Vec<_>>().join(",");
+    line.push_str("\r\n");
+    line
+}
+
+impl Db {
+    /// Write a table to `writer` as CSV, see [`DbRead::export_csv`].
+    pub async fn export_csv<T, W>(&self, table: T, writer: W) -> anyhow::Result<u64>
+    where
+        T: Into<CsvExport>,
+        W: AsyncWrite + Unpin,
+    {
+        self.reader().export_csv(table, writer).await
+    }
+}
+
+impl DbRead {
+    /// Write a table to `writer` as CSV, a header row of column names
+    /// and then one record per row, returning how many rows were written.
+    ///
+    /// Values are written as sqlite holds them: blobs (hashes, content)
+    /// as lowercase hex, timestamps as micros or text depending on the
+    /// column, `NULL` as an empty field.
+    /// The rows come from one read transaction.
+    pub async fn export_csv<T, W>(&self, table: T, mut writer: W) -> anyhow::Result<u64>
+    where
+        T: Into<CsvExport>,
+        W: AsyncWrite + Unpin,
+    {
+        let export = table.into();
+        let table = export.table.name();
+        let _permit = self.permits.acquire().await?;
+        let mut tx = self.pool.begin().await?;
+
+        let known: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?1);")
+            .bind(table)
+            .fetch_all(&mut tx)
+            .await?;
+        let columns = match export.columns {
+            Some(columns) => {
+                // spliced into the sql below, so only ever real names
+                if let Some(unknown) = columns.iter().find(|c| !known.contains(c)) {
+                    anyhow::bail!("{} has no column {}", table, unknown);
+                }
+                columns
+            }
+            None => known,
+        };
+        if columns.is_empty() {
+            anyhow::bail!("no columns to export from {}", table);
+        }
+
+        let select = columns
+            .iter()
+            .map(|c| {
+                format!(
+                    "CASE typeof(\"{0}\") WHEN 'blob' THEN lower(hex(\"{0}\")) \
+                    ELSE CAST(\"{0}\" AS TEXT) END",
+                    c
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!("SELECT {} FROM {} ORDER BY rowid;", select, table);
+
+        writer
+            .write_all(record(columns.iter().map(String::as_str)).as_bytes())
+            .await?;
+        let mut written = 0;
+        let mut rows = sqlx::query(&sql).fetch(&mut tx);
+        while let Some(row) = rows.try_next().await? {
+            let fields = (0..columns.len())
+                .map(|i| row.try_get::<Option<String>, _>(i))
+                .collect::<Result<Vec<_>, _>>()?;
+            let line = record(fields.iter().map(|f| f.as_deref().unwrap_or("")));
+            writer.write_all(line.as_bytes()).await?;
+            written += 1;
+        }
+        writer.flush().await?;
+        Ok(written)
+    }
+}
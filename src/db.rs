@@ -0,0 +1,1196 @@
+use crate::analyze::analyze_task;
+use crate::attach::{attach_all, attachments_current, SharedAttachments};
+use crate::changes::{watch_changes, watch_entries, Changes};
+use crate::checkpoint::{checkpoint_task, wal_size_task};
+use crate::element::SELECT_ELEMENTS;
+use crate::error::{from_open_error, into_sqlx, is_bad_key, is_not_a_database};
+use crate::explain::Explainer;
+use crate::functions::register_functions;
+use crate::hash_list::HashList;
+use crate::integrity::integrity_task;
+use crate::key_provider::DbKey;
+use crate::migrations::validate_schema;
+use crate::open_key::{has_file, keyed_on_open, OpenKey};
+use crate::permit::Permits;
+use crate::retry::{is_busy, with_retry};
+use crate::row_counts::{row_count_task, RowCounts};
+use crate::snapshot::{open_or_restore, url_file};
+use crate::statement_cache::StatementCache;
+use crate::trace::Tracer;
+use crate::vacuum::apply_auto_vacuum;
+use crate::{
+    CheckpointMetrics, CheckpointMode, CheckpointResult, CommitMarker, DbConfig, DbError, DbKind,
+    DbWriter, DhtLocation, Element, Encryption, Entry, EntryFilter, EntryHash, EntryType,
+    ExplainedQuery, Header, HeaderHash, PermitGuard, PermitMetrics, RetryPolicy, Timestamp,
+    WriteOutcome,
+};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::{ConnectOptions, Connection, Execute, Executor, SqliteConnection};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::Instrument;
+
+/// The current database key, shared by every pool so connections
+/// opened after a rekey pick up the new one.
+/// `None` for plaintext databases.
+pub(crate) type SharedKey = Arc<std::sync::RwLock<Option<DbKey>>>;
+
+/// Execute a NUL terminated statement embedding key material.
+///
+/// This deliberately bypasses sqlx: its executor would log the statement
+/// and keep a copy of the sql around in its statement cache. Callers
+/// build `cmd` with [`DbKey::statement`] so it is wiped on drop.
+pub(crate) fn exec_secret(con: &mut SqliteConnection, what: &str, cmd: &[u8]) -> sqlx::Result<()> {
+    // safety: the handle is live for the duration of `con` and no
+    // statement is mid-step on it while we hold `&mut`
+    unsafe { exec_secret_on(con.as_raw_handle(), what, cmd) }.map_err(sqlx::Error::Protocol)
+}
+
+/// [`exec_secret`] on a raw handle, for drivers other than sqlx.
+///
+/// # Safety
+///
+/// `handle` must be an open connection nothing else is using.
+pub(crate) unsafe fn exec_secret_on(
+    handle: *mut libsqlite3_sys::sqlite3,
+    what: &str,
+    cmd: &[u8],
+) -> Result<(), String> {
+    use libsqlite3_sys::{sqlite3_errmsg, sqlite3_exec, SQLITE_OK};
+    use std::ffi::CStr;
+
+    assert_eq!(cmd.last(), Some(&0), "statement must be NUL terminated");
+    let rc = sqlite3_exec(
+        handle,
+        cmd.as_ptr() as *const _,
+        None,
+        std::ptr::null_mut(),
+        std::ptr::null_mut(),
+    );
+    if rc != SQLITE_OK {
+        let msg = CStr::from_ptr(sqlite3_errmsg(handle))
+            .to_string_lossy()
+            .into_owned();
+        return Err(format!("{} failed ({}): {}", what, rc, msg));
+    }
+    Ok(())
+}
+
+/// Run a key pragma such as `key` or `rekey` on `con`.
+pub(crate) fn key_pragma(
+    con: &mut SqliteConnection,
+    pragma: &str,
+    key: &DbKey,
+) -> sqlx::Result<()> {
+    let cmd = key.statement(&format!("PRAGMA {} = ", pragma), ";");
+    exec_secret(con, &format!("PRAGMA {}", pragma), &cmd)
+}
+
+/// Apply the encryption key to a freshly opened connection.
+/// This must run on every connection before it touches the database.
+fn apply_encryption_key(con: &mut SqliteConnection, key: &DbKey) -> sqlx::Result<()> {
+    key_pragma(con, "key", key)
+}
+
+/// The SQLCipher tuning pragmas `config` asks for.
+pub(crate) fn cipher_pragmas(config: &DbConfig) -> Vec<String> {
+    let mut pragmas = Vec::new();
+    if let Some(size) = config.cipher_page_size {
+        pragmas.push(format!("PRAGMA cipher_page_size = {};", size));
+    }
+    if let Some(iter) = config.kdf_iter {
+        pragmas.push(format!("PRAGMA kdf_iter = {};", iter));
+    }
+    if let Some(algorithm) = config.cipher_hmac_algorithm {
+        pragmas.push(format!(
+            "PRAGMA cipher_hmac_algorithm = {};",
+            algorithm.as_str()
+        ));
+    }
+    if let Some(size) = config.cipher_plaintext_header_size {
+        pragmas.push(format!("PRAGMA cipher_plaintext_header_size = {};", size));
+    }
+    pragmas
+}
+
+/// SQLCipher tuning, must run after the key and before first access.
+/// Only for connections keyed after opening, in memory ones, the rest
+/// get it as they open, see [`crate::open_key`].
+async fn apply_cipher_settings(con: &mut SqliteConnection, config: &DbConfig) -> sqlx::Result<()> {
+    for pragma in cipher_pragmas(config) {
+        con.execute(&*pragma).await?;
+    }
+    Ok(())
+}
+
+/// True if the linked sqlite is SQLCipher.
+pub(crate) async fn is_sqlcipher(con: &mut SqliteConnection) -> sqlx::Result<bool> {
+    Ok(sqlx::query("PRAGMA cipher_version;")
+        .fetch_optional(con)
+        .await?
+        .is_some())
+}
+
+/// SQLCipher only notices a wrong key on the first real read,
+/// so force one now and report it as such.
+async fn verify_key(con: &mut SqliteConnection) -> sqlx::Result<()> {
+    match health_check(con).await {
+        Err(err) if is_not_a_database(&err) => {
+            // stock sqlite ignores the key, so the file really is bad
+            if is_sqlcipher(con).await? {
+                Err(into_sqlx(DbError::BadEncryptionKey))
+            } else {
+                Err(into_sqlx(DbError::Corrupt(err)))
+            }
+        }
+        res => res,
+    }
+}
+
+/// Check the key the connection was opened with, then apply the
+/// per-connection pragmas sqlx doesn't manage for us.
+///
+/// File connections must come from [`OpenKey::options`] or
+/// [`connect_keyed`], which key them before sqlx runs anything.
+pub(crate) async fn init_connection(
+    con: &mut SqliteConnection,
+    config: &DbConfig,
+    key: Option<&DbKey>,
+) -> sqlx::Result<()> {
+    if let Some(key) = key {
+        let handle = con.as_raw_handle();
+        if !keyed_on_open(handle) {
+            // safety: the handle is live for the duration of `con`
+            if unsafe { has_file(handle) } {
+                return Err(sqlx::Error::Protocol(
+                    "connection to an encrypted database was opened without its key".into(),
+                ));
+            }
+            // in memory, there was nothing on disk for sqlx's pragmas to read
+            apply_encryption_key(con, key)?;
+            apply_cipher_settings(con, config).await?;
+        }
+        verify_key(con).await?;
+    }
+    register_functions(con, config)?;
+    if let Some(cache_size) = config.cache_size {
+        con.execute(&*format!("PRAGMA cache_size = {};", cache_size))
+            .await?;
+    }
+    if let Some(mmap_size) = config.mmap_size {
+        con.execute(&*format!("PRAGMA mmap_size = {};", mmap_size))
+            .await?;
+    }
+    if let Some(pages) = config.wal_autocheckpoint {
+        con.execute(&*format!("PRAGMA wal_autocheckpoint = {};", pages))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Cheap query that fails if the connection is dead
+/// or the key no longer decrypts the database.
+pub(crate) async fn health_check(con: &mut SqliteConnection) -> sqlx::Result<()> {
+    sqlx::query("SELECT count(*) FROM sqlite_master")
+        .fetch_one(con)
+        .await?;
+    Ok(())
+}
+
+/// Apply the session settings sqlx manages itself.
+///
+/// sqlx runs `journal_mode`, `synchronous` and `foreign_keys` on every
+/// connection it opens, falling back to its own defaults, so they're
+/// set here to the configured values. The key is already in place by
+/// then, see [`crate::open_key`].
+pub(crate) fn connect_options(
+    options: SqliteConnectOptions,
+    config: &DbConfig,
+) -> SqliteConnectOptions {
+    options
+        .journal_mode(config.journal_mode.clone())
+        .synchronous(config.synchronous.clone())
+        .busy_timeout(config.busy_timeout)
+        .foreign_keys(config.foreign_keys)
+        .statement_cache_capacity(config.statement_cache_capacity)
+}
+
+/// Open a single connection to `file`, or to `options` if it has no
+/// file, keyed with `key` as it opens. Run [`init_connection`] on it
+/// with the same key before using it.
+pub(crate) async fn connect_keyed(
+    options: SqliteConnectOptions,
+    file: Option<&Path>,
+    config: &DbConfig,
+    key: Option<&DbKey>,
+) -> sqlx::Result<SqliteConnection> {
+    let open_key = OpenKey::register(Arc::new(std::sync::RwLock::new(key.cloned())), config);
+    match open_key
+        .options(connect_options(options, config), file)
+        .connect()
+        .await
+    {
+        Err(err) if is_bad_key(&err) => Err(into_sqlx(DbError::BadEncryptionKey)),
+        res => res,
+    }
+}
+
+/// Build a pool over `options`, keying each new connection with
+/// `open_key` as it opens, initializing it and,
+/// while `check_on_acquire` is set, checking idle ones still work before
+/// handing them out.
+/// Entries inserted on its connections go to `changes`, if given.
+async fn make_pool(
+    options: SqliteConnectOptions,
+    config: &DbConfig,
+    open_key: Arc<OpenKey>,
+    attachments: SharedAttachments,
+    check_on_acquire: Arc<AtomicBool>,
+    connections: RangeInclusive<u32>,
+    changes: Option<Changes>,
+) -> sqlx::Result<SqlitePool> {
+    let options = connect_options(options, config);
+    let current = attachments.clone();
+    // returning false makes the pool drop the connection and open
+    // a new one, which runs through `after_connect` again
+    let pool_options = SqlitePoolOptions::new()
+        .min_connections(*connections.start())
+        .max_connections(*connections.end())
+        // sqlx 0.5 only calls `before_acquire` with its own ping test
+        // turned off, so without this no connection is ever checked
+        .test_before_acquire(false)
+        .before_acquire(move |con| {
+            let attachments = current.clone();
+            let check = check_on_acquire.load(Ordering::SeqCst);
+            Box::pin(async move {
+                if check {
+                    match health_check(con).await {
+                        // only locked out for now, the connection is fine
+                        Err(err) if !is_busy(&err) => return Ok(false),
+                        _ => (),
+                    }
+                }
+                Ok(attachments_current(con, &attachments)
+                    .await
+                    .unwrap_or(false))
+            })
+        });
+    let pool_options = match changes.clone() {
+        // back in the pool once the transaction is done with it
+        Some(changes) => pool_options.after_release(move |_| {
+            changes.publish();
+            true
+        }),
+        None => pool_options,
+    };
+    // shared rather than copied into every connection, it holds the key
+    let config = Arc::new(config.clone());
+    pool_options
+        .after_connect(move |con| {
+            let config = config.clone();
+            // also keeps it registered while the pool may open connections
+            let key = open_key.key();
+            let attachments = attachments.clone();
+            let changes = changes.clone();
+            Box::pin(async move {
+                init_connection(con, &config, key.as_ref()).await?;
+                if let Some(changes) = changes {
+                    watch_changes(con, &changes).await?;
+                }
+                attach_all(con, &config, &attachments).await
+            })
+        })
+        .connect_with(options)
+        .await
+}
+
+/// Most `?` parameters one statement may bind, sqlite's
+/// `SQLITE_MAX_VARIABLE_NUMBER` default before 3.32.
+pub(crate) const MAX_BOUND_PARAMS: usize = 999;
+
+/// Every column [`Entry`] reads.
+pub(crate) const ENTRY_COLUMNS: &str = "hash, dht_loc, created_at, entry_type, size_bytes, content";
+
+pub(crate) const INSERT_ENTRY: &str = "INSERT INTO entries
+    (hash, dht_loc, created_at, entry_type, size_bytes, content)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6)";
+
+pub(crate) const INSERT_HEADER: &str = "INSERT INTO headers
+    (hash, author, seq, prev_hash, entry_hash, type, timestamp)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
+
+/// SQL matching `column` within the inclusive arc bound to the
+/// next two `?` placeholders, start then end.
+///
+/// `start > end` means the arc wraps past `u32::MAX` back to 0,
+/// so it covers both ends of the space rather than the middle.
+pub(crate) fn arc_condition(column: &str, start: u32, end: u32) -> String {
+    if start <= end {
+        format!("{0} >= ? AND {0} <= ?", column)
+    } else {
+        format!("({0} >= ? OR {0} <= ?)", column)
+    }
+}
+
+/// Aborts the wrapped task once the last handle is dropped.
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Handle to an encrypted entry database.
+/// Cheap to clone, all clones share the same connections.
+#[derive(Clone)]
+pub struct Db {
+    read: DbRead,
+    pub(crate) write: DbWrite,
+    pub(crate) kind: Option<DbKind>,
+    pub(crate) options: SqliteConnectOptions,
+    pub(crate) config: DbConfig,
+    pub(crate) key: SharedKey,
+    pub(crate) attachments: SharedAttachments,
+    /// Whether the writer is health checked on acquire.
+    pub(crate) check_writer: Arc<AtomicBool>,
+    write_queue: DbWriter,
+    _checkpoint_task: Option<Arc<AbortOnDrop>>,
+    _wal_size_task: Option<Arc<AbortOnDrop>>,
+    _analyze_task: Option<Arc<AbortOnDrop>>,
+    _integrity_task: Option<Arc<AbortOnDrop>>,
+    _row_count_task: Option<Arc<AbortOnDrop>>,
+    _write_queue_task: Arc<AbortOnDrop>,
+}
+
+impl Db {
+    /// Open (or create) the database at `path`, applying the encryption
+    /// key and ensuring the schema exists.
+    /// `path` may also be a sqlx url such as `sqlite::memory:`.
+    pub async fn open<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<Self> {
+        Self::open_with(path, DbConfig::default()).await
+    }
+
+    /// Like [`Db::open`], but with an explicit configuration.
+    pub async fn open_with<P: AsRef<std::path::Path>>(
+        path: P,
+        config: DbConfig,
+    ) -> anyhow::Result<Self> {
+        // parse once so in-memory urls resolve to the same database
+        // for both the writer and the readers
+        let url = path.as_ref().to_string_lossy();
+        let options: SqliteConnectOptions = url.parse()?;
+        open_or_restore(url_file(&url).as_deref(), options, None, config).await
+    }
+
+    /// Open the in-memory database called `name`, shared by every handle
+    /// in this process opened with the same name, so a test can open a
+    /// second handle on the "same file". It's gone once the last handle
+    /// closes.
+    ///
+    /// Each `sqlite::memory:` handle gets a database of its own instead,
+    /// shared only between its reader and writer pools.
+    pub async fn open_test(name: &str) -> anyhow::Result<Self> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            anyhow::bail!("invalid test database name {:?}", name);
+        }
+        let uri = format!("file:{}?mode=memory&cache=shared", name);
+        Self::open_with(uri, DbConfig::default()).await
+    }
+
+    /// Open (or create) the database for `kind` under `data_root`.
+    pub async fn open_kind<P: AsRef<std::path::Path>>(
+        data_root: P,
+        kind: DbKind,
+        config: DbConfig,
+    ) -> anyhow::Result<Self> {
+        let path = kind.path(data_root);
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let options = SqliteConnectOptions::new().filename(&path);
+        open_or_restore(Some(&path), options, Some(kind), config).await
+    }
+
+    pub(crate) async fn open_options(
+        options: SqliteConnectOptions,
+        file: Option<&Path>,
+        kind: Option<DbKind>,
+        config: DbConfig,
+    ) -> anyhow::Result<Self> {
+        let kind_name = kind.as_ref().map_or("path", DbKind::name);
+        let trace = Tracer::new(kind_name, "write", &config);
+        let op = trace.op("open");
+        let res = Self::open_traced(options, file, kind, config, trace)
+            .instrument(op.span.clone())
+            .await;
+        op.finish_unknown(&res).await;
+        res
+    }
+
+    async fn open_traced(
+        options: SqliteConnectOptions,
+        file: Option<&Path>,
+        kind: Option<DbKind>,
+        config: DbConfig,
+        trace: Tracer,
+    ) -> anyhow::Result<Self> {
+        // fetch the key once up front rather than per pooled connection
+        let key = match &config.encryption {
+            Some(Encryption::SqlCipher(source)) => Some(source.db_key(kind.as_ref()).await?),
+            Some(Encryption::None) => None,
+            None => anyhow::bail!("no encryption configured, use Encryption::None for plaintext"),
+        };
+        let key: SharedKey = Arc::new(std::sync::RwLock::new(key));
+        let attachments = SharedAttachments::default();
+        let changes = Changes::new(config.subscription_capacity);
+
+        // the writer must come first, it is the one allowed to create the file
+        let options = options.create_if_missing(true);
+        let open_key = Arc::new(OpenKey::register(key.clone(), &config));
+        let pool_options = open_key.options(options.clone(), file);
+        // turned off while rekeying, see `Db::rekey`
+        let check_writer = Arc::new(AtomicBool::new(config.health_check_on_acquire));
+        let write = match make_pool(
+            pool_options.clone(),
+            &config,
+            open_key.clone(),
+            attachments.clone(),
+            check_writer.clone(),
+            1..=1,
+            Some(changes.clone()),
+        )
+        .await
+        {
+            Ok(write) => write,
+            Err(err) => return Err(from_open_error(err)),
+        };
+        let explain = Explainer::new(config.explain_queries);
+        // only a wake-up, readers check the marker in their snapshot
+        let (commits, commits_rx) = watch::channel(CommitMarker(0));
+        let trace = trace.explaining_on(&write);
+        let write = DbWrite {
+            pool: write,
+            retry: config.retry_policy.clone(),
+            explain: explain.clone(),
+            permits: Permits::new(trace.kind(), "write", 1, &config),
+            commits: Arc::new(commits),
+            checkpoints: Arc::default(),
+            statements: StatementCache::new(config.statement_cache_capacity),
+            trace,
+            changes,
+        };
+        if let Some(mode) = config.auto_vacuum {
+            // before migrating, so a new database never needs the rebuild
+            apply_auto_vacuum(&mut *write.pool.acquire().await?, mode).await?;
+        }
+        write.migrate().await?;
+        validate_schema(&mut *write.pool.acquire().await?).await?;
+        let row_counts = RowCounts::default();
+        write.on_commit({
+            let row_counts = row_counts.clone();
+            move |summary| row_counts.apply(summary)
+        });
+        watch_entries(&mut *write.pool.acquire().await?).await?;
+
+        let read = match make_pool(
+            pool_options.read_only(true),
+            &config,
+            open_key,
+            attachments.clone(),
+            Arc::new(AtomicBool::new(config.health_check_on_acquire)),
+            config.min_read_connections..=config.max_read_connections,
+            None,
+        )
+        .await
+        {
+            Ok(read) => read,
+            Err(err) => return Err(from_open_error(err)),
+        };
+
+        let _checkpoint_task = config.background_checkpoint.map(|(interval, mode)| {
+            Arc::new(AbortOnDrop(tokio::task::spawn(checkpoint_task(
+                write.clone(),
+                interval,
+                mode,
+            ))))
+        });
+        let _wal_size_task = config.checkpoint_wal_above.map(|(max_bytes, poll)| {
+            Arc::new(AbortOnDrop(tokio::task::spawn(wal_size_task(
+                write.clone(),
+                max_bytes,
+                poll,
+            ))))
+        });
+        let _analyze_task = config.analyze_every.map(|interval| {
+            Arc::new(AbortOnDrop(tokio::task::spawn(analyze_task(
+                write.clone(),
+                interval,
+            ))))
+        });
+
+        let (write_queue, task) = DbWriter::spawn(write.clone(), config.write_coalescing);
+        let _write_queue_task = Arc::new(AbortOnDrop(task));
+
+        let trace = Tracer::new(write.trace.kind(), "read", &config).explaining_on(&read);
+        let read = DbRead {
+            pool: read,
+            retry: config.retry_policy.clone(),
+            explain,
+            permits: Permits::new(trace.kind(), "read", config.max_read_connections, &config),
+            commits: commits_rx,
+            statements: StatementCache::new(config.statement_cache_capacity),
+            trace,
+            row_counts,
+        };
+        let _integrity_task = config.integrity_check_every.clone().map(|schedule| {
+            Arc::new(AbortOnDrop(tokio::task::spawn(integrity_task(
+                read.clone(),
+                schedule,
+            ))))
+        });
+        let _row_count_task = config.count_table_rows_every.map(|interval| {
+            Arc::new(AbortOnDrop(tokio::task::spawn(row_count_task(
+                read.clone(),
+                interval,
+            ))))
+        });
+        Ok(Self {
+            read,
+            write,
+            kind,
+            options,
+            config,
+            key,
+            attachments,
+            check_writer,
+            write_queue,
+            _checkpoint_task,
+            _wal_size_task,
+            _analyze_task,
+            _integrity_task,
+            _row_count_task,
+            _write_queue_task,
+        })
+    }
+
+    /// The plans of every statement run so far, if
+    /// [`DbConfig::explain_queries`] is on.
+    pub fn explained_queries(&self) -> Vec<ExplainedQuery> {
+        self.read.explain.explained()
+    }
+
+    /// The kind of database, if opened via [`Db::open_kind`].
+    pub fn kind(&self) -> Option<&DbKind> {
+        self.kind.as_ref()
+    }
+
+    /// Read-only access to the database.
+    pub fn reader(&self) -> &DbRead {
+        &self.read
+    }
+
+    /// Write access to the database.
+    pub fn writer(&self) -> &DbWrite {
+        &self.write
+    }
+
+    /// Queue of writes sharing transactions, for many small
+    /// concurrent writers.
+    pub fn write_queue(&self) -> &DbWriter {
+        &self.write_queue
+    }
+
+    /// Insert a single entry in its own transaction.
+    pub async fn insert_entry(&self, entry: &Entry) -> anyhow::Result<WriteOutcome> {
+        self.write.insert_entry(entry).await
+    }
+
+    /// Insert an entry unless it's already held.
+    pub async fn upsert_entry(&self, entry: &Entry) -> anyhow::Result<WriteOutcome> {
+        self.write.upsert_entry(entry).await
+    }
+
+    /// Insert many entries in a single transaction.
+    pub async fn insert_entries(&self, entries: &[Entry]) -> anyhow::Result<WriteOutcome> {
+        self.write.insert_entries(entries).await
+    }
+
+    /// Delete an entry and any headers creating it.
+    pub async fn delete_entry(&self, hash: &EntryHash) -> anyhow::Result<WriteOutcome> {
+        self.write.delete_entry(hash).await
+    }
+
+    /// Delete every entry matching `filter`.
+    pub async fn purge(&self, filter: &EntryFilter) -> anyhow::Result<WriteOutcome> {
+        self.write.purge(filter).await
+    }
+
+    /// Check both the writer and a reader can still reach the database.
+    pub async fn ping(&self) -> anyhow::Result<()> {
+        health_check(&mut *self.write.pool.acquire().await?).await?;
+        health_check(&mut *self.read.pool.acquire().await?).await?;
+        Ok(())
+    }
+
+    /// SQLCipher's version, `None` if the linked sqlite is plain
+    /// sqlite, which ignores keys and leaves files unencrypted.
+    pub async fn cipher_version(&self) -> anyhow::Result<Option<String>> {
+        let mut con = self.read.pool.acquire().await?;
+        Ok(sqlx::query_scalar("PRAGMA cipher_version;")
+            .fetch_optional(&mut con)
+            .await?)
+    }
+
+    /// Shut the database down cleanly.
+    ///
+    /// Waits for in-flight reads and writes to finish, refreshes stale
+    /// planner statistics with `PRAGMA optimize`, truncates the WAL
+    /// with a final checkpoint, then closes every connection. Clones
+    /// share the closed pools, so everything they try afterwards fails
+    /// straight away rather than waiting on a connection.
+    pub async fn close(self) -> anyhow::Result<()> {
+        for task in [
+            &self._checkpoint_task,
+            &self._wal_size_task,
+            &self._analyze_task,
+            &self._integrity_task,
+            &self._row_count_task,
+        ]
+        .iter()
+        .copied()
+        .flatten()
+        {
+            task.0.abort();
+        }
+        // commits anything buffered, later writes fail with the queue closed
+        let flushed = self.write_queue.flush().await;
+        self._write_queue_task.0.abort();
+
+        // waits for every checked out reader to be returned
+        self.read.pool.close().await;
+
+        // acquiring the writer waits out any in-flight write
+        let optimized = if self.config.optimize_on_close {
+            self.write.optimize().await
+        } else {
+            Ok(())
+        };
+        let res = self.write.checkpoint(CheckpointMode::Truncate).await;
+        self.write.pool.close().await;
+        flushed?;
+        optimized?;
+        res?;
+
+        Ok(())
+    }
+
+    /// Insert a single header in its own transaction.
+    pub async fn insert_header(&self, header: &Header) -> anyhow::Result<WriteOutcome> {
+        self.write.insert_header(header).await
+    }
+
+    /// Apply any pending schema migrations, returning how many ran.
+    /// Opening already does this, so it only matters for long-lived handles.
+    pub async fn migrate(&self) -> anyhow::Result<usize> {
+        self.write.migrate().await
+    }
+
+    /// Run a WAL checkpoint through the writer.
+    pub async fn checkpoint(&self, mode: CheckpointMode) -> anyhow::Result<CheckpointResult> {
+        self.write.checkpoint(mode).await
+    }
+
+    /// Fetch all entries within the given (inclusive) location
+    /// and creation time ranges.
+    /// The location range wraps if `dht_loc_start > dht_loc_end`.
+    pub async fn query_entries(
+        &self,
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+    ) -> anyhow::Result<Vec<Entry>> {
+        self.read
+            .query_entries(dht_loc_start, dht_loc_end, created_at_start, created_at_end)
+            .await
+    }
+
+    /// Fetch all entries matching `filter`.
+    pub async fn filter_entries(&self, filter: &EntryFilter) -> anyhow::Result<Vec<Entry>> {
+        self.read.filter_entries(filter).await
+    }
+
+    /// Count the entries within the given (inclusive) location
+    /// and creation time ranges, without fetching them.
+    /// The location range wraps if `dht_loc_start > dht_loc_end`.
+    pub async fn count_entries(
+        &self,
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+    ) -> anyhow::Result<u64> {
+        self.read
+            .count_entries(dht_loc_start, dht_loc_end, created_at_start, created_at_end)
+            .await
+    }
+
+    /// Total content bytes of the entries matching `filter`,
+    /// e.g. everything held within a location range.
+    pub async fn bytes_held(&self, filter: &EntryFilter) -> anyhow::Result<u64> {
+        self.read.bytes_held(filter).await
+    }
+
+    /// True if we hold the entry with `hash`.
+    pub async fn entry_exists(&self, hash: &EntryHash) -> anyhow::Result<bool> {
+        self.read.entry_exists(hash).await
+    }
+
+    /// Fetch the entry with `hash`.
+    pub async fn get_entry(&self, hash: &EntryHash) -> anyhow::Result<Option<Entry>> {
+        self.read.get_entry(hash).await
+    }
+
+    /// Fetch every entry we hold out of `hashes`, in no particular order.
+    pub async fn get_entries(&self, hashes: &[EntryHash]) -> anyhow::Result<Vec<Entry>> {
+        self.read.get_entries(hashes).await
+    }
+
+    /// Fetch a header and the entry it creates, if any.
+    pub async fn get_element(&self, header_hash: &HeaderHash) -> anyhow::Result<Option<Element>> {
+        self.read.get_element(header_hash).await
+    }
+}
+
+/// The single writer connection.
+/// SQLite only supports one writer at a time, so every write
+/// is funneled through here rather than contending for the lock.
+#[derive(Clone)]
+pub struct DbWrite {
+    pub(crate) pool: SqlitePool,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) explain: Explainer,
+    pub(crate) permits: Permits,
+    /// Announces each [`DbWrite::commit_and_notify`].
+    pub(crate) commits: Arc<watch::Sender<CommitMarker>>,
+    pub(crate) checkpoints: Arc<std::sync::Mutex<CheckpointMetrics>>,
+    pub(crate) statements: StatementCache,
+    pub(crate) trace: Tracer,
+    pub(crate) changes: Changes,
+}
+
+impl DbWrite {
+    /// Wait for the write permit, held until the guard is dropped.
+    /// Every write takes it, so this shuts them out for as long as
+    /// it's held.
+    pub async fn permit(&self) -> anyhow::Result<PermitGuard> {
+        self.permits.acquire().await
+    }
+
+    /// Waits on the write permit so far.
+    pub fn permit_metrics(&self) -> PermitMetrics {
+        self.permits.metrics()
+    }
+
+    /// Insert a single entry in its own transaction.
+    pub async fn insert_entry(&self, entry: &Entry) -> anyhow::Result<WriteOutcome> {
+        let res = with_retry(
+            self.trace.op("insert_entry"),
+            &self.retry,
+            &self.permits,
+            || async {
+                let entry = entry.clone();
+                let statements = self.statements.clone();
+                let mut con = self.pool.acquire().await?;
+                con.transaction(move |tx| {
+                    Box::pin(async move {
+                        statements.record(tx, INSERT_ENTRY);
+                        sqlx::query(INSERT_ENTRY)
+                            .bind(&entry.hash)
+                            .bind(DhtLocation(entry.dht_loc()))
+                            .bind(entry.created_at)
+                            .bind(entry.entry_type)
+                            .bind(entry.size_bytes())
+                            .bind(entry.content)
+                            .execute(tx)
+                            .await
+                    })
+                })
+                .await
+            },
+        )
+        .await?;
+        Ok(WriteOutcome::inserted(res.rows_affected(), 1))
+    }
+
+    /// Insert an entry unless one with the same hash is already held,
+    /// in which case it's counted as ignored.
+    ///
+    /// Entries are content addressed and never change,
+    /// so there's nothing to update on a conflict.
+    pub async fn upsert_entry(&self, entry: &Entry) -> anyhow::Result<WriteOutcome> {
+        let sql = format!("{} ON CONFLICT (hash) DO NOTHING", INSERT_ENTRY);
+        let res = with_retry(
+            self.trace.op("upsert_entry"),
+            &self.retry,
+            &self.permits,
+            || async {
+                sqlx::query(&sql)
+                    .bind(&entry.hash)
+                    .bind(DhtLocation(entry.dht_loc()))
+                    .bind(entry.created_at)
+                    .bind(entry.entry_type)
+                    .bind(entry.size_bytes())
+                    .bind(&entry.content)
+                    .execute(&self.pool)
+                    .await
+            },
+        )
+        .await?;
+        Ok(WriteOutcome::inserted(res.rows_affected(), 1))
+    }
+
+    /// Insert many entries in a single transaction,
+    /// as few multi-row statements as the bound parameter limit allows.
+    /// Nothing is inserted if any of them fails.
+    pub async fn insert_entries(&self, entries: &[Entry]) -> anyhow::Result<WriteOutcome> {
+        let inserted = self.insert_entry_batch(entries, false).await?;
+        Ok(WriteOutcome::inserted(inserted, entries.len() as u64))
+    }
+
+    /// Insert `entries` in one transaction, skipping ones already held
+    /// if `skip_held`, returning how many were inserted.
+    pub(crate) async fn insert_entry_batch(
+        &self,
+        entries: &[Entry],
+        skip_held: bool,
+    ) -> anyhow::Result<u64> {
+        let on_conflict = if skip_held {
+            " ON CONFLICT (hash) DO NOTHING"
+        } else {
+            ""
+        };
+        let inserted = with_retry(
+            self.trace.op("insert_entry_batch"),
+            &self.retry,
+            &self.permits,
+            || async {
+                let entries = entries.to_vec();
+                let mut con = self.pool.acquire().await?;
+                con.transaction(move |tx| {
+                    Box::pin(async move {
+                        let mut inserted = 0;
+                        for chunk in entries.chunks(MAX_BOUND_PARAMS / 6) {
+                            let sql = format!(
+                                "INSERT INTO entries ({}) VALUES {}{};",
+                                ENTRY_COLUMNS,
+                                vec!["(?, ?, ?, ?, ?, ?)"; chunk.len()].join(", "),
+                                on_conflict
+                            );
+                            let mut query = sqlx::query(&sql);
+                            for entry in chunk {
+                                query = query
+                                    .bind(&entry.hash)
+                                    .bind(DhtLocation(entry.dht_loc()))
+                                    .bind(entry.created_at)
+                                    .bind(entry.entry_type)
+                                    .bind(entry.size_bytes())
+                                    .bind(&entry.content);
+                            }
+                            inserted += query.execute(&mut *tx).await?.rows_affected();
+                        }
+                        Ok(inserted)
+                    })
+                })
+                .await
+            },
+        )
+        .await?;
+        Ok(inserted)
+    }
+
+    /// Insert a single header in its own transaction.
+    /// Fails if the referenced entry isn't stored.
+    pub async fn insert_header(&self, header: &Header) -> anyhow::Result<WriteOutcome> {
+        let res = with_retry(
+            self.trace.op("insert_header"),
+            &self.retry,
+            &self.permits,
+            || async {
+                let header = header.clone();
+                let mut con = self.pool.acquire().await?;
+                con.transaction(move |tx| {
+                    Box::pin(async move {
+                        sqlx::query(INSERT_HEADER)
+                            .bind(header.hash)
+                            .bind(header.author)
+                            .bind(header.seq)
+                            .bind(header.prev_hash)
+                            .bind(header.entry_hash)
+                            .bind(header.header_type)
+                            .bind(header.timestamp)
+                            .execute(tx)
+                            .await
+                    })
+                })
+                .await
+            },
+        )
+        .await?;
+        Ok(WriteOutcome::inserted(res.rows_affected(), 1))
+    }
+
+    /// Delete an entry, and with it any headers creating it.
+    /// One that isn't held is counted as ignored.
+    pub async fn delete_entry(&self, hash: &EntryHash) -> anyhow::Result<WriteOutcome> {
+        let res = with_retry(
+            self.trace.op("delete_entry"),
+            &self.retry,
+            &self.permits,
+            || async {
+                sqlx::query!("DELETE FROM entries WHERE hash = ?1", hash)
+                    .execute(&self.pool)
+                    .await
+            },
+        )
+        .await?;
+        Ok(WriteOutcome::deleted(res.rows_affected(), 1))
+    }
+
+    /// Delete every entry matching `filter`.
+    ///
+    /// A single statement, so either all of them go or none do.
+    /// Headers creating them are removed by the foreign key cascade
+    /// but not counted.
+    pub async fn purge(&self, filter: &EntryFilter) -> anyhow::Result<WriteOutcome> {
+        let matching = filter.select("hash", None);
+        // a subquery, so a limit and order on the filter still apply
+        let sql = format!(
+            "DELETE FROM entries WHERE hash IN ({});",
+            matching.sql.trim_end_matches(';')
+        );
+        self.explain.check(&self.pool, &sql).await;
+        let res = with_retry(
+            self.trace.op("purge").statement(&sql, &[&matching]),
+            &self.retry,
+            &self.permits,
+            || async {
+                sqlx::query_with(&sql, matching.arguments())
+                    .execute(&self.pool)
+                    .await
+            },
+        )
+        .await?;
+        let deleted = res.rows_affected();
+        Ok(WriteOutcome::deleted(deleted, deleted))
+    }
+}
+
+/// Pool of read-only connections.
+#[derive(Clone)]
+pub struct DbRead {
+    pub(crate) pool: SqlitePool,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) explain: Explainer,
+    pub(crate) permits: Permits,
+    pub(crate) commits: watch::Receiver<CommitMarker>,
+    pub(crate) statements: StatementCache,
+    pub(crate) trace: Tracer,
+    pub(crate) row_counts: RowCounts,
+}
+
+impl DbRead {
+    /// Wait for one of the read permits, held until the guard is
+    /// dropped.
+    pub async fn permit(&self) -> anyhow::Result<PermitGuard> {
+        self.permits.acquire().await
+    }
+
+    /// Waits on the read permits so far.
+    pub fn permit_metrics(&self) -> PermitMetrics {
+        self.permits.metrics()
+    }
+
+    /// Fetch all entries within the given (inclusive) location
+    /// and creation time ranges.
+    /// The location range wraps if `dht_loc_start > dht_loc_end`.
+    pub async fn query_entries(
+        &self,
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+    ) -> anyhow::Result<Vec<Entry>> {
+        self.filter_entries(
+            &EntryFilter::new()
+                .loc_range(dht_loc_start, dht_loc_end)
+                .time_range(created_at_start, created_at_end),
+        )
+        .await
+    }
+
+    /// Fetch all entries matching `filter`.
+    pub async fn filter_entries(&self, filter: &EntryFilter) -> anyhow::Result<Vec<Entry>> {
+        let query = filter.select(ENTRY_COLUMNS, None);
+        self.read(move |reader| {
+            let query = query.clone();
+            Box::pin(async move { reader.fetch_entries(&query).await })
+        })
+        .await
+    }
+
+    /// Count the entries within the given (inclusive) location
+    /// and creation time ranges, without fetching them.
+    /// The location range wraps if `dht_loc_start > dht_loc_end`.
+    pub async fn count_entries(
+        &self,
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+    ) -> anyhow::Result<u64> {
+        let filter = EntryFilter::new()
+            .loc_range(dht_loc_start, dht_loc_end)
+            .time_range(created_at_start, created_at_end);
+        self.read(move |reader| {
+            let filter = filter.clone();
+            Box::pin(async move { reader.count_matching(&filter).await })
+        })
+        .await
+    }
+
+    /// Total content bytes of the entries matching `filter`,
+    /// e.g. everything held within a location range.
+    ///
+    /// Summed from the stored sizes, so no content is read.
+    pub async fn bytes_held(&self, filter: &EntryFilter) -> anyhow::Result<u64> {
+        // SUM over no rows is NULL
+        let query = filter.select("COALESCE(SUM(size_bytes), 0)", None);
+        self.explain.check(&self.pool, &query.sql).await;
+        let (bytes,): (i64,) = with_retry(
+            self.trace.op("bytes_held").statement(&query.sql, &[&query]),
+            &self.retry,
+            &self.permits,
+            || async {
+                sqlx::query_as_with(&query.sql, query.arguments())
+                    .fetch_one(&self.pool)
+                    .await
+            },
+        )
+        .await?;
+        Ok(bytes as u64)
+    }
+
+    /// True if we hold the entry with `hash`.
+    pub async fn entry_exists(&self, hash: &EntryHash) -> anyhow::Result<bool> {
+        let query = || sqlx::query!("SELECT 1 AS found FROM entries WHERE hash = ?1;", hash);
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let found = with_retry(
+            self.trace.op("entry_exists").statement(sql, &[&hash]),
+            &self.retry,
+            &self.permits,
+            || async { query().fetch_optional(&self.pool).await },
+        )
+        .await?;
+        Ok(found.is_some())
+    }
+
+    /// Fetch the entry with `hash`.
+    pub async fn get_entry(&self, hash: &EntryHash) -> anyhow::Result<Option<Entry>> {
+        let query = || {
+            sqlx::query_as!(
+                Entry,
+                r#"SELECT
+                    hash AS "hash!: EntryHash",
+                    created_at AS "created_at: Timestamp",
+                    entry_type AS "entry_type: EntryType",
+                    content
+                FROM entries WHERE hash = ?1;"#,
+                hash
+            )
+        };
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let out = with_retry(
+            self.trace.op("get_entry").statement(sql, &[&hash]),
+            &self.retry,
+            &self.permits,
+            || async {
+                let mut con = self.pool.acquire().await?;
+                self.statements.record(&mut con, query().sql());
+                query().fetch_optional(&mut con).await
+            },
+        )
+        .await?;
+        Ok(out)
+    }
+
+    /// Fetch every entry we hold out of `hashes`, in no particular order.
+    ///
+    /// The hashes are loaded into a temp table and joined against,
+    /// so however long the list it costs a handful of statements
+    /// rather than one query per hash.
+    pub async fn get_entries(&self, hashes: &[EntryHash]) -> anyhow::Result<Vec<Entry>> {
+        let wanted = HashList::new(hashes.iter().map(|hash| hash.get_raw_39()));
+        let out = with_retry(
+            self.trace.op("get_entries"),
+            &self.retry,
+            &self.permits,
+            || async {
+                let mut con = self.pool.acquire().await?;
+                let wanted = wanted.clone();
+                let explain = self.explain.clone();
+                // rolling back on error takes the temp table with it
+                con.transaction(move |tx| {
+                    Box::pin(async move {
+                        wanted.create_temp_table(tx, "wanted_hashes").await?;
+                        let sql = "SELECT entries.*
+                        FROM wanted_hashes
+                        JOIN entries ON entries.hash = wanted_hashes.hash
+                        ;";
+                        // only explainable once the temp table exists
+                        explain.check_on(&mut *tx, sql).await;
+                        let out = sqlx::query_as::<_, Entry>(sql).fetch_all(&mut *tx).await?;
+                        tx.execute("DROP TABLE wanted_hashes;").await?;
+                        Ok(out)
+                    })
+                })
+                .await
+            },
+        )
+        .await?;
+        Ok(out)
+    }
+
+    /// Fetch a header and the entry it creates, if any.
+    /// The entry is `None` if the header has none or it isn't held.
+    pub async fn get_element(&self, header_hash: &HeaderHash) -> anyhow::Result<Option<Element>> {
+        let sql = format!("{} WHERE headers.hash = ?1;", SELECT_ELEMENTS);
+        self.explain.check(&self.pool, &sql).await;
+        let out = with_retry(
+            self.trace
+                .op("get_element")
+                .statement(&sql, &[&header_hash]),
+            &self.retry,
+            &self.permits,
+            || async {
+                let mut con = self.pool.acquire().await?;
+                sqlx::query_as::<_, Element>(&sql)
+                    .bind(header_hash)
+                    .fetch_optional(&mut con)
+                    .await
+            },
+        )
+        .await?;
+        Ok(out)
+    }
+}
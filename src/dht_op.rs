@@ -0,0 +1,478 @@
+use crate::db::arc_condition;
+use crate::hash_list::HashList;
+use crate::retry::with_retry;
+use crate::{Db, DbRead, DbWrite, DhtLocation, OpHash, WriteOutcome};
+use chrono::prelude::*;
+use futures::TryStreamExt;
+use rand::Rng;
+use sqlx::{Connection, Execute};
+
+/// Which kind of DHT operation an op is.
+/// Stored as the variant name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, sqlx::Type)]
+#[sqlx(type_name = "text")]
+pub enum DhtOpType {
+    StoreElement,
+    StoreEntry,
+    RegisterAgentActivity,
+    RegisterUpdatedContent,
+    RegisterUpdatedElement,
+    RegisterDeletedBy,
+    RegisterDeletedEntryHeader,
+    RegisterAddLink,
+    RegisterRemoveLink,
+}
+
+/// Outcome of validating an op.
+/// Stored as the discriminant, so never renumber these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, sqlx::Type)]
+#[repr(u8)]
+pub enum ValidationStatus {
+    /// Not validated yet.
+    Pending = 0,
+    Valid = 1,
+    Rejected = 2,
+    /// Validation gave up, e.g. dependencies never arrived.
+    Abandoned = 3,
+}
+
+/// Demo DHT operation type for database.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DhtOp {
+    pub op_hash: OpHash,
+    pub op_type: DhtOpType,
+    /// DHT location of the op's basis hash.
+    pub basis_loc: u32,
+    pub authored_timestamp: DateTime<Utc>,
+    /// When the integration workflow processed the op, if it has.
+    pub when_integrated: Option<DateTime<Utc>>,
+    pub validation_status: ValidationStatus,
+    /// The op that has to be integrated before this one can be.
+    pub dependency: Option<OpHash>,
+}
+
+impl DhtOp {
+    /// Generate a random, not yet validated `StoreEntry` op.
+    pub fn rand() -> Self {
+        Self {
+            op_hash: OpHash::rand(),
+            op_type: DhtOpType::StoreEntry,
+            basis_loc: rand::thread_rng().gen(),
+            authored_timestamp: Utc::now(),
+            when_integrated: None,
+            validation_status: ValidationStatus::Pending,
+            dependency: None,
+        }
+    }
+}
+
+impl Db {
+    /// Insert a single op, merging into the stored one if it's already held.
+    pub async fn insert_op(&self, op: &DhtOp) -> anyhow::Result<WriteOutcome> {
+        self.write.insert_op(op).await
+    }
+
+    /// Record the validation outcome of an op and mark it integrated.
+    pub async fn integrate_op(
+        &self,
+        op_hash: &OpHash,
+        status: ValidationStatus,
+    ) -> anyhow::Result<()> {
+        self.write.integrate_op(op_hash, status).await
+    }
+
+    /// Record the validation outcome of an op without integrating it.
+    pub async fn set_validation_status(
+        &self,
+        op_hash: &OpHash,
+        status: ValidationStatus,
+    ) -> anyhow::Result<()> {
+        self.write.set_validation_status(op_hash, status).await
+    }
+
+    /// Record the same validation outcome for a batch of ops,
+    /// returning how many were held.
+    pub async fn set_validation_statuses(
+        &self,
+        op_hashes: &[OpHash],
+        status: ValidationStatus,
+    ) -> anyhow::Result<u64> {
+        self.write.set_validation_statuses(op_hashes, status).await
+    }
+
+    /// Delete an op and its receipts.
+    pub async fn delete_op(&self, op_hash: &OpHash) -> anyhow::Result<WriteOutcome> {
+        self.write.delete_op(op_hash).await
+    }
+
+    /// Fetch a single op by hash.
+    pub async fn get_op(&self, op_hash: &OpHash) -> anyhow::Result<Option<DhtOp>> {
+        self.reader().get_op(op_hash).await
+    }
+
+    /// Fetch all integrated ops within the given (inclusive) basis
+    /// location and authored time ranges, only of `op_type` if given.
+    /// The location range wraps if `basis_loc_start > basis_loc_end`.
+    pub async fn query_ops(
+        &self,
+        op_type: Option<DhtOpType>,
+        basis_loc_start: u32,
+        basis_loc_end: u32,
+        authored_start: DateTime<Utc>,
+        authored_end: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<DhtOp>> {
+        self.reader()
+            .query_ops(
+                op_type,
+                basis_loc_start,
+                basis_loc_end,
+                authored_start,
+                authored_end,
+            )
+            .await
+    }
+
+    /// Fetch every op the integration workflow has yet to process.
+    pub async fn ops_pending_integration(&self) -> anyhow::Result<Vec<DhtOp>> {
+        self.reader().ops_pending_integration().await
+    }
+
+    /// Fetch every op still waiting on app validation, oldest first.
+    pub async fn ops_awaiting_validation(&self) -> anyhow::Result<Vec<DhtOp>> {
+        self.reader().ops_awaiting_validation().await
+    }
+
+    /// Fetch every op with validation outcome `status`, oldest first.
+    pub async fn ops_with_status(&self, status: ValidationStatus) -> anyhow::Result<Vec<DhtOp>> {
+        self.reader().ops_with_status(status).await
+    }
+}
+
+/// Keeps the stored validation outcome if the new copy is still pending,
+/// and leaves the row alone entirely if that changes nothing.
+pub(crate) const INSERT_OP: &str = "INSERT INTO dht_ops
+    (op_hash, op_type, basis_loc, authored_timestamp,
+        when_integrated, validation_status, dependency)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+    ON CONFLICT (op_hash) DO UPDATE SET
+        when_integrated =
+            COALESCE(excluded.when_integrated, when_integrated),
+        validation_status =
+            COALESCE(NULLIF(excluded.validation_status, 0), validation_status)
+    WHERE COALESCE(excluded.when_integrated, when_integrated)
+            IS NOT when_integrated
+        OR COALESCE(NULLIF(excluded.validation_status, 0), validation_status)
+            IS NOT validation_status";
+
+impl DbWrite {
+    /// Insert a single op.
+    ///
+    /// Gossip delivers the same op more than once, so an op that's
+    /// already held isn't an error. Its immutable columns are left as they
+    /// are, and the validation outcome is only overwritten if this copy
+    /// isn't pending.
+    pub async fn insert_op(&self, op: &DhtOp) -> anyhow::Result<WriteOutcome> {
+        let outcome = with_retry(
+            self.trace.op("insert_op"),
+            &self.retry,
+            &self.permits,
+            || async {
+                let op = op.clone();
+                let mut con = self.pool.acquire().await?;
+                // a copy merged into the held op counts one row, the same
+                // as a new op, so tell them apart by looking it up first
+                con.transaction(move |tx| {
+                    Box::pin(async move {
+                        let existed = sqlx::query!(
+                            "SELECT 1 AS found FROM dht_ops WHERE op_hash = ?1",
+                            op.op_hash
+                        )
+                        .fetch_optional(&mut *tx)
+                        .await?
+                        .is_some();
+                        let res = sqlx::query(INSERT_OP)
+                            .bind(op.op_hash)
+                            .bind(op.op_type)
+                            .bind(DhtLocation(op.basis_loc))
+                            .bind(op.authored_timestamp)
+                            .bind(op.when_integrated)
+                            .bind(op.validation_status)
+                            .bind(op.dependency)
+                            .execute(&mut *tx)
+                            .await?;
+                        Ok(WriteOutcome::upserted(existed, res.rows_affected()))
+                    })
+                })
+                .await
+            },
+        )
+        .await?;
+        Ok(outcome)
+    }
+
+    /// Record the validation outcome of an op and mark it integrated now.
+    /// Fails if the op isn't stored.
+    pub async fn integrate_op(
+        &self,
+        op_hash: &OpHash,
+        status: ValidationStatus,
+    ) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let query = || {
+            sqlx::query!(
+                "UPDATE dht_ops SET validation_status = ?2, when_integrated = ?3
+                WHERE op_hash = ?1",
+                op_hash,
+                status,
+                now
+            )
+        };
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let res = with_retry(
+            self.trace
+                .op("integrate_op")
+                .statement(sql, &[&op_hash, &status, &now]),
+            &self.retry,
+            &self.permits,
+            || async { query().execute(&self.pool).await },
+        )
+        .await?;
+        if res.rows_affected() == 0 {
+            anyhow::bail!("no such op");
+        }
+        Ok(())
+    }
+
+    /// Record the validation outcome of an op, leaving it for
+    /// the integration workflow to pick up.
+    /// Fails if the op isn't stored.
+    pub async fn set_validation_status(
+        &self,
+        op_hash: &OpHash,
+        status: ValidationStatus,
+    ) -> anyhow::Result<()> {
+        let query = || {
+            sqlx::query!(
+                "UPDATE dht_ops SET validation_status = ?2 WHERE op_hash = ?1",
+                op_hash,
+                status
+            )
+        };
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let res = with_retry(
+            self.trace
+                .op("set_validation_status")
+                .statement(sql, &[&op_hash, &status]),
+            &self.retry,
+            &self.permits,
+            || async { query().execute(&self.pool).await },
+        )
+        .await?;
+        if res.rows_affected() == 0 {
+            anyhow::bail!("no such op");
+        }
+        Ok(())
+    }
+
+    /// Record the same validation outcome for a batch of ops in one
+    /// statement, returning how many were held.
+    /// Hashes that aren't stored are skipped rather than failing the batch.
+    pub async fn set_validation_statuses(
+        &self,
+        op_hashes: &[OpHash],
+        status: ValidationStatus,
+    ) -> anyhow::Result<u64> {
+        let op_hashes = HashList::new(op_hashes.iter().map(|hash| hash.get_raw_39()));
+        let sql = format!(
+            "UPDATE dht_ops SET validation_status = ?1 WHERE op_hash IN ({});",
+            HashList::select(2)
+        );
+        self.explain.check(&self.pool, &sql).await;
+        let updated = with_retry(
+            self.trace
+                .op("set_validation_statuses")
+                .statement(&sql, &[&status, &op_hashes.json()]),
+            &self.retry,
+            &self.permits,
+            || async {
+                sqlx::query(&sql)
+                    .bind(status)
+                    .bind(op_hashes.json())
+                    .execute(&self.pool)
+                    .await
+            },
+        )
+        .await?
+        .rows_affected();
+        Ok(updated)
+    }
+
+    /// Delete an op, along with its validation receipts.
+    pub async fn delete_op(&self, op_hash: &OpHash) -> anyhow::Result<WriteOutcome> {
+        let res = with_retry(
+            self.trace.op("delete_op"),
+            &self.retry,
+            &self.permits,
+            || async {
+                sqlx::query!("DELETE FROM dht_ops WHERE op_hash = ?1", op_hash)
+                    .execute(&self.pool)
+                    .await
+            },
+        )
+        .await?;
+        Ok(WriteOutcome::deleted(res.rows_affected(), 1))
+    }
+}
+
+impl DbRead {
+    /// Fetch a single op by hash.
+    pub async fn get_op(&self, op_hash: &OpHash) -> anyhow::Result<Option<DhtOp>> {
+        let query = || {
+            sqlx::query_as!(
+                DhtOp,
+                r#"SELECT
+                    op_hash AS "op_hash!: OpHash",
+                    op_type AS "op_type: DhtOpType",
+                    basis_loc AS "basis_loc: u32",
+                    authored_timestamp AS "authored_timestamp: DateTime<Utc>",
+                    when_integrated AS "when_integrated: DateTime<Utc>",
+                    validation_status AS "validation_status!: ValidationStatus",
+                    dependency AS "dependency: OpHash"
+                FROM dht_ops WHERE op_hash = ?1;"#,
+                op_hash
+            )
+        };
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let out = with_retry(
+            self.trace.op("get_op").statement(sql, &[&op_hash]),
+            &self.retry,
+            &self.permits,
+            || async { query().fetch_optional(&self.pool).await },
+        )
+        .await?;
+        Ok(out)
+    }
+
+    /// Fetch all integrated ops within the given (inclusive) basis
+    /// location and authored time ranges, only of `op_type` if given.
+    /// The location range wraps if `basis_loc_start > basis_loc_end`.
+    pub async fn query_ops(
+        &self,
+        op_type: Option<DhtOpType>,
+        basis_loc_start: u32,
+        basis_loc_end: u32,
+        authored_start: DateTime<Utc>,
+        authored_end: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<DhtOp>> {
+        // gossip only ever offers what we've integrated
+        let sql = format!(
+            "SELECT * FROM dht_ops
+            WHERE {}{}
+            AND authored_timestamp >= ?
+            AND authored_timestamp <= ?
+            AND when_integrated IS NOT NULL
+            ;",
+            if op_type.is_some() {
+                "op_type = ? AND "
+            } else {
+                ""
+            },
+            arc_condition("basis_loc", basis_loc_start, basis_loc_end)
+        );
+        self.explain.check(&self.pool, &sql).await;
+        let out = with_retry(
+            self.trace.op("query_ops").statement(
+                &sql,
+                &[
+                    &op_type,
+                    &DhtLocation(basis_loc_start),
+                    &DhtLocation(basis_loc_end),
+                    &authored_start,
+                    &authored_end,
+                ],
+            ),
+            &self.retry,
+            &self.permits,
+            || async {
+                let mut query = sqlx::query_as::<_, DhtOp>(&sql);
+                if let Some(op_type) = op_type {
+                    query = query.bind(op_type);
+                }
+                query
+                    .bind(DhtLocation(basis_loc_start))
+                    .bind(DhtLocation(basis_loc_end))
+                    .bind(authored_start)
+                    .bind(authored_end)
+                    .fetch(&self.pool)
+                    .try_collect::<Vec<_>>()
+                    .await
+            },
+        )
+        .await?;
+        Ok(out)
+    }
+
+    /// Fetch every op the integration workflow has yet to process.
+    pub async fn ops_pending_integration(&self) -> anyhow::Result<Vec<DhtOp>> {
+        let query = || {
+            sqlx::query_as!(
+                DhtOp,
+                r#"SELECT
+                    op_hash AS "op_hash!: OpHash",
+                    op_type AS "op_type: DhtOpType",
+                    basis_loc AS "basis_loc: u32",
+                    authored_timestamp AS "authored_timestamp: DateTime<Utc>",
+                    when_integrated AS "when_integrated: DateTime<Utc>",
+                    validation_status AS "validation_status!: ValidationStatus",
+                    dependency AS "dependency: OpHash"
+                FROM dht_ops WHERE when_integrated IS NULL;"#
+            )
+        };
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let out = with_retry(
+            self.trace.op("ops_pending_integration").statement(sql, &[]),
+            &self.retry,
+            &self.permits,
+            || async { query().fetch(&self.pool).try_collect::<Vec<_>>().await },
+        )
+        .await?;
+        Ok(out)
+    }
+
+    /// Fetch every op that hasn't been validated yet, oldest first.
+    pub async fn ops_awaiting_validation(&self) -> anyhow::Result<Vec<DhtOp>> {
+        self.ops_with_status(ValidationStatus::Pending).await
+    }
+
+    /// Fetch every op with validation outcome `status`, oldest first.
+    ///
+    /// Only pending ops are indexed, any other status reads every op.
+    pub async fn ops_with_status(&self, status: ValidationStatus) -> anyhow::Result<Vec<DhtOp>> {
+        // spliced rather than bound, sqlite only uses the partial
+        // index when it can see the query matches its WHERE
+        let sql = format!(
+            "SELECT * FROM dht_ops
+            WHERE validation_status = {}
+            ORDER BY authored_timestamp;",
+            status as u8
+        );
+        self.explain.check(&self.pool, &sql).await;
+        let out = with_retry(
+            self.trace.op("ops_with_status").statement(&sql, &[]),
+            &self.retry,
+            &self.permits,
+            || async {
+                sqlx::query_as::<_, DhtOp>(&sql)
+                    .fetch(&self.pool)
+                    .try_collect::<Vec<_>>()
+                    .await
+            },
+        )
+        .await?;
+        Ok(out)
+    }
+}
@@ -0,0 +1,335 @@
+//! Compact binary dumps of every data table, for shipping fixtures and
+//! bug reports.
+//!
+//! A dump is [`MAGIC`], then length-prefixed MessagePack records: first a
+//! [`DumpInfo`] header with the schema version and each table's columns
+//! and row count, then every row of those tables in order, each an array
+//! of its column values. A length is a little-endian `u32`.
+
+use crate::backend::bind_values;
+use crate::migrations::applied;
+use crate::{Db, DbRead, DbWrite, Value, WriteOutcome};
+use futures::TryStreamExt;
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use sqlx::Row;
+use std::convert::TryFrom;
+use std::path::Path;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
+
+/// The first bytes of every dump.
+const MAGIC: &[u8; 8] = b"SPKDUMP\0";
+
+/// Bumped whenever the layout of a dump changes.
+const FORMAT_VERSION: u32 = 1;
+
+/// The tables a dump holds, parents before the tables whose foreign
+/// keys point at them. `commit_seq` and `snapshots` describe the live
+/// database rather than its data, so they're left out.
+const DUMP_TABLES: &[&str] = &[
+    "entries",
+    "headers",
+    "dht_ops",
+    "validation_receipts",
+    "links",
+    "agent_store",
+    "cap_grants",
+    "cap_grant_functions",
+    "cap_grant_assignees",
+    "cap_claims",
+];
+
+/// The header of a dump, as [`Db::dump`] wrote it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpInfo {
+    pub format_version: u32,
+    /// The latest migration applied to the dumped database.
+    pub schema_version: i64,
+    pub tables: Vec<DumpTable>,
+}
+
+/// One table of a dump.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpTable {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub rows: u64,
+}
+
+/// Written as the matching MessagePack type.
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Null => s.serialize_none(),
+            Self::Integer(i) => s.serialize_i64(*i),
+            Self::Real(r) => s.serialize_f64(*r),
+            Self::Text(t) => s.serialize_str(t),
+            Self::Blob(b) => s.serialize_bytes(b),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct ValueVisitor;
+
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = Value;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("nil, an integer, a float, a string or bytes")
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+                Ok(Value::Null)
+            }
+
+            fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+                Ok(Value::Null)
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+                Ok(Value::Integer(v))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+                i64::try_from(v)
+                    .map(Value::Integer)
+                    .map_err(|_| E::custom("integer out of range"))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+                Ok(Value::Real(v))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+                Ok(Value::Text(v.to_string()))
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Value, E> {
+                Ok(Value::Blob(v.to_vec()))
+            }
+        }
+
+        d.deserialize_any(ValueVisitor)
+    }
+}
+
+async fn write_record<W, T>(writer: &mut W, record: &T) -> anyhow::Result<()>
+where
+    W: AsyncWriteExt + Unpin,
+    T: Serialize,
+{
+    let bytes = rmp_serde::to_vec_named(record)?;
+    writer
+        .write_all(&(bytes.len() as u32).to_le_bytes())
+        .await?;
+    writer.write_all(&bytes).await?;
+    Ok(())
+}
+
+/// Reads the records of a dump out of its bytes.
+struct Records<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Records<'a> {
+    fn next<T: de::DeserializeOwned>(&mut self) -> anyhow::Result<T> {
+        if self.bytes.len() < 4 {
+            anyhow::bail!("dump is truncated");
+        }
+        let (len, rest) = self.bytes.split_at(4);
+        let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        if rest.len() < len {
+            anyhow::bail!("dump is truncated");
+        }
+        let (record, rest) = rest.split_at(len);
+        self.bytes = rest;
+        Ok(rmp_serde::from_slice(record)?)
+    }
+}
+
+impl Db {
+    /// Write every data table to a new file at `path`, see [`DbRead::dump`].
+    pub async fn dump<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<DumpInfo> {
+        self.reader().dump(path).await
+    }
+
+    /// Load a dump written by [`Db::dump`], see [`DbWrite::load`].
+    pub async fn load<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<WriteOutcome> {
+        self.write.load(path).await
+    }
+}
+
+impl DbRead {
+    /// Write every data table to a new file at `path`, unencrypted,
+    /// returning its header. `path` must not exist yet.
+    ///
+    /// Rows are written in rowid order from one read transaction,
+    /// so the same data always dumps to the same bytes.
+    pub async fn dump<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<DumpInfo> {
+        let _permit = self.permits.acquire().await?;
+        let mut tx = self.pool.begin().await?;
+
+        let mut info = DumpInfo {
+            format_version: FORMAT_VERSION,
+            schema_version: applied(&mut tx).await?.map_or(0, |(_, version)| version),
+            tables: Vec::with_capacity(DUMP_TABLES.len()),
+        };
+        for table in DUMP_TABLES {
+            let columns: Vec<String> =
+                sqlx::query_scalar("SELECT name FROM pragma_table_info(?1) ORDER BY cid;")
+                    .bind(table)
+                    .fetch_all(&mut tx)
+                    .await?;
+            let rows: i64 = sqlx::query_scalar(&format!("SELECT count(*) FROM {};", table))
+                .fetch_one(&mut tx)
+                .await?;
+            info.tables.push(DumpTable {
+                name: table.to_string(),
+                columns,
+                rows: rows as u64,
+            });
+        }
+
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path.as_ref())
+            .await?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(MAGIC).await?;
+        write_record(&mut writer, &info).await?;
+        for table in &info.tables {
+            let select = table
+                .columns
+                .iter()
+                .map(|c| format!("typeof(\"{0}\"), \"{0}\"", c))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let sql = format!("SELECT {} FROM {} ORDER BY rowid;", select, table.name);
+            let mut rows = sqlx::query(&sql).fetch(&mut tx);
+            while let Some(row) = rows.try_next().await? {
+                let mut values = Vec::with_capacity(table.columns.len());
+                for i in 0..table.columns.len() {
+                    let at = 2 * i + 1;
+                    values.push(match row.try_get::<String, _>(at - 1)?.as_str() {
+                        "integer" => Value::Integer(row.try_get_unchecked(at)?),
+                        "real" => Value::Real(row.try_get_unchecked(at)?),
+                        "text" => Value::Text(row.try_get_unchecked(at)?),
+                        "blob" => Value::Blob(row.try_get_unchecked(at)?),
+                        _ => Value::Null,
+                    });
+                }
+                write_record(&mut writer, &values).await?;
+            }
+        }
+        writer.flush().await?;
+        writer.into_inner().sync_all().await?;
+        Ok(info)
+    }
+}
+
+impl DbWrite {
+    /// Load a dump written by [`DbRead::dump`] in one transaction.
+    ///
+    /// The dump must be at this database's schema version. Rows already
+    /// held are ignored, so loading the same dump twice is harmless.
+    /// The whole file is checked before anything is written, and a dump
+    /// that's truncated or doesn't match its header loads nothing.
+    pub async fn load<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<WriteOutcome> {
+        let mut bytes = Vec::new();
+        tokio::fs::File::open(path.as_ref())
+            .await?
+            .read_to_end(&mut bytes)
+            .await?;
+        if !bytes.starts_with(MAGIC) {
+            anyhow::bail!("not a dump");
+        }
+        let mut records = Records {
+            bytes: &bytes[MAGIC.len()..],
+        };
+        let info: DumpInfo = records.next()?;
+        if info.format_version != FORMAT_VERSION {
+            anyhow::bail!(
+                "dump is in format version {}, this build reads {}",
+                info.format_version,
+                FORMAT_VERSION
+            );
+        }
+
+        let mut con = self.pool.acquire().await?;
+        let current = applied(&mut con).await?.map_or(0, |(_, version)| version);
+        if info.schema_version != current {
+            anyhow::bail!(
+                "dump is at schema version {} but the database is at {}",
+                info.schema_version,
+                current
+            );
+        }
+        let mut tables = Vec::with_capacity(info.tables.len());
+        for table in info.tables {
+            // names are spliced into sql, so only ever tables we'd dump
+            if !DUMP_TABLES.contains(&table.name.as_str()) {
+                anyhow::bail!("dump has a table {} that can't be loaded", table.name);
+            }
+            let columns: Vec<String> =
+                sqlx::query_scalar("SELECT name FROM pragma_table_info(?1) ORDER BY cid;")
+                    .bind(&table.name)
+                    .fetch_all(&mut con)
+                    .await?;
+            if columns != table.columns {
+                anyhow::bail!("dump's columns of {} don't match the database", table.name);
+            }
+            let mut rows = Vec::with_capacity(table.rows as usize);
+            for n in 0..table.rows {
+                let row: Vec<Value> = records
+                    .next()
+                    .map_err(|err| anyhow::anyhow!("{} row {}: {}", table.name, n + 1, err))?;
+                if row.len() != columns.len() {
+                    anyhow::bail!(
+                        "{} row {} has {} values for {} columns",
+                        table.name,
+                        n + 1,
+                        row.len(),
+                        columns.len()
+                    );
+                }
+                rows.push(row);
+            }
+            tables.push((table, rows));
+        }
+        if !records.bytes.is_empty() {
+            anyhow::bail!("dump has more rows than its header says");
+        }
+        drop(con);
+
+        self.write(move |writer| {
+            Box::pin(async move {
+                let mut outcome = WriteOutcome::default();
+                for (table, rows) in &tables {
+                    let sql = format!(
+                        "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT DO NOTHING;",
+                        table.name,
+                        table
+                            .columns
+                            .iter()
+                            .map(|c| format!("\"{}\"", c))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        vec!["?"; table.columns.len()].join(", ")
+                    );
+                    for row in rows {
+                        let affected = bind_values(sqlx::query(&sql), row)
+                            .execute(&mut writer.tx)
+                            .await?
+                            .rows_affected();
+                        outcome += WriteOutcome::inserted(affected, 1);
+                    }
+                }
+                Ok(outcome)
+            })
+        })
+        .await
+    }
+}
@@ -0,0 +1,131 @@
+use crate::retry::with_retry;
+use crate::{AgentPubKey, Db, DbRead, Entry, Header, HeaderHash, Timestamp};
+use futures::TryStreamExt;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Execute, Row};
+
+/// Headers joined with their entries, in the shape [`Element`] reads.
+/// Append a `WHERE` on `headers` columns.
+pub(crate) const SELECT_ELEMENTS: &str = "SELECT headers.*,
+    entries.created_at AS entry_created_at,
+    entries.entry_type AS entry_entry_type,
+    entries.content AS entry_content
+FROM headers
+LEFT JOIN entries ON entries.hash = headers.entry_hash";
+
+/// A header together with the entry it creates, if any.
+/// This is the unit Holochain actually stores and serves.
+#[derive(Debug, Clone)]
+pub struct Element {
+    pub header: Header,
+    /// `None` for headers without an entry, or whose entry isn't held.
+    pub entry: Option<Entry>,
+}
+
+/// Rows from `headers LEFT JOIN entries`, with the entry columns
+/// prefixed `entry_` - the hash is the header's `entry_hash`.
+impl<'r> sqlx::FromRow<'r, SqliteRow> for Element {
+    fn from_row(row: &'r SqliteRow) -> sqlx::Result<Self> {
+        let header = Header::from_row(row)?;
+        // NULL when the join found nothing
+        let created_at: Option<Timestamp> = row.try_get("entry_created_at")?;
+        let entry = match (&header.entry_hash, created_at) {
+            (Some(hash), Some(created_at)) => Some(Entry {
+                hash: hash.clone(),
+                created_at,
+                entry_type: row.try_get("entry_entry_type")?,
+                content: row.try_get("entry_content")?,
+            }),
+            _ => None,
+        };
+        Ok(Self { header, entry })
+    }
+}
+
+impl Db {
+    /// Fetch `author`'s elements with chain positions within the given
+    /// (inclusive) range, in chain order.
+    pub async fn query_by_author(
+        &self,
+        author: &AgentPubKey,
+        seq_start: u32,
+        seq_end: u32,
+    ) -> anyhow::Result<Vec<Element>> {
+        self.reader()
+            .query_by_author(author, seq_start, seq_end)
+            .await
+    }
+
+    /// The seq and hash of the latest header on `author`'s chain,
+    /// or `None` if nothing of theirs is held.
+    pub async fn chain_head(
+        &self,
+        author: &AgentPubKey,
+    ) -> anyhow::Result<Option<(u32, HeaderHash)>> {
+        self.reader().chain_head(author).await
+    }
+}
+
+impl DbRead {
+    /// Fetch `author`'s elements with chain positions within the given
+    /// (inclusive) range, in chain order.
+    pub async fn query_by_author(
+        &self,
+        author: &AgentPubKey,
+        seq_start: u32,
+        seq_end: u32,
+    ) -> anyhow::Result<Vec<Element>> {
+        // served straight off headers_author_seq_idx, already in order
+        let sql = format!(
+            "{} WHERE headers.author = ?1 AND headers.seq >= ?2 AND headers.seq <= ?3
+            ORDER BY headers.seq;",
+            SELECT_ELEMENTS
+        );
+        self.explain.check(&self.pool, &sql).await;
+        let out = with_retry(
+            self.trace
+                .op("query_by_author")
+                .statement(&sql, &[&author, &seq_start, &seq_end]),
+            &self.retry,
+            &self.permits,
+            || async {
+                sqlx::query_as::<_, Element>(&sql)
+                    .bind(author)
+                    .bind(seq_start)
+                    .bind(seq_end)
+                    .fetch(&self.pool)
+                    .try_collect::<Vec<_>>()
+                    .await
+            },
+        )
+        .await?;
+        Ok(out)
+    }
+
+    /// The seq and hash of the latest header on `author`'s chain,
+    /// or `None` if nothing of theirs is held.
+    pub async fn chain_head(
+        &self,
+        author: &AgentPubKey,
+    ) -> anyhow::Result<Option<(u32, HeaderHash)>> {
+        // the last key in headers_author_seq_idx for this author
+        let query = || {
+            sqlx::query!(
+                r#"SELECT seq AS "seq: u32", hash AS "hash!: HeaderHash" FROM headers
+                WHERE author = ?1
+                ORDER BY seq DESC LIMIT 1;"#,
+                author
+            )
+        };
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let head = with_retry(
+            self.trace.op("chain_head").statement(sql, &[&author]),
+            &self.retry,
+            &self.permits,
+            || async { query().fetch_optional(&self.pool).await },
+        )
+        .await?;
+        Ok(head.map(|head| (head.seq, head.hash)))
+    }
+}
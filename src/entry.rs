@@ -0,0 +1,157 @@
+use crate::{Db, EntryHash, Timestamp};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, Sqlite, Type};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+/// What an entry holds.
+///
+/// Stored as the variant name, with an app entry's indices appended
+/// as `App:<zome_index>:<entry_def_index>`, so one column can be
+/// matched for an exact type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntryType {
+    /// An agent's public key.
+    Agent,
+    /// Defined by an app zome.
+    App {
+        zome_index: u8,
+        entry_def_index: u8,
+    },
+    CapGrant,
+    CapClaim,
+}
+
+impl fmt::Display for EntryType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Agent => write!(f, "Agent"),
+            Self::App {
+                zome_index,
+                entry_def_index,
+            } => write!(f, "App:{}:{}", zome_index, entry_def_index),
+            Self::CapGrant => write!(f, "CapGrant"),
+            Self::CapClaim => write!(f, "CapClaim"),
+        }
+    }
+}
+
+impl FromStr for EntryType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "Agent" => Ok(Self::Agent),
+            "CapGrant" => Ok(Self::CapGrant),
+            "CapClaim" => Ok(Self::CapClaim),
+            _ => match s.split(':').collect::<Vec<_>>().as_slice() {
+                ["App", zome_index, entry_def_index] => Ok(Self::App {
+                    zome_index: zome_index.parse()?,
+                    entry_def_index: entry_def_index.parse()?,
+                }),
+                _ => anyhow::bail!("unknown entry type {:?}", s),
+            },
+        }
+    }
+}
+
+impl Type<Sqlite> for EntryType {
+    fn type_info() -> SqliteTypeInfo {
+        <str as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <str as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for EntryType {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        args.push(SqliteArgumentValue::Text(Cow::Owned(self.to_string())));
+        IsNull::No
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for EntryType {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        Ok(<&str as Decode<Sqlite>>::decode(value)?.parse()?)
+    }
+}
+
+/// Demo entry type for database.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Entry {
+    pub hash: EntryHash,
+    pub created_at: Timestamp,
+    pub entry_type: EntryType,
+    /// The app entry, MessagePack encoded.
+    pub content: Vec<u8>,
+}
+
+impl Entry {
+    /// Generate a random entry, typed as the first app entry def.
+    pub fn rand() -> Self {
+        Self {
+            hash: EntryHash::rand(),
+            created_at: Timestamp::now(),
+            entry_type: EntryType::App {
+                zome_index: 0,
+                entry_def_index: 0,
+            },
+            content: Vec::new(),
+        }
+    }
+
+    /// An entry holding `content`, addressed by its blake2b hash
+    /// and typed as the first app entry def.
+    pub fn from_content(content: Vec<u8>) -> Self {
+        Self {
+            hash: EntryHash::with_data(&content),
+            created_at: Timestamp::now(),
+            entry_type: EntryType::App {
+                zome_index: 0,
+                entry_def_index: 0,
+            },
+            content,
+        }
+    }
+
+    /// Where on the DHT the entry lives, which is also what's stored
+    /// in the `dht_loc` column.
+    pub fn dht_loc(&self) -> u32 {
+        self.hash.get_loc()
+    }
+
+    /// Length of the content, which is what's stored in the
+    /// `size_bytes` column. sqlite caps blobs well under 4GiB.
+    pub fn size_bytes(&self) -> u32 {
+        self.content.len() as u32
+    }
+}
+
+impl Db {
+    /// Store `value` MessagePack encoded as an entry,
+    /// returning the entry so its hash can be looked up later.
+    /// Storing the same value again is a no-op.
+    pub async fn put_typed<T: Serialize>(&self, value: &T) -> anyhow::Result<Entry> {
+        let entry = Entry::from_content(rmp_serde::to_vec_named(value)?);
+        self.upsert_entry(&entry).await?;
+        Ok(entry)
+    }
+
+    /// Fetch the entry with `hash` and decode its content as a `T`.
+    pub async fn get_typed<T: DeserializeOwned>(
+        &self,
+        hash: &EntryHash,
+    ) -> anyhow::Result<Option<T>> {
+        match self.get_entry(hash).await? {
+            Some(entry) => Ok(Some(rmp_serde::from_slice(&entry.content)?)),
+            None => Ok(None),
+        }
+    }
+}
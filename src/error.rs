@@ -0,0 +1,75 @@
+/// Errors callers may want to tell apart.
+/// Returned inside `anyhow::Error`, use `downcast_ref::<DbError>()`.
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    /// The database exists but the key doesn't decrypt it.
+    #[error("wrong encryption key for database")]
+    BadEncryptionKey,
+    /// The file is damaged, or not a sqlite database at all.
+    #[error("database file is corrupt or not a database: {0}")]
+    Corrupt(sqlx::Error),
+    /// The tables / indexes don't match what this version expects,
+    /// e.g. the file was created by an incompatible build.
+    #[error("database schema mismatch: {0}")]
+    SchemaMismatch(String),
+    /// No read or write permit came free within
+    /// [`crate::DbConfig::permit_timeout`], the database is overloaded.
+    #[error("timed out after {1:?} waiting for a {0} permit")]
+    PermitTimeout(&'static str, std::time::Duration),
+    /// [`crate::DbRead::read_after`] gave up on getting a snapshot that
+    /// includes the commit it was waiting for.
+    #[error("read snapshot still at commit {1} after waiting for commit {0}")]
+    StaleSnapshot(u64, u64),
+}
+
+/// SQLITE_CORRUPT
+const SQLITE_CORRUPT: i32 = 11;
+/// SQLITE_NOTADB
+const SQLITE_NOTADB: i32 = 26;
+
+/// Primary sqlite result code of `err`, if it came from the database.
+pub(crate) fn sqlite_code(err: &sqlx::Error) -> Option<i32> {
+    match err {
+        sqlx::Error::Database(err) => err
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            // extended result codes keep the primary code in the low byte
+            .map(|code| code & 0xff),
+        _ => None,
+    }
+}
+
+/// How a key that fails its first read while opening is reported, see
+/// [`crate::open_key`].
+pub(crate) const WRONG_KEY: &str = "wrong encryption key for database";
+
+/// True if the connection failed to open because its key was wrong.
+pub(crate) fn is_bad_key(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(err) => err.message().contains(WRONG_KEY),
+        _ => false,
+    }
+}
+
+/// True if sqlite couldn't make sense of the file contents.
+pub(crate) fn is_not_a_database(err: &sqlx::Error) -> bool {
+    matches!(sqlite_code(err), Some(SQLITE_NOTADB) | Some(SQLITE_CORRUPT))
+}
+
+/// Smuggle a [`DbError`] out through sqlx, e.g. from `after_connect`.
+pub(crate) fn into_sqlx(err: DbError) -> sqlx::Error {
+    sqlx::Error::Configuration(Box::new(err))
+}
+
+/// Recover errors raised while opening into their [`DbError`] form.
+pub(crate) fn from_open_error(err: sqlx::Error) -> anyhow::Error {
+    match err {
+        sqlx::Error::Configuration(inner) if inner.is::<DbError>() => {
+            (*inner.downcast::<DbError>().unwrap()).into()
+        }
+        err if is_bad_key(&err) => DbError::BadEncryptionKey.into(),
+        // the key read the file fine, or there is none, so it's the file
+        err if is_not_a_database(&err) => DbError::Corrupt(err).into(),
+        err => err.into(),
+    }
+}
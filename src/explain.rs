@@ -0,0 +1,119 @@
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::{Executor, Row, SqliteConnection};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// The plan sqlite picked for one of our statements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExplainedQuery {
+    pub sql: String,
+    /// `EXPLAIN QUERY PLAN` detail lines, outermost first.
+    pub plan: Vec<String>,
+    /// True if some step walks a whole table instead of an index.
+    pub full_scan: bool,
+}
+
+/// Runs `EXPLAIN QUERY PLAN` the first time each statement is used,
+/// when enabled with [`crate::DbConfig::explain_queries`].
+/// Clones share what has been explained so far.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Explainer(Option<Arc<Mutex<HashMap<String, ExplainedQuery>>>>);
+
+/// `SCAN TABLE x` without `USING ... INDEX` reads every row.
+fn is_full_scan(detail: &str) -> bool {
+    detail.starts_with("SCAN ")
+        && !detail.contains(" INDEX ")
+        && !detail.starts_with("SCAN CONSTANT")
+        && !detail.starts_with("SCAN SUBQUERY")
+}
+
+/// The `EXPLAIN QUERY PLAN` detail lines for `sql` on `con`,
+/// placeholders left unbound.
+pub(crate) async fn query_plan(con: &mut SqliteConnection, sql: &str) -> sqlx::Result<Vec<String>> {
+    let plan: sqlx::Result<Vec<String>> = sqlx::query(&format!("EXPLAIN QUERY PLAN {}", sql))
+        .persistent(false)
+        .try_map(|row: SqliteRow| row.try_get("detail"))
+        .fetch_all(&mut *con)
+        .await;
+    // sqlx keeps the last uncached statement around until the next one
+    // replaces it, and until then it holds shared cache table locks,
+    // so swap it for one that touches no tables
+    let evicted = con.execute("SELECT 1;").await;
+    match (plan, evicted) {
+        (Ok(plan), Ok(_)) => Ok(plan),
+        (Err(err), _) | (_, Err(err)) => Err(err),
+    }
+}
+
+impl Explainer {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self(if enabled {
+            Some(Arc::new(Mutex::new(HashMap::new())))
+        } else {
+            None
+        })
+    }
+
+    /// Explain `sql` on a connection from `pool` unless already done,
+    /// logging the plan.
+    pub(crate) async fn check(&self, pool: &SqlitePool, sql: &str) {
+        if !self.wants(sql) {
+            return;
+        }
+        match pool.acquire().await {
+            Ok(mut con) => self.check_on(&mut con, sql).await,
+            Err(err) => tracing::warn!(sql, ?err, "explain query plan failed"),
+        }
+    }
+
+    /// Explain `sql` on `con` unless already done, logging the plan.
+    /// Needed for statements that only make sense on that connection,
+    /// e.g. ones reading its temp tables.
+    ///
+    /// Placeholders are left unbound, sqlite plans without their values.
+    /// Failing to explain is logged rather than failing the real query.
+    pub(crate) async fn check_on(&self, con: &mut SqliteConnection, sql: &str) {
+        let seen = match &self.0 {
+            Some(seen) if self.wants(sql) => seen,
+            _ => return,
+        };
+
+        let plan = match query_plan(con, sql).await {
+            Ok(plan) => plan,
+            Err(err) => {
+                tracing::warn!(sql, ?err, "explain query plan failed");
+                return;
+            }
+        };
+        let full_scan = plan.iter().any(|detail| is_full_scan(detail));
+        if full_scan {
+            tracing::warn!(sql, plan = %plan.join("; "), "full table scan");
+        } else {
+            tracing::info!(sql, plan = %plan.join("; "), "query plan");
+        }
+
+        seen.lock().unwrap().insert(
+            sql.to_string(),
+            ExplainedQuery {
+                sql: sql.to_string(),
+                plan,
+                full_scan,
+            },
+        );
+    }
+
+    fn wants(&self, sql: &str) -> bool {
+        match &self.0 {
+            Some(seen) => !seen.lock().unwrap().contains_key(sql),
+            None => false,
+        }
+    }
+
+    /// Every statement explained so far.
+    pub(crate) fn explained(&self) -> Vec<ExplainedQuery> {
+        match &self.0 {
+            Some(seen) => seen.lock().unwrap().values().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+}
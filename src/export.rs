@@ -0,0 +1,86 @@
+use crate::db::{connect_options, exec_secret, init_connection, is_sqlcipher};
+use crate::{Db, DbConfig, Encryption};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Executor};
+use std::path::Path;
+
+/// Quote `path` as a sql string literal.
+pub(crate) fn quote_path(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "''"))
+}
+
+impl Db {
+    /// Write an unencrypted copy of the database to `path`,
+    /// for inspection with standard sqlite tooling.
+    /// `path` must not exist yet.
+    pub async fn export_plaintext<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let _permit = self.write.permits.acquire().await?;
+        let mut con = self.write.pool.acquire().await?;
+
+        if !is_sqlcipher(&mut con).await? || self.key.read().unwrap().is_none() {
+            // nothing to decrypt, a plain copy will do
+            sqlx::query("VACUUM INTO ?1;")
+                .bind(&*path.to_string_lossy())
+                .execute(&mut con)
+                .await?;
+            return Ok(());
+        }
+
+        sqlx::query("ATTACH DATABASE ?1 AS plaintext KEY '';")
+            .bind(&*path.to_string_lossy())
+            .execute(&mut con)
+            .await?;
+        let res = con.execute("SELECT sqlcipher_export('plaintext');").await;
+        con.execute("DETACH DATABASE plaintext;").await?;
+        res?;
+        Ok(())
+    }
+
+    /// Create an encrypted database at `path` from the unencrypted
+    /// database at `plaintext`, then open it with `config`.
+    /// `path` must not exist yet.
+    pub async fn import_plaintext<P: AsRef<Path>, Q: AsRef<Path>>(
+        plaintext: P,
+        path: Q,
+        config: DbConfig,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let plain_config = config.clone().encryption(Encryption::None);
+        let mut con = connect_options(
+            SqliteConnectOptions::new().filename(plaintext.as_ref()),
+            &plain_config,
+        )
+        .connect()
+        .await?;
+        init_connection(&mut con, &plain_config, None).await?;
+
+        let key = match &config.encryption {
+            Some(Encryption::SqlCipher(source)) => Some(source.db_key(None).await?),
+            _ => None,
+        };
+
+        match key {
+            Some(key) if is_sqlcipher(&mut con).await? => {
+                let cmd = key.statement(
+                    &format!("ATTACH DATABASE {} AS encrypted KEY ", quote_path(path)),
+                    ";",
+                );
+                exec_secret(&mut con, "ATTACH DATABASE", &cmd)?;
+                let res = con.execute("SELECT sqlcipher_export('encrypted');").await;
+                con.execute("DETACH DATABASE encrypted;").await?;
+                res?;
+            }
+            _ => {
+                // stock sqlite can't encrypt, copy as-is
+                sqlx::query("VACUUM INTO ?1;")
+                    .bind(&*path.to_string_lossy())
+                    .execute(&mut con)
+                    .await?;
+            }
+        }
+        drop(con);
+
+        Self::open_with(path, config).await
+    }
+}
@@ -0,0 +1,194 @@
+use crate::db::arc_condition;
+use crate::{AgentPubKey, Cursor, DhtLocation, EntryType, Timestamp};
+use sqlx::sqlite::SqliteArguments;
+use sqlx::Arguments;
+
+/// Order to return entries in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryOrder {
+    /// By location, then creation time, then hash.
+    DhtLoc,
+    /// Oldest first, ties broken by hash.
+    CreatedAt,
+    /// Newest first, ties broken by hash.
+    CreatedAtDesc,
+}
+
+impl EntryOrder {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Self::DhtLoc => "dht_loc, created_at, hash",
+            Self::CreatedAt => "created_at, hash",
+            Self::CreatedAtDesc => "created_at DESC, hash DESC",
+        }
+    }
+}
+
+/// Which entries a query covers.
+/// Every condition is optional, an empty filter matches everything.
+///
+/// ```no_run
+/// # use spike_sqlx::*;
+/// # use chrono::prelude::*;
+/// let filter = EntryFilter::new()
+///     .loc_range(0, u32::MAX / 2)
+///     .time_range(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0).into(), Timestamp::now())
+///     .order(EntryOrder::CreatedAt)
+///     .limit(100);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryFilter {
+    pub(crate) loc_range: Option<(u32, u32)>,
+    pub(crate) time_range: Option<(Timestamp, Timestamp)>,
+    pub(crate) author: Option<AgentPubKey>,
+    pub(crate) entry_type: Option<EntryType>,
+    pub(crate) limit: Option<u32>,
+    pub(crate) order: Option<EntryOrder>,
+}
+
+/// A value to bind, in placeholder order.
+#[derive(Debug, Clone)]
+enum Param {
+    U32(u32),
+    Loc(DhtLocation),
+    Time(Timestamp),
+    Blob(Vec<u8>),
+    Type(EntryType),
+}
+
+/// Parameterized SQL compiled from an [`EntryFilter`].
+#[derive(Debug, Clone)]
+pub(crate) struct FilterSql {
+    pub(crate) sql: String,
+    params: Vec<Param>,
+}
+
+impl FilterSql {
+    /// Fresh arguments for one execution of [`FilterSql::sql`].
+    pub(crate) fn arguments(&self) -> SqliteArguments<'static> {
+        let mut args = SqliteArguments::default();
+        for param in &self.params {
+            match param {
+                Param::U32(v) => args.add(*v),
+                Param::Loc(v) => args.add(*v),
+                Param::Time(v) => args.add(*v),
+                Param::Blob(v) => args.add(v.clone()),
+                Param::Type(v) => args.add(*v),
+            }
+        }
+        args
+    }
+
+    /// The same arguments as driver-neutral values.
+    pub(crate) fn values(&self) -> Vec<crate::Value> {
+        use crate::Value;
+        self.params
+            .iter()
+            .map(|param| match param {
+                Param::U32(v) => Value::Integer(i64::from(*v)),
+                Param::Loc(v) => Value::Integer(i64::from(v.0)),
+                Param::Time(v) => Value::Integer(v.0),
+                Param::Blob(v) => Value::Blob(v.clone()),
+                Param::Type(v) => Value::Text(v.to_string()),
+            })
+            .collect()
+    }
+}
+
+impl EntryFilter {
+    /// Match every entry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only entries within this (inclusive) location range,
+    /// wrapping if `start > end`.
+    pub fn loc_range(mut self, start: u32, end: u32) -> Self {
+        self.loc_range = Some((start, end));
+        self
+    }
+
+    /// Only entries created within this (inclusive) time range.
+    pub fn time_range(mut self, start: Timestamp, end: Timestamp) -> Self {
+        self.time_range = Some((start, end));
+        self
+    }
+
+    /// Only entries a header by `author` creates.
+    pub fn author(mut self, author: AgentPubKey) -> Self {
+        self.author = Some(author);
+        self
+    }
+
+    /// Only entries of this type.
+    pub fn entry_type(mut self, entry_type: EntryType) -> Self {
+        self.entry_type = Some(entry_type);
+        self
+    }
+
+    /// Return at most `limit` entries.
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Return entries in this order, unspecified otherwise.
+    pub fn order(mut self, order: EntryOrder) -> Self {
+        self.order = Some(order);
+        self
+    }
+
+    /// `SELECT <columns> FROM entries` restricted by this filter,
+    /// and to rows after `after` in [`EntryOrder::DhtLoc`] order.
+    ///
+    /// Every value is bound, never spliced into the sql.
+    pub(crate) fn select(&self, columns: &str, after: Option<&Cursor>) -> FilterSql {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        if let Some((start, end)) = self.loc_range {
+            conditions.push(arc_condition("dht_loc", start, end));
+            params.push(Param::Loc(DhtLocation(start)));
+            params.push(Param::Loc(DhtLocation(end)));
+        }
+        if let Some((start, end)) = self.time_range {
+            conditions.push("created_at >= ? AND created_at <= ?".to_string());
+            params.push(Param::Time(start));
+            params.push(Param::Time(end));
+        }
+        if let Some(author) = &self.author {
+            // EXISTS rather than a join, an entry can have several headers
+            conditions.push(
+                "EXISTS (SELECT 1 FROM headers
+                    WHERE headers.entry_hash = entries.hash AND headers.author = ?)"
+                    .to_string(),
+            );
+            params.push(Param::Blob(author.get_raw_39().to_vec()));
+        }
+        if let Some(entry_type) = self.entry_type {
+            conditions.push("entry_type = ?".to_string());
+            params.push(Param::Type(entry_type));
+        }
+        if let Some(after) = after {
+            conditions.push("(dht_loc, created_at, hash) > (?, ?, ?)".to_string());
+            params.push(Param::Loc(DhtLocation(after.dht_loc)));
+            params.push(Param::Time(after.created_at));
+            params.push(Param::Blob(after.hash.get_raw_39().to_vec()));
+        }
+
+        let mut sql = format!("SELECT {} FROM entries", columns);
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        if let Some(order) = self.order {
+            sql.push_str(" ORDER BY ");
+            sql.push_str(order.as_sql());
+        }
+        if let Some(limit) = self.limit {
+            sql.push_str(" LIMIT ?");
+            params.push(Param::U32(limit));
+        }
+        sql.push(';');
+        FilterSql { sql, params }
+    }
+}
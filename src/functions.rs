@@ -0,0 +1,416 @@
+//! Custom sql functions, registered on every connection: our own, and
+//! any the application adds with [`crate::DbConfig::function`].
+
+use crate::hash::loc_bytes;
+use crate::{DbConfig, Value};
+use libsqlite3_sys::{
+    sqlite3, sqlite3_aggregate_context, sqlite3_context, sqlite3_create_function_v2,
+    sqlite3_result_blob, sqlite3_result_double, sqlite3_result_error, sqlite3_result_error_nomem,
+    sqlite3_result_int64, sqlite3_result_null, sqlite3_result_text, sqlite3_user_data,
+    sqlite3_value, sqlite3_value_blob, sqlite3_value_bytes, sqlite3_value_double,
+    sqlite3_value_int64, sqlite3_value_text, sqlite3_value_type, SQLITE_BLOB, SQLITE_DETERMINISTIC,
+    SQLITE_FLOAT, SQLITE_INTEGER, SQLITE_NULL, SQLITE_OK, SQLITE_TEXT, SQLITE_TRANSIENT,
+    SQLITE_UTF8,
+};
+use sqlx::SqliteConnection;
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::os::raw::{c_int, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+
+type AppFn = Arc<dyn Fn(&[Value]) -> anyhow::Result<Value> + Send + Sync>;
+
+/// Names of functions only we register, per connection.
+const RESERVED_PREFIX: &str = "spike_sqlx_";
+
+/// An application-defined scalar sql function, registered on every
+/// connection by [`crate::DbConfig::function`].
+///
+/// ```no_run
+/// # use spike_sqlx::*;
+/// let double = SqlFunction::new("double_it", 1, |args| match &args[0] {
+///     Value::Integer(i) => Ok(Value::Integer(i * 2)),
+///     _ => Ok(Value::Null),
+/// })
+/// .deterministic();
+/// let config = DbConfig::new().function(double);
+/// ```
+#[derive(Clone)]
+pub struct SqlFunction {
+    name: String,
+    args: usize,
+    deterministic: bool,
+    func: AppFn,
+}
+
+impl SqlFunction {
+    /// A function called `name` taking `args` arguments, computed by `func`.
+    /// An `Err` from `func` fails the statement calling it.
+    pub fn new<F>(name: &str, args: usize, func: F) -> Self
+    where
+        F: Fn(&[Value]) -> anyhow::Result<Value> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            args,
+            deterministic: false,
+            func: Arc::new(func),
+        }
+    }
+
+    /// Promise the same arguments always give the same result,
+    /// so sqlite may use the function in indexes and factor calls out.
+    pub fn deterministic(mut self) -> Self {
+        self.deterministic = true;
+        self
+    }
+}
+
+impl std::fmt::Debug for SqlFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqlFunction")
+            .field("name", &self.name)
+            .field("args", &self.args)
+            .field("deterministic", &self.deterministic)
+            .finish()
+    }
+}
+
+/// `xor_agg(blob)`: bytewise XOR of every non-NULL blob in the group.
+/// Shorter blobs are treated as zero padded, so the result is as long
+/// as the longest input, or empty if there were none.
+unsafe extern "C" fn xor_agg_step(
+    ctx: *mut sqlite3_context,
+    _argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    let value = *argv;
+    if sqlite3_value_type(value) == SQLITE_NULL {
+        return;
+    }
+    // zeroed by sqlite on first use, freed by it after xor_agg_final
+    let acc = sqlite3_aggregate_context(ctx, std::mem::size_of::<*mut Vec<u8>>() as c_int)
+        as *mut *mut Vec<u8>;
+    if acc.is_null() {
+        sqlite3_result_error_nomem(ctx);
+        return;
+    }
+    if (*acc).is_null() {
+        *acc = Box::into_raw(Box::new(Vec::new()));
+    }
+    let acc = &mut **acc;
+
+    let len = sqlite3_value_bytes(value) as usize;
+    let ptr = sqlite3_value_blob(value) as *const u8;
+    if len == 0 || ptr.is_null() {
+        return;
+    }
+    let bytes = std::slice::from_raw_parts(ptr, len);
+    if acc.len() < len {
+        acc.resize(len, 0);
+    }
+    for (a, b) in acc.iter_mut().zip(bytes) {
+        *a ^= b;
+    }
+}
+
+unsafe extern "C" fn xor_agg_final(ctx: *mut sqlite3_context) {
+    // passing 0 doesn't allocate, NULL means step never ran
+    let acc = sqlite3_aggregate_context(ctx, 0) as *mut *mut Vec<u8>;
+    let out = if acc.is_null() || (*acc).is_null() {
+        Vec::new()
+    } else {
+        *Box::from_raw(*acc)
+    };
+    sqlite3_result_blob(
+        ctx,
+        out.as_ptr() as *const _,
+        out.len() as c_int,
+        SQLITE_TRANSIENT(),
+    );
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// `unhex(text)`: the blob spelled out by a string of hex digits,
+/// NULL if it isn't one. Lets a list of blobs travel as JSON text,
+/// see [`crate::hash_list::HashList`].
+unsafe extern "C" fn unhex(ctx: *mut sqlite3_context, _argc: c_int, argv: *mut *mut sqlite3_value) {
+    let value = *argv;
+    // text first, bytes after, so the length is of the text form
+    let ptr = sqlite3_value_text(value);
+    let len = sqlite3_value_bytes(value) as usize;
+    if ptr.is_null() || !len.is_multiple_of(2) {
+        sqlite3_result_null(ctx);
+        return;
+    }
+    let text = std::slice::from_raw_parts(ptr, len);
+    let out: Option<Vec<u8>> = text
+        .chunks(2)
+        .map(|pair| Some((hex_digit(pair[0])? << 4) | hex_digit(pair[1])?))
+        .collect();
+    match out {
+        Some(out) => sqlite3_result_blob(
+            ctx,
+            out.as_ptr() as *const _,
+            out.len() as c_int,
+            SQLITE_TRANSIENT(),
+        ),
+        None => sqlite3_result_null(ctx),
+    }
+}
+
+type ScalarFn = unsafe extern "C" fn(*mut sqlite3_context, c_int, *mut *mut sqlite3_value);
+type FinalFn = unsafe extern "C" fn(*mut sqlite3_context);
+
+/// Register a one argument function, scalar if `step` is `None`.
+///
+/// # Safety
+///
+/// `handle` must be an open connection.
+unsafe fn create_function(
+    handle: *mut sqlite3,
+    name: &[u8],
+    func: Option<ScalarFn>,
+    step: Option<ScalarFn>,
+    last: Option<FinalFn>,
+) -> sqlx::Result<()> {
+    let rc = sqlite3_create_function_v2(
+        handle,
+        name.as_ptr() as *const _,
+        1,
+        SQLITE_UTF8 | SQLITE_DETERMINISTIC,
+        std::ptr::null_mut(),
+        func,
+        step,
+        last,
+        None,
+    );
+    if rc != SQLITE_OK {
+        let name = String::from_utf8_lossy(&name[..name.len() - 1]);
+        return Err(sqlx::Error::Protocol(format!(
+            "registering {} failed ({})",
+            name, rc
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) unsafe fn read_value(value: *mut sqlite3_value) -> Value {
+    match sqlite3_value_type(value) {
+        SQLITE_INTEGER => Value::Integer(sqlite3_value_int64(value)),
+        SQLITE_FLOAT => Value::Real(sqlite3_value_double(value)),
+        SQLITE_TEXT => {
+            // text first, bytes after, so the length is of the text form
+            let ptr = sqlite3_value_text(value);
+            let len = sqlite3_value_bytes(value) as usize;
+            if ptr.is_null() {
+                return Value::Text(String::new());
+            }
+            let text = std::slice::from_raw_parts(ptr, len);
+            Value::Text(String::from_utf8_lossy(text).into_owned())
+        }
+        SQLITE_BLOB => {
+            let len = sqlite3_value_bytes(value) as usize;
+            let ptr = sqlite3_value_blob(value) as *const u8;
+            if len == 0 || ptr.is_null() {
+                return Value::Blob(Vec::new());
+            }
+            Value::Blob(std::slice::from_raw_parts(ptr, len).to_vec())
+        }
+        _ => Value::Null,
+    }
+}
+
+unsafe fn set_result(ctx: *mut sqlite3_context, value: Value) {
+    match value {
+        Value::Null => sqlite3_result_null(ctx),
+        Value::Integer(i) => sqlite3_result_int64(ctx, i),
+        Value::Real(r) => sqlite3_result_double(ctx, r),
+        Value::Text(text) => sqlite3_result_text(
+            ctx,
+            text.as_ptr() as *const _,
+            text.len() as c_int,
+            SQLITE_TRANSIENT(),
+        ),
+        Value::Blob(bytes) => sqlite3_result_blob(
+            ctx,
+            bytes.as_ptr() as *const _,
+            bytes.len() as c_int,
+            SQLITE_TRANSIENT(),
+        ),
+    }
+}
+
+unsafe fn set_error(ctx: *mut sqlite3_context, message: &str) {
+    sqlite3_result_error(ctx, message.as_ptr() as *const _, message.len() as c_int);
+}
+
+/// Calls the [`AppFn`] registered as the function's user data.
+/// A panic is caught and reported as an error, it mustn't unwind
+/// into sqlite.
+unsafe extern "C" fn call_app_fn(
+    ctx: *mut sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    let func = &*(sqlite3_user_data(ctx) as *const AppFn);
+    let args: Vec<Value> = (0..argc as usize)
+        .map(|i| read_value(*argv.add(i)))
+        .collect();
+    match catch_unwind(AssertUnwindSafe(|| func(&args))) {
+        Ok(Ok(value)) => set_result(ctx, value),
+        Ok(Err(err)) => set_error(ctx, &err.to_string()),
+        Err(_) => set_error(ctx, "sql function panicked"),
+    }
+}
+
+/// Frees the [`AppFn`] once sqlite is done with the function,
+/// when it's replaced or the connection closes.
+unsafe extern "C" fn drop_app_fn(func: *mut c_void) {
+    drop(Box::from_raw(func as *mut AppFn));
+}
+
+/// Register `function` on the connection behind `handle`.
+///
+/// # Safety
+///
+/// `handle` must be an open connection.
+unsafe fn create_app_function(handle: *mut sqlite3, function: &SqlFunction) -> sqlx::Result<()> {
+    let name = crate::index::check_identifier(&function.name)
+        .ok()
+        .and_then(|_| CString::new(function.name.as_str()).ok())
+        .ok_or_else(|| {
+            sqlx::Error::Configuration(
+                format!("invalid sql function name {:?}", function.name).into(),
+            )
+        })?;
+    // replacing spike_sqlx_written would free what the writer's commit
+    // hooks still use, see `crate::changes::track_changes`
+    if function
+        .name
+        .to_ascii_lowercase()
+        .starts_with(RESERVED_PREFIX)
+    {
+        return Err(sqlx::Error::Configuration(
+            format!("sql function name {} is reserved", function.name).into(),
+        ));
+    }
+    let args = c_int::try_from(function.args)
+        .ok()
+        .filter(|args| *args <= 127)
+        .ok_or_else(|| {
+            sqlx::Error::Configuration(
+                format!("sql function {} takes too many arguments", function.name).into(),
+            )
+        })?;
+    let mut flags = SQLITE_UTF8;
+    if function.deterministic {
+        flags |= SQLITE_DETERMINISTIC;
+    }
+    let func = Box::into_raw(Box::new(function.func.clone()));
+    // sqlite calls drop_app_fn even when registering fails
+    let rc = sqlite3_create_function_v2(
+        handle,
+        name.as_ptr(),
+        args,
+        flags,
+        func as *mut c_void,
+        Some(call_app_fn),
+        None,
+        None,
+        Some(drop_app_fn),
+    );
+    if rc != SQLITE_OK {
+        return Err(sqlx::Error::Protocol(format!(
+            "registering {} failed ({})",
+            function.name, rc
+        )));
+    }
+    Ok(())
+}
+
+/// `holo_dht_loc(hash)`: where on the DHT a holo-hash lives. Takes the
+/// stored 39 bytes, or just the 32 hash bytes and works the location out.
+fn holo_dht_loc(args: &[Value]) -> anyhow::Result<Value> {
+    let loc = match args[0].as_bytes() {
+        Some(hash) if hash.len() == crate::HOLO_HASH_LEN => {
+            let mut loc = [0; 4];
+            loc.copy_from_slice(&hash[35..]);
+            u32::from_le_bytes(loc)
+        }
+        Some(hash) if hash.len() == 32 => u32::from_le_bytes(loc_bytes(hash)),
+        _ => return Ok(Value::Null),
+    };
+    Ok(Value::Integer(i64::from(loc)))
+}
+
+/// `blake2b(blob)`: the 32 byte blake2b hash of a blob, or of text as UTF-8,
+/// the hash an entry's content is addressed by.
+fn blake2b(args: &[Value]) -> anyhow::Result<Value> {
+    Ok(match args[0].as_bytes() {
+        Some(data) => Value::Blob(
+            blake2b_simd::Params::new()
+                .hash_length(32)
+                .hash(data)
+                .as_bytes()
+                .to_vec(),
+        ),
+        None => Value::Null,
+    })
+}
+
+/// `arc_contains(loc, start, end)`: 1 if `loc` is on the arc from
+/// `start` to `end` inclusive, which wraps past `u32::MAX` when the
+/// end is before the start, as an agent's storage arc does.
+fn arc_contains(args: &[Value]) -> anyhow::Result<Value> {
+    let (loc, start, end) = match (args[0].as_i64(), args[1].as_i64(), args[2].as_i64()) {
+        (Some(loc), Some(start), Some(end)) => (loc, start, end),
+        _ => return Ok(Value::Null),
+    };
+    let contains = if start <= end {
+        loc >= start && loc <= end
+    } else {
+        loc >= start || loc <= end
+    };
+    Ok(Value::Integer(contains as i64))
+}
+
+/// Register our functions on `con`, then the application's from `config`.
+pub(crate) fn register_functions(
+    con: &mut SqliteConnection,
+    config: &DbConfig,
+) -> sqlx::Result<()> {
+    let handle = con.as_raw_handle();
+    // safety: the handle is live for the duration of `con`, and the
+    // callbacks only touch memory sqlite hands them
+    unsafe {
+        create_function(
+            handle,
+            b"xor_agg\0",
+            None,
+            Some(xor_agg_step),
+            Some(xor_agg_final),
+        )?;
+        create_function(handle, b"unhex\0", Some(unhex), None, None)?;
+        for function in [
+            SqlFunction::new("holo_dht_loc", 1, holo_dht_loc).deterministic(),
+            SqlFunction::new("blake2b", 1, blake2b).deterministic(),
+            SqlFunction::new("arc_contains", 3, arc_contains).deterministic(),
+        ]
+        .iter()
+        .chain(&config.functions)
+        {
+            create_app_function(handle, function)?;
+        }
+    }
+    Ok(())
+}
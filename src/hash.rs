@@ -0,0 +1,153 @@
+//! Typed holo-hashes, so one kind can't be passed where another is expected.
+
+use rand::Rng;
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, Sqlite, Type};
+use std::borrow::Cow;
+use std::fmt;
+
+/// Bytes in a holo-hash: 3 type prefix, 32 hash, 4 location.
+pub const HOLO_HASH_LEN: usize = 39;
+
+/// The 4 location bytes for a 32 byte hash: a 16 byte blake2b of it,
+/// xor-folded down. As holo_hash does, so locations agree with the
+/// rest of the network.
+pub(crate) fn loc_bytes(raw_32: &[u8]) -> [u8; 4] {
+    let hash = blake2b_simd::Params::new().hash_length(16).hash(raw_32);
+    let mut out = [0; 4];
+    for (i, b) in hash.as_bytes().iter().enumerate() {
+        out[i % 4] ^= b;
+    }
+    out
+}
+
+macro_rules! holo_hash {
+    ($(#[$doc:meta])* $name:ident, $prefix:expr) => {
+        $(#[$doc])*
+        #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name([u8; HOLO_HASH_LEN]);
+
+        impl $name {
+            /// The 3 bytes every hash of this kind starts with.
+            pub const PREFIX: [u8; 3] = $prefix;
+
+            /// Wrap a 32 byte hash, adding the prefix and location.
+            pub fn from_raw_32(raw_32: [u8; 32]) -> Self {
+                let mut bytes = [0; HOLO_HASH_LEN];
+                bytes[..3].copy_from_slice(&Self::PREFIX);
+                bytes[3..35].copy_from_slice(&raw_32);
+                bytes[35..].copy_from_slice(&loc_bytes(&raw_32));
+                Self(bytes)
+            }
+
+            /// Check the length and prefix of a full 39 byte hash.
+            pub fn from_raw_39(bytes: &[u8]) -> anyhow::Result<Self> {
+                if bytes.len() != HOLO_HASH_LEN {
+                    anyhow::bail!(
+                        "{} must be {} bytes, got {}",
+                        stringify!($name),
+                        HOLO_HASH_LEN,
+                        bytes.len()
+                    );
+                }
+                if bytes[..3] != Self::PREFIX {
+                    anyhow::bail!("not a {}, prefix {:02x?}", stringify!($name), &bytes[..3]);
+                }
+                let mut out = [0; HOLO_HASH_LEN];
+                out.copy_from_slice(bytes);
+                Ok(Self(out))
+            }
+
+            /// The blake2b-256 hash of `data`.
+            pub fn with_data(data: &[u8]) -> Self {
+                let mut raw_32 = [0; 32];
+                raw_32.copy_from_slice(
+                    blake2b_simd::Params::new()
+                        .hash_length(32)
+                        .hash(data)
+                        .as_bytes(),
+                );
+                Self::from_raw_32(raw_32)
+            }
+
+            /// A random hash.
+            pub fn rand() -> Self {
+                Self::from_raw_32(rand::thread_rng().gen())
+            }
+
+            /// Just the 32 hash bytes.
+            pub fn get_raw_32(&self) -> &[u8] {
+                &self.0[3..35]
+            }
+
+            /// All 39 bytes, as stored.
+            pub fn get_raw_39(&self) -> &[u8] {
+                &self.0
+            }
+
+            /// Where on the DHT this hash lives, from its last 4 bytes.
+            pub fn get_loc(&self) -> u32 {
+                let mut loc = [0; 4];
+                loc.copy_from_slice(&self.0[35..]);
+                u32::from_le_bytes(loc)
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}(", stringify!($name))?;
+                for b in self.get_raw_32() {
+                    write!(f, "{:02x}", b)?;
+                }
+                write!(f, ")")
+            }
+        }
+
+        impl Type<Sqlite> for $name {
+            fn type_info() -> SqliteTypeInfo {
+                <Vec<u8> as Type<Sqlite>>::type_info()
+            }
+
+            fn compatible(ty: &SqliteTypeInfo) -> bool {
+                <Vec<u8> as Type<Sqlite>>::compatible(ty)
+            }
+        }
+
+        impl<'q> Encode<'q, Sqlite> for $name {
+            fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+                args.push(SqliteArgumentValue::Blob(Cow::Owned(self.0.to_vec())));
+                IsNull::No
+            }
+        }
+
+        impl<'r> Decode<'r, Sqlite> for $name {
+            fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+                let bytes = <&[u8] as Decode<Sqlite>>::decode(value)?;
+                Ok(Self::from_raw_39(bytes)?)
+            }
+        }
+    };
+}
+
+holo_hash!(
+    /// Address of an [`crate::Entry`].
+    EntryHash,
+    [0x84, 0x21, 0x24]
+);
+holo_hash!(
+    /// Address of a [`crate::Header`].
+    HeaderHash,
+    [0x84, 0x29, 0x24]
+);
+holo_hash!(
+    /// Address of a [`crate::DhtOp`].
+    OpHash,
+    [0x84, 0x24, 0x24]
+);
+holo_hash!(
+    /// An agent's public key, which doubles as their address.
+    AgentPubKey,
+    [0x84, 0x20, 0x24]
+);
@@ -0,0 +1,70 @@
+//! Binding lists of hashes of any length.
+//!
+//! Spelling a list out as `IN (?, ?, ...)` runs into sqlite's limit on
+//! bound parameters, and means a fresh statement per list length.
+//! Instead the list goes in as one JSON array of hex strings, which
+//! `json_each` turns back into rows and our `unhex` into blobs.
+
+use sqlx::{Executor, SqliteConnection};
+use std::fmt::Write;
+
+/// Hashes for a statement to match against, bound as a single parameter.
+#[derive(Debug, Clone)]
+pub(crate) struct HashList {
+    json: String,
+}
+
+impl HashList {
+    pub(crate) fn new<'a>(hashes: impl IntoIterator<Item = &'a [u8]>) -> Self {
+        let mut json = String::from("[");
+        for (i, hash) in hashes.into_iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push('"');
+            for b in hash {
+                write!(json, "{:02x}", b).unwrap();
+            }
+            json.push('"');
+        }
+        json.push(']');
+        Self { json }
+    }
+
+    /// A subquery yielding each hash as a blob, reading the list from
+    /// parameter `?{param}`, for `WHERE hash IN (...)`.
+    pub(crate) fn select(param: usize) -> String {
+        format!("SELECT unhex(value) FROM json_each(?{})", param)
+    }
+
+    /// The parameter to bind.
+    pub(crate) fn json(&self) -> &str {
+        &self.json
+    }
+
+    /// Load the hashes into a new temp table `table (hash)`, once for
+    /// a list several statements join against. Duplicates are dropped.
+    ///
+    /// Temp tables live outside the main database, so this works on a
+    /// read-only connection too. The table is `con`'s until dropped.
+    pub(crate) async fn create_temp_table(
+        &self,
+        con: &mut SqliteConnection,
+        table: &str,
+    ) -> sqlx::Result<()> {
+        con.execute(&*format!(
+            "CREATE TEMP TABLE {} (hash BLOB PRIMARY KEY);",
+            table
+        ))
+        .await?;
+        sqlx::query(&format!(
+            "INSERT OR IGNORE INTO {} (hash) {};",
+            table,
+            Self::select(1)
+        ))
+        .bind(self.json())
+        .execute(&mut *con)
+        .await?;
+        Ok(())
+    }
+}
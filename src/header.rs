@@ -0,0 +1,52 @@
+use crate::{AgentPubKey, EntryHash, HeaderHash};
+use chrono::prelude::*;
+use rand::Rng;
+
+/// Which kind of action a header records.
+/// Stored as the variant name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "text")]
+pub enum HeaderType {
+    Dna,
+    AgentValidationPkg,
+    InitZomesComplete,
+    CreateLink,
+    DeleteLink,
+    OpenChain,
+    CloseChain,
+    Create,
+    Update,
+    Delete,
+}
+
+/// Demo header type for database.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Header {
+    pub hash: HeaderHash,
+    /// The agent whose source chain this header is on.
+    pub author: AgentPubKey,
+    /// Position in the author's source chain.
+    pub seq: u32,
+    /// The previous header on the chain, `None` only for `Dna`.
+    pub prev_hash: Option<HeaderHash>,
+    /// The entry this header creates, if any.
+    pub entry_hash: Option<EntryHash>,
+    #[sqlx(rename = "type")]
+    pub header_type: HeaderType,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Header {
+    /// Generate a random `Create` header for `entry_hash`.
+    pub fn rand(entry_hash: EntryHash) -> Self {
+        Self {
+            hash: HeaderHash::rand(),
+            author: AgentPubKey::rand(),
+            seq: rand::thread_rng().gen_range(1, u32::MAX),
+            prev_hash: Some(HeaderHash::rand()),
+            entry_hash: Some(entry_hash),
+            header_type: HeaderType::Create,
+            timestamp: Utc::now(),
+        }
+    }
+}
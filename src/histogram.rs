@@ -0,0 +1,102 @@
+use crate::db::arc_condition;
+use crate::retry::with_retry;
+use crate::{Db, DbRead, DhtLocation};
+use chrono::prelude::*;
+use futures::TryStreamExt;
+
+/// What we hold in one time slice of an arc.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistogramBucket {
+    /// Start of the slice, inclusive.
+    pub start: DateTime<Utc>,
+    /// Ops authored within the slice.
+    pub count: u64,
+    /// XOR of every op hash in the slice, zero padded to the longest.
+    /// Equal counts and XORs mean both sides almost certainly
+    /// hold the same ops.
+    pub xor_hash: Vec<u8>,
+}
+
+impl Db {
+    /// Summarize the integrated ops within the given (inclusive) basis
+    /// location range per `bucket` of authored time, oldest first.
+    /// Empty buckets are left out.
+    pub async fn op_histogram(
+        &self,
+        basis_loc_start: u32,
+        basis_loc_end: u32,
+        bucket: chrono::Duration,
+    ) -> anyhow::Result<Vec<HistogramBucket>> {
+        self.reader()
+            .op_histogram(basis_loc_start, basis_loc_end, bucket)
+            .await
+    }
+}
+
+impl DbRead {
+    /// Summarize the integrated ops within the given (inclusive) basis
+    /// location range per `bucket` of authored time, oldest first.
+    /// Empty buckets are left out.
+    /// The location range wraps if `basis_loc_start > basis_loc_end`.
+    ///
+    /// Buckets are aligned to the unix epoch, so two nodes using the
+    /// same bucket size compare like for like.
+    pub async fn op_histogram(
+        &self,
+        basis_loc_start: u32,
+        basis_loc_end: u32,
+        bucket: chrono::Duration,
+    ) -> anyhow::Result<Vec<HistogramBucket>> {
+        let bucket_ms = bucket.num_milliseconds();
+        if bucket_ms <= 0 {
+            anyhow::bail!("histogram buckets must be at least 1ms");
+        }
+        // exact integer milliseconds, julianday() would round
+        let sql = format!(
+            "SELECT
+                (CAST(strftime('%s', authored_timestamp) AS INTEGER) * 1000
+                    + CAST(substr(strftime('%f', authored_timestamp), 4) AS INTEGER))
+                    / ? AS bucket,
+                count(*),
+                xor_agg(op_hash)
+            FROM dht_ops
+            WHERE {}
+            AND when_integrated IS NOT NULL
+            GROUP BY bucket
+            ORDER BY bucket
+            ;",
+            arc_condition("basis_loc", basis_loc_start, basis_loc_end)
+        );
+        self.explain.check(&self.pool, &sql).await;
+        let rows: Vec<(i64, i64, Vec<u8>)> = with_retry(
+            self.trace.op("op_histogram").statement(
+                &sql,
+                &[
+                    &bucket_ms,
+                    &DhtLocation(basis_loc_start),
+                    &DhtLocation(basis_loc_end),
+                ],
+            ),
+            &self.retry,
+            &self.permits,
+            || async {
+                sqlx::query_as(&sql)
+                    .bind(bucket_ms)
+                    .bind(DhtLocation(basis_loc_start))
+                    .bind(DhtLocation(basis_loc_end))
+                    .fetch(&self.pool)
+                    .try_collect()
+                    .await
+            },
+        )
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(index, count, xor_hash)| HistogramBucket {
+                start: Utc.timestamp_millis(index * bucket_ms),
+                count: count as u64,
+                xor_hash,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,64 @@
+pub mod lmdb;
+
+use crate::{Db, DbWrite, Entry};
+use futures::{Stream, StreamExt};
+
+/// How far a [`Db::import`] has got.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportProgress {
+    /// Entries taken from the stream and committed.
+    pub received: u64,
+    /// Of those, how many weren't already held.
+    pub inserted: u64,
+    /// Transactions committed so far.
+    pub chunks: u64,
+}
+
+impl Db {
+    /// Drain `entries` into the database, committing every `chunk_size`
+    /// of them and calling `on_progress` after each commit.
+    pub async fn import<S, F>(
+        &self,
+        entries: S,
+        chunk_size: usize,
+        on_progress: F,
+    ) -> anyhow::Result<ImportProgress>
+    where
+        S: Stream<Item = Entry>,
+        F: FnMut(ImportProgress),
+    {
+        self.write.import(entries, chunk_size, on_progress).await
+    }
+}
+
+impl DbWrite {
+    /// Drain `entries` into the database, committing every `chunk_size`
+    /// of them and calling `on_progress` after each commit.
+    ///
+    /// Entries already held are skipped, syncing tends to see some twice.
+    /// On error, every chunk before the failing one stays committed,
+    /// and the last progress reported says how far that was.
+    pub async fn import<S, F>(
+        &self,
+        entries: S,
+        chunk_size: usize,
+        mut on_progress: F,
+    ) -> anyhow::Result<ImportProgress>
+    where
+        S: Stream<Item = Entry>,
+        F: FnMut(ImportProgress),
+    {
+        if chunk_size == 0 {
+            anyhow::bail!("import chunk size must be at least 1");
+        }
+        let mut progress = ImportProgress::default();
+        let mut chunks = Box::pin(entries.chunks(chunk_size));
+        while let Some(chunk) = chunks.next().await {
+            progress.inserted += self.insert_entry_batch(&chunk, true).await?;
+            progress.received += chunk.len() as u64;
+            progress.chunks += 1;
+            on_progress(progress);
+        }
+        Ok(progress)
+    }
+}
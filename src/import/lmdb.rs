@@ -0,0 +1,787 @@
+//! Bulk import of a legacy Holochain LMDB environment, for moving an
+//! existing install over to this schema.
+//!
+//! The environment's `data.mdb` is read directly, without linking
+//! LMDB: only committed pages are followed, from the newest of the two
+//! meta pages, so it may be read while nothing else writes to it.
+//! 64-bit little-endian environments only, as Holochain created them.
+//!
+//! These stores are imported, keyed by the 39 byte hash of the value
+//! and holding rkv blobs of MessagePack, as `holochain_state` wrote them:
+//!
+//! - `element_vault_headers`: `(Header, Signature)`, a [`Header`] each.
+//! - `element_vault_public_entries`: `Entry`, an [`Entry`] each, typed
+//!   and dated by the earliest header creating it. Entries no header
+//!   creates are skipped, there's nothing to type them by.
+//! - `integrated_dht_ops`, `integration_limbo` and `validation_limbo`:
+//!   light ops, a [`DhtOp`] each, authored when their header was.
+//!   Ops whose header isn't in the environment are skipped.
+//!
+//! Missing stores are taken to be empty.
+
+use crate::{
+    AgentPubKey, Db, DbWrite, DhtOp, DhtOpType, Entry, EntryHash, EntryType, Header, HeaderHash,
+    HeaderType, OpHash, Timestamp, ValidationStatus,
+};
+use anyhow::Context;
+use chrono::prelude::*;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use serde_bytes::ByteBuf;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+const HEADERS: &str = "element_vault_headers";
+const ENTRIES: &str = "element_vault_public_entries";
+/// With the validation status and integration time the ops get.
+const OP_STORES: [&str; 3] = [
+    "integrated_dht_ops",
+    "integration_limbo",
+    "validation_limbo",
+];
+
+// ---- the LMDB file format, as of LMDB 0.9 ----
+
+const PAGE_HEADER: usize = 16;
+const NODE_HEADER: usize = 8;
+const META_MAGIC: u32 = 0xBEEF_C0DE;
+const META_VERSION: u32 = 1;
+
+const P_BRANCH: u16 = 0x01;
+const P_LEAF: u16 = 0x02;
+const P_META: u16 = 0x08;
+const P_LEAF2: u16 = 0x20;
+
+const F_BIGDATA: u16 = 0x01;
+const F_SUBDATA: u16 = 0x02;
+const F_DUPDATA: u16 = 0x04;
+
+/// The root of an empty database.
+const P_INVALID: u64 = !0;
+/// Deeper than any real tree, so a corrupt file can't loop forever.
+const MAX_DEPTH: usize = 32;
+
+fn u16_at(bytes: &[u8], at: usize) -> anyhow::Result<u16> {
+    match bytes.get(at..at + 2) {
+        Some(b) => Ok(u16::from_le_bytes(b.try_into().unwrap())),
+        None => anyhow::bail!("data.mdb is truncated or corrupt"),
+    }
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> anyhow::Result<u32> {
+    match bytes.get(at..at + 4) {
+        Some(b) => Ok(u32::from_le_bytes(b.try_into().unwrap())),
+        None => anyhow::bail!("data.mdb is truncated or corrupt"),
+    }
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> anyhow::Result<u64> {
+    match bytes.get(at..at + 8) {
+        Some(b) => Ok(u64::from_le_bytes(b.try_into().unwrap())),
+        None => anyhow::bail!("data.mdb is truncated or corrupt"),
+    }
+}
+
+fn slice_at(bytes: &[u8], at: usize, len: usize) -> anyhow::Result<&[u8]> {
+    match bytes.get(at..at + len) {
+        Some(b) => Ok(b),
+        None => anyhow::bail!("data.mdb is truncated or corrupt"),
+    }
+}
+
+/// The `MDB_db` record describing one B+tree.
+#[derive(Debug, Clone, Copy)]
+struct Tree {
+    /// `md_pad`, which for the free list of a meta page is the page size.
+    pad: u32,
+    root: u64,
+}
+
+impl Tree {
+    const SIZE: usize = 48;
+
+    fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            pad: u32_at(bytes, 0)?,
+            root: u64_at(bytes, 40)?,
+        })
+    }
+}
+
+/// Called with each leaf node's key, flags and data.
+type VisitNode<'a> = dyn FnMut(&[u8], u16, &[u8]) -> anyhow::Result<()> + 'a;
+/// Called with each key and value.
+type VisitRecord<'a> = dyn FnMut(&[u8], &[u8]) -> anyhow::Result<()> + 'a;
+
+/// A read-only view of an environment's `data.mdb`.
+struct Env {
+    /// Seeked to each page as it's read.
+    file: Mutex<File>,
+    page_size: usize,
+    main: Tree,
+}
+
+impl Env {
+    fn open(dir: &Path) -> anyhow::Result<Self> {
+        let mut file = File::open(dir.join("data.mdb"))?;
+        let mut meta = vec![0; PAGE_HEADER + 136];
+        file.read_exact(&mut meta)?;
+        let (first, txnid) = Self::meta(&meta)?;
+        let page_size = first.0.pad as usize;
+        if page_size < 512 || !page_size.is_power_of_two() {
+            anyhow::bail!("data.mdb has an invalid page size {}", page_size);
+        }
+        file.seek(SeekFrom::Start(page_size as u64))?;
+        file.read_exact(&mut meta)?;
+        let (second, second_txnid) = Self::meta(&meta)?;
+        let main = if second_txnid > txnid {
+            second.1
+        } else {
+            first.1
+        };
+        Ok(Self {
+            file: Mutex::new(file),
+            page_size,
+            main,
+        })
+    }
+
+    /// The free list and main trees of a meta page, and its transaction.
+    fn meta(page: &[u8]) -> anyhow::Result<((Tree, Tree), u64)> {
+        if u16_at(page, 10)? & P_META == 0 || u32_at(page, PAGE_HEADER)? != META_MAGIC {
+            anyhow::bail!("not an LMDB environment");
+        }
+        let version = u32_at(page, PAGE_HEADER + 4)?;
+        if version != META_VERSION {
+            anyhow::bail!("unsupported LMDB data version {}", version);
+        }
+        let dbs = PAGE_HEADER + 24;
+        let free = Tree::parse(slice_at(page, dbs, Tree::SIZE)?)?;
+        let main = Tree::parse(slice_at(page, dbs + Tree::SIZE, Tree::SIZE)?)?;
+        let txnid = u64_at(page, dbs + 2 * Tree::SIZE + 8)?;
+        Ok(((free, main), txnid))
+    }
+
+    fn read(&self, offset: u64, len: usize) -> anyhow::Result<Vec<u8>> {
+        let mut buf = vec![0; len];
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn page(&self, pgno: u64) -> anyhow::Result<Vec<u8>> {
+        self.read(pgno * self.page_size as u64, self.page_size)
+    }
+
+    /// The named database `name`, `None` if there's no such store.
+    fn store(&self, name: &str) -> anyhow::Result<Option<Tree>> {
+        let mut found = None;
+        self.walk(self.main, 0, &mut |key, flags, data| {
+            if key == name.as_bytes() && flags & F_SUBDATA != 0 {
+                found = Some(Tree::parse(data)?);
+            }
+            Ok(())
+        })?;
+        Ok(found)
+    }
+
+    /// Every leaf node of `tree` in key order, as key, flags and the
+    /// raw node data.
+    fn walk(&self, tree: Tree, depth: usize, f: &mut VisitNode<'_>) -> anyhow::Result<()> {
+        if tree.root != P_INVALID {
+            self.walk_page(&self.page(tree.root)?, depth, f)?;
+        }
+        Ok(())
+    }
+
+    fn walk_page(&self, page: &[u8], depth: usize, f: &mut VisitNode<'_>) -> anyhow::Result<()> {
+        if depth > MAX_DEPTH {
+            anyhow::bail!("data.mdb nests deeper than any real tree, it's corrupt");
+        }
+        let flags = u16_at(page, 10)?;
+        let lower = u16_at(page, 12)? as usize;
+        let count = lower.saturating_sub(PAGE_HEADER) / 2;
+        if flags & P_LEAF2 != 0 {
+            // fixed size keys packed back to back, no nodes
+            let size = u16_at(page, 8)? as usize;
+            for i in 0..count {
+                f(slice_at(page, PAGE_HEADER + i * size, size)?, 0, &[])?;
+            }
+            return Ok(());
+        }
+        for i in 0..count {
+            let node = u16_at(page, PAGE_HEADER + 2 * i)? as usize;
+            let lo = u16_at(page, node)? as u64;
+            let hi = u16_at(page, node + 2)? as u64;
+            let node_flags = u16_at(page, node + 4)?;
+            let key_size = u16_at(page, node + 6)? as usize;
+            let key = slice_at(page, node + NODE_HEADER, key_size)?;
+            if flags & P_BRANCH != 0 {
+                let child = lo | hi << 16 | (node_flags as u64) << 32;
+                self.walk_page(&self.page(child)?, depth + 1, f)?;
+            } else if flags & P_LEAF != 0 {
+                let data_size = (lo | hi << 16) as usize;
+                let at = node + NODE_HEADER + key_size;
+                if node_flags & F_BIGDATA != 0 {
+                    // too big for the page, it's in overflow pages of its own
+                    let pgno = u64_at(page, at)?;
+                    let offset = pgno * self.page_size as u64 + PAGE_HEADER as u64;
+                    f(key, node_flags, &self.read(offset, data_size)?)?;
+                } else {
+                    f(key, node_flags, slice_at(page, at, data_size)?)?;
+                }
+            } else {
+                anyhow::bail!("data.mdb has a page that's neither branch nor leaf");
+            }
+        }
+        Ok(())
+    }
+
+    /// Every key and value of `tree` in key order, a key with sorted
+    /// duplicates once for each of them.
+    fn scan(&self, tree: Tree, f: &mut VisitRecord<'_>) -> anyhow::Result<()> {
+        self.walk(tree, 0, &mut |key, flags, data| {
+            if flags & F_DUPDATA == 0 {
+                return f(key, data);
+            }
+            let mut each = |dup: &[u8], _: u16, _: &[u8]| f(key, dup);
+            if flags & F_SUBDATA != 0 {
+                // many duplicates get a tree of their own, keyed by value
+                self.walk(Tree::parse(data)?, 1, &mut each)
+            } else {
+                // a few are kept inline, as a page of their own
+                self.walk_page(data, 1, &mut each)
+            }
+        })
+    }
+}
+
+// ---- what holochain_state kept in it ----
+
+/// rkv tags every value with its type: a blob is tag 9 and then the
+/// bytes, bincode length prefixed.
+const RKV_BLOB: u8 = 9;
+
+fn decode<T: serde::de::DeserializeOwned>(value: &[u8]) -> anyhow::Result<T> {
+    Ok(rmp_serde::from_slice(rkv_blob(value)?)?)
+}
+
+fn rkv_blob(value: &[u8]) -> anyhow::Result<&[u8]> {
+    if value.first() != Some(&RKV_BLOB) {
+        anyhow::bail!("not an rkv blob");
+    }
+    let len = u64_at(value, 1)? as usize;
+    slice_at(value, 9, len)
+}
+
+/// Seconds and nanoseconds since the unix epoch.
+#[derive(Deserialize)]
+struct LegacyTimestamp(i64, u32);
+
+impl LegacyTimestamp {
+    fn to_date_time(&self) -> anyhow::Result<DateTime<Utc>> {
+        match Utc.timestamp_opt(self.0, self.1) {
+            chrono::LocalResult::Single(time) => Ok(time),
+            _ => anyhow::bail!("timestamp {}.{:09} is out of range", self.0, self.1),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyAppEntryType {
+    id: u8,
+    zome_id: u8,
+}
+
+#[derive(Deserialize)]
+enum LegacyEntryType {
+    AgentPubKey,
+    App(LegacyAppEntryType),
+    CapClaim,
+    CapGrant,
+}
+
+impl From<LegacyEntryType> for EntryType {
+    fn from(entry_type: LegacyEntryType) -> Self {
+        match entry_type {
+            LegacyEntryType::AgentPubKey => Self::Agent,
+            LegacyEntryType::App(app) => Self::App {
+                zome_index: app.zome_id,
+                entry_def_index: app.id,
+            },
+            LegacyEntryType::CapClaim => Self::CapClaim,
+            LegacyEntryType::CapGrant => Self::CapGrant,
+        }
+    }
+}
+
+/// The fields of any header this schema keeps, the rest are ignored.
+#[derive(Deserialize)]
+struct LegacyHeaderFields {
+    author: ByteBuf,
+    timestamp: LegacyTimestamp,
+    /// Missing on `Dna`, which is 0.
+    #[serde(default)]
+    header_seq: u32,
+    #[serde(default)]
+    prev_header: Option<ByteBuf>,
+    #[serde(default)]
+    entry_hash: Option<ByteBuf>,
+    #[serde(default)]
+    entry_type: Option<LegacyEntryType>,
+}
+
+#[derive(Deserialize)]
+enum LegacyHeader {
+    Dna(LegacyHeaderFields),
+    AgentValidationPkg(LegacyHeaderFields),
+    InitZomesComplete(LegacyHeaderFields),
+    CreateLink(LegacyHeaderFields),
+    DeleteLink(LegacyHeaderFields),
+    OpenChain(LegacyHeaderFields),
+    CloseChain(LegacyHeaderFields),
+    Create(LegacyHeaderFields),
+    Update(LegacyHeaderFields),
+    Delete(LegacyHeaderFields),
+}
+
+/// A header and its signature.
+#[derive(Deserialize)]
+struct LegacySignedHeader(LegacyHeader, IgnoredAny);
+
+#[derive(Deserialize)]
+enum LegacyEntry {
+    Agent(ByteBuf),
+    App(ByteBuf),
+    CapClaim(IgnoredAny),
+    CapGrant(IgnoredAny),
+}
+
+/// An op by the hashes it's made of: its header, sometimes an entry,
+/// and always last its basis.
+#[derive(Deserialize)]
+enum LegacyOpLight {
+    StoreElement(ByteBuf, IgnoredAny, ByteBuf),
+    StoreEntry(ByteBuf, IgnoredAny, ByteBuf),
+    RegisterAgentActivity(ByteBuf, ByteBuf),
+    RegisterUpdatedContent(ByteBuf, IgnoredAny, ByteBuf),
+    RegisterUpdatedElement(ByteBuf, IgnoredAny, ByteBuf),
+    RegisterDeletedBy(ByteBuf, ByteBuf),
+    RegisterDeletedEntryHeader(ByteBuf, ByteBuf),
+    RegisterAddLink(ByteBuf, ByteBuf),
+    RegisterRemoveLink(ByteBuf, ByteBuf),
+}
+
+impl LegacyOpLight {
+    /// The op type, header hash and basis hash.
+    fn parts(&self) -> (DhtOpType, &[u8], &[u8]) {
+        use LegacyOpLight::*;
+        match self {
+            StoreElement(header, _, basis) => (DhtOpType::StoreElement, header, basis),
+            StoreEntry(header, _, basis) => (DhtOpType::StoreEntry, header, basis),
+            RegisterAgentActivity(header, basis) => {
+                (DhtOpType::RegisterAgentActivity, header, basis)
+            }
+            RegisterUpdatedContent(header, _, basis) => {
+                (DhtOpType::RegisterUpdatedContent, header, basis)
+            }
+            RegisterUpdatedElement(header, _, basis) => {
+                (DhtOpType::RegisterUpdatedElement, header, basis)
+            }
+            RegisterDeletedBy(header, basis) => (DhtOpType::RegisterDeletedBy, header, basis),
+            RegisterDeletedEntryHeader(header, basis) => {
+                (DhtOpType::RegisterDeletedEntryHeader, header, basis)
+            }
+            RegisterAddLink(header, basis) => (DhtOpType::RegisterAddLink, header, basis),
+            RegisterRemoveLink(header, basis) => (DhtOpType::RegisterRemoveLink, header, basis),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+enum LegacyValidationStatus {
+    Valid,
+    Rejected,
+    Abandoned,
+}
+
+impl From<LegacyValidationStatus> for ValidationStatus {
+    fn from(status: LegacyValidationStatus) -> Self {
+        match status {
+            LegacyValidationStatus::Valid => Self::Valid,
+            LegacyValidationStatus::Rejected => Self::Rejected,
+            LegacyValidationStatus::Abandoned => Self::Abandoned,
+        }
+    }
+}
+
+/// Any of the op stores' values: an integrated op has both a status and
+/// an integration time, one in integration limbo only a status, and one
+/// still in validation limbo neither (its own status field is ignored).
+#[derive(Deserialize)]
+struct LegacyOpValue {
+    #[serde(default)]
+    validation_status: Option<LegacyValidationStatus>,
+    op: LegacyOpLight,
+    #[serde(default)]
+    when_integrated: Option<LegacyTimestamp>,
+}
+
+fn decode_header(key: &[u8], value: &[u8]) -> anyhow::Result<(Header, Option<EntryType>)> {
+    let LegacySignedHeader(header, _) = decode(value)?;
+    use LegacyHeader::*;
+    let (header_type, fields) = match header {
+        Dna(f) => (HeaderType::Dna, f),
+        AgentValidationPkg(f) => (HeaderType::AgentValidationPkg, f),
+        InitZomesComplete(f) => (HeaderType::InitZomesComplete, f),
+        CreateLink(f) => (HeaderType::CreateLink, f),
+        DeleteLink(f) => (HeaderType::DeleteLink, f),
+        OpenChain(f) => (HeaderType::OpenChain, f),
+        CloseChain(f) => (HeaderType::CloseChain, f),
+        Create(f) => (HeaderType::Create, f),
+        Update(f) => (HeaderType::Update, f),
+        Delete(f) => (HeaderType::Delete, f),
+    };
+    let header = Header {
+        hash: HeaderHash::from_raw_39(key)?,
+        author: AgentPubKey::from_raw_39(&fields.author)?,
+        seq: fields.header_seq,
+        prev_hash: match &fields.prev_header {
+            Some(hash) => Some(HeaderHash::from_raw_39(hash)?),
+            None => None,
+        },
+        entry_hash: match &fields.entry_hash {
+            Some(hash) => Some(EntryHash::from_raw_39(hash)?),
+            None => None,
+        },
+        header_type,
+        timestamp: fields.timestamp.to_date_time()?,
+    };
+    Ok((header, fields.entry_type.map(Into::into)))
+}
+
+/// What the headers say about the entries and ops they go with.
+#[derive(Default)]
+struct HeaderIndex {
+    /// The type of each entry and when it was first created.
+    entries: HashMap<EntryHash, (Timestamp, EntryType)>,
+    /// When each header was authored.
+    authored: HashMap<HeaderHash, DateTime<Utc>>,
+}
+
+impl HeaderIndex {
+    fn build(env: &Env) -> anyhow::Result<Self> {
+        let mut index = Self::default();
+        let tree = match env.store(HEADERS)? {
+            Some(tree) => tree,
+            None => return Ok(index),
+        };
+        env.scan(tree, &mut |key, value| {
+            let (header, entry_type) = decode_header(key, value)?;
+            if let (Some(entry_hash), Some(entry_type)) = (&header.entry_hash, entry_type) {
+                let created = Timestamp::from(header.timestamp);
+                let earliest = index
+                    .entries
+                    .entry(entry_hash.clone())
+                    .or_insert((created, entry_type));
+                if created < earliest.0 {
+                    *earliest = (created, entry_type);
+                }
+            }
+            index.authored.insert(header.hash, header.timestamp);
+            Ok(())
+        })?;
+        Ok(index)
+    }
+}
+
+type Records = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// The records of `store` a chunk at a time, read on a blocking thread
+/// one chunk ahead of the writes.
+fn read_chunks(
+    env: Arc<Env>,
+    store: &'static str,
+    chunk_size: usize,
+) -> mpsc::Receiver<anyhow::Result<Records>> {
+    let (tx, rx) = mpsc::channel(1);
+    tokio::task::spawn_blocking(move || {
+        let res = (|| {
+            let tree = match env.store(store)? {
+                Some(tree) => tree,
+                None => return Ok(()),
+            };
+            let mut chunk = Vec::with_capacity(chunk_size);
+            env.scan(tree, &mut |key, value| {
+                chunk.push((key.to_vec(), value.to_vec()));
+                if chunk.len() == chunk_size {
+                    tx.blocking_send(Ok(std::mem::take(&mut chunk)))
+                        .map_err(|_| anyhow::anyhow!("import stopped"))?;
+                }
+                Ok(())
+            })?;
+            if !chunk.is_empty() {
+                tx.blocking_send(Ok(chunk))
+                    .map_err(|_| anyhow::anyhow!("import stopped"))?;
+            }
+            Ok(())
+        })();
+        if let Err(err) = res {
+            let _ = tx.blocking_send(Err(err));
+        }
+    });
+    rx
+}
+
+/// Like [`INSERT_HEADER`](crate::db::INSERT_HEADER), but leaving out
+/// headers already held and those whose entry isn't.
+const INSERT_IMPORTED_HEADER: &str = "INSERT INTO headers
+    (hash, author, seq, prev_hash, entry_hash, type, timestamp)
+    SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7
+    WHERE ?5 IS NULL OR EXISTS (SELECT 1 FROM entries WHERE hash = ?5)
+    ON CONFLICT (hash) DO NOTHING";
+
+/// Which environment [`Db::import_lmdb`] imports, and how.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LmdbImport {
+    dir: PathBuf,
+    chunk_size: usize,
+    dry_run: bool,
+}
+
+impl LmdbImport {
+    /// Import the environment in `dir`, the directory holding
+    /// `data.mdb`, 1000 records to a transaction.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            chunk_size: 1000,
+            dry_run: false,
+        }
+    }
+
+    /// Records to a transaction, at least 1.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Read and decode everything, reporting progress as usual, but
+    /// write nothing. Checks an environment imports before committing
+    /// to it.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+/// How far a [`Db::import_lmdb`] has got.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LmdbProgress {
+    /// Records read and decoded, skipped ones included.
+    pub entries: u64,
+    pub headers: u64,
+    pub ops: u64,
+    /// Records left out, see [`lmdb`](self).
+    pub skipped: u64,
+    /// Rows written, not counting entries and headers already held.
+    /// Always 0 on a dry run.
+    pub written: u64,
+    /// Chunks done, each committed in its own transaction unless on a
+    /// dry run.
+    pub chunks: u64,
+}
+
+impl Db {
+    /// Import a legacy LMDB environment, see [`DbWrite::import_lmdb`].
+    pub async fn import_lmdb<F>(
+        &self,
+        import: &LmdbImport,
+        on_progress: F,
+    ) -> anyhow::Result<LmdbProgress>
+    where
+        F: FnMut(LmdbProgress),
+    {
+        self.write.import_lmdb(import, on_progress).await
+    }
+}
+
+impl DbWrite {
+    /// Import the entries, headers and ops of a legacy LMDB environment,
+    /// a chunk of records to a transaction, calling `on_progress` after
+    /// each chunk. Entries go first, so headers find theirs.
+    ///
+    /// Importing again is safe, what's already held is left as it is.
+    /// On error, every chunk before the failing one stays committed.
+    pub async fn import_lmdb<F>(
+        &self,
+        import: &LmdbImport,
+        mut on_progress: F,
+    ) -> anyhow::Result<LmdbProgress>
+    where
+        F: FnMut(LmdbProgress),
+    {
+        let dir = import.dir.clone();
+        let env = Arc::new(tokio::task::spawn_blocking(move || Env::open(&dir)).await??);
+        let index = {
+            let env = env.clone();
+            tokio::task::spawn_blocking(move || HeaderIndex::build(&env)).await??
+        };
+        let mut progress = LmdbProgress::default();
+
+        let mut chunks = read_chunks(env.clone(), ENTRIES, import.chunk_size);
+        while let Some(records) = chunks.recv().await {
+            let records = records?;
+            let mut entries = Vec::with_capacity(records.len());
+            for (key, value) in records {
+                progress.entries += 1;
+                let entry = decode_entry(&key, &value, &index)
+                    .with_context(|| format!("{} record {}", ENTRIES, progress.entries))?;
+                match entry {
+                    Some(entry) => entries.push(entry),
+                    None => progress.skipped += 1,
+                }
+            }
+            if !import.dry_run {
+                progress.written += self.insert_entry_batch(&entries, true).await?;
+            }
+            progress.chunks += 1;
+            on_progress(progress);
+        }
+
+        let mut chunks = read_chunks(env.clone(), HEADERS, import.chunk_size);
+        while let Some(records) = chunks.recv().await {
+            let mut headers = Vec::new();
+            for (key, value) in records? {
+                progress.headers += 1;
+                let (header, _) = decode_header(&key, &value)
+                    .with_context(|| format!("{} record {}", HEADERS, progress.headers))?;
+                headers.push(header);
+            }
+            if !import.dry_run {
+                progress.written += self.write_imported_headers(headers).await?;
+            }
+            progress.chunks += 1;
+            on_progress(progress);
+        }
+
+        for store in OP_STORES.iter() {
+            let mut chunks = read_chunks(env.clone(), store, import.chunk_size);
+            while let Some(records) = chunks.recv().await {
+                let mut ops = Vec::new();
+                for (key, value) in records? {
+                    progress.ops += 1;
+                    let op = decode_op(&key, &value, &index)
+                        .with_context(|| format!("{} record {}", store, progress.ops))?;
+                    match op {
+                        Some(op) => ops.push(op),
+                        None => progress.skipped += 1,
+                    }
+                }
+                if !import.dry_run {
+                    progress.written += self.write_imported_ops(ops).await?;
+                }
+                progress.chunks += 1;
+                on_progress(progress);
+            }
+        }
+        Ok(progress)
+    }
+
+    async fn write_imported_headers(&self, headers: Vec<Header>) -> anyhow::Result<u64> {
+        self.with_retrying_txn(|writer| {
+            let headers = headers.clone();
+            Box::pin(async move {
+                let mut written = 0;
+                for header in headers {
+                    written += sqlx::query(INSERT_IMPORTED_HEADER)
+                        .bind(header.hash)
+                        .bind(header.author)
+                        .bind(header.seq)
+                        .bind(header.prev_hash)
+                        .bind(header.entry_hash)
+                        .bind(header.header_type)
+                        .bind(header.timestamp)
+                        .execute(&mut writer.tx)
+                        .await?
+                        .rows_affected();
+                }
+                Ok(written)
+            })
+        })
+        .await
+    }
+
+    async fn write_imported_ops(&self, ops: Vec<DhtOp>) -> anyhow::Result<u64> {
+        let written = ops.len() as u64;
+        self.with_retrying_txn(|writer| {
+            let ops = ops.clone();
+            Box::pin(async move {
+                for op in &ops {
+                    writer.insert_op(op).await?;
+                }
+                Ok(())
+            })
+        })
+        .await?;
+        Ok(written)
+    }
+}
+
+/// `None` if no header creates it.
+fn decode_entry(key: &[u8], value: &[u8], index: &HeaderIndex) -> anyhow::Result<Option<Entry>> {
+    let hash = EntryHash::from_raw_39(key)?;
+    let content = match decode(value)? {
+        LegacyEntry::Agent(key) => key.into_vec(),
+        LegacyEntry::App(bytes) => bytes.into_vec(),
+        // kept as holochain encoded them, this schema has no shape for them
+        LegacyEntry::CapClaim(_) | LegacyEntry::CapGrant(_) => rkv_blob(value)?.to_vec(),
+    };
+    Ok(index
+        .entries
+        .get(&hash)
+        .map(|&(created_at, entry_type)| Entry {
+            hash,
+            created_at,
+            entry_type,
+            content,
+        }))
+}
+
+/// `None` if its header isn't in the environment.
+fn decode_op(key: &[u8], value: &[u8], index: &HeaderIndex) -> anyhow::Result<Option<DhtOp>> {
+    let value: LegacyOpValue = decode(value)?;
+    let (op_type, header, basis) = value.op.parts();
+    let authored = match index.authored.get(&HeaderHash::from_raw_39(header)?) {
+        Some(&authored) => authored,
+        None => return Ok(None),
+    };
+    if basis.len() != crate::HOLO_HASH_LEN {
+        anyhow::bail!(
+            "basis must be {} bytes, got {}",
+            crate::HOLO_HASH_LEN,
+            basis.len()
+        );
+    }
+    Ok(Some(DhtOp {
+        op_hash: OpHash::from_raw_39(key)?,
+        op_type,
+        // the last 4 bytes of a holo-hash
+        basis_loc: u32::from_le_bytes(basis[35..].try_into().unwrap()),
+        authored_timestamp: authored,
+        when_integrated: match &value.when_integrated {
+            Some(time) => Some(time.to_date_time()?),
+            None => None,
+        },
+        validation_status: value
+            .validation_status
+            .map_or(ValidationStatus::Pending, Into::into),
+        dependency: None,
+    }))
+}
@@ -0,0 +1,177 @@
+use crate::migrations::expected_schema;
+use crate::{Db, DbRead, DbWrite};
+
+/// Definition of an index to create at runtime.
+///
+/// ```no_run
+/// # use spike_sqlx::*;
+/// let spec = IndexSpec::new("entries_dht_loc_idx", "entries").column("dht_loc");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexSpec {
+    pub(crate) name: String,
+    pub(crate) table: String,
+    pub(crate) columns: Vec<String>,
+    pub(crate) unique: bool,
+}
+
+impl IndexSpec {
+    /// An index called `name` on `table`, add at least one column.
+    pub fn new(name: impl Into<String>, table: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            table: table.into(),
+            columns: Vec::new(),
+            unique: false,
+        }
+    }
+
+    /// Append a column, in index order.
+    pub fn column(mut self, column: impl Into<String>) -> Self {
+        self.columns.push(column.into());
+        self
+    }
+
+    /// Make this a `UNIQUE` index.
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+
+    /// The `CREATE INDEX` statement, with every identifier checked.
+    fn to_sql(&self) -> anyhow::Result<String> {
+        check_name(&self.name)?;
+        check_identifier(&self.table)?;
+        if self.columns.is_empty() {
+            anyhow::bail!("index {} has no columns", self.name);
+        }
+        for column in &self.columns {
+            check_identifier(column)?;
+        }
+        Ok(format!(
+            "CREATE {}INDEX IF NOT EXISTS \"{}\" ON \"{}\" ({});",
+            if self.unique { "UNIQUE " } else { "" },
+            self.name,
+            self.table,
+            self.columns
+                .iter()
+                .map(|column| format!("\"{}\"", column))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ))
+    }
+}
+
+/// An index present in the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexInfo {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<String>,
+    /// True if a migration created it, these can't be dropped.
+    pub from_migration: bool,
+}
+
+/// Identifiers are spliced into the sql, so only allow plain ones.
+pub(crate) fn check_identifier(ident: &str) -> anyhow::Result<()> {
+    let mut chars = ident.chars();
+    let valid = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        anyhow::bail!("invalid identifier {:?}", ident);
+    }
+    Ok(())
+}
+
+/// sqlite reserves the `sqlite_` prefix for its own objects.
+fn check_name(name: &str) -> anyhow::Result<()> {
+    check_identifier(name)?;
+    if name.to_ascii_lowercase().starts_with("sqlite_") {
+        anyhow::bail!("index names may not start with sqlite_");
+    }
+    Ok(())
+}
+
+/// Names of the indexes the migrations own.
+async fn migration_indexes() -> anyhow::Result<Vec<String>> {
+    Ok(expected_schema()
+        .await?
+        .into_iter()
+        .filter(|((kind, _), _)| kind == "index")
+        .map(|((_, name), _)| name)
+        .collect())
+}
+
+impl Db {
+    /// Create an index without a schema migration.
+    /// Does nothing if an index called `spec.name` already exists.
+    pub async fn create_index(&self, spec: &IndexSpec) -> anyhow::Result<()> {
+        self.write.create_index(spec).await
+    }
+
+    /// Drop an index created with [`Db::create_index`].
+    pub async fn drop_index(&self, name: &str) -> anyhow::Result<()> {
+        self.write.drop_index(name).await
+    }
+
+    /// Every index on the database's tables.
+    pub async fn list_indexes(&self) -> anyhow::Result<Vec<IndexInfo>> {
+        self.reader().list_indexes().await
+    }
+}
+
+impl DbWrite {
+    /// Create an index without a schema migration.
+    /// Does nothing if an index called `spec.name` already exists.
+    pub async fn create_index(&self, spec: &IndexSpec) -> anyhow::Result<()> {
+        sqlx::query(&spec.to_sql()?).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Drop an index created with [`DbWrite::create_index`].
+    ///
+    /// Indexes from migrations are refused, the schema check
+    /// on the next open would fail without them.
+    pub async fn drop_index(&self, name: &str) -> anyhow::Result<()> {
+        check_name(name)?;
+        if migration_indexes().await?.iter().any(|owned| owned == name) {
+            anyhow::bail!("index {} belongs to the schema, it can't be dropped", name);
+        }
+        sqlx::query(&format!("DROP INDEX \"{}\";", name))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+impl DbRead {
+    /// Every index on the database's tables.
+    pub async fn list_indexes(&self) -> anyhow::Result<Vec<IndexInfo>> {
+        let owned = migration_indexes().await?;
+        let mut con = self.pool.acquire().await?;
+        // automatic indexes (primary keys, UNIQUE constraints) have no sql
+        let indexes: Vec<(String, String)> = sqlx::query_as(
+            "SELECT name, tbl_name FROM sqlite_master
+            WHERE type = 'index' AND sql IS NOT NULL
+            ORDER BY tbl_name, name;",
+        )
+        .fetch_all(&mut con)
+        .await?;
+
+        let mut out = Vec::with_capacity(indexes.len());
+        for (name, table) in indexes {
+            let columns: Vec<(String,)> =
+                sqlx::query_as("SELECT name FROM pragma_index_info(?1) ORDER BY seqno;")
+                    .bind(&name)
+                    .fetch_all(&mut con)
+                    .await?;
+            out.push(IndexInfo {
+                from_migration: owned.contains(&name),
+                name,
+                table,
+                columns: columns.into_iter().map(|(column,)| column).collect(),
+            });
+        }
+        Ok(out)
+    }
+}
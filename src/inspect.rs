@@ -0,0 +1,179 @@
+//! A one-shot look at a database's schema, size and settings, for
+//! operators checking on a cell's database.
+
+use crate::backend::row_values;
+use crate::console::cell;
+use crate::migrations::{applied, MIGRATOR};
+use crate::{Db, DbStats};
+use std::fmt;
+
+/// The pragmas [`Db::inspect`] reports, as the writer has them.
+pub const INSPECTED_PRAGMAS: &[&str] = &[
+    "journal_mode",
+    "synchronous",
+    "auto_vacuum",
+    "foreign_keys",
+    "busy_timeout",
+    "cache_size",
+    "wal_autocheckpoint",
+    "temp_store",
+    "mmap_size",
+    "secure_delete",
+    "cipher_version",
+];
+
+/// A table, index, trigger or view, as `sqlite_master` has it, leaving
+/// out the indexes sqlite makes for constraints, which have no SQL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaObject {
+    /// "table", "index", "trigger" or "view".
+    pub kind: String,
+    pub name: String,
+    /// The table it belongs to, its own name for a table.
+    pub table: String,
+    pub sql: String,
+}
+
+/// What [`Db::inspect`] found. Displays as a report for people.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inspection {
+    /// The latest migration applied, `None` if the file never was.
+    pub schema_version: Option<i64>,
+    /// The latest migration this build knows.
+    pub latest_version: i64,
+    /// By table, then kind and name.
+    pub objects: Vec<SchemaObject>,
+    pub stats: DbStats,
+    /// Each of [`INSPECTED_PRAGMAS`] with its value, leaving out those
+    /// this sqlite doesn't have, such as `cipher_version` without
+    /// SQLCipher.
+    pub pragmas: Vec<(String, String)>,
+}
+
+impl Inspection {
+    /// The value of `pragma`, if it was reported.
+    pub fn pragma(&self, pragma: &str) -> Option<&str> {
+        self.pragmas
+            .iter()
+            .find(|(name, _)| name == pragma)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The indexes on `table`.
+    pub fn indexes<'a>(&'a self, table: &'a str) -> impl Iterator<Item = &'a SchemaObject> {
+        self.objects
+            .iter()
+            .filter(move |object| object.kind == "index" && object.table == table)
+    }
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.schema_version {
+            Some(version) => writeln!(
+                f,
+                "schema version {} (this build knows up to {})",
+                version, self.latest_version
+            )?,
+            None => writeln!(f, "never migrated")?,
+        }
+        let stats = &self.stats;
+        writeln!(
+            f,
+            "file {} bytes, wal {} bytes, {} of {} pages of {} bytes in use",
+            stats.file_bytes,
+            stats.wal_bytes,
+            stats.page_count.saturating_sub(stats.freelist_count),
+            stats.page_count,
+            stats.page_size
+        )?;
+
+        writeln!(f, "\npragmas")?;
+        for (name, value) in &self.pragmas {
+            writeln!(f, "  {} = {}", name, value)?;
+        }
+
+        let mut tables: Vec<&str> = self
+            .objects
+            .iter()
+            .map(|object| object.table.as_str())
+            .collect();
+        tables.dedup();
+        for table in tables {
+            write!(f, "\n{}", table)?;
+            if let Some(table) = stats.table(table) {
+                write!(f, ", {} rows", table.rows)?;
+                if let Some(bytes) = table.bytes {
+                    write!(f, ", {} bytes with indexes", bytes)?;
+                }
+            }
+            writeln!(f)?;
+            for object in self.objects.iter().filter(|object| object.table == table) {
+                for line in object.sql.lines() {
+                    writeln!(f, "  {}", line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Db {
+    /// The schema version, every table, index and trigger with its
+    /// definition, row counts, file and WAL sizes, and the writer's
+    /// pragmas, for a health check at a glance.
+    ///
+    /// Counts every table's rows, see [`crate::DbRead::stats`], so it
+    /// takes a while on a large file.
+    pub async fn inspect(&self) -> anyhow::Result<Inspection> {
+        let stats = self.reader().stats().await?;
+
+        let (schema_version, objects) = {
+            let read = self.reader();
+            let _permit = read.permits.acquire().await?;
+            let mut con = read.pool.acquire().await?;
+            let version = applied(&mut con).await?.map(|(_, version)| version);
+            let objects: Vec<(String, String, String, String)> = sqlx::query_as(
+                "SELECT type, name, tbl_name, sql FROM sqlite_master
+                WHERE sql IS NOT NULL
+                ORDER BY tbl_name, type = 'index', type = 'trigger', name;",
+            )
+            .fetch_all(&mut con)
+            .await?;
+            let objects = objects
+                .into_iter()
+                .map(|(kind, name, table, sql)| SchemaObject {
+                    kind,
+                    name,
+                    table,
+                    sql,
+                })
+                .collect();
+            (version, objects)
+        };
+
+        let mut pragmas = Vec::new();
+        {
+            let _permit = self.write.permits.acquire().await?;
+            let mut con = self.write.pool.acquire().await?;
+            for pragma in INSPECTED_PRAGMAS {
+                let row = sqlx::query(&format!("PRAGMA {};", pragma))
+                    .fetch_optional(&mut con)
+                    .await?;
+                if let Some(value) = row.map(|row| row_values(&row)).transpose()? {
+                    if let Some(value) = value.first() {
+                        pragmas.push((pragma.to_string(), cell(value)));
+                    }
+                }
+            }
+        }
+
+        Ok(Inspection {
+            schema_version,
+            latest_version: MIGRATOR.iter().last().map_or(0, |m| m.version),
+            objects,
+            stats,
+            pragmas,
+        })
+    }
+}
@@ -0,0 +1,106 @@
+//! Checking the file for damage, on demand or on a timer, so corruption
+//! is caught before it's backed up or gossiped on.
+
+use crate::error::is_not_a_database;
+use crate::{Db, DbError, DbRead};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What `PRAGMA integrity_check` or `quick_check` found, from
+/// [`Db::integrity_check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Whether it was the quick check, which skips matching indexes
+    /// against their tables.
+    pub quick: bool,
+    /// One line per problem sqlite found, at most 100, empty if none.
+    pub problems: Vec<String>,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Why a background integrity check failed, handed to the callback
+/// given to [`crate::DbConfig::integrity_check_every`].
+#[derive(Debug)]
+pub enum IntegrityFailure {
+    /// The check ran and found problems.
+    Problems(IntegrityReport),
+    /// The check couldn't run, e.g. [`DbError::Corrupt`] for a file too
+    /// damaged to read.
+    Error(anyhow::Error),
+}
+
+pub(crate) type OnIntegrityFailure = Arc<dyn Fn(IntegrityFailure) + Send + Sync>;
+
+/// When to check in the background, and who to tell.
+#[derive(Clone)]
+pub(crate) struct IntegritySchedule {
+    pub(crate) interval: Duration,
+    pub(crate) quick: bool,
+    pub(crate) on_failure: OnIntegrityFailure,
+}
+
+impl std::fmt::Debug for IntegritySchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IntegritySchedule")
+            .field("interval", &self.interval)
+            .field("quick", &self.quick)
+            .finish()
+    }
+}
+
+impl Db {
+    /// See [`DbRead::integrity_check`].
+    pub async fn integrity_check(&self, quick: bool) -> anyhow::Result<IntegrityReport> {
+        self.reader().integrity_check(quick).await
+    }
+}
+
+impl DbRead {
+    /// Run `PRAGMA quick_check` if `quick`, else the full
+    /// `PRAGMA integrity_check`, over the database and anything
+    /// attached to it.
+    ///
+    /// Both read every page, and the full check every index too, so
+    /// this holds a read connection for a while on a large file.
+    /// An unreadable file fails with [`DbError::Corrupt`] rather than
+    /// a report.
+    pub async fn integrity_check(&self, quick: bool) -> anyhow::Result<IntegrityReport> {
+        let _permit = self.permits.acquire().await?;
+        let pragma = match quick {
+            true => "PRAGMA quick_check;",
+            false => "PRAGMA integrity_check;",
+        };
+        let lines: Vec<String> = match sqlx::query_scalar(pragma).fetch_all(&self.pool).await {
+            Ok(lines) => lines,
+            Err(err) if is_not_a_database(&err) => return Err(DbError::Corrupt(err).into()),
+            Err(err) => return Err(err.into()),
+        };
+        let problems = match lines.as_slice() {
+            [ok] if ok == "ok" => Vec::new(),
+            _ => lines,
+        };
+        Ok(IntegrityReport { quick, problems })
+    }
+}
+
+/// Check every `interval` until the task is aborted, reporting failures
+/// to the schedule's callback.
+pub(crate) async fn integrity_task(read: DbRead, schedule: IntegritySchedule) {
+    let mut ticker = tokio::time::interval(schedule.interval);
+    // the first tick completes immediately, opening already checked the key
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let failure = match read.integrity_check(schedule.quick).await {
+            Ok(report) if report.is_ok() => continue,
+            Ok(report) => IntegrityFailure::Problems(report),
+            Err(err) => IntegrityFailure::Error(err),
+        };
+        (schedule.on_failure)(failure);
+    }
+}
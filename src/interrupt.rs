@@ -0,0 +1,92 @@
+//! Stopping reads nobody is waiting for anymore.
+//!
+//! sqlx steps statements on a worker thread per connection, so
+//! dropping a query future leaves the step running until it finds a
+//! row or finishes, holding its connection and snapshot all that time.
+//! Touching the connection again before that step is done isn't safe,
+//! and sqlx gives no way to wait for it, so cancellable reads run on a
+//! task of their own. Giving up only interrupts the step, and the task
+//! ends the read once the step has returned.
+//!
+//! The interrupt stays pending while any statement on the connection
+//! is part way through. A read only steps statements to the end, so
+//! none is left that way for it to hit the rollback that ends the read.
+//! A stream stops part way, so it resets its statements first, which
+//! is safe there as every step it started has returned.
+
+use libsqlite3_sys::{
+    sqlite3, sqlite3_interrupt, sqlite3_next_stmt, sqlite3_reset, sqlite3_stmt_busy,
+};
+use sqlx::SqliteConnection;
+use std::sync::{Arc, Mutex};
+
+/// Raw connection handle, only ever used to interrupt.
+struct Handle(*mut sqlite3);
+
+// safety: sqlite3_interrupt may be called from any thread, and it is
+// the only thing the handle is used for
+unsafe impl Send for Handle {}
+
+/// Reset every statement on `con` left part way through, so nothing
+/// is running and a pending interrupt won't hit the next statement.
+///
+/// Only call once no step is in flight on the worker.
+pub(crate) fn reset_statements(con: &mut SqliteConnection) {
+    let handle = con.as_raw_handle();
+    // safety: we hold the connection and nothing is stepping on it,
+    // resetting a statement sqlx caches is what it does before reuse
+    unsafe {
+        let mut stmt = sqlite3_next_stmt(handle, std::ptr::null_mut());
+        while !stmt.is_null() {
+            if sqlite3_stmt_busy(stmt) != 0 {
+                sqlite3_reset(stmt);
+            }
+            stmt = sqlite3_next_stmt(handle, stmt);
+        }
+    }
+}
+
+/// Lets the side giving up on a read interrupt the connection another
+/// task is reading on.
+///
+/// The reading task [`arm`](Self::arm)s it while it holds the
+/// connection, the other side owns an [`InterruptOnDrop`].
+/// Clones share the same handle.
+#[derive(Clone, Default)]
+pub(crate) struct Interrupt(Arc<Mutex<Option<Handle>>>);
+
+impl Interrupt {
+    /// Point at `con` until the guard is dropped, which has to happen
+    /// before the connection is let go.
+    pub(crate) fn arm(&self, con: &mut SqliteConnection) -> Armed<'_> {
+        *self.0.lock().unwrap() = Some(Handle(con.as_raw_handle()));
+        Armed(self)
+    }
+
+    /// Interrupt the connection, if still armed.
+    pub(crate) fn fire(&self) {
+        if let Some(handle) = &*self.0.lock().unwrap() {
+            // safety: armed only while the connection is held, and
+            // disarming waits on the lock we hold
+            unsafe { sqlite3_interrupt(handle.0) };
+        }
+    }
+}
+
+/// Disarms its [`Interrupt`] when dropped, unwinding included.
+pub(crate) struct Armed<'a>(&'a Interrupt);
+
+impl Drop for Armed<'_> {
+    fn drop(&mut self) {
+        *self.0 .0.lock().unwrap() = None;
+    }
+}
+
+/// Fires its [`Interrupt`] when dropped.
+pub(crate) struct InterruptOnDrop(pub(crate) Interrupt);
+
+impl Drop for InterruptOnDrop {
+    fn drop(&mut self) {
+        self.0.fire();
+    }
+}
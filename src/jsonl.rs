@@ -0,0 +1,287 @@
+//! JSON Lines dumps of the entries and ops tables: one object per row,
+//! tagged with its table, hashes and content in base64.
+//!
+//! Readable without sqlcipher, and loadable into another database.
+
+use crate::db::ENTRY_COLUMNS;
+use crate::{
+    Db, DbRead, DbWrite, DhtOp, DhtOpType, Entry, EntryFilter, EntryHash, OpHash, Timestamp,
+    ValidationStatus, WriteOutcome,
+};
+use chrono::prelude::*;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Entries inserted per transaction by an import.
+const IMPORT_CHUNK: usize = 500;
+
+/// Which rows [`Db::export_jsonl`] writes, every entry and op by default.
+#[derive(Debug, Clone)]
+pub struct JsonlFilter {
+    entries: Option<EntryFilter>,
+    ops: bool,
+}
+
+impl Default for JsonlFilter {
+    fn default() -> Self {
+        Self {
+            entries: Some(EntryFilter::new()),
+            ops: true,
+        }
+    }
+}
+
+impl JsonlFilter {
+    /// Every entry and op.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only entries matching `filter`.
+    pub fn entries(mut self, filter: EntryFilter) -> Self {
+        self.entries = Some(filter);
+        self
+    }
+
+    /// No entries at all.
+    pub fn without_entries(mut self) -> Self {
+        self.entries = None;
+        self
+    }
+
+    /// No ops at all.
+    pub fn without_ops(mut self) -> Self {
+        self.ops = false;
+        self
+    }
+}
+
+mod base64_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&base64::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        base64::decode(String::deserialize(d)?).map_err(serde::de::Error::custom)
+    }
+}
+
+mod base64_option {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Option<Vec<u8>>, s: S) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => s.serialize_str(&base64::encode(bytes)),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(d)?
+            .map(|s| base64::decode(s).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// One line of a dump.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "table")]
+enum Line {
+    #[serde(rename = "entries")]
+    Entry(EntryLine),
+    #[serde(rename = "dht_ops")]
+    Op(OpLine),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EntryLine {
+    #[serde(with = "base64_bytes")]
+    hash: Vec<u8>,
+    /// For reading only, an import works it out from the hash.
+    #[serde(default)]
+    dht_loc: u32,
+    /// Micros since the unix epoch.
+    created_at: i64,
+    entry_type: String,
+    #[serde(with = "base64_bytes")]
+    content: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpLine {
+    #[serde(with = "base64_bytes")]
+    op_hash: Vec<u8>,
+    op_type: DhtOpType,
+    basis_loc: u32,
+    authored_timestamp: DateTime<Utc>,
+    when_integrated: Option<DateTime<Utc>>,
+    validation_status: ValidationStatus,
+    #[serde(with = "base64_option")]
+    dependency: Option<Vec<u8>>,
+}
+
+impl From<&Entry> for EntryLine {
+    fn from(entry: &Entry) -> Self {
+        Self {
+            hash: entry.hash.get_raw_39().to_vec(),
+            dht_loc: entry.dht_loc(),
+            created_at: entry.created_at.0,
+            entry_type: entry.entry_type.to_string(),
+            content: entry.content.clone(),
+        }
+    }
+}
+
+impl EntryLine {
+    fn into_entry(self) -> anyhow::Result<Entry> {
+        Ok(Entry {
+            hash: EntryHash::from_raw_39(&self.hash)?,
+            created_at: Timestamp(self.created_at),
+            entry_type: self.entry_type.parse()?,
+            content: self.content,
+        })
+    }
+}
+
+impl From<&DhtOp> for OpLine {
+    fn from(op: &DhtOp) -> Self {
+        Self {
+            op_hash: op.op_hash.get_raw_39().to_vec(),
+            op_type: op.op_type,
+            basis_loc: op.basis_loc,
+            authored_timestamp: op.authored_timestamp,
+            when_integrated: op.when_integrated,
+            validation_status: op.validation_status,
+            dependency: op
+                .dependency
+                .as_ref()
+                .map(|hash| hash.get_raw_39().to_vec()),
+        }
+    }
+}
+
+impl OpLine {
+    fn into_op(self) -> anyhow::Result<DhtOp> {
+        Ok(DhtOp {
+            op_hash: OpHash::from_raw_39(&self.op_hash)?,
+            op_type: self.op_type,
+            basis_loc: self.basis_loc,
+            authored_timestamp: self.authored_timestamp,
+            when_integrated: self.when_integrated,
+            validation_status: self.validation_status,
+            dependency: self
+                .dependency
+                .map(|raw| OpHash::from_raw_39(&raw))
+                .transpose()?,
+        })
+    }
+}
+
+async fn write_line<W: AsyncWrite + Unpin>(writer: &mut W, line: &Line) -> anyhow::Result<()> {
+    let mut json = serde_json::to_vec(line)?;
+    json.push(b'\n');
+    writer.write_all(&json).await?;
+    Ok(())
+}
+
+impl Db {
+    /// Write the rows `filter` selects to `writer` as JSON Lines,
+    /// see [`DbRead::export_jsonl`].
+    pub async fn export_jsonl<W: AsyncWrite + Unpin>(
+        &self,
+        writer: W,
+        filter: &JsonlFilter,
+    ) -> anyhow::Result<u64> {
+        self.reader().export_jsonl(writer, filter).await
+    }
+
+    /// Load a JSON Lines dump, see [`DbWrite::import_jsonl`].
+    pub async fn import_jsonl<R: AsyncRead + Unpin>(
+        &self,
+        reader: R,
+    ) -> anyhow::Result<WriteOutcome> {
+        self.write.import_jsonl(reader).await
+    }
+}
+
+impl DbRead {
+    /// Write the rows `filter` selects to `writer` as JSON Lines,
+    /// entries then ops, returning how many lines were written.
+    ///
+    /// Everything comes from one read transaction, so the dump is a
+    /// consistent snapshot however slow `writer` is, at the cost of
+    /// pinning a reader connection until it's done.
+    pub async fn export_jsonl<W: AsyncWrite + Unpin>(
+        &self,
+        mut writer: W,
+        filter: &JsonlFilter,
+    ) -> anyhow::Result<u64> {
+        let _permit = self.permits.acquire().await?;
+        let mut tx = self.pool.begin().await?;
+        let mut lines = 0;
+        if let Some(entries) = &filter.entries {
+            let query = entries.select(ENTRY_COLUMNS, None);
+            let mut rows =
+                sqlx::query_as_with::<_, Entry, _>(&query.sql, query.arguments()).fetch(&mut tx);
+            while let Some(entry) = rows.try_next().await? {
+                write_line(&mut writer, &Line::Entry(EntryLine::from(&entry))).await?;
+                lines += 1;
+            }
+        }
+        if filter.ops {
+            let mut rows = sqlx::query_as::<_, DhtOp>("SELECT * FROM dht_ops ORDER BY op_hash;")
+                .fetch(&mut tx);
+            while let Some(op) = rows.try_next().await? {
+                write_line(&mut writer, &Line::Op(OpLine::from(&op))).await?;
+                lines += 1;
+            }
+        }
+        writer.flush().await?;
+        Ok(lines)
+    }
+}
+
+impl DbWrite {
+    /// Load a JSON Lines dump as written by [`DbRead::export_jsonl`].
+    ///
+    /// Entries already held are ignored and ops are merged as by
+    /// [`DbWrite::insert_op`], so loading the same dump twice is harmless.
+    /// Entries are committed in batches: on a bad line, everything
+    /// before its batch stays loaded and the error names the line.
+    pub async fn import_jsonl<R: AsyncRead + Unpin>(
+        &self,
+        reader: R,
+    ) -> anyhow::Result<WriteOutcome> {
+        let mut outcome = WriteOutcome::default();
+        let mut entries = Vec::with_capacity(IMPORT_CHUNK);
+        let mut lines = BufReader::new(reader).lines();
+        let mut number = 0;
+        while let Some(line) = lines.next_line().await? {
+            number += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let at = |err: anyhow::Error| anyhow::anyhow!("line {}: {}", number, err);
+            match serde_json::from_str(&line).map_err(|err| at(err.into()))? {
+                Line::Entry(entry) => entries.push(entry.into_entry().map_err(at)?),
+                Line::Op(op) => outcome += self.insert_op(&op.into_op().map_err(at)?).await?,
+            }
+            if entries.len() == IMPORT_CHUNK {
+                outcome += self.import_entry_chunk(&entries).await?;
+                entries.clear();
+            }
+        }
+        if !entries.is_empty() {
+            outcome += self.import_entry_chunk(&entries).await?;
+        }
+        Ok(outcome)
+    }
+
+    async fn import_entry_chunk(&self, entries: &[Entry]) -> anyhow::Result<WriteOutcome> {
+        let inserted = self.insert_entry_batch(entries, true).await?;
+        Ok(WriteOutcome::inserted(inserted, entries.len() as u64))
+    }
+}
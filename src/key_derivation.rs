@@ -0,0 +1,56 @@
+//! Per-database keys derived from a single master key.
+
+use crate::{DbKind, SecretKey};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+/// Domain separation for the HKDF extract step.
+/// Changing this changes every derived key.
+const SALT: &[u8] = b"spike-sqlx per-database key v1";
+
+/// Derives a unique key for each [`DbKind`] via
+/// `HKDF-SHA256(master_key, info = db_kind || dna_hash [|| agent])`,
+/// so compromising one cell's database key doesn't expose the others.
+#[derive(Debug, Clone)]
+pub struct KeyDerivation {
+    master: SecretKey,
+}
+
+impl KeyDerivation {
+    /// Derive from `master`.
+    pub fn new(master: SecretKey) -> Self {
+        Self { master }
+    }
+
+    /// The key for the database of `kind`.
+    pub fn derive(&self, kind: &DbKind) -> SecretKey {
+        let hk = Hkdf::<Sha256>::new(Some(SALT), self.master.as_bytes());
+        let mut key = [0; 32];
+        hk.expand(&info(kind), &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        let out = SecretKey::new(key);
+        zeroize::Zeroize::zeroize(&mut key);
+        out
+    }
+}
+
+/// Unambiguous encoding of `kind`: every variable length
+/// component is length-prefixed, so no two kinds share an encoding.
+fn info(kind: &DbKind) -> Vec<u8> {
+    fn push(out: &mut Vec<u8>, part: &[u8]) {
+        out.extend_from_slice(&(part.len() as u32).to_be_bytes());
+        out.extend_from_slice(part);
+    }
+
+    let mut out = Vec::new();
+    push(&mut out, kind.name().as_bytes());
+    match kind {
+        DbKind::Authored(dna, agent) => {
+            push(&mut out, &dna.0);
+            push(&mut out, agent.get_raw_39());
+        }
+        DbKind::Dht(dna) | DbKind::Cache(dna) => push(&mut out, &dna.0),
+        DbKind::Conductor => (),
+    }
+    out
+}
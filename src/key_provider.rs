@@ -0,0 +1,396 @@
+//! Sourcing the database encryption key.
+
+use crate::{DbKind, KeyDerivation};
+use futures::future::BoxFuture;
+use lair_keystore_api::actor::{LairClientApi, LairClientApiSender};
+use lair_keystore_api::internal::sign_ed25519::SignEd25519PubKey;
+use std::path::PathBuf;
+use std::sync::Arc;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Message lair signs to derive the database key.
+/// Changing this changes every derived key.
+const LAIR_KEY_CONTEXT: &[u8] = b"spike-sqlx database encryption key v1";
+
+/// Simulate getting an encryption key from Lair.
+#[cfg(feature = "test-keys")]
+fn get_encryption_key_shim() -> [u8; 32] {
+    [
+        26, 111, 7, 31, 52, 204, 156, 103, 203, 171, 156, 89, 98, 51, 158, 143, 57, 134, 93, 56,
+        199, 225, 53, 141, 39, 77, 145, 130, 136, 108, 96, 201,
+    ]
+}
+
+/// A 32 byte database key, wiped from memory on drop.
+/// Deliberately not `Debug`-printable beyond its type name.
+#[derive(Clone)]
+pub struct SecretKey([u8; 32]);
+
+impl SecretKey {
+    /// Wrap raw key bytes.
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    /// Borrow the raw key bytes.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Parse 64 hex digits.
+    pub fn from_hex(hex: &str) -> anyhow::Result<Self> {
+        fn nibble(c: u8) -> anyhow::Result<u8> {
+            match c {
+                b'0'..=b'9' => Ok(c - b'0'),
+                b'a'..=b'f' => Ok(c - b'a' + 10),
+                b'A'..=b'F' => Ok(c - b'A' + 10),
+                _ => anyhow::bail!("invalid hex digit in key"),
+            }
+        }
+
+        let hex = hex.as_bytes();
+        if hex.len() != 64 {
+            anyhow::bail!("expected a 64 hex digit key, got {} digits", hex.len());
+        }
+        let mut key = Self([0; 32]);
+        for (b, pair) in key.0.iter_mut().zip(hex.chunks(2)) {
+            *b = nibble(pair[0])? << 4 | nibble(pair[1])?;
+        }
+        Ok(key)
+    }
+
+    /// A NUL terminated `<prefix>"x'<hex>'"<suffix>` statement,
+    /// wiped when the returned buffer is dropped.
+    pub(crate) fn statement(&self, prefix: &str, suffix: &str) -> Zeroizing<Vec<u8>> {
+        const HEX: &[u8; 16] = b"0123456789ABCDEF";
+        // reserve up front so the buffer never reallocates,
+        // which would leave an unwiped copy behind
+        let mut cmd = Zeroizing::new(Vec::with_capacity(prefix.len() + suffix.len() + 5 + 64));
+        cmd.extend_from_slice(prefix.as_bytes());
+        cmd.extend_from_slice(b"\"x'");
+        for b in &self.0 {
+            cmd.push(HEX[(b >> 4) as usize]);
+            cmd.push(HEX[(b & 0xf) as usize]);
+        }
+        cmd.extend_from_slice(b"'\"");
+        cmd.extend_from_slice(suffix.as_bytes());
+        cmd.push(0);
+        cmd
+    }
+}
+
+impl From<[u8; 32]> for SecretKey {
+    fn from(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl std::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretKey(..)")
+    }
+}
+
+/// Whatever is handed to `PRAGMA key`.
+#[derive(Clone)]
+pub(crate) enum DbKey {
+    /// Used as the cipher key directly.
+    Raw(SecretKey),
+    /// Stretched into the cipher key by SQLCipher's KDF.
+    Passphrase(Zeroizing<String>),
+}
+
+impl DbKey {
+    /// A NUL terminated `<prefix><key literal><suffix>` statement,
+    /// wiped when the returned buffer is dropped.
+    pub(crate) fn statement(&self, prefix: &str, suffix: &str) -> Zeroizing<Vec<u8>> {
+        match self {
+            Self::Raw(key) => key.statement(prefix, suffix),
+            Self::Passphrase(pass) => {
+                // worst case every byte is a quote needing escaping
+                let mut cmd = Zeroizing::new(Vec::with_capacity(
+                    prefix.len() + suffix.len() + 3 + 2 * pass.len(),
+                ));
+                cmd.extend_from_slice(prefix.as_bytes());
+                cmd.push(b'\'');
+                for &b in pass.as_bytes() {
+                    if b == b'\'' {
+                        cmd.push(b'\'');
+                    }
+                    cmd.push(b);
+                }
+                cmd.push(b'\'');
+                cmd.extend_from_slice(suffix.as_bytes());
+                cmd.push(0);
+                cmd
+            }
+        }
+    }
+}
+
+/// A pluggable backend for database keys,
+/// so deployments can swap where keys live without code changes.
+pub trait EncryptionKeyProvider: Send + Sync {
+    /// The key for the database of `kind`,
+    /// `None` when the database was opened by path rather than kind.
+    fn get_key<'a>(&'a self, kind: Option<&'a DbKind>) -> BoxFuture<'a, anyhow::Result<SecretKey>>;
+}
+
+/// Always hands out the same key.
+#[derive(Debug, Clone)]
+pub struct StaticKeyProvider(SecretKey);
+
+impl StaticKeyProvider {
+    /// Hand out `key` for every database.
+    pub fn new(key: SecretKey) -> Self {
+        Self(key)
+    }
+
+    /// The fixed shim key standing in for Lair.
+    #[cfg(feature = "test-keys")]
+    pub fn shim() -> Self {
+        Self(SecretKey(get_encryption_key_shim()))
+    }
+}
+
+impl EncryptionKeyProvider for StaticKeyProvider {
+    fn get_key<'a>(&'a self, _: Option<&'a DbKind>) -> BoxFuture<'a, anyhow::Result<SecretKey>> {
+        Box::pin(async move { Ok(self.0.clone()) })
+    }
+}
+
+/// Reads a 64 hex digit key from an environment variable.
+#[derive(Debug, Clone)]
+pub struct EnvKeyProvider {
+    var: String,
+}
+
+impl EnvKeyProvider {
+    /// Read the key from `$var` each time one is needed.
+    pub fn new<S: Into<String>>(var: S) -> Self {
+        Self { var: var.into() }
+    }
+}
+
+impl EncryptionKeyProvider for EnvKeyProvider {
+    fn get_key<'a>(&'a self, _: Option<&'a DbKind>) -> BoxFuture<'a, anyhow::Result<SecretKey>> {
+        Box::pin(async move {
+            let hex = Zeroizing::new(
+                std::env::var(&self.var)
+                    .map_err(|e| anyhow::anyhow!("reading key from ${}: {}", self.var, e))?,
+            );
+            SecretKey::from_hex(hex.trim())
+        })
+    }
+}
+
+/// Reads the key from a file holding either
+/// the 32 raw key bytes or 64 hex digits.
+#[derive(Debug, Clone)]
+pub struct FileKeyProvider {
+    path: PathBuf,
+}
+
+impl FileKeyProvider {
+    /// Read the key from `path` each time one is needed.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl EncryptionKeyProvider for FileKeyProvider {
+    fn get_key<'a>(&'a self, _: Option<&'a DbKind>) -> BoxFuture<'a, anyhow::Result<SecretKey>> {
+        Box::pin(async move {
+            let bytes =
+                Zeroizing::new(tokio::fs::read(&self.path).await.map_err(|e| {
+                    anyhow::anyhow!("reading key from {}: {}", self.path.display(), e)
+                })?);
+            if bytes.len() == 32 {
+                let mut key = SecretKey([0; 32]);
+                key.0.copy_from_slice(&bytes);
+                return Ok(key);
+            }
+            match std::str::from_utf8(&bytes) {
+                Ok(hex) => SecretKey::from_hex(hex.trim()),
+                Err(_) => {
+                    anyhow::bail!("{} holds neither a raw nor a hex key", self.path.display())
+                }
+            }
+        })
+    }
+}
+
+impl EncryptionKeyProvider for KeyDerivation {
+    fn get_key<'a>(&'a self, kind: Option<&'a DbKind>) -> BoxFuture<'a, anyhow::Result<SecretKey>> {
+        Box::pin(async move {
+            match kind {
+                Some(kind) => Ok(self.derive(kind)),
+                None => anyhow::bail!("derived keys need a DbKind, open with Db::open_kind"),
+            }
+        })
+    }
+}
+
+/// Fetches the database key from a running lair keystore.
+///
+/// Lair never hands out secret bytes, so the key is derived by having
+/// lair sign a fixed context message with an ed25519 keypair it holds.
+/// ed25519 signatures are deterministic, so the same keypair always
+/// yields the same database key.
+#[derive(Clone)]
+pub struct LairKeyProvider {
+    client: ghost_actor::GhostSender<LairClientApi>,
+    pub_key: SignEd25519PubKey,
+}
+
+impl LairKeyProvider {
+    /// Derive keys from the lair keypair identified by `pub_key`.
+    pub fn new(
+        client: ghost_actor::GhostSender<LairClientApi>,
+        pub_key: SignEd25519PubKey,
+    ) -> Self {
+        Self { client, pub_key }
+    }
+
+    /// Connect to the lair ipc socket under `lair_root`.
+    pub async fn connect<P: AsRef<std::path::Path>>(
+        lair_root: P,
+        pub_key: SignEd25519PubKey,
+    ) -> anyhow::Result<Self> {
+        let config = lair_keystore_api::Config::builder()
+            .set_root_path(lair_root.as_ref())
+            .build();
+        let (client, _evt) = lair_keystore_api::ipc::spawn_client_ipc(config).await?;
+        Ok(Self::new(client, pub_key))
+    }
+
+    /// Fetch the 32 byte database key.
+    pub async fn get_key(&self) -> anyhow::Result<SecretKey> {
+        let sig = self
+            .client
+            .sign_ed25519_sign_by_pub_key(self.pub_key.clone(), Arc::new(LAIR_KEY_CONTEXT.to_vec()))
+            .await?;
+        // the signature is the key material, wipe it once hashed. Should
+        // the client still share it, its copy is out of our reach
+        let sig = Zeroizing::new(Arc::try_unwrap(sig.0).unwrap_or_else(|sig| sig.to_vec()));
+        let mut hash = blake2b_simd::Params::new().hash_length(32).hash(&sig);
+        let mut key = SecretKey([0; 32]);
+        key.0.copy_from_slice(hash.as_bytes());
+        wipe_hash(&mut hash);
+        Ok(key)
+    }
+}
+
+/// Zero `hash`, which blake2b_simd gives no way to do.
+fn wipe_hash(hash: &mut blake2b_simd::Hash) {
+    // safety: a Hash is a byte array and its length, with no padding
+    // or invariant that all zeroes would break
+    let bytes = unsafe {
+        std::slice::from_raw_parts_mut(
+            hash as *mut blake2b_simd::Hash as *mut u8,
+            std::mem::size_of::<blake2b_simd::Hash>(),
+        )
+    };
+    bytes.zeroize();
+}
+
+impl EncryptionKeyProvider for LairKeyProvider {
+    fn get_key<'a>(&'a self, _: Option<&'a DbKind>) -> BoxFuture<'a, anyhow::Result<SecretKey>> {
+        Box::pin(LairKeyProvider::get_key(self))
+    }
+}
+
+/// Where the 32 byte database encryption key comes from.
+#[derive(Clone)]
+pub enum KeySource {
+    /// The fixed shim key standing in for Lair.
+    #[cfg(feature = "test-keys")]
+    Shim,
+    /// An explicit raw key.
+    Raw(SecretKey),
+    /// Derived by a lair keystore.
+    Lair(LairKeyProvider),
+    /// Derived per database from a master key,
+    /// only usable with [`crate::Db::open_kind`].
+    Derived(KeyDerivation),
+    /// Any other backend.
+    Provider(Arc<dyn EncryptionKeyProvider>),
+    /// A human-chosen passphrase, run through SQLCipher's own KDF
+    /// (see [`crate::DbConfig::kdf_iter`]) rather than used as the key.
+    /// Meant for tooling and manual recovery, not the conductor.
+    /// Wiped from memory on drop, see [`KeySource::passphrase`].
+    Passphrase(Zeroizing<String>),
+}
+
+impl std::fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "test-keys")]
+            Self::Shim => f.write_str("Shim"),
+            // never print key material
+            Self::Raw(_) => f.write_str("Raw(..)"),
+            Self::Lair(p) => write!(f, "Lair({:?})", p.pub_key),
+            Self::Derived(_) => f.write_str("Derived(..)"),
+            Self::Provider(_) => f.write_str("Provider(..)"),
+            Self::Passphrase(_) => f.write_str("Passphrase(..)"),
+        }
+    }
+}
+
+/// Whether, and how, the database file is encrypted.
+#[derive(Debug, Clone)]
+pub enum Encryption {
+    /// Plain sqlite, no key pragma is ever issued.
+    /// For local development, CI and benchmarking without SQLCipher.
+    None,
+    /// SQLCipher keyed from the given source.
+    SqlCipher(KeySource),
+}
+
+impl KeySource {
+    /// Wrap a custom backend.
+    pub fn provider<P: EncryptionKeyProvider + 'static>(provider: P) -> Self {
+        Self::Provider(Arc::new(provider))
+    }
+
+    /// Key with a passphrase, see [`KeySource::Passphrase`].
+    pub fn passphrase(pass: impl Into<String>) -> Self {
+        Self::Passphrase(Zeroizing::new(pass.into()))
+    }
+
+    /// Resolve what to hand `PRAGMA key` for the database of `kind`.
+    pub(crate) async fn db_key(&self, kind: Option<&DbKind>) -> anyhow::Result<DbKey> {
+        match self {
+            Self::Passphrase(pass) => {
+                // sqlite would stop reading the statement at the NUL
+                if pass.is_empty() || pass.contains('\0') {
+                    anyhow::bail!("passphrase must be non-empty and free of NUL bytes");
+                }
+                Ok(DbKey::Passphrase(pass.clone()))
+            }
+            _ => Ok(DbKey::Raw(self.get_key(kind).await?)),
+        }
+    }
+}
+
+impl EncryptionKeyProvider for KeySource {
+    fn get_key<'a>(&'a self, kind: Option<&'a DbKind>) -> BoxFuture<'a, anyhow::Result<SecretKey>> {
+        Box::pin(async move {
+            match self {
+                #[cfg(feature = "test-keys")]
+                Self::Shim => Ok(SecretKey(get_encryption_key_shim())),
+                Self::Raw(key) => Ok(key.clone()),
+                Self::Lair(p) => LairKeyProvider::get_key(p).await,
+                Self::Derived(d) => d.get_key(kind).await,
+                Self::Provider(p) => p.get_key(kind).await,
+                Self::Passphrase(_) => anyhow::bail!("a passphrase has no raw key bytes"),
+            }
+        })
+    }
+}
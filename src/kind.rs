@@ -0,0 +1,52 @@
+use crate::AgentPubKey;
+use std::path::{Path, PathBuf};
+
+/// Hash identifying a DNA.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DnaHash(pub Vec<u8>);
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Which database a file holds.
+/// Holochain keeps one encrypted database per cell / space.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DbKind {
+    /// Source chain data authored by one agent in one DNA.
+    Authored(DnaHash, AgentPubKey),
+    /// Data this node holds for the DHT of a DNA.
+    Dht(DnaHash),
+    /// Data fetched from the network and cached, not held for the DHT.
+    Cache(DnaHash),
+    /// Conductor-wide state.
+    Conductor,
+}
+
+impl DbKind {
+    /// Short name of this kind, also used as the directory name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Authored(..) => "authored",
+            Self::Dht(_) => "dht",
+            Self::Cache(_) => "cache",
+            Self::Conductor => "conductor",
+        }
+    }
+
+    /// The database file for this kind under `data_root`.
+    pub fn path<P: AsRef<Path>>(&self, data_root: P) -> PathBuf {
+        let file = match self {
+            Self::Authored(dna, agent) => {
+                format!("{}-{}", to_hex(&dna.0), to_hex(agent.get_raw_39()))
+            }
+            Self::Dht(dna) | Self::Cache(dna) => to_hex(&dna.0),
+            Self::Conductor => "conductor".to_string(),
+        };
+        data_root
+            .as_ref()
+            .join(self.name())
+            .join(file)
+            .with_extension("sqlite3")
+    }
+}
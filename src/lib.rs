@@ -0,0 +1,131 @@
+//! Spike exploring encrypted-at-rest sqlite storage for Holochain via sqlx.
+
+mod actor;
+mod agent_store;
+mod analyze;
+mod attach;
+mod audit;
+mod backend;
+mod backup;
+mod blob;
+mod capability;
+mod changes;
+mod checkpoint;
+mod commit;
+mod config;
+mod console;
+mod csv;
+mod db;
+mod dht_op;
+mod dump;
+mod element;
+mod entry;
+mod error;
+mod explain;
+mod export;
+mod filter;
+mod functions;
+mod hash;
+mod hash_list;
+mod header;
+mod histogram;
+mod import;
+mod index;
+mod inspect;
+mod integrity;
+mod interrupt;
+mod jsonl;
+mod key_derivation;
+mod key_provider;
+mod kind;
+mod link;
+mod loc;
+mod metrics;
+mod migrations;
+mod open_key;
+#[cfg(feature = "otel")]
+mod otel;
+mod outcome;
+mod page;
+mod permit;
+#[cfg(feature = "postgres")]
+mod postgres_db;
+mod reader;
+mod receipt;
+mod rekey;
+mod retry;
+mod row_counts;
+#[cfg(feature = "rusqlite-backend")]
+mod rusqlite_backend;
+#[cfg(feature = "rusqlite-backend")]
+mod rusqlite_db;
+mod search;
+mod slow_query;
+mod snapshot;
+mod statement_cache;
+mod stats;
+mod stream;
+mod timestamp;
+mod trace;
+mod vacuum;
+mod write_queue;
+mod writer;
+
+pub use actor::*;
+pub use agent_store::*;
+pub use audit::*;
+pub use backend::*;
+pub use blob::*;
+pub use capability::*;
+pub use changes::*;
+pub use checkpoint::*;
+pub use commit::*;
+pub use config::*;
+pub use console::*;
+pub use csv::*;
+pub use db::*;
+pub use dht_op::*;
+pub use dump::*;
+pub use element::*;
+pub use entry::*;
+pub use error::*;
+pub use explain::*;
+pub use filter::*;
+pub use functions::*;
+pub use hash::*;
+pub use header::*;
+pub use histogram::*;
+pub use import::*;
+pub use index::*;
+pub use inspect::*;
+pub use integrity::*;
+pub use jsonl::*;
+pub use key_derivation::*;
+pub use key_provider::*;
+pub use kind::*;
+pub use link::*;
+pub use loc::*;
+pub use metrics::*;
+#[cfg(feature = "otel")]
+pub use otel::*;
+pub use outcome::*;
+pub use page::*;
+pub use permit::*;
+#[cfg(feature = "postgres")]
+pub use postgres_db::*;
+pub use reader::*;
+pub use receipt::*;
+pub use retry::*;
+pub use row_counts::*;
+#[cfg(feature = "rusqlite-backend")]
+pub use rusqlite_backend::*;
+#[cfg(feature = "rusqlite-backend")]
+pub use rusqlite_db::*;
+pub use snapshot::*;
+pub use statement_cache::*;
+pub use stats::*;
+pub use timestamp::*;
+pub use trace::TraceVerbosity;
+pub use vacuum::*;
+pub use write_queue::*;
+pub use writer::*;
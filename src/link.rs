@@ -0,0 +1,183 @@
+use crate::retry::with_retry;
+use crate::{Db, DbRead, DbWrite, EntryHash, HeaderHash, WriteOutcome};
+use futures::TryStreamExt;
+use sqlx::Execute;
+
+/// Demo link type for database.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Link {
+    /// The CreateLink header that made this link.
+    pub create_header: HeaderHash,
+    pub base_hash: EntryHash,
+    pub target_hash: EntryHash,
+    pub tag: Vec<u8>,
+    pub zome_index: u8,
+    pub link_type: u8,
+    /// The DeleteLink header that removed this link, if any.
+    pub delete_header: Option<HeaderHash>,
+}
+
+impl Link {
+    /// Generate a random live link from `base_hash` with `tag`.
+    pub fn rand(base_hash: EntryHash, tag: Vec<u8>) -> Self {
+        Self {
+            create_header: HeaderHash::rand(),
+            base_hash,
+            target_hash: EntryHash::rand(),
+            tag,
+            zome_index: 0,
+            link_type: 0,
+            delete_header: None,
+        }
+    }
+}
+
+/// Smallest blob greater than every blob starting with `prefix`,
+/// `None` if there is no such blob (all `0xff`, or empty).
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+impl Db {
+    /// Insert a single link in its own transaction.
+    pub async fn insert_link(&self, link: &Link) -> anyhow::Result<WriteOutcome> {
+        self.write.insert_link(link).await
+    }
+
+    /// Mark the link made by `create_header` as deleted by `delete_header`.
+    pub async fn delete_link(
+        &self,
+        create_header: &HeaderHash,
+        delete_header: &HeaderHash,
+    ) -> anyhow::Result<()> {
+        self.write.delete_link(create_header, delete_header).await
+    }
+
+    /// Fetch the live links on `base` whose tag starts with `tag_prefix`,
+    /// ordered by tag.
+    pub async fn get_links(
+        &self,
+        base: &EntryHash,
+        tag_prefix: &[u8],
+    ) -> anyhow::Result<Vec<Link>> {
+        self.reader().get_links(base, tag_prefix).await
+    }
+}
+
+pub(crate) const INSERT_LINK: &str = "INSERT INTO links
+    (create_header, base_hash, target_hash, tag,
+        zome_index, link_type, delete_header)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
+
+impl DbWrite {
+    /// Insert a single link in its own transaction.
+    pub async fn insert_link(&self, link: &Link) -> anyhow::Result<WriteOutcome> {
+        let res = with_retry(
+            self.trace.op("insert_link"),
+            &self.retry,
+            &self.permits,
+            || async {
+                sqlx::query(INSERT_LINK)
+                    .bind(&link.create_header)
+                    .bind(&link.base_hash)
+                    .bind(&link.target_hash)
+                    .bind(&link.tag)
+                    .bind(link.zome_index)
+                    .bind(link.link_type)
+                    .bind(&link.delete_header)
+                    .execute(&self.pool)
+                    .await
+            },
+        )
+        .await?;
+        Ok(WriteOutcome::inserted(res.rows_affected(), 1))
+    }
+
+    /// Mark the link made by `create_header` as deleted by `delete_header`.
+    /// Fails if the link isn't stored.
+    pub async fn delete_link(
+        &self,
+        create_header: &HeaderHash,
+        delete_header: &HeaderHash,
+    ) -> anyhow::Result<()> {
+        let query = || {
+            sqlx::query!(
+                "UPDATE links SET delete_header = ?2 WHERE create_header = ?1",
+                create_header,
+                delete_header
+            )
+        };
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let res = with_retry(
+            self.trace
+                .op("delete_link")
+                .statement(sql, &[&create_header, &delete_header]),
+            &self.retry,
+            &self.permits,
+            || async { query().execute(&self.pool).await },
+        )
+        .await?;
+        if res.rows_affected() == 0 {
+            anyhow::bail!("no such link");
+        }
+        Ok(())
+    }
+}
+
+impl DbRead {
+    /// Fetch the live links on `base` whose tag starts with `tag_prefix`,
+    /// ordered by tag.
+    pub async fn get_links(
+        &self,
+        base: &EntryHash,
+        tag_prefix: &[u8],
+    ) -> anyhow::Result<Vec<Link>> {
+        let end = prefix_end(tag_prefix);
+        // a bounded range rather than LIKE / substr, so sqlite
+        // can seek straight to the prefix on links_base_tag_idx
+        let sql = match end {
+            Some(_) => {
+                "SELECT * FROM links
+                WHERE base_hash = ?1
+                AND tag >= ?2
+                AND tag < ?3
+                AND delete_header IS NULL
+                ORDER BY tag
+                ;"
+            }
+            None => {
+                "SELECT * FROM links
+                WHERE base_hash = ?1
+                AND tag >= ?2
+                AND delete_header IS NULL
+                ORDER BY tag
+                ;"
+            }
+        };
+        self.explain.check(&self.pool, sql).await;
+        let out = with_retry(
+            self.trace
+                .op("get_links")
+                .statement(sql, &[&base, &tag_prefix, &end]),
+            &self.retry,
+            &self.permits,
+            || async {
+                let mut query = sqlx::query_as::<_, Link>(sql).bind(base).bind(tag_prefix);
+                if let Some(end) = &end {
+                    query = query.bind(end);
+                }
+                query.fetch(&self.pool).try_collect::<Vec<_>>().await
+            },
+        )
+        .await?;
+        Ok(out)
+    }
+}
@@ -0,0 +1,51 @@
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, Sqlite, Type};
+use std::convert::TryFrom;
+
+/// A location on the DHT, as bound to and read from sqlite.
+///
+/// sqlite only has signed integers, so this is stored widened to 64
+/// bits: every location is non-negative and compares in the same order
+/// as the `u32` it came from. Squeezing it into 32 bits would make the
+/// upper half of the space negative and break arc range queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DhtLocation(pub u32);
+
+impl From<u32> for DhtLocation {
+    fn from(loc: u32) -> Self {
+        Self(loc)
+    }
+}
+
+impl From<DhtLocation> for u32 {
+    fn from(loc: DhtLocation) -> Self {
+        loc.0
+    }
+}
+
+impl Type<Sqlite> for DhtLocation {
+    fn type_info() -> SqliteTypeInfo {
+        <i64 as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <i64 as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for DhtLocation {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        args.push(SqliteArgumentValue::Int64(i64::from(self.0)));
+        IsNull::No
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for DhtLocation {
+    /// Fails on anything outside the `u32` range rather than wrapping.
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let loc = <i64 as Decode<Sqlite>>::decode(value)?;
+        Ok(Self(u32::try_from(loc)?))
+    }
+}
@@ -1,177 +1,580 @@
+//! Exercise and measure the spike from the command line.
+//!
+//! ```shell
+//! spike-sqlx init --path db.sqlite3 --key-file db.key
+//! spike-sqlx insert --path db.sqlite3 --key-file db.key --count 10000
+//! spike-sqlx query --path db.sqlite3 --key-file db.key --loc-start 0 --loc-end 1000000
+//! spike-sqlx bench --path db.sqlite3 --key-file db.key --profile write-heavy
+//! spike-sqlx repl --path db.sqlite3 --lair-root ~/.lair --lair-pub-key <base64>
+//! spike-sqlx key-rotate --path db.sqlite3 --key-file db.key --new-key-file new.key
+//! ```
+//!
+//! Without `--key-file` or `--lair-root` the database is plain sqlite.
+
 use chrono::prelude::*;
-use futures::StreamExt;
+use rand::seq::SliceRandom;
 use rand::Rng;
-use sqlx::*;
-
-/// Simulate getting an encryption key from Lair.
-fn get_encryption_key_shim() -> [u8; 32] {
-    [
-        26, 111, 7, 31, 52, 204, 156, 103, 203, 171, 156, 89, 98, 51, 158, 143, 57, 134, 93, 56,
-        199, 225, 53, 141, 39, 77, 145, 130, 136, 108, 96, 201,
-    ]
-}
-
-/// Demo entry type for database.
-#[derive(Debug, Clone, sqlx::FromRow)]
-pub struct Entry {
-    hash: Vec<u8>,
-    dht_loc: u32,
-    created_at: DateTime<Utc>,
-}
-
-impl Entry {
-    /// Generate a random entry
-    pub fn rand() -> Self {
-        let mut hash = vec![0; 4];
-        rand::thread_rng().fill(&mut hash[..]);
-
-        Self {
-            hash,
-            dht_loc: rand::thread_rng().gen(),
-            created_at: Utc::now(),
+use spike_sqlx::*;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use tokio::io::AsyncBufReadExt;
+
+/// Where the database is, and how it's keyed.
+#[derive(Debug, StructOpt)]
+struct DbArgs {
+    /// The database file, or a sqlx url such as `sqlite::memory:`.
+    #[structopt(long, parse(from_os_str))]
+    path: PathBuf,
+    /// A file holding the SQLCipher key, as 32 raw bytes or 64 hex
+    /// digits.
+    #[structopt(long, parse(from_os_str))]
+    key_file: Option<PathBuf>,
+    /// The root directory of a running lair keystore to derive the key
+    /// with, as the conductor does.
+    #[structopt(
+        long,
+        parse(from_os_str),
+        conflicts_with = "key-file",
+        requires = "lair-pub-key"
+    )]
+    lair_root: Option<PathBuf>,
+    /// The lair keypair the key is derived from, in base64.
+    #[structopt(long, requires = "lair-root")]
+    lair_pub_key: Option<String>,
+}
+
+impl DbArgs {
+    /// Where the key comes from, `None` for plain sqlite.
+    async fn key_source(&self) -> anyhow::Result<Option<KeySource>> {
+        let source = match (&self.key_file, &self.lair_root, &self.lair_pub_key) {
+            (Some(key_file), _, _) => {
+                Some(KeySource::provider(FileKeyProvider::new(key_file.clone())))
+            }
+            (None, Some(lair_root), Some(pub_key)) => {
+                let pub_key = base64::decode(pub_key)?;
+                let lair = LairKeyProvider::connect(lair_root, pub_key.into()).await?;
+                Some(KeySource::Lair(lair))
+            }
+            _ => None,
+        };
+        Ok(source)
+    }
+
+    async fn open(&self) -> anyhow::Result<Db> {
+        let encryption = match self.key_source().await? {
+            Some(source) => Encryption::SqlCipher(source),
+            None => Encryption::None,
+        };
+        Db::open_with(&self.path, DbConfig::new().encryption(encryption)).await
+    }
+}
+
+/// What a benchmark spends its time on.
+#[derive(Debug, Clone, Copy)]
+enum Profile {
+    WriteHeavy,
+    ReadHeavy,
+    Mixed,
+}
+
+impl Profile {
+    /// The share of operations that insert, the rest read.
+    fn write_ratio(self) -> f64 {
+        match self {
+            Self::WriteHeavy => 0.9,
+            Self::ReadHeavy => 0.1,
+            Self::Mixed => 0.5,
         }
     }
 }
 
-async fn make_connection<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<SqliteConnection> {
-    let mut con = SqliteConnection::connect(&path.as_ref().to_string_lossy()).await?;
+impl FromStr for Profile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "write-heavy" => Ok(Self::WriteHeavy),
+            "read-heavy" => Ok(Self::ReadHeavy),
+            "mixed" => Ok(Self::Mixed),
+            _ => anyhow::bail!(
+                "unknown profile {}, expected write-heavy, read-heavy or mixed",
+                s
+            ),
+        }
+    }
+}
+
+/// An RFC 3339 time, or microseconds since the unix epoch.
+fn parse_time(s: &str) -> anyhow::Result<Timestamp> {
+    match s.parse::<i64>() {
+        Ok(micros) => Ok(Timestamp(micros)),
+        Err(_) => Ok(DateTime::parse_from_rfc3339(s)?.with_timezone(&Utc).into()),
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Exercise and measure the sqlx entry database")]
+enum Command {
+    /// Create the database, and its key file if there isn't one yet.
+    Init {
+        #[structopt(flatten)]
+        db: DbArgs,
+    },
+    /// Insert random entries.
+    Insert {
+        #[structopt(flatten)]
+        db: DbArgs,
+        #[structopt(long, default_value = "1000")]
+        count: usize,
+        /// Bytes of random content per entry.
+        #[structopt(long, default_value = "256")]
+        content_bytes: usize,
+        /// Entries per transaction.
+        #[structopt(long, default_value = "1000")]
+        batch: usize,
+    },
+    /// Print the entries in a location and time range.
+    Query {
+        #[structopt(flatten)]
+        db: DbArgs,
+        #[structopt(long, default_value = "0")]
+        loc_start: u32,
+        /// Below `loc-start` wraps past the end of the location space.
+        #[structopt(long, default_value = "4294967295")]
+        loc_end: u32,
+        /// RFC 3339, or microseconds since the unix epoch.
+        #[structopt(long, parse(try_from_str = parse_time))]
+        since: Option<Timestamp>,
+        /// RFC 3339, or microseconds since the unix epoch.
+        #[structopt(long, parse(try_from_str = parse_time))]
+        until: Option<Timestamp>,
+        #[structopt(long)]
+        limit: Option<u32>,
+    },
+    /// Run a workload for a while and report throughput and latencies.
+    Bench {
+        #[structopt(flatten)]
+        db: DbArgs,
+        /// write-heavy, read-heavy or mixed.
+        #[structopt(long, default_value = "mixed")]
+        profile: Profile,
+        #[structopt(long, default_value = "10")]
+        seconds: u64,
+        /// Tasks running operations at once.
+        #[structopt(long, default_value = "8")]
+        concurrency: usize,
+        /// Entries inserted before timing starts, for reads to find.
+        #[structopt(long, default_value = "1000")]
+        seed: usize,
+    },
+    /// Print the schema version, tables and indexes, row counts, file
+    /// and WAL sizes, and the pragmas the writer runs with.
+    Inspect {
+        #[structopt(flatten)]
+        db: DbArgs,
+    },
+    /// Re-encrypt the database under a new key and check it reopens
+    /// with it.
+    ///
+    /// The current key comes from `--key-file` or `--lair-root` and
+    /// `--lair-pub-key` as for every command, the new one from
+    /// `--new-key-file`, written with a random key if it doesn't exist,
+    /// or `--new-lair-pub-key` under the same lair. Nothing else may
+    /// have the database open meanwhile. Refuses unless this build's
+    /// sqlite is SQLCipher.
+    KeyRotate {
+        #[structopt(flatten)]
+        db: DbArgs,
+        /// A file holding the new key, as 32 raw bytes or 64 hex digits.
+        #[structopt(
+            long,
+            parse(from_os_str),
+            required_unless = "new-lair-pub-key",
+            conflicts_with = "new-lair-pub-key"
+        )]
+        new_key_file: Option<PathBuf>,
+        /// The lair keypair to derive the new key from, in base64.
+        #[structopt(long, requires = "lair-root")]
+        new_lair_pub_key: Option<String>,
+        /// Copy the database here, under the current key, before
+        /// rotating.
+        #[structopt(long, parse(from_os_str))]
+        backup: Option<PathBuf>,
+    },
+    /// Run SQL typed at a prompt, printing what it returns as a table.
+    ///
+    /// Statements end with `;` and may span lines. `.tables`,
+    /// `.schema [table]` and `.quit` work as in the sqlite3 shell.
+    Repl {
+        #[structopt(flatten)]
+        db: DbArgs,
+    },
+}
+
+fn random_entry(content_bytes: usize) -> Entry {
+    let mut rng = rand::thread_rng();
+    Entry::from_content((0..content_bytes).map(|_| rng.gen()).collect())
+}
+
+/// Write a random key to `key_file` in hex, readable only by its owner,
+/// unless the file is already there.
+fn create_key_file(key_file: &Path) -> anyhow::Result<()> {
+    if key_file.exists() {
+        return Ok(());
+    }
+    let key: [u8; 32] = rand::thread_rng().gen();
+    let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+    std::fs::write(key_file, hex)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(key_file, std::fs::Permissions::from_mode(0o600))?;
+    }
+    println!("wrote a new key to {}", key_file.display());
+    Ok(())
+}
+
+async fn init(args: DbArgs) -> anyhow::Result<()> {
+    if let Some(key_file) = &args.key_file {
+        create_key_file(key_file)?;
+    }
+    let db = args.open().await?;
+    let stats = db.stats().await?;
+    println!(
+        "{} is ready, {} tables in {} bytes",
+        args.path.display(),
+        stats.tables.len(),
+        stats.disk_bytes()
+    );
+    db.close().await
+}
+
+async fn insert(
+    args: DbArgs,
+    count: usize,
+    content_bytes: usize,
+    batch: usize,
+) -> anyhow::Result<()> {
+    let db = args.open().await?;
+    let started = Instant::now();
+    let mut inserted = 0;
+    let mut left = count;
+    while left > 0 {
+        let entries: Vec<_> = (0..left.min(batch.max(1)))
+            .map(|_| random_entry(content_bytes))
+            .collect();
+        left -= entries.len();
+        inserted += db.insert_entries(&entries).await?.inserted;
+    }
+    let elapsed = started.elapsed();
+    println!(
+        "inserted {} entries in {:.2?}, {:.0} per second",
+        inserted,
+        elapsed,
+        inserted as f64 / elapsed.as_secs_f64()
+    );
+    db.close().await
+}
+
+async fn query(args: DbArgs, filter: EntryFilter) -> anyhow::Result<()> {
+    let db = args.open().await?;
+    let entries = db.filter_entries(&filter).await?;
+    for entry in &entries {
+        println!(
+            "{:?} loc={} type={} created_at={} bytes={}",
+            entry.hash,
+            entry.dht_loc(),
+            entry.entry_type,
+            entry.created_at.to_date_time()?.to_rfc3339(),
+            entry.content.len()
+        );
+    }
+    println!("{} entries", entries.len());
+    db.close().await
+}
+
+async fn bench(
+    args: DbArgs,
+    profile: Profile,
+    seconds: u64,
+    concurrency: usize,
+    seed: usize,
+) -> anyhow::Result<()> {
+    let db = args.open().await?;
+    let seeded: Vec<_> = (0..seed).map(|_| random_entry(256)).collect();
+    for chunk in seeded.chunks(1000) {
+        db.insert_entries(chunk).await?;
+    }
+    let hashes: Vec<_> = seeded.into_iter().map(|entry| entry.hash).collect();
+    let before = db.metrics_snapshot();
+
+    let deadline = Instant::now() + Duration::from_secs(seconds);
+    let started = Instant::now();
+    let mut tasks = Vec::new();
+    for _ in 0..concurrency.max(1) {
+        let (db, hashes) = (db.clone(), hashes.clone());
+        tasks.push(tokio::spawn(async move {
+            let mut ops = 0u64;
+            while Instant::now() < deadline {
+                let write = rand::thread_rng().gen_bool(profile.write_ratio());
+                // picked before awaiting, the rng can't be held across it
+                let read = hashes.choose(&mut rand::thread_rng()).cloned();
+                match read {
+                    Some(hash) if !write => {
+                        db.get_entry(&hash).await?;
+                    }
+                    _ => {
+                        db.insert_entry(&random_entry(256)).await?;
+                    }
+                }
+                ops += 1;
+            }
+            Ok::<_, anyhow::Error>(ops)
+        }));
+    }
+    let mut ops = 0;
+    for task in tasks {
+        ops += task.await??;
+    }
+    let elapsed = started.elapsed();
+
+    println!(
+        "{:?}: {} operations in {:.2?}, {:.0} per second",
+        profile,
+        ops,
+        elapsed,
+        ops as f64 / elapsed.as_secs_f64()
+    );
+    let after = db.metrics_snapshot();
+    for metrics in &after.ops {
+        // only what ran while timing
+        let calls = metrics.calls() - before.op(metrics.op).map_or(0, OpMetrics::calls);
+        if calls == 0 {
+            continue;
+        }
+        let quantile = |q| metrics.latency_us.quantile(q).unwrap_or(0);
+        println!(
+            "  {:<16} {:>9} calls  p50 <= {}us  p99 <= {}us  max {}us",
+            metrics.op,
+            calls,
+            quantile(0.5),
+            quantile(0.99),
+            metrics.latency_us.max
+        );
+    }
+    db.close().await
+}
 
-    let key = get_encryption_key_shim();
-    let mut cmd =
-        *br#"PRAGMA key = "x'0000000000000000000000000000000000000000000000000000000000000000'";"#;
+async fn key_rotate(args: DbArgs, new: DbArgs, backup: Option<PathBuf>) -> anyhow::Result<()> {
+    if args.key_file.is_none() && args.lair_root.is_none() {
+        anyhow::bail!("a plain sqlite file has no key to rotate, give its current key");
+    }
+    if args.key_file.is_some() && args.key_file == new.key_file
+        || args.lair_pub_key.is_some() && args.lair_pub_key == new.lair_pub_key
     {
-        use std::io::Write;
-        let mut c = std::io::Cursor::new(&mut cmd[16..80]);
-        for b in &key {
-            write!(c, "{:02X}", b)?;
+        anyhow::bail!("the new key is the current one");
+    }
+
+    let db = args.open().await?;
+    // plain sqlite opens the file whatever the key, never having
+    // encrypted it, and would ignore the rekey
+    if db.cipher_version().await?.is_none() {
+        db.close().await?;
+        anyhow::bail!(
+            "this build's sqlite isn't SQLCipher, {} is not encrypted and has no key to rotate",
+            args.path.display()
+        );
+    }
+    if let Some(key_file) = &new.key_file {
+        create_key_file(key_file)?;
+    }
+    let new_key = match new.key_source().await? {
+        Some(source) => source.get_key(None).await?,
+        None => anyhow::bail!("no new key given"),
+    };
+    if let Some(backup) = &backup {
+        db.backup_to(backup, None).await?;
+        println!("backed up under the current key to {}", backup.display());
+    }
+    db.rekey(*new_key.as_bytes()).await?;
+    db.close().await?;
+
+    // everything as the next user of the file will see it
+    let db = new.open().await?;
+    let report = db.integrity_check(true).await?;
+    if !report.is_ok() {
+        for problem in &report.problems {
+            eprintln!("{}", problem);
         }
+        anyhow::bail!("the database reopened under the new key but fails its quick check");
     }
-    con.execute(std::str::from_utf8(&cmd).unwrap()).await?;
-
-    // set to faster write-ahead-log mode
-    // con.pragma_update(None, "journal_mode", &"WAL".to_string())?;
-
-    // create entries table
-    con.execute(
-        "CREATE TABLE IF NOT EXISTS entries (
-                hash            BLOB PRIMARY KEY,
-                dht_loc         INT NOT NULL,
-                created_at      TEXT NOT NULL
-            );",
-        // NO_PARAMS,
-    )
-    .await?;
-
-    // create dht_loc + created_at index
-    // we can have as many indexes as we want
-    // i.e. we could have separate dht_loc only index
-    // if we want queries that don't care about created_at, etc.
-    con.execute(
-        "CREATE INDEX IF NOT EXISTS entries_query_idx ON entries (
-                dht_loc, created_at
-            );",
-        // NO_PARAMS,
-    )
-    .await?;
-    Ok(con)
-}
-
-// fn handle_query(
-//     &mut self,
-//     dht_loc_start: u32,
-//     dht_loc_end: u32,
-//     created_at_start: DateTime<Utc>,
-//     created_at_end: DateTime<Utc>,
-// ) -> DbApiHandlerResult<Vec<Entry>> {
-//     // this transaction is needed even less since we're not writing,
-//     // but if we were reading from multiple tables would keep them
-//     // consistent.
-//     let tx = self.con.transaction()?;
-
-//     let mut out = Vec::new();
-
-//     {
-//         // Really we'd want to use dht_arc with the start / half-length,
-//         // then branch on potential for a wrapping space that inverts
-//         // the `> <` signs below - but this is just a PoC.
-//         let mut query = tx.prepare_cached(
-//             "SELECT hash, dht_loc, created_at FROM entries
-// WHERE dht_loc >= ?1
-// AND dht_loc <= ?2
-// AND created_at >= ?3
-// AND created_at <= ?4;",
-//         )?;
-
-//         // map our results to the rust struct
-//         for r in query.query_map(
-//             params![dht_loc_start, dht_loc_end, created_at_start, created_at_end,],
-//             |row| {
-//                 Ok(Entry {
-//                     hash: row.get(0)?,
-//                     dht_loc: row.get(1)?,
-//                     created_at: row.get(2)?,
-//                 })
-//             },
-//         )? {
-//             out.push(r?);
-//         }
-//     }
-
-//     // we're not writing, we can rollback this transaction
-//     // essentially a no-op
-//     tx.rollback()?;
-
-//     Ok(async move { Ok(out) }.boxed().into())
-// }
+    let stats = db.stats().await?;
+    println!(
+        "{} is now under the new key, {} tables in {} bytes reopened and checked",
+        new.path.display(),
+        stats.tables.len(),
+        stats.disk_bytes()
+    );
+    if let Some(key_file) = &args.key_file {
+        println!(
+            "{} still holds the old key, remove it once nothing needs it",
+            key_file.display()
+        );
+    }
+    db.close().await
+}
+
+/// A string literal for `text`.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// What a dot command at the prompt runs, and whether to print the
+/// values as they are rather than as a table, `None` to quit.
+fn dot_command(line: &str) -> anyhow::Result<Option<(String, bool)>> {
+    let mut words = line.split_whitespace();
+    let sql = match (words.next().unwrap_or_default(), words.next()) {
+        (".quit", _) | (".exit", _) => return Ok(None),
+        (".tables", _) => (
+            "SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name;".to_string(),
+            false,
+        ),
+        (".schema", None) => (
+            "SELECT sql || ';' FROM sqlite_master WHERE sql IS NOT NULL ORDER BY tbl_name, name;"
+                .to_string(),
+            true,
+        ),
+        (".schema", Some(table)) => (
+            format!(
+                "SELECT sql || ';' FROM sqlite_master
+                WHERE tbl_name = {} AND sql IS NOT NULL ORDER BY name;",
+                quote(table)
+            ),
+            true,
+        ),
+        _ => anyhow::bail!(
+            "unknown command {}, try .tables, .schema [table] or .quit",
+            line
+        ),
+    };
+    Ok(Some(sql))
+}
+
+async fn repl(args: DbArgs) -> anyhow::Result<()> {
+    let db = args.open().await?;
+    // no prompts when reading a script
+    let interactive = std::io::stdin().is_terminal();
+    let prompt = |continuing: bool| {
+        if interactive {
+            print!("{}", if continuing { "   ...> " } else { "sqlite> " });
+            let _ = std::io::stdout().flush();
+        }
+    };
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    let mut statement = String::new();
+    prompt(false);
+    while let Some(line) = lines.next_line().await? {
+        let (sql, raw) = if statement.is_empty() && line.trim_start().starts_with('.') {
+            match dot_command(line.trim()) {
+                Ok(Some(command)) => command,
+                Ok(None) => break,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    prompt(false);
+                    continue;
+                }
+            }
+        } else if line.trim_end().ends_with(';') {
+            statement.push_str(&line);
+            (std::mem::take(&mut statement), false)
+        } else {
+            if !line.trim().is_empty() {
+                statement.push_str(&line);
+                statement.push('\n');
+            }
+            prompt(!statement.is_empty());
+            continue;
+        };
+        match db.run_sql(&sql).await {
+            Ok(outputs) => {
+                for output in outputs {
+                    if raw {
+                        for value in output.rows.iter().flatten() {
+                            if let Value::Text(text) = value {
+                                println!("{}", text);
+                            }
+                        }
+                    } else {
+                        print!("{}", output);
+                    }
+                }
+            }
+            Err(err) => eprintln!("error: {:#}", err),
+        }
+        prompt(false);
+    }
+    db.close().await
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // spawn the database actor
-    let mut con = make_connection("sqlite::memory:").await?;
-
-    let entry = Entry::rand();
-    let entry_hash = entry.hash.clone();
-
-    con.transaction(|tx| {
-        Box::pin(async {
-            sqlx::query("INSERT INTO entries (hash, dht_loc, created_at) VALUES (?1, ?2, ?3)")
-                .bind(entry.hash)
-                .bind(entry.dht_loc)
-                .bind(entry.created_at)
-                .execute(tx)
-                .await
-        })
-    })
-    .await?;
-
-    let fetched: Vec<Entry> = con
-        .transaction(|tx| {
-            Box::pin(async {
-                let start = Utc.ymd(1970, 1, 1).and_hms(0, 1, 1);
-                let end = Utc::now();
-                sqlx::query_as::<_, Entry>(
-                    "SELECT hash, dht_loc, created_at FROM entries
-            WHERE dht_loc >= ?1
-            AND dht_loc <= ?2
-            AND created_at >= ?3
-            AND created_at <= ?4
-            ;",
-                )
-                .bind(0)
-                .bind(u32::MAX)
-                .bind(start)
-                .bind(end)
-                .fetch(tx)
-                .collect::<Vec<_>>()
-                .await
-                .into_iter()
-                .collect::<Result<Vec<_>>>()
-            })
-        })
-        .await?;
-
-    // let res = con.query(0, u32::MAX, start, end).await?;
-    println!("{:#?}", fetched);
-
-    Ok(())
+    match Command::from_args() {
+        Command::Init { db } => init(db).await,
+        Command::Insert {
+            db,
+            count,
+            content_bytes,
+            batch,
+        } => insert(db, count, content_bytes, batch).await,
+        Command::Query {
+            db,
+            loc_start,
+            loc_end,
+            since,
+            until,
+            limit,
+        } => {
+            let mut filter = EntryFilter::new().loc_range(loc_start, loc_end);
+            if since.is_some() || until.is_some() {
+                filter = filter.time_range(
+                    since.unwrap_or(Timestamp(0)),
+                    until.unwrap_or_else(Timestamp::now),
+                );
+            }
+            if let Some(limit) = limit {
+                filter = filter.limit(limit);
+            }
+            query(db, filter).await
+        }
+        Command::Bench {
+            db,
+            profile,
+            seconds,
+            concurrency,
+            seed,
+        } => bench(db, profile, seconds, concurrency, seed).await,
+        Command::Inspect { db } => {
+            let db = db.open().await?;
+            print!("{}", db.inspect().await?);
+            db.close().await
+        }
+        Command::KeyRotate {
+            db,
+            new_key_file,
+            new_lair_pub_key,
+            backup,
+        } => {
+            let new = DbArgs {
+                path: db.path.clone(),
+                key_file: new_key_file,
+                lair_root: new_lair_pub_key.as_ref().and(db.lair_root.clone()),
+                lair_pub_key: new_lair_pub_key,
+            };
+            key_rotate(db, new, backup).await
+        }
+        Command::Repl { db } => repl(db).await,
+    }
 }
@@ -0,0 +1,532 @@
+//! Latency and row count histograms per operation, for dashboards to
+//! show how the database is holding up.
+//!
+//! Every operation [`crate::DbConfig::tracing`] makes a span for is
+//! also recorded here, whatever the verbosity, alongside how busy each
+//! side's connections are.
+
+use crate::permit::Permits;
+use crate::{Db, DbRead, DbWrite, PermitMetrics, TableRows};
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Upper bounds of the latency buckets, in microseconds.
+pub const LATENCY_BUCKETS_US: &[u64] = &[
+    50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 10_000_000,
+];
+
+/// Upper bounds of the row count buckets.
+pub const ROW_BUCKETS: &[u64] = &[0, 1, 2, 5, 10, 25, 50, 100, 250, 500, 1_000, 10_000];
+
+/// Counts of observations falling in each of a fixed set of buckets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    /// Inclusive upper bound of each bucket, ascending.
+    pub bounds: &'static [u64],
+    /// Observations per bucket, not cumulative, with one more at the
+    /// end for those past the last bound.
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum: u64,
+    pub max: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [u64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            count: 0,
+            sum: 0,
+            max: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: u64) {
+        let bucket = self.bounds.partition_point(|bound| *bound < value);
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum += value;
+        self.max = self.max.max(value);
+    }
+
+    /// Mean of the observations, 0 before any.
+    pub fn mean(&self) -> f64 {
+        match self.count {
+            0 => 0.0,
+            n => self.sum as f64 / n as f64,
+        }
+    }
+
+    /// An upper bound on the `q` quantile (0 to 1): the bound of the
+    /// bucket it falls in, or the largest observation if that's lower.
+    /// `None` before any observations.
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let bound = self.bounds.get(i).copied().unwrap_or(u64::MAX);
+                return Some(bound.min(self.max));
+            }
+        }
+        Some(self.max)
+    }
+}
+
+/// How one operation has done since the database was opened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpMetrics {
+    /// The operation, as named in its `db_op` span.
+    pub op: &'static str,
+    /// "read" or "write".
+    pub side: &'static str,
+    /// Microseconds per call, retries included.
+    pub latency_us: Histogram,
+    /// Rows returned per call, for reads that know it.
+    pub rows_returned: Histogram,
+    /// Rows inserted, updated or deleted per call, for writes that
+    /// know it.
+    pub rows_written: Histogram,
+    /// Calls that returned an error.
+    pub errors: u64,
+    /// Calls that took longer than
+    /// [`crate::DbConfig::slow_query_threshold`].
+    pub slow: u64,
+}
+
+impl OpMetrics {
+    fn new(op: &'static str, side: &'static str) -> Self {
+        Self {
+            op,
+            side,
+            latency_us: Histogram::new(LATENCY_BUCKETS_US),
+            rows_returned: Histogram::new(ROW_BUCKETS),
+            rows_written: Histogram::new(ROW_BUCKETS),
+            errors: 0,
+            slow: 0,
+        }
+    }
+
+    /// Calls so far, failed ones included.
+    pub fn calls(&self) -> u64 {
+        self.latency_us.count
+    }
+}
+
+/// How busy one side's connections are, and how long callers have
+/// waited for one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolMetrics {
+    /// "read" or "write".
+    pub side: &'static str,
+    /// Most connections the side opens, and so how many callers it
+    /// serves at once.
+    pub max_connections: u32,
+    /// Connections open now.
+    pub size: u32,
+    /// Open connections no one is using.
+    pub idle: u32,
+    /// Microseconds each caller waited for a connection, timed out
+    /// ones included.
+    pub acquire_wait_us: Histogram,
+    /// Waits so far.
+    pub waits: PermitMetrics,
+}
+
+impl PoolMetrics {
+    fn new(side: &'static str, pool: &SqlitePool, permits: &Permits) -> Self {
+        Self {
+            side,
+            max_connections: permits.permits(),
+            size: pool.size(),
+            idle: pool.num_idle() as u32,
+            acquire_wait_us: permits.wait_histogram(),
+            waits: permits.metrics(),
+        }
+    }
+
+    /// Connections in use now.
+    pub fn in_use(&self) -> u32 {
+        self.size.saturating_sub(self.idle)
+    }
+
+    /// The share of `max_connections` in use now, 0 to 1.
+    pub fn utilization(&self) -> f64 {
+        match self.max_connections {
+            0 => 0.0,
+            max => self.in_use() as f64 / max as f64,
+        }
+    }
+}
+
+/// Every operation's metrics at one moment, from
+/// [`Db::metrics_snapshot`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsSnapshot {
+    /// The [`crate::DbKind::name`], or "path" for a database opened by
+    /// path.
+    pub db_kind: &'static str,
+    /// Since the database was opened.
+    pub elapsed: Duration,
+    /// By side, then operation name.
+    pub ops: Vec<OpMetrics>,
+    /// By side.
+    pub pools: Vec<PoolMetrics>,
+    /// Approximate rows of every table counted so far, by name, see
+    /// [`crate::DbConfig::count_table_rows_every`].
+    pub tables: Vec<TableRows>,
+}
+
+impl MetricsSnapshot {
+    /// The metrics of `op`, on whichever side ran it.
+    pub fn op(&self, op: &str) -> Option<&OpMetrics> {
+        self.ops.iter().find(|metrics| metrics.op == op)
+    }
+
+    /// The connections of `side`, "read" or "write".
+    pub fn pool(&self, side: &str) -> Option<&PoolMetrics> {
+        self.pools.iter().find(|pool| pool.side == side)
+    }
+
+    /// The approximate rows of `table`, if it's been counted.
+    pub fn table_rows(&self, table: &str) -> Option<u64> {
+        self.tables
+            .iter()
+            .find(|rows| rows.table == table)
+            .map(|rows| rows.rows)
+    }
+
+    /// Mean calls of `op` per second since the database was opened.
+    pub fn throughput(&self, op: &str) -> f64 {
+        match (self.op(op), self.elapsed.as_secs_f64()) {
+            (Some(metrics), secs) if secs > 0.0 => metrics.calls() as f64 / secs,
+            _ => 0.0,
+        }
+    }
+
+    /// In the Prometheus text exposition format, latencies in seconds.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        self.histograms(
+            &mut out,
+            "spike_sqlx_op_duration_seconds",
+            "Time taken by each database operation, retries included.",
+            |metrics| &metrics.latency_us,
+            1e-6,
+        );
+        self.histograms(
+            &mut out,
+            "spike_sqlx_op_rows_returned",
+            "Rows returned by each read.",
+            |metrics| &metrics.rows_returned,
+            1.0,
+        );
+        self.histograms(
+            &mut out,
+            "spike_sqlx_op_rows_written",
+            "Rows inserted, updated or deleted by each write.",
+            |metrics| &metrics.rows_written,
+            1.0,
+        );
+        self.counters(
+            &mut out,
+            "spike_sqlx_op_errors_total",
+            "Database operations that failed.",
+            |metrics| metrics.errors,
+        );
+        self.counters(
+            &mut out,
+            "spike_sqlx_op_slow_total",
+            "Database operations slower than the slow query threshold.",
+            |metrics| metrics.slow,
+        );
+        self.pool_metrics(&mut out);
+        let name = "spike_sqlx_table_rows";
+        writeln!(out, "# HELP {} Approximate rows in each table.", name).unwrap();
+        writeln!(out, "# TYPE {} gauge", name).unwrap();
+        for rows in &self.tables {
+            writeln!(
+                out,
+                "{}{{db_kind=\"{}\",table=\"{}\"}} {}",
+                name, self.db_kind, rows.table, rows.rows
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// Both sides together, each op only ever runs on one of them.
+    fn merge(mut self, other: Self) -> Self {
+        self.ops.extend(other.ops);
+        self.ops.sort_by_key(|metrics| (metrics.side, metrics.op));
+        self.pools.extend(other.pools);
+        self.pools.sort_by_key(|pool| pool.side);
+        self.tables.extend(other.tables);
+        self.elapsed = self.elapsed.max(other.elapsed);
+        self
+    }
+
+    fn labels(&self, metrics: &OpMetrics) -> String {
+        format!(
+            "db_kind=\"{}\",side=\"{}\",op=\"{}\"",
+            self.db_kind, metrics.side, metrics.op
+        )
+    }
+
+    fn counters(
+        &self,
+        out: &mut String,
+        name: &str,
+        help: &str,
+        counter: impl Fn(&OpMetrics) -> u64,
+    ) {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} counter", name).unwrap();
+        for metrics in &self.ops {
+            writeln!(
+                out,
+                "{}{{{}}} {}",
+                name,
+                self.labels(metrics),
+                counter(metrics)
+            )
+            .unwrap();
+        }
+    }
+
+    fn histograms(
+        &self,
+        out: &mut String,
+        name: &str,
+        help: &str,
+        histogram: impl Fn(&OpMetrics) -> &Histogram,
+        scale: f64,
+    ) {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} histogram", name).unwrap();
+        for metrics in &self.ops {
+            histogram_lines(out, name, &self.labels(metrics), histogram(metrics), scale);
+        }
+    }
+
+    fn pool_metrics(&self, out: &mut String) {
+        let name = "spike_sqlx_pool_connections";
+        writeln!(out, "# HELP {} Open connections, by state.", name).unwrap();
+        writeln!(out, "# TYPE {} gauge", name).unwrap();
+        for pool in &self.pools {
+            let labels = self.pool_labels(pool);
+            writeln!(out, "{}{{{},state=\"idle\"}} {}", name, labels, pool.idle).unwrap();
+            writeln!(
+                out,
+                "{}{{{},state=\"in_use\"}} {}",
+                name,
+                labels,
+                pool.in_use()
+            )
+            .unwrap();
+        }
+        self.pool_series(
+            out,
+            "spike_sqlx_pool_max_connections",
+            "Most connections each side opens.",
+            "gauge",
+            |pool| pool.max_connections as u64,
+        );
+        let name = "spike_sqlx_pool_acquire_wait_seconds";
+        writeln!(out, "# HELP {} Time waited for a connection.", name).unwrap();
+        writeln!(out, "# TYPE {} histogram", name).unwrap();
+        for pool in &self.pools {
+            histogram_lines(
+                out,
+                name,
+                &self.pool_labels(pool),
+                &pool.acquire_wait_us,
+                1e-6,
+            );
+        }
+        self.pool_series(
+            out,
+            "spike_sqlx_pool_acquire_timeouts_total",
+            "Waits for a connection that timed out.",
+            "counter",
+            |pool| pool.waits.timed_out,
+        );
+        self.pool_series(
+            out,
+            "spike_sqlx_pool_slow_acquires_total",
+            "Waits for a connection longer than the slow acquire threshold.",
+            "counter",
+            |pool| pool.waits.slow,
+        );
+    }
+
+    fn pool_labels(&self, pool: &PoolMetrics) -> String {
+        format!("db_kind=\"{}\",side=\"{}\"", self.db_kind, pool.side)
+    }
+
+    fn pool_series(
+        &self,
+        out: &mut String,
+        name: &str,
+        help: &str,
+        kind: &str,
+        value: impl Fn(&PoolMetrics) -> u64,
+    ) {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+        for pool in &self.pools {
+            writeln!(
+                out,
+                "{}{{{}}} {}",
+                name,
+                self.pool_labels(pool),
+                value(pool)
+            )
+            .unwrap();
+        }
+    }
+}
+
+/// The lines of one histogram with `labels`, skipped while it's empty.
+fn histogram_lines(out: &mut String, name: &str, labels: &str, histogram: &Histogram, scale: f64) {
+    if histogram.count == 0 {
+        return;
+    }
+    let mut cumulative = 0;
+    for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+        cumulative += count;
+        writeln!(
+            out,
+            "{}_bucket{{{},le=\"{}\"}} {}",
+            name,
+            labels,
+            *bound as f64 * scale,
+            cumulative
+        )
+        .unwrap();
+    }
+    writeln!(
+        out,
+        "{}_bucket{{{},le=\"+Inf\"}} {}",
+        name, labels, histogram.count
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "{}_sum{{{}}} {}",
+        name,
+        labels,
+        histogram.sum as f64 * scale
+    )
+    .unwrap();
+    writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count).unwrap();
+}
+
+#[derive(Debug)]
+struct Inner {
+    opened: Instant,
+    ops: BTreeMap<&'static str, OpMetrics>,
+}
+
+/// Metrics of the operations on one side of the database.
+/// Clones share the same metrics.
+#[derive(Debug, Clone)]
+pub(crate) struct Metrics {
+    kind: &'static str,
+    side: &'static str,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Metrics {
+    pub(crate) fn new(kind: &'static str, side: &'static str) -> Self {
+        Self {
+            kind,
+            side,
+            inner: Arc::new(Mutex::new(Inner {
+                opened: Instant::now(),
+                ops: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// Note that `op` took `elapsed`, and how many rows it returned or
+    /// wrote if that's known.
+    pub(crate) fn record(
+        &self,
+        op: &'static str,
+        elapsed: Duration,
+        rows: Option<u64>,
+        ok: bool,
+        slow: bool,
+    ) {
+        let mut inner = self.inner.lock().unwrap();
+        let metrics = inner
+            .ops
+            .entry(op)
+            .or_insert_with(|| OpMetrics::new(op, self.side));
+        metrics.latency_us.observe(elapsed.as_micros() as u64);
+        if let Some(rows) = rows {
+            match self.side {
+                "write" => metrics.rows_written.observe(rows),
+                _ => metrics.rows_returned.observe(rows),
+            }
+        }
+        if !ok {
+            metrics.errors += 1;
+        }
+        if slow {
+            metrics.slow += 1;
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        let inner = self.inner.lock().unwrap();
+        MetricsSnapshot {
+            db_kind: self.kind,
+            elapsed: inner.opened.elapsed(),
+            ops: inner.ops.values().cloned().collect(),
+            pools: Vec::new(),
+            tables: Vec::new(),
+        }
+    }
+}
+
+impl Db {
+    /// Latency and row counts of every operation so far, and how busy
+    /// the connections are, readers and writer together.
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.reader()
+            .metrics_snapshot()
+            .merge(self.writer().metrics_snapshot())
+    }
+}
+
+impl DbRead {
+    /// Latency and row counts of the operations on the read pool so
+    /// far, how busy it is, and the tables' row counts.
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        let mut snapshot = self.trace.metrics().snapshot();
+        snapshot.pools = vec![PoolMetrics::new("read", &self.pool, &self.permits)];
+        snapshot.tables = self.row_counts.snapshot();
+        snapshot
+    }
+}
+
+impl DbWrite {
+    /// Latency and row counts of the operations on the writer so far,
+    /// and how busy it is.
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        let mut snapshot = self.trace.metrics().snapshot();
+        snapshot.pools = vec![PoolMetrics::new("write", &self.pool, &self.permits)];
+        snapshot
+    }
+}
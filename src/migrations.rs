@@ -0,0 +1,125 @@
+//! Schema migrations, embedded from `migrations/` at build time.
+//!
+//! Add a step with `cargo sqlx migrate add <description>`.
+//! Only ever append, sqlx checksums every applied migration and
+//! refuses to open a file whose history no longer matches.
+
+use crate::{DbError, DbWrite};
+use sqlx::migrate::Migrator;
+use sqlx::{Connection, SqliteConnection};
+use std::collections::BTreeMap;
+
+pub(crate) static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Applied migration count and latest version, if sqlx has migrated the file before.
+pub(crate) async fn applied(con: &mut SqliteConnection) -> sqlx::Result<Option<(i64, i64)>> {
+    let exists: Option<(i64,)> = sqlx::query_as(
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations';",
+    )
+    .fetch_optional(&mut *con)
+    .await?;
+    if exists.is_none() {
+        return Ok(None);
+    }
+    sqlx::query_as("SELECT COUNT(*), COALESCE(MAX(version), 0) FROM _sqlx_migrations;")
+        .fetch_one(con)
+        .await
+        .map(Some)
+}
+
+impl DbWrite {
+    /// Apply any pending migrations, each in its own transaction,
+    /// returning how many were applied.
+    ///
+    /// Fails without touching the file if it was migrated
+    /// by a newer version than this one knows about.
+    pub async fn migrate(&self) -> anyhow::Result<usize> {
+        let mut con = self.pool.acquire().await?;
+        let (before, current) = applied(&mut con).await?.unwrap_or((0, 0));
+        let latest = MIGRATOR.iter().last().map_or(0, |m| m.version);
+        if current > latest {
+            anyhow::bail!(
+                "database is at schema version {} but this build only knows up to {}",
+                current,
+                latest
+            );
+        }
+
+        MIGRATOR.run(&mut con).await?;
+
+        let (after, _) = applied(&mut con).await?.unwrap_or((0, 0));
+        Ok((after - before) as usize)
+    }
+}
+
+/// `(type, name)` -> normalized DDL of every table / index in the schema.
+async fn schema(con: &mut SqliteConnection) -> sqlx::Result<BTreeMap<(String, String), String>> {
+    let rows: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT type, name, sql FROM sqlite_master
+        WHERE type IN ('table', 'index')
+            AND sql IS NOT NULL
+            AND name NOT LIKE 'sqlite_%'
+            AND name != '_sqlx_migrations';",
+    )
+    .fetch_all(con)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(kind, name, sql)| ((kind, name), normalize(&sql)))
+        .collect())
+}
+
+/// sqlite stores DDL as written, so ignore formatting differences.
+fn normalize(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut pending_space = false;
+    for c in sql.chars() {
+        if c.is_whitespace() {
+            pending_space = true;
+            continue;
+        }
+        let tight = |c: char| matches!(c, '(' | ')' | ',');
+        if pending_space && !out.is_empty() && !tight(c) && !out.ends_with(tight) {
+            out.push(' ');
+        }
+        pending_space = false;
+        out.push(c);
+    }
+    out
+}
+
+/// The schema the embedded migrations produce, built on a scratch
+/// in-memory database.
+pub(crate) async fn expected_schema() -> anyhow::Result<BTreeMap<(String, String), String>> {
+    let mut scratch = SqliteConnection::connect("sqlite::memory:").await?;
+    MIGRATOR.run(&mut scratch).await?;
+    let expected = schema(&mut scratch).await?;
+    scratch.close().await?;
+    Ok(expected)
+}
+
+/// Compare the schema on `con` with the one the embedded migrations
+/// produce on a scratch in-memory database.
+///
+/// Missing or altered tables / indexes fail with
+/// [`DbError::SchemaMismatch`], extra ones are left alone.
+pub(crate) async fn validate_schema(con: &mut SqliteConnection) -> anyhow::Result<()> {
+    let expected = expected_schema().await?;
+    let actual = schema(con).await?;
+    let mut problems = Vec::new();
+    for ((kind, name), sql) in &expected {
+        match actual.get(&(kind.clone(), name.clone())) {
+            None => problems.push(format!("missing {} {}", kind, name)),
+            Some(found) if found != sql => problems.push(format!(
+                "{} {} is `{}`, expected `{}`",
+                kind, name, found, sql
+            )),
+            Some(_) => (),
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(DbError::SchemaMismatch(problems.join("; ")).into())
+    }
+}
@@ -0,0 +1,253 @@
+//! Keying connections from inside `sqlite3_open_v2`.
+//!
+//! SQLCipher needs `PRAGMA key` before anything reads the file, but
+//! sqlx 0.5 runs `journal_mode`, `foreign_keys` and `synchronous` on
+//! every connection it opens, before `after_connect` gets a look in.
+//! So the key is applied by a sqlite auto extension instead, which runs
+//! while the connection is being opened.
+//!
+//! The extension is process wide, so connections that should be keyed
+//! carry the id of their key as a `spike_sqlx_key` uri parameter, and
+//! the key itself stays in [`OpenKey`]'s registry rather than the uri.
+//!
+//! A key SQLCipher can't read the file with fails the open with
+//! [`WRONG_KEY`], anything else wrong with the file is left for sqlx
+//! to run into.
+
+use crate::db::{cipher_pragmas, exec_secret_on, SharedKey};
+use crate::error::WRONG_KEY;
+use crate::key_provider::DbKey;
+use crate::DbConfig;
+use libsqlite3_sys::{
+    sqlite3, sqlite3_api_routines, sqlite3_auto_extension, sqlite3_busy_timeout,
+    sqlite3_db_filename, sqlite3_exec, sqlite3_finalize, sqlite3_mprintf, sqlite3_prepare_v2,
+    sqlite3_step, sqlite3_uri_parameter, SQLITE_ERROR, SQLITE_NOTADB, SQLITE_OK, SQLITE_ROW,
+};
+use sqlx::sqlite::SqliteConnectOptions;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Once};
+
+/// The uri parameter naming a connection's entry in [`REGISTERED`].
+const PARAM: &[u8] = b"spike_sqlx_key\0";
+
+/// How sqlite calls an extension's entry point.
+type EntryPoint =
+    unsafe extern "C" fn(*mut sqlite3, *mut *mut c_char, *const sqlite3_api_routines) -> c_int;
+
+/// What a connection needs applied as it opens.
+struct OnOpen {
+    key: SharedKey,
+    /// The SQLCipher tuning, which must also come before first access.
+    pragmas: Vec<CString>,
+    /// In milliseconds, for checking the key before sqlx sets it.
+    busy_timeout: c_int,
+}
+
+static INSTALL: Once = Once::new();
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static REGISTERED: Mutex<Vec<(u64, Arc<OnOpen>)>> = Mutex::new(Vec::new());
+/// Handles keyed as they opened, until [`keyed_on_open`] takes them.
+static KEYED: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+/// Never panic across the ffi boundary over a poisoned lock.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A key connections opened through [`OpenKey::options`] are keyed
+/// with. Unregistered on drop, so keep it for as long as anything may
+/// still open connections with those options.
+pub(crate) struct OpenKey {
+    id: u64,
+    on_open: Arc<OnOpen>,
+}
+
+impl OpenKey {
+    /// Register `key`, read as each connection opens so a rekey is
+    /// picked up, and the cipher settings `config` asks for.
+    pub(crate) fn register(key: SharedKey, config: &DbConfig) -> Self {
+        INSTALL.call_once(|| {
+            // safety: sqlite calls the entry point with the signature
+            // it's declared with here, the binding just erases it
+            unsafe {
+                let entry = std::mem::transmute::<EntryPoint, unsafe extern "C" fn()>(key_on_open);
+                sqlite3_auto_extension(Some(entry));
+            }
+        });
+        let pragmas = cipher_pragmas(config)
+            .into_iter()
+            .map(|pragma| CString::new(pragma).expect("pragmas hold no NUL"))
+            .collect();
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let busy_timeout = config.busy_timeout.as_millis().min(c_int::MAX as u128) as c_int;
+        let on_open = Arc::new(OnOpen {
+            key,
+            pragmas,
+            busy_timeout,
+        });
+        lock(&REGISTERED).push((id, on_open.clone()));
+        Self { id, on_open }
+    }
+
+    /// The key as it is now.
+    pub(crate) fn key(&self) -> Option<DbKey> {
+        self.on_open.key.read().unwrap().clone()
+    }
+
+    /// `options` opening `file` with this key, or unchanged if there's
+    /// no file, which leaves the key to [`crate::db::init_connection`].
+    pub(crate) fn options(
+        &self,
+        options: SqliteConnectOptions,
+        file: Option<&Path>,
+    ) -> SqliteConnectOptions {
+        match file {
+            Some(file) => options.filename(file_uri(file, self.id)),
+            None => options,
+        }
+    }
+}
+
+impl Drop for OpenKey {
+    fn drop(&mut self) {
+        lock(&REGISTERED).retain(|(id, _)| *id != self.id);
+    }
+}
+
+/// `file` as a sqlite uri carrying `id`.
+fn file_uri(file: &Path, id: u64) -> String {
+    let path = file.to_string_lossy();
+    // an empty authority, so an absolute path isn't read as a host
+    let mut uri = String::from(if path.starts_with('/') {
+        "file://"
+    } else {
+        "file:"
+    });
+    for c in path.chars() {
+        match c {
+            '%' | '?' | '#' => uri.push_str(&format!("%{:02X}", c as u32)),
+            c => uri.push(c),
+        }
+    }
+    uri.push_str(&format!("?spike_sqlx_key={}", id));
+    uri
+}
+
+/// True if `handle` was keyed while it opened. Only answers once.
+pub(crate) fn keyed_on_open(handle: *mut sqlite3) -> bool {
+    let mut keyed = lock(&KEYED);
+    match keyed.iter().position(|h| *h == handle as usize) {
+        Some(i) => {
+            keyed.swap_remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+/// True if `handle`'s main database is a file rather than memory.
+///
+/// # Safety
+///
+/// `handle` must be an open connection.
+pub(crate) unsafe fn has_file(handle: *mut sqlite3) -> bool {
+    let name = sqlite3_db_filename(handle, b"main\0".as_ptr() as *const _);
+    !name.is_null() && *name != 0
+}
+
+/// The auto extension, run by sqlite on every connection it opens.
+unsafe extern "C" fn key_on_open(
+    db: *mut sqlite3,
+    err_msg: *mut *mut c_char,
+    _api: *const sqlite3_api_routines,
+) -> c_int {
+    // a handle freed without being taken may come back for a new one
+    lock(&KEYED).retain(|h| *h != db as usize);
+    match apply(db) {
+        Ok(()) => SQLITE_OK,
+        Err((rc, msg)) => {
+            let msg = CString::new(msg.replace('\0', "")).unwrap_or_default();
+            *err_msg = sqlite3_mprintf(b"%s\0".as_ptr() as *const _, msg.as_ptr());
+            rc
+        }
+    }
+}
+
+/// Key `db` if it's one of ours, failing with the result code and
+/// message to open it with otherwise.
+unsafe fn apply(db: *mut sqlite3) -> Result<(), (c_int, String)> {
+    let failed = |msg: String| (SQLITE_ERROR, msg);
+    let name = sqlite3_db_filename(db, b"main\0".as_ptr() as *const _);
+    if name.is_null() {
+        return Ok(());
+    }
+    let id = sqlite3_uri_parameter(name, PARAM.as_ptr() as *const _);
+    if id.is_null() {
+        // not one of ours, or a plaintext database
+        return Ok(());
+    }
+    let id: u64 = CStr::from_ptr(id)
+        .to_str()
+        .ok()
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| failed("malformed spike_sqlx_key".into()))?;
+    let on_open = lock(&REGISTERED)
+        .iter()
+        .find(|(registered, _)| *registered == id)
+        .map(|(_, on_open)| on_open.clone())
+        .ok_or_else(|| failed("no key registered for this connection".into()))?;
+    let key = on_open
+        .key
+        .read()
+        .unwrap_or_else(|p| p.into_inner())
+        .clone();
+    let key = match key {
+        Some(key) => key,
+        None => return Ok(()),
+    };
+    let cmd = key.statement("PRAGMA key = ", ";");
+    exec_secret_on(db, "PRAGMA key", &cmd).map_err(failed)?;
+    for pragma in &on_open.pragmas {
+        exec_secret_on(db, "cipher settings", pragma.as_bytes_with_nul()).map_err(failed)?;
+    }
+    // SQLCipher only notices a wrong key on the first real read, make
+    // it here so it isn't taken for a broken file further on. Stock
+    // sqlite ignores the key, so there it really is the file.
+    sqlite3_busy_timeout(db, on_open.busy_timeout);
+    let rc = sqlite3_exec(
+        db,
+        b"SELECT count(*) FROM sqlite_master;\0".as_ptr() as *const _,
+        None,
+        std::ptr::null_mut(),
+        std::ptr::null_mut(),
+    );
+    if rc & 0xff == SQLITE_NOTADB && is_sqlcipher(db) {
+        return Err((SQLITE_NOTADB, WRONG_KEY.into()));
+    }
+    lock(&KEYED).push(db as usize);
+    Ok(())
+}
+
+/// True if `db` answers `PRAGMA cipher_version`, as only SQLCipher does.
+unsafe fn is_sqlcipher(db: *mut sqlite3) -> bool {
+    let mut stmt = std::ptr::null_mut();
+    let sql = b"PRAGMA cipher_version;\0";
+    if sqlite3_prepare_v2(
+        db,
+        sql.as_ptr() as *const _,
+        -1,
+        &mut stmt,
+        std::ptr::null_mut(),
+    ) != SQLITE_OK
+    {
+        return false;
+    }
+    let row = sqlite3_step(stmt) == SQLITE_ROW;
+    sqlite3_finalize(stmt);
+    row
+}
@@ -0,0 +1,41 @@
+//! Exporting the `db_op` spans through OpenTelemetry, so a request
+//! traced across the conductor shows the time it spent in sqlite.
+//!
+//! Every op's span is a child of whichever span the caller was in, so
+//! a request only has to run inside a span that carries its trace
+//! context, see [`in_trace_context`]. Writes through the
+//! [`crate::DbWriter`] queue are committed in batches serving several
+//! requests, each batch's `write_batch` span is linked to the spans
+//! its writes were submitted from instead.
+
+use opentelemetry::api;
+use std::future::Future;
+use tracing::instrument::Instrumented;
+use tracing::{Instrument, Span, Subscriber};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt, PreSampledTracer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// A `tracing` layer exporting spans, the `db_op` ones included,
+/// through `tracer`.
+pub fn otel_layer<S, T>(tracer: T) -> OpenTelemetryLayer<S, T>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    T: api::Tracer + PreSampledTracer + 'static,
+{
+    OpenTelemetryLayer::new(tracer)
+}
+
+/// Run `fut` as part of the trace `cx`, e.g. one extracted from the
+/// headers of an incoming request, so every database operation it
+/// runs is exported within that trace.
+pub fn in_trace_context<F: Future>(cx: &api::Context, fut: F) -> Instrumented<F> {
+    let span = tracing::info_span!("db_request");
+    span.set_parent(cx);
+    fut.instrument(span)
+}
+
+/// The trace context of the current span, to hand on to a request
+/// made elsewhere.
+pub fn current_trace_context() -> api::Context {
+    Span::current().context()
+}
@@ -0,0 +1,60 @@
+/// What a write did to the rows it was aimed at,
+/// e.g. whether an op received over gossip was actually new.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteOutcome {
+    /// Rows that weren't held before.
+    pub inserted: u64,
+    /// Held rows that were changed.
+    pub updated: u64,
+    pub deleted: u64,
+    /// Rows left as they were, such as a repeated insert
+    /// or deleting something that isn't held.
+    pub ignored: u64,
+}
+
+impl WriteOutcome {
+    /// `affected` of `attempted` rows were inserted, the rest were already held.
+    pub(crate) fn inserted(affected: u64, attempted: u64) -> Self {
+        Self {
+            inserted: affected,
+            ignored: attempted.saturating_sub(affected),
+            ..Self::default()
+        }
+    }
+
+    /// An upsert of one row that `existed` before, `affected` by the statement.
+    pub(crate) fn upserted(existed: bool, affected: u64) -> Self {
+        if existed {
+            Self {
+                updated: affected,
+                ignored: 1 - affected.min(1),
+                ..Self::default()
+            }
+        } else {
+            Self::inserted(affected, 1)
+        }
+    }
+
+    /// `affected` of `attempted` rows were deleted, the rest weren't held.
+    pub(crate) fn deleted(affected: u64, attempted: u64) -> Self {
+        Self {
+            deleted: affected,
+            ignored: attempted.saturating_sub(affected),
+            ..Self::default()
+        }
+    }
+
+    /// True if any row was inserted, updated or deleted.
+    pub fn changed(&self) -> bool {
+        self.inserted + self.updated + self.deleted > 0
+    }
+}
+
+impl std::ops::AddAssign for WriteOutcome {
+    fn add_assign(&mut self, other: Self) {
+        self.inserted += other.inserted;
+        self.updated += other.updated;
+        self.deleted += other.deleted;
+        self.ignored += other.ignored;
+    }
+}
@@ -0,0 +1,140 @@
+use crate::db::ENTRY_COLUMNS;
+use crate::{Db, DbRead, Entry, EntryFilter, EntryHash, EntryOrder, Timestamp};
+
+/// Where a paged query picks up from.
+///
+/// Opaque to callers, round trip it through `to_string` / `parse`
+/// to hand it across the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub(crate) dht_loc: u32,
+    pub(crate) created_at: Timestamp,
+    pub(crate) hash: EntryHash,
+}
+
+impl Cursor {
+    /// Continue after `entry`.
+    fn after(entry: &Entry) -> Self {
+        Self {
+            dht_loc: entry.dht_loc(),
+            created_at: entry.created_at,
+            hash: entry.hash.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:08x}.{}.", self.dht_loc, self.created_at.as_micros())?;
+        for b in self.hash.get_raw_39() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Cursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut parts = s.splitn(3, '.');
+        let mut next = || {
+            parts
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("malformed cursor"))
+        };
+        let dht_loc = u32::from_str_radix(next()?, 16)?;
+        let micros: i64 = next()?.parse()?;
+        let hash = next()?;
+        if hash.len() % 2 != 0 {
+            anyhow::bail!("malformed cursor");
+        }
+        let hash = (0..hash.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hash[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()?;
+        let hash = EntryHash::from_raw_39(&hash)?;
+        Ok(Self {
+            dht_loc,
+            created_at: Timestamp(micros),
+            hash,
+        })
+    }
+}
+
+/// How much of a range query to fetch.
+#[derive(Debug, Clone)]
+pub struct QueryPage {
+    /// Most rows to return.
+    pub limit: u32,
+    /// `None` for the first page, then the previous page's [`Page::next`].
+    pub cursor: Option<Cursor>,
+}
+
+impl QueryPage {
+    /// The first `limit` rows.
+    pub fn first(limit: u32) -> Self {
+        Self {
+            limit,
+            cursor: None,
+        }
+    }
+}
+
+/// One page of results.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Where the next page starts, `None` once there are no more rows.
+    pub next: Option<Cursor>,
+}
+
+impl Db {
+    /// Fetch the entries matching `filter` a page at a time,
+    /// in [`EntryOrder::DhtLoc`] order.
+    pub async fn query_entries_page(
+        &self,
+        filter: &EntryFilter,
+        page: &QueryPage,
+    ) -> anyhow::Result<Page<Entry>> {
+        self.reader().query_entries_page(filter, page).await
+    }
+}
+
+impl DbRead {
+    /// Fetch the entries matching `filter` a page at a time,
+    /// in [`EntryOrder::DhtLoc`] order.
+    /// The page replaces any order or limit set on the filter.
+    ///
+    /// Pages seek past the cursor rather than using OFFSET,
+    /// so later pages cost no more than the first.
+    pub async fn query_entries_page(
+        &self,
+        filter: &EntryFilter,
+        page: &QueryPage,
+    ) -> anyhow::Result<Page<Entry>> {
+        if page.limit == 0 {
+            anyhow::bail!("page limit must be at least 1");
+        }
+        // one extra row tells us whether there is another page
+        let query = filter
+            .clone()
+            .order(EntryOrder::DhtLoc)
+            .limit(page.limit + 1)
+            .select(ENTRY_COLUMNS, page.cursor.as_ref());
+        let mut items = self
+            .read(move |reader| {
+                let query = query.clone();
+                Box::pin(async move { reader.fetch_entries(&query).await })
+            })
+            .await?;
+
+        let next = if items.len() > page.limit as usize {
+            items.truncate(page.limit as usize);
+            items.last().map(Cursor::after)
+        } else {
+            None
+        };
+        Ok(Page { items, next })
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{DbConfig, DbError, Histogram, LATENCY_BUCKETS_US};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// How long callers have waited for permits on one side of the database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PermitMetrics {
+    /// Permits handed out.
+    pub acquired: u64,
+    /// Callers that gave up after the permit timeout.
+    pub timed_out: u64,
+    /// Callers that waited at least
+    /// [`crate::DbConfig::slow_acquire_threshold`], timed out ones
+    /// included.
+    pub slow: u64,
+    /// Summed over every acquired permit.
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+}
+
+impl PermitMetrics {
+    /// Mean wait per acquired permit.
+    pub fn mean_wait(&self) -> Duration {
+        match self.acquired {
+            0 => Duration::from_secs(0),
+            n => self.total_wait / n as u32,
+        }
+    }
+}
+
+/// Leave to use the database, given back on drop.
+#[derive(Debug)]
+pub struct PermitGuard {
+    _permit: OwnedSemaphorePermit,
+}
+
+#[derive(Debug)]
+struct Waits {
+    totals: PermitMetrics,
+    wait_us: Histogram,
+}
+
+/// Bounds how many callers use one side of the database at once,
+/// so a burst of tasks queues here, fairly and with a timeout,
+/// rather than piling onto sqlite's locks.
+/// Clones share the same permits.
+#[derive(Debug, Clone)]
+pub(crate) struct Permits {
+    semaphore: Arc<Semaphore>,
+    permits: u32,
+    timeout: Duration,
+    slow: Option<Duration>,
+    kind: &'static str,
+    /// "read" or "write", for the timeout error.
+    side: &'static str,
+    waits: Arc<Mutex<Waits>>,
+}
+
+impl Permits {
+    /// As many permits as the side has connections, so holding one
+    /// means a connection is free.
+    pub(crate) fn new(
+        kind: &'static str,
+        side: &'static str,
+        permits: u32,
+        config: &DbConfig,
+    ) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(permits as usize)),
+            permits,
+            timeout: config.permit_timeout,
+            slow: config.slow_acquire_threshold,
+            kind,
+            side,
+            waits: Arc::new(Mutex::new(Waits {
+                totals: PermitMetrics::default(),
+                wait_us: Histogram::new(LATENCY_BUCKETS_US),
+            })),
+        }
+    }
+
+    /// Wait for a permit, failing with [`DbError::PermitTimeout`]
+    /// after the configured timeout.
+    pub(crate) async fn acquire(&self) -> anyhow::Result<PermitGuard> {
+        let start = Instant::now();
+        let permit =
+            tokio::time::timeout(self.timeout, self.semaphore.clone().acquire_owned()).await;
+        let waited = start.elapsed();
+        let slow = self.slow.filter(|slow| waited >= *slow).is_some();
+        if slow {
+            // a writer queued up this long is the first sign it can't
+            // keep up
+            tracing::warn!(
+                db_kind = self.kind,
+                side = self.side,
+                waited_ms = waited.as_millis() as u64,
+                timed_out = permit.is_err(),
+                connections = self.permits,
+                "slow wait for a database connection"
+            );
+        }
+        let mut waits = self.waits.lock().unwrap();
+        waits.wait_us.observe(waited.as_micros() as u64);
+        let metrics = &mut waits.totals;
+        if slow {
+            metrics.slow += 1;
+        }
+        match permit {
+            Ok(permit) => {
+                metrics.acquired += 1;
+                metrics.total_wait += waited;
+                metrics.max_wait = metrics.max_wait.max(waited);
+                Ok(PermitGuard {
+                    // the semaphore is never closed
+                    _permit: permit.expect("permit semaphore closed"),
+                })
+            }
+            Err(_) => {
+                metrics.timed_out += 1;
+                Err(DbError::PermitTimeout(self.side, waited).into())
+            }
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> PermitMetrics {
+        self.waits.lock().unwrap().totals
+    }
+
+    /// Microseconds waited per caller, timed out ones included.
+    pub(crate) fn wait_histogram(&self) -> Histogram {
+        self.waits.lock().unwrap().wait_us.clone()
+    }
+
+    /// How many callers may hold a permit at once.
+    pub(crate) fn permits(&self) -> u32 {
+        self.permits
+    }
+}
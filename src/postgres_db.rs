@@ -0,0 +1,389 @@
+//! The entry API on a Postgres server, through sqlx's Postgres driver.
+//!
+//! The schema is the one sqlite reaches after every migration, written
+//! out for Postgres in `migrations_postgres/`. Statements are shared with
+//! the sqlite side where the SQL is portable, and only rewritten where the
+//! dialects differ:
+//!
+//! - placeholders: sqlite's `?` and `?N` become `$N`
+//! - upserts: Postgres has no `IS NOT` for nullable comparisons and wants
+//!   the existing row named in `ON CONFLICT DO UPDATE`
+//! - integers: there are no unsigned types, so `u32`s go in as `BIGINT`
+//!   and `ValidationStatus` as a `SMALLINT`
+//!
+//! There's no encryption here, at-rest encryption is the server's job.
+
+use crate::db::{ENTRY_COLUMNS, INSERT_ENTRY, INSERT_HEADER};
+use crate::{
+    DhtOp, Entry, EntryFilter, EntryHash, Header, OpHash, Timestamp, ValidationStatus, Value,
+    WriteOutcome,
+};
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgArguments, PgPool, PgRow, Postgres};
+use sqlx::query::Query;
+use sqlx::Row;
+
+static PG_MIGRATOR: Migrator = sqlx::migrate!("./migrations_postgres");
+
+type PgQuery<'q> = Query<'q, Postgres, PgArguments>;
+
+/// Rewrite sqlite's `?` / `?N` placeholders as Postgres' `$N`,
+/// leaving quoted strings alone.
+pub(crate) fn numbered_placeholders(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len() + 8);
+    let mut chars = sql.chars().peekable();
+    let mut quoted = false;
+    let mut next = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                quoted = !quoted;
+                out.push(c);
+            }
+            '?' if !quoted => {
+                let mut digits = String::new();
+                while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                    digits.push(*d);
+                    chars.next();
+                }
+                if digits.is_empty() {
+                    next += 1;
+                    digits = next.to_string();
+                }
+                out.push('$');
+                out.push_str(&digits);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// [`crate::dht_op::INSERT_OP`] for Postgres.
+const INSERT_OP: &str = "INSERT INTO dht_ops AS held
+    (op_hash, op_type, basis_loc, authored_timestamp,
+        when_integrated, validation_status, dependency)
+    VALUES ($1, $2, $3, $4, $5, $6, $7)
+    ON CONFLICT (op_hash) DO UPDATE SET
+        when_integrated =
+            COALESCE(excluded.when_integrated, held.when_integrated),
+        validation_status =
+            COALESCE(NULLIF(excluded.validation_status, 0), held.validation_status)
+    WHERE COALESCE(excluded.when_integrated, held.when_integrated)
+            IS DISTINCT FROM held.when_integrated
+        OR COALESCE(NULLIF(excluded.validation_status, 0), held.validation_status)
+            IS DISTINCT FROM held.validation_status";
+
+fn bind_entry<'q>(query: PgQuery<'q>, entry: &Entry) -> PgQuery<'q> {
+    query
+        .bind(entry.hash.get_raw_39().to_vec())
+        .bind(i64::from(entry.dht_loc()))
+        .bind(entry.created_at.0)
+        .bind(entry.entry_type.to_string())
+        .bind(i64::from(entry.size_bytes()))
+        .bind(entry.content.clone())
+}
+
+fn bind_values(mut query: PgQuery<'_>, values: Vec<Value>) -> PgQuery<'_> {
+    for value in values {
+        query = match value {
+            Value::Null => query.bind(None::<i64>),
+            Value::Integer(v) => query.bind(v),
+            Value::Real(v) => query.bind(v),
+            Value::Text(v) => query.bind(v),
+            Value::Blob(v) => query.bind(v),
+        };
+    }
+    query
+}
+
+fn entry_from_row(row: &PgRow) -> anyhow::Result<Entry> {
+    Ok(Entry {
+        hash: EntryHash::from_raw_39(&row.try_get::<Vec<u8>, _>("hash")?)?,
+        created_at: Timestamp(row.try_get("created_at")?),
+        entry_type: row.try_get::<String, _>("entry_type")?.parse()?,
+        content: row.try_get("content")?,
+    })
+}
+
+fn validation_status(value: i16) -> anyhow::Result<ValidationStatus> {
+    Ok(match value {
+        0 => ValidationStatus::Pending,
+        1 => ValidationStatus::Valid,
+        2 => ValidationStatus::Rejected,
+        3 => ValidationStatus::Abandoned,
+        _ => anyhow::bail!("unknown validation status {}", value),
+    })
+}
+
+fn op_from_row(row: &PgRow) -> anyhow::Result<DhtOp> {
+    Ok(DhtOp {
+        op_hash: OpHash::from_raw_39(&row.try_get::<Vec<u8>, _>("op_hash")?)?,
+        op_type: row.try_get("op_type")?,
+        basis_loc: row.try_get::<i64, _>("basis_loc")? as u32,
+        authored_timestamp: row.try_get("authored_timestamp")?,
+        when_integrated: row.try_get("when_integrated")?,
+        validation_status: validation_status(row.try_get("validation_status")?)?,
+        dependency: row
+            .try_get::<Option<Vec<u8>>, _>("dependency")?
+            .map(|raw| OpHash::from_raw_39(&raw))
+            .transpose()?,
+    })
+}
+
+/// Handle to an entry database on a Postgres server, with the same
+/// async entry API as [`crate::Db`].
+///
+/// There's no read / write split or write permit: the server handles
+/// concurrent writers itself. Cheap to clone, all clones share the pool.
+#[derive(Clone)]
+pub struct PgDb {
+    pool: PgPool,
+}
+
+impl PgDb {
+    /// Connect to the database at `url` (`postgres://...`), and apply
+    /// any pending migrations.
+    pub async fn open(url: &str) -> anyhow::Result<Self> {
+        Self::from_pool(PgPool::connect(url).await?).await
+    }
+
+    /// Use an already configured pool, applying any pending migrations.
+    pub async fn from_pool(pool: PgPool) -> anyhow::Result<Self> {
+        let db = Self { pool };
+        db.migrate().await?;
+        Ok(db)
+    }
+
+    /// Apply any pending migrations, returning how many ran.
+    ///
+    /// Fails without touching the schema if it was migrated
+    /// by a newer version than this one knows about.
+    pub async fn migrate(&self) -> anyhow::Result<usize> {
+        let applied = || async {
+            let exists: bool =
+                sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+                    .fetch_one(&self.pool)
+                    .await?;
+            if !exists {
+                return Ok::<_, sqlx::Error>((0, 0));
+            }
+            sqlx::query_as::<_, (i64, i64)>(
+                "SELECT COUNT(*), COALESCE(MAX(version), 0) FROM _sqlx_migrations",
+            )
+            .fetch_one(&self.pool)
+            .await
+        };
+        let (before, current) = applied().await?;
+        let latest = PG_MIGRATOR.iter().last().map_or(0, |m| m.version);
+        if current > latest {
+            anyhow::bail!(
+                "database is at schema version {} but this build only knows up to {}",
+                current,
+                latest
+            );
+        }
+        PG_MIGRATOR.run(&self.pool).await?;
+        let (after, _) = applied().await?;
+        Ok((after - before) as usize)
+    }
+
+    /// Close every connection in the pool, which clones share, so
+    /// their queries fail with the pool closed from then on.
+    pub async fn close(self) {
+        self.pool.close().await
+    }
+
+    /// Insert a single entry.
+    pub async fn insert_entry(&self, entry: &Entry) -> anyhow::Result<WriteOutcome> {
+        let sql = numbered_placeholders(INSERT_ENTRY);
+        let res = bind_entry(sqlx::query(&sql), entry)
+            .execute(&self.pool)
+            .await?;
+        Ok(WriteOutcome::inserted(res.rows_affected(), 1))
+    }
+
+    /// Insert an entry unless one with the same hash is already held,
+    /// in which case it's counted as ignored.
+    pub async fn upsert_entry(&self, entry: &Entry) -> anyhow::Result<WriteOutcome> {
+        let sql = numbered_placeholders(&format!("{} ON CONFLICT (hash) DO NOTHING", INSERT_ENTRY));
+        let res = bind_entry(sqlx::query(&sql), entry)
+            .execute(&self.pool)
+            .await?;
+        Ok(WriteOutcome::inserted(res.rows_affected(), 1))
+    }
+
+    /// Insert many entries in a single transaction.
+    /// Nothing is inserted if any of them fails.
+    pub async fn insert_entries(&self, entries: &[Entry]) -> anyhow::Result<WriteOutcome> {
+        let sql = numbered_placeholders(INSERT_ENTRY);
+        let mut tx = self.pool.begin().await?;
+        let mut inserted = 0;
+        for entry in entries {
+            inserted += bind_entry(sqlx::query(&sql), entry)
+                .execute(&mut tx)
+                .await?
+                .rows_affected();
+        }
+        tx.commit().await?;
+        Ok(WriteOutcome::inserted(inserted, entries.len() as u64))
+    }
+
+    /// Delete an entry, and with it any headers creating it.
+    /// One that isn't held is counted as ignored.
+    pub async fn delete_entry(&self, hash: &EntryHash) -> anyhow::Result<WriteOutcome> {
+        let res = sqlx::query("DELETE FROM entries WHERE hash = $1")
+            .bind(hash.get_raw_39())
+            .execute(&self.pool)
+            .await?;
+        Ok(WriteOutcome::deleted(res.rows_affected(), 1))
+    }
+
+    /// True if we hold the entry with `hash`.
+    pub async fn entry_exists(&self, hash: &EntryHash) -> anyhow::Result<bool> {
+        Ok(sqlx::query("SELECT 1 FROM entries WHERE hash = $1")
+            .bind(hash.get_raw_39())
+            .fetch_optional(&self.pool)
+            .await?
+            .is_some())
+    }
+
+    /// Fetch the entry with `hash`.
+    pub async fn get_entry(&self, hash: &EntryHash) -> anyhow::Result<Option<Entry>> {
+        let sql = format!("SELECT {} FROM entries WHERE hash = $1", ENTRY_COLUMNS);
+        sqlx::query(&sql)
+            .bind(hash.get_raw_39())
+            .fetch_optional(&self.pool)
+            .await?
+            .as_ref()
+            .map(entry_from_row)
+            .transpose()
+    }
+
+    /// Fetch all entries matching `filter`.
+    pub async fn filter_entries(&self, filter: &EntryFilter) -> anyhow::Result<Vec<Entry>> {
+        let query = filter.select(ENTRY_COLUMNS, None);
+        let sql = numbered_placeholders(&query.sql);
+        bind_values(sqlx::query(&sql), query.values())
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(entry_from_row)
+            .collect()
+    }
+
+    /// Fetch all entries within the given (inclusive) location
+    /// and creation time ranges.
+    /// The location range wraps if `dht_loc_start > dht_loc_end`.
+    pub async fn query_entries(
+        &self,
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+    ) -> anyhow::Result<Vec<Entry>> {
+        self.filter_entries(
+            &EntryFilter::new()
+                .loc_range(dht_loc_start, dht_loc_end)
+                .time_range(created_at_start, created_at_end),
+        )
+        .await
+    }
+
+    /// Count the entries within the given (inclusive) location
+    /// and creation time ranges, without fetching them.
+    /// The location range wraps if `dht_loc_start > dht_loc_end`.
+    pub async fn count_entries(
+        &self,
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+    ) -> anyhow::Result<u64> {
+        let query = EntryFilter::new()
+            .loc_range(dht_loc_start, dht_loc_end)
+            .time_range(created_at_start, created_at_end)
+            .select("count(*)", None);
+        let sql = numbered_placeholders(&query.sql);
+        let count: i64 = bind_values(sqlx::query(&sql), query.values())
+            .fetch_one(&self.pool)
+            .await?
+            .try_get(0)?;
+        Ok(count as u64)
+    }
+
+    /// Insert a single header.
+    /// Fails if the referenced entry isn't stored.
+    pub async fn insert_header(&self, header: &Header) -> anyhow::Result<WriteOutcome> {
+        let sql = numbered_placeholders(INSERT_HEADER);
+        let res = sqlx::query(&sql)
+            .bind(header.hash.get_raw_39())
+            .bind(header.author.get_raw_39())
+            .bind(i64::from(header.seq))
+            .bind(header.prev_hash.as_ref().map(|hash| hash.get_raw_39()))
+            .bind(header.entry_hash.as_ref().map(|hash| hash.get_raw_39()))
+            .bind(header.header_type)
+            .bind(header.timestamp)
+            .execute(&self.pool)
+            .await?;
+        Ok(WriteOutcome::inserted(res.rows_affected(), 1))
+    }
+
+    /// Insert a single op, merging into the stored one if it's already
+    /// held, see [`crate::DbWrite::insert_op`].
+    pub async fn insert_op(&self, op: &DhtOp) -> anyhow::Result<WriteOutcome> {
+        let mut tx = self.pool.begin().await?;
+        // postgres counts a merged op as one row, same as a new one,
+        // so whether it was held is asked in the same transaction
+        let existed = sqlx::query("SELECT 1 FROM dht_ops WHERE op_hash = $1")
+            .bind(op.op_hash.get_raw_39())
+            .fetch_optional(&mut tx)
+            .await?
+            .is_some();
+        let res = sqlx::query(INSERT_OP)
+            .bind(op.op_hash.get_raw_39())
+            .bind(op.op_type)
+            .bind(i64::from(op.basis_loc))
+            .bind(op.authored_timestamp)
+            .bind(op.when_integrated)
+            .bind(op.validation_status as i16)
+            .bind(op.dependency.as_ref().map(|hash| hash.get_raw_39()))
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(WriteOutcome::upserted(existed, res.rows_affected()))
+    }
+
+    /// Fetch a single op by hash.
+    pub async fn get_op(&self, op_hash: &OpHash) -> anyhow::Result<Option<DhtOp>> {
+        sqlx::query(
+            "SELECT op_hash, op_type, basis_loc, authored_timestamp,
+                when_integrated, validation_status, dependency
+            FROM dht_ops WHERE op_hash = $1",
+        )
+        .bind(op_hash.get_raw_39())
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(op_from_row)
+        .transpose()
+    }
+
+    /// Record the validation outcome of an op without integrating it.
+    /// Fails if the op isn't stored.
+    pub async fn set_validation_status(
+        &self,
+        op_hash: &OpHash,
+        status: ValidationStatus,
+    ) -> anyhow::Result<()> {
+        let res = sqlx::query("UPDATE dht_ops SET validation_status = $2 WHERE op_hash = $1")
+            .bind(op_hash.get_raw_39())
+            .bind(status as i16)
+            .execute(&self.pool)
+            .await?;
+        if res.rows_affected() == 0 {
+            anyhow::bail!("no such op");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,328 @@
+use crate::backend::{bind_values, row_values};
+use crate::commit::commit_seq;
+use crate::db::ENTRY_COLUMNS;
+use crate::element::SELECT_ELEMENTS;
+use crate::explain::Explainer;
+use crate::filter::FilterSql;
+use crate::interrupt::{Interrupt, InterruptOnDrop};
+use crate::retry::is_busy;
+use crate::statement_cache::StatementCache;
+use crate::{
+    AgentPubKey, CommitMarker, Db, DbError, DbRead, Element, Entry, EntryFilter, EntryHash,
+    EntryType, HeaderHash, Timestamp, Value,
+};
+use futures::future::BoxFuture;
+use futures::TryStreamExt;
+use libsqlite3_sys::{sqlite3_finalize, sqlite3_prepare_v2, sqlite3_stmt_readonly, SQLITE_OK};
+use sqlx::sqlite::SqliteRow;
+use sqlx::SqliteConnection;
+use sqlx::{Execute, Sqlite, Transaction};
+use std::os::raw::c_int;
+use std::time::Instant;
+use tracing::Instrument;
+
+/// One read transaction, handed to the closure passed to [`Db::read`].
+///
+/// Everything read through it comes from the same snapshot, however
+/// many statements it takes. It runs on the read-only pool, and only
+/// has reads to offer.
+///
+/// Each call steps its statement to the end rather than stopping at the
+/// row it wanted, so none is left part way once it returns, where it
+/// would keep an interrupt pending for the rollback.
+pub struct Reader {
+    pub(crate) tx: Transaction<'static, Sqlite>,
+    explain: Explainer,
+    statements: StatementCache,
+}
+
+impl Reader {
+    /// Run the compiled filter, logging its plan if explaining.
+    pub(crate) async fn fetch_entries(&mut self, query: &FilterSql) -> anyhow::Result<Vec<Entry>> {
+        self.explain.check_on(&mut self.tx, &query.sql).await;
+        self.statements.record(&mut self.tx, &query.sql);
+        let out = sqlx::query_as_with::<_, Entry, _>(&query.sql, query.arguments())
+            .fetch(&mut self.tx)
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(out)
+    }
+
+    /// Run a statement of the application's own, binding `params` to
+    /// its `?` placeholders in order, and return every row it produced.
+    /// For the sql functions registered on every connection, see
+    /// [`crate::DbConfig::function`].
+    ///
+    /// Fails without running anything if `sql` would write, whichever
+    /// connection the reader is on.
+    pub async fn query(&mut self, sql: &str, params: &[Value]) -> anyhow::Result<Vec<Vec<Value>>> {
+        check_read_only(&mut self.tx, sql)?;
+        self.explain.check_on(&mut self.tx, sql).await;
+        let rows: Vec<SqliteRow> = bind_values(sqlx::query(sql), params)
+            .fetch(&mut self.tx)
+            .try_collect()
+            .await?;
+        Ok(rows.iter().map(row_values).collect::<sqlx::Result<_>>()?)
+    }
+
+    /// Fetch all entries matching `filter`.
+    pub async fn filter_entries(&mut self, filter: &EntryFilter) -> anyhow::Result<Vec<Entry>> {
+        self.fetch_entries(&filter.select(ENTRY_COLUMNS, None))
+            .await
+    }
+
+    /// Count the entries matching `filter`, without fetching them.
+    pub async fn count_matching(&mut self, filter: &EntryFilter) -> anyhow::Result<u64> {
+        let query = filter.select("count(*)", None);
+        self.explain.check_on(&mut self.tx, &query.sql).await;
+        let counts: Vec<(i64,)> = sqlx::query_as_with(&query.sql, query.arguments())
+            .fetch_all(&mut self.tx)
+            .await?;
+        Ok(counts.first().map_or(0, |(count,)| *count as u64))
+    }
+
+    /// True if we hold the entry with `hash`.
+    pub async fn entry_exists(&mut self, hash: &EntryHash) -> anyhow::Result<bool> {
+        let query = || sqlx::query!("SELECT 1 AS found FROM entries WHERE hash = ?1;", hash);
+        self.explain.check_on(&mut self.tx, query().sql()).await;
+        let found = query().fetch_all(&mut self.tx).await?;
+        Ok(!found.is_empty())
+    }
+
+    /// Fetch the entry with `hash`.
+    pub async fn get_entry(&mut self, hash: &EntryHash) -> anyhow::Result<Option<Entry>> {
+        let query = || {
+            sqlx::query_as!(
+                Entry,
+                r#"SELECT
+                    hash AS "hash!: EntryHash",
+                    created_at AS "created_at: Timestamp",
+                    entry_type AS "entry_type: EntryType",
+                    content
+                FROM entries WHERE hash = ?1;"#,
+                hash
+            )
+        };
+        self.explain.check_on(&mut self.tx, query().sql()).await;
+        self.statements.record(&mut self.tx, query().sql());
+        let out = query().fetch_all(&mut self.tx).await?;
+        Ok(out.into_iter().next())
+    }
+
+    /// Fetch a header and the entry it creates, if any.
+    pub async fn get_element(
+        &mut self,
+        header_hash: &HeaderHash,
+    ) -> anyhow::Result<Option<Element>> {
+        let sql = format!("{} WHERE headers.hash = ?1;", SELECT_ELEMENTS);
+        self.explain.check_on(&mut self.tx, &sql).await;
+        let out = sqlx::query_as::<_, Element>(&sql)
+            .bind(header_hash)
+            .fetch_all(&mut self.tx)
+            .await?;
+        Ok(out.into_iter().next())
+    }
+
+    /// Fetch `author`'s elements with chain positions within the given
+    /// (inclusive) range, in chain order.
+    pub async fn query_by_author(
+        &mut self,
+        author: &AgentPubKey,
+        seq_start: u32,
+        seq_end: u32,
+    ) -> anyhow::Result<Vec<Element>> {
+        let sql = format!(
+            "{} WHERE headers.author = ?1 AND headers.seq >= ?2 AND headers.seq <= ?3
+            ORDER BY headers.seq;",
+            SELECT_ELEMENTS
+        );
+        self.explain.check_on(&mut self.tx, &sql).await;
+        let out = sqlx::query_as::<_, Element>(&sql)
+            .bind(author)
+            .bind(seq_start)
+            .bind(seq_end)
+            .fetch(&mut self.tx)
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(out)
+    }
+}
+
+/// Fail unless every statement in `sql` only reads, going by
+/// `sqlite3_stmt_readonly`. One that doesn't compile is left for sqlx
+/// to report.
+fn check_read_only(con: &mut SqliteConnection, sql: &str) -> anyhow::Result<()> {
+    let handle = con.as_raw_handle();
+    let mut rest = sql.as_bytes();
+    while !rest.is_empty() {
+        let mut stmt = std::ptr::null_mut();
+        let mut tail = std::ptr::null();
+        // safety: we hold the connection and nothing is stepping on it,
+        // and the statement is finalized before the next is prepared
+        let (rc, read_only) = unsafe {
+            let rc = sqlite3_prepare_v2(
+                handle,
+                rest.as_ptr() as *const _,
+                rest.len() as c_int,
+                &mut stmt,
+                &mut tail,
+            );
+            let read_only = stmt.is_null() || sqlite3_stmt_readonly(stmt) != 0;
+            sqlite3_finalize(stmt);
+            (rc, read_only)
+        };
+        if rc != SQLITE_OK || tail.is_null() {
+            break;
+        }
+        if !read_only {
+            anyhow::bail!("a reader only runs statements that read, not {:?}", sql);
+        }
+        match tail as usize - rest.as_ptr() as usize {
+            0 => break,
+            used => rest = &rest[used..],
+        }
+    }
+    Ok(())
+}
+
+impl Db {
+    /// Run `f` in a single read transaction, so everything it reads
+    /// comes from one snapshot.
+    ///
+    /// ```no_run
+    /// # use spike_sqlx::*;
+    /// # async fn f(db: Db, header_hash: HeaderHash) -> anyhow::Result<()> {
+    /// let (element, held) = db
+    ///     .read(move |reader| {
+    ///         let header_hash = header_hash.clone();
+    ///         Box::pin(async move {
+    ///             let element = reader.get_element(&header_hash).await?;
+    ///             let held = reader.count_matching(&EntryFilter::new()).await?;
+    ///             Ok((element, held))
+    ///         })
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn read<F, R>(&self, f: F) -> anyhow::Result<R>
+    where
+        F: for<'r> FnMut(&'r mut Reader) -> BoxFuture<'r, anyhow::Result<R>> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.reader().read(f).await
+    }
+}
+
+impl DbRead {
+    /// Run `f` in a single read transaction, so everything it reads
+    /// comes from one snapshot.
+    ///
+    /// If it fails because the database is busy or locked `f` is run
+    /// again on a fresh transaction, as the retry policy allows,
+    /// so anything else it does has to be safe to repeat.
+    ///
+    /// Dropping the returned future interrupts the statement `f` is
+    /// running, so a slow scan nobody is waiting for anymore gives up
+    /// its connection straight away. `f` runs on a task of its own to
+    /// allow that, which is why it can't borrow. Give up on the whole
+    /// read like that rather than on one [`Reader`] call inside `f`,
+    /// with a timeout say: sqlx would carry on stepping that call's
+    /// statement on its worker thread while the connection is rolled
+    /// back and reused.
+    pub async fn read<F, R>(&self, f: F) -> anyhow::Result<R>
+    where
+        F: for<'r> FnMut(&'r mut Reader) -> BoxFuture<'r, anyhow::Result<R>> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.read_since(None, f).await
+    }
+
+    /// [`DbRead::read`], first waiting for a snapshot at or past `after`.
+    pub(crate) async fn read_since<F, R>(
+        &self,
+        after: Option<CommitMarker>,
+        mut f: F,
+    ) -> anyhow::Result<R>
+    where
+        F: for<'r> FnMut(&'r mut Reader) -> BoxFuture<'r, anyhow::Result<R>> + Send + 'static,
+        R: Send + 'static,
+    {
+        // released when the task is done with the connection
+        let permit = self.permits.acquire().await?;
+        let (pool, retry, explain) = (self.pool.clone(), self.retry.clone(), self.explain.clone());
+        let statements = self.statements.clone();
+        let mut commits = self.commits.clone();
+        let interrupt = Interrupt::default();
+        let _on_drop = InterruptOnDrop(interrupt.clone());
+        let mut op = self.trace.op("read_txn");
+        let span = op.span.clone();
+        let task = async move {
+            let _permit = permit;
+            let res = async {
+                let start = Instant::now();
+                let mut attempt = 1;
+                loop {
+                    let mut reader = Reader {
+                        tx: pool.begin().await?,
+                        explain: explain.clone(),
+                        statements: statements.clone(),
+                    };
+                    if let Some(CommitMarker(wanted)) = after {
+                        let seen = commit_seq(&mut reader.tx).await?;
+                        if seen < wanted {
+                            reader.tx.rollback().await?;
+                            if attempt >= retry.max_attempts || start.elapsed() >= retry.max_elapsed
+                            {
+                                return Err(DbError::StaleSnapshot(wanted, seen).into());
+                            }
+                            // woken early by the next commit_and_notify
+                            let backoff = retry.backoff(attempt - 1);
+                            if let Ok(Err(_)) =
+                                tokio::time::timeout(backoff, commits.changed()).await
+                            {
+                                // the writer is gone, nothing will wake us
+                                tokio::time::sleep(backoff).await;
+                            }
+                            attempt += 1;
+                            continue;
+                        }
+                    }
+                    let armed = interrupt.arm(&mut reader.tx);
+                    let res = f(&mut reader).await;
+                    // a late interrupt would otherwise hit the rollback.
+                    // One that came in time stopped the statement running
+                    // then, and every other has been stepped to the end,
+                    // so it's cleared as the rollback starts. Resetting
+                    // statements here instead could race a step sqlx is
+                    // still taking on its worker thread.
+                    drop(armed);
+                    // nothing was written, ending the snapshot is all either would do
+                    reader.tx.rollback().await?;
+                    match res {
+                        Err(err)
+                            if err.downcast_ref::<sqlx::Error>().is_some_and(is_busy)
+                                && attempt < retry.max_attempts
+                                && start.elapsed() < retry.max_elapsed =>
+                        {
+                            op.retrying(&err);
+                            tokio::time::sleep(retry.backoff(attempt - 1)).await;
+                            attempt += 1;
+                        }
+                        res => return res,
+                    }
+                }
+            }
+            .await;
+            op.finish_unknown(&res).await;
+            res
+        };
+        let task = tokio::task::spawn(task.instrument(span));
+        match task.await {
+            Ok(res) => res,
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            // the runtime is shutting down
+            Err(err) => Err(err.into()),
+        }
+    }
+}
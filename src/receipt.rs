@@ -0,0 +1,128 @@
+use crate::retry::with_retry;
+use crate::{AgentPubKey, Db, DbRead, DbWrite, OpHash, WriteOutcome};
+use chrono::prelude::*;
+use futures::TryStreamExt;
+use sqlx::Execute;
+
+/// Demo validation receipt type for database.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ValidationReceipt {
+    /// The op that was validated.
+    pub op_hash: OpHash,
+    /// The validator vouching for it.
+    pub signer: AgentPubKey,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ValidationReceipt {
+    /// Generate a receipt for `op_hash` from a random signer.
+    pub fn rand(op_hash: OpHash) -> Self {
+        Self {
+            op_hash,
+            signer: AgentPubKey::rand(),
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+impl Db {
+    /// Store a receipt, ignoring repeats from the same signer.
+    pub async fn insert_receipt(
+        &self,
+        receipt: &ValidationReceipt,
+    ) -> anyhow::Result<WriteOutcome> {
+        self.write.insert_receipt(receipt).await
+    }
+
+    /// How many distinct validators have sent a receipt for `op_hash`.
+    pub async fn count_receipts(&self, op_hash: &OpHash) -> anyhow::Result<u32> {
+        self.reader().count_receipts(op_hash).await
+    }
+
+    /// Hashes of the ops with fewer than `threshold` receipts,
+    /// i.e. the ones the publish workflow should keep republishing.
+    pub async fn ops_needing_more_receipts(&self, threshold: u32) -> anyhow::Result<Vec<OpHash>> {
+        self.reader().ops_needing_more_receipts(threshold).await
+    }
+}
+
+impl DbWrite {
+    /// Store a receipt, ignoring repeats from the same signer.
+    /// Fails if the op isn't stored.
+    pub async fn insert_receipt(
+        &self,
+        receipt: &ValidationReceipt,
+    ) -> anyhow::Result<WriteOutcome> {
+        let res = with_retry(
+            self.trace.op("insert_receipt"),
+            &self.retry,
+            &self.permits,
+            || async {
+                // validators resend receipts until they see us stop publishing
+                sqlx::query!(
+                    "INSERT INTO validation_receipts (op_hash, signer, timestamp)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (op_hash, signer) DO NOTHING",
+                    receipt.op_hash,
+                    receipt.signer,
+                    receipt.timestamp,
+                )
+                .execute(&self.pool)
+                .await
+            },
+        )
+        .await?;
+        Ok(WriteOutcome::inserted(res.rows_affected(), 1))
+    }
+}
+
+impl DbRead {
+    /// How many distinct validators have sent a receipt for `op_hash`.
+    pub async fn count_receipts(&self, op_hash: &OpHash) -> anyhow::Result<u32> {
+        let query = || {
+            sqlx::query_scalar!(
+                r#"SELECT count(*) AS "count: u32" FROM validation_receipts WHERE op_hash = ?1;"#,
+                op_hash
+            )
+        };
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let count = with_retry(
+            self.trace.op("count_receipts").statement(sql, &[&op_hash]),
+            &self.retry,
+            &self.permits,
+            || async { query().fetch_one(&self.pool).await },
+        )
+        .await?;
+        Ok(count)
+    }
+
+    /// Hashes of the ops with fewer than `threshold` receipts,
+    /// i.e. the ones the publish workflow should keep republishing.
+    pub async fn ops_needing_more_receipts(&self, threshold: u32) -> anyhow::Result<Vec<OpHash>> {
+        // the left join keeps ops nobody has vouched for yet
+        let query = || {
+            sqlx::query_scalar!(
+                r#"SELECT dht_ops.op_hash AS "op_hash!: OpHash" FROM dht_ops
+                LEFT JOIN validation_receipts
+                    ON validation_receipts.op_hash = dht_ops.op_hash
+                GROUP BY dht_ops.op_hash
+                HAVING count(validation_receipts.signer) < ?1
+                ;"#,
+                threshold
+            )
+        };
+        let sql = query().sql();
+        self.explain.check(&self.pool, sql).await;
+        let out = with_retry(
+            self.trace
+                .op("ops_needing_more_receipts")
+                .statement(sql, &[&threshold]),
+            &self.retry,
+            &self.permits,
+            || async { query().fetch(&self.pool).try_collect::<Vec<_>>().await },
+        )
+        .await?;
+        Ok(out)
+    }
+}
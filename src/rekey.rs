@@ -0,0 +1,101 @@
+use crate::backup::main_file;
+use crate::db::{connect_keyed, health_check, init_connection, is_sqlcipher, key_pragma};
+use crate::key_provider::DbKey;
+use crate::{Db, DbError, SecretKey};
+use sqlx::Executor;
+use std::sync::atomic::Ordering;
+
+/// Holds off the writer's health check until dropped, which restores it
+/// even if the rekey is cancelled part way.
+struct UncheckedWriter<'a>(&'a Db);
+
+impl<'a> UncheckedWriter<'a> {
+    fn new(db: &'a Db) -> Self {
+        db.check_writer.store(false, Ordering::SeqCst);
+        Self(db)
+    }
+}
+
+impl Drop for UncheckedWriter<'_> {
+    fn drop(&mut self) {
+        let check = self.0.config.health_check_on_acquire;
+        self.0.check_writer.store(check, Ordering::SeqCst);
+    }
+}
+
+impl Db {
+    /// Re-encrypt the database under `new_key` with `PRAGMA rekey`,
+    /// then check a fresh connection opens it with that key and one
+    /// with the old key no longer does.
+    ///
+    /// Fails without changing anything if the database is plaintext or
+    /// the linked sqlite isn't SQLCipher, which would ignore the pragma.
+    ///
+    /// Connections opened from here on use the new key. Pooled readers
+    /// still keyed with the old one fail their health check on next
+    /// acquire and are replaced, so keep
+    /// [`crate::DbConfig::health_check_on_acquire`] enabled when rekeying.
+    pub async fn rekey(&self, new_key: [u8; 32]) -> anyhow::Result<()> {
+        let new_key = DbKey::Raw(SecretKey::new(new_key));
+        let old_key = match self.key.read().unwrap().clone() {
+            Some(key) => key,
+            None => anyhow::bail!("cannot rekey a plaintext database"),
+        };
+        // the old key would still open it, failing the check below
+        if let (DbKey::Raw(old), DbKey::Raw(new)) = (&old_key, &new_key) {
+            if old.as_bytes() == new.as_bytes() {
+                anyhow::bail!("the new key is the key the database already has");
+            }
+        }
+        {
+            // mid-rekey the writer's key and the stored one disagree
+            let _unchecked = UncheckedWriter::new(self);
+            let _permit = self.write.permits.acquire().await?;
+            let mut con = self.write.pool.acquire().await?;
+
+            if !is_sqlcipher(&mut con).await? {
+                anyhow::bail!("cannot rekey without SQLCipher, this sqlite ignores keys");
+            }
+
+            // hold the write lock for the whole page rewrite
+            con.execute("BEGIN EXCLUSIVE;").await?;
+            if let Err(err) = key_pragma(&mut con, "rekey", &new_key) {
+                con.execute("ROLLBACK;").await?;
+                return Err(err.into());
+            }
+            con.execute("COMMIT;").await?;
+
+            *self.key.write().unwrap() = Some(new_key.clone());
+        }
+
+        let file = main_file(self).await?;
+        let mut check = connect_keyed(
+            self.options.clone(),
+            file.as_deref(),
+            &self.config,
+            Some(&new_key),
+        )
+        .await?;
+        init_connection(&mut check, &self.config, Some(&new_key)).await?;
+        health_check(&mut check).await?;
+
+        let stale = async {
+            let mut stale = connect_keyed(
+                self.options.clone(),
+                file.as_deref(),
+                &self.config,
+                Some(&old_key),
+            )
+            .await?;
+            init_connection(&mut stale, &self.config, Some(&old_key)).await
+        };
+        match stale.await {
+            Err(sqlx::Error::Configuration(err))
+                if matches!(err.downcast_ref(), Some(DbError::BadEncryptionKey)) => {}
+            Ok(()) => anyhow::bail!("the database still opens under the old key after rekey"),
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(())
+    }
+}
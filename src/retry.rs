@@ -0,0 +1,100 @@
+use crate::error::sqlite_code;
+use crate::permit::Permits;
+use crate::trace::{RowCount, TracedOp};
+use rand::Rng;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+/// How transactions are retried when sqlite reports the database
+/// as busy or locked.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts including the first, 1 disables retrying.
+    pub max_attempts: u32,
+    /// Backoff before the first retry, doubled on each subsequent one.
+    pub initial_backoff: Duration,
+    /// Upper bound on a single backoff.
+    pub max_backoff: Duration,
+    /// Give up once this much time has passed since the first attempt.
+    pub max_elapsed: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            initial_backoff: Duration::from_millis(5),
+            max_backoff: Duration::from_millis(500),
+            max_elapsed: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Backoff before retry number `retry` (starting at 0),
+    /// with full jitter so contending tasks don't retry in lockstep.
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        let exp = self
+            .initial_backoff
+            .checked_mul(1 << retry.min(16))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+        let max_micros = exp.as_micros().max(1) as u64;
+        Duration::from_micros(rand::thread_rng().gen_range(0, max_micros + 1))
+    }
+}
+
+/// True if `err` is a transient SQLITE_BUSY / SQLITE_LOCKED failure.
+pub fn is_busy(err: &sqlx::Error) -> bool {
+    matches!(sqlite_code(err), Some(5) | Some(6))
+}
+
+/// Run `f` until it succeeds, fails with a non-busy error,
+/// or the policy is exhausted, all within the span of `op`.
+///
+/// Holds one of `permits` throughout, backoffs included.
+pub(crate) async fn with_retry<T, F, Fut>(
+    mut op: TracedOp<'_>,
+    policy: &RetryPolicy,
+    permits: &Permits,
+    mut f: F,
+) -> anyhow::Result<T>
+where
+    T: RowCount,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = sqlx::Result<T>>,
+{
+    let span = op.span.clone();
+    let res = async {
+        let _permit = permits.acquire().await?;
+        let start = Instant::now();
+        let mut attempt = 1;
+        loop {
+            match f().await {
+                Err(err)
+                    if is_busy(&err)
+                        && attempt < policy.max_attempts
+                        && start.elapsed() < policy.max_elapsed =>
+                {
+                    op.retrying(&err);
+                    tokio::time::sleep(policy.backoff(attempt - 1)).await;
+                    attempt += 1;
+                }
+                res => return Ok(res?),
+            }
+        }
+    }
+    .instrument(span)
+    .await;
+    op.finish(&res).await;
+    res
+}
@@ -0,0 +1,123 @@
+//! Row counts per table kept up to date from each commit, so dashboards
+//! can show them on every scrape without scanning tables of millions
+//! of rows.
+//!
+//! Every commit's [`CommitSummary`] moves the counts by the rows it
+//! inserted and deleted. Counting with `count(*)`, on a timer or
+//! through [`Db::stats`], puts them right again.
+
+use crate::stats::{count_rows, table_names};
+use crate::{CommitSummary, Db, DbRead};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// One table's approximate row count, in
+/// [`crate::MetricsSnapshot::tables`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableRows {
+    pub table: String,
+    /// As last counted, plus the rows inserted and less those deleted
+    /// by every commit since.
+    pub rows: u64,
+    /// How long ago it was last counted.
+    pub since_counted: Duration,
+}
+
+#[derive(Debug)]
+struct Count {
+    rows: i64,
+    /// `None` until counted, before then `rows` is only the change.
+    counted: Option<Instant>,
+}
+
+/// The row counts of every table, shared by the reader that counts them
+/// and the commit hook that moves them.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RowCounts(Arc<Mutex<BTreeMap<String, Count>>>);
+
+impl RowCounts {
+    /// Move the counts by what a commit inserted and deleted.
+    pub(crate) fn apply(&self, summary: &CommitSummary) {
+        let mut counts = self.0.lock().unwrap();
+        for changes in &summary.tables {
+            let count = counts.entry(changes.table.clone()).or_insert(Count {
+                rows: 0,
+                counted: None,
+            });
+            count.rows += changes.inserted as i64 - changes.deleted as i64;
+        }
+    }
+
+    /// Replace the counts with `counted`, forgetting tables that have
+    /// gone.
+    pub(crate) fn reconcile(&self, counted: Vec<(String, u64)>) {
+        let now = Instant::now();
+        let mut counts = self.0.lock().unwrap();
+        *counts = counted
+            .into_iter()
+            .map(|(table, rows)| {
+                let count = Count {
+                    rows: rows as i64,
+                    counted: Some(now),
+                };
+                (table, count)
+            })
+            .collect();
+    }
+
+    /// Every table counted so far, by name.
+    pub(crate) fn snapshot(&self) -> Vec<TableRows> {
+        let counts = self.0.lock().unwrap();
+        counts
+            .iter()
+            .filter_map(|(table, count)| {
+                Some(TableRows {
+                    table: table.clone(),
+                    rows: count.rows.max(0) as u64,
+                    since_counted: count.counted?.elapsed(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Db {
+    /// See [`DbRead::count_table_rows`].
+    pub async fn count_table_rows(&self) -> anyhow::Result<Vec<TableRows>> {
+        self.reader().count_table_rows().await
+    }
+}
+
+impl DbRead {
+    /// Count every table's rows with `count(*)`, in one read
+    /// transaction, putting right the counts kept from each commit.
+    ///
+    /// This scans every table. A write committing while it does may be
+    /// left out of the counts until the next time.
+    pub async fn count_table_rows(&self) -> anyhow::Result<Vec<TableRows>> {
+        let _permit = self.permits.acquire().await?;
+        let mut tx = self.pool.begin().await?;
+        let mut counted = Vec::new();
+        for table in table_names(&mut tx).await? {
+            let rows = count_rows(&mut tx, &table).await?;
+            counted.push((table, rows));
+        }
+        tx.rollback().await?;
+        self.row_counts.reconcile(counted);
+        Ok(self.row_counts.snapshot())
+    }
+}
+
+/// Count rows now and then every `interval` until the task is aborted.
+pub(crate) async fn row_count_task(read: DbRead, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        // the first tick completes immediately, there's nothing to show
+        // until the first count
+        ticker.tick().await;
+        if let Err(err) = read.count_table_rows().await {
+            tracing::warn!(?err, "background row count failed");
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{DbBackend, Statement, Value};
+use futures::future::BoxFuture;
+use rusqlite::types::{ToSqlOutput, ValueRef};
+use rusqlite::ToSql;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// [`DbBackend`] over a single rusqlite connection.
+///
+/// rusqlite blocks, so every call runs on tokio's blocking pool and
+/// calls take turns on the connection.
+#[derive(Clone)]
+pub struct RusqliteBackend {
+    con: Arc<Mutex<rusqlite::Connection>>,
+}
+
+impl ToSql for Value {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::Borrowed(match self {
+            Value::Null => ValueRef::Null,
+            Value::Integer(v) => ValueRef::Integer(*v),
+            Value::Real(v) => ValueRef::Real(*v),
+            Value::Text(v) => ValueRef::Text(v.as_bytes()),
+            Value::Blob(v) => ValueRef::Blob(v),
+        }))
+    }
+}
+
+impl From<ValueRef<'_>> for Value {
+    fn from(value: ValueRef<'_>) -> Self {
+        match value {
+            ValueRef::Null => Self::Null,
+            ValueRef::Integer(v) => Self::Integer(v),
+            ValueRef::Real(v) => Self::Real(v),
+            ValueRef::Text(v) => Self::Text(String::from_utf8_lossy(v).into_owned()),
+            ValueRef::Blob(v) => Self::Blob(v.to_vec()),
+        }
+    }
+}
+
+impl RusqliteBackend {
+    /// Run `f` on the connection on the blocking pool.
+    async fn with_con<F, R>(&self, f: F) -> anyhow::Result<R>
+    where
+        F: FnOnce(&mut rusqlite::Connection) -> rusqlite::Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let con = self.con.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            // a panic elsewhere leaves the connection usable
+            let mut con = con.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            f(&mut con)
+        })
+        .await?;
+        Ok(res?)
+    }
+}
+
+impl DbBackend for RusqliteBackend {
+    fn open(path: &Path) -> BoxFuture<'static, anyhow::Result<Self>> {
+        let path = path.to_path_buf();
+        Box::pin(async move {
+            let con =
+                tokio::task::spawn_blocking(move || rusqlite::Connection::open(path)).await??;
+            Ok(Self {
+                con: Arc::new(Mutex::new(con)),
+            })
+        })
+    }
+
+    fn execute<'a>(
+        &'a self,
+        sql: &'a str,
+        params: &'a [Value],
+    ) -> BoxFuture<'a, anyhow::Result<u64>> {
+        let (sql, params) = (sql.to_string(), params.to_vec());
+        Box::pin(self.with_con(move |con| {
+            let changed = con.prepare_cached(&sql)?.execute(&params)?;
+            Ok(changed as u64)
+        }))
+    }
+
+    fn query<'a>(
+        &'a self,
+        sql: &'a str,
+        params: &'a [Value],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<Vec<Value>>>> {
+        let (sql, params) = (sql.to_string(), params.to_vec());
+        Box::pin(self.with_con(move |con| {
+            let mut statement = con.prepare_cached(&sql)?;
+            let columns = statement.column_count();
+            let mut rows = statement.query(&params)?;
+            let mut out = Vec::new();
+            while let Some(row) = rows.next()? {
+                out.push(
+                    (0..columns)
+                        .map(|i| row.get_raw_checked(i).map(Value::from))
+                        .collect::<rusqlite::Result<_>>()?,
+                );
+            }
+            Ok(out)
+        }))
+    }
+
+    fn transaction(&self, statements: Vec<Statement>) -> BoxFuture<'_, anyhow::Result<u64>> {
+        Box::pin(self.with_con(move |con| {
+            // rolled back on drop if a statement fails
+            let tx = con.transaction()?;
+            let mut changed = 0;
+            for statement in &statements {
+                changed += tx
+                    .prepare_cached(&statement.sql)?
+                    .execute(&statement.params)? as u64;
+            }
+            tx.commit()?;
+            Ok(changed)
+        }))
+    }
+}
@@ -0,0 +1,533 @@
+//! The entry API on rusqlite, for comparing against [`crate::Db`].
+//!
+//! sqlx steps its statements on a worker thread per connection, but
+//! everything around a step (preparing, binding, reading rows out)
+//! still happens on whichever executor thread polls the future, so a
+//! slow page read or lock wait stalls other tasks. Here each connection
+//! lives on a thread of its own and callers only ever wait on a channel.
+
+use crate::changes::{track_changes, Changes, WATCH_TRIGGERS};
+use crate::db::{cipher_pragmas, exec_secret_on, ENTRY_COLUMNS, INSERT_ENTRY};
+use crate::migrations::MIGRATOR;
+use crate::{
+    AutoVacuum, CommitSummary, DbConfig, DbError, Encryption, Entry, EntryFilter, EntryHash,
+    SqliteJournalMode, SqliteSynchronous, Timestamp, Value, WriteOutcome,
+};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, NO_PARAMS};
+use std::any::Any;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{mpsc, oneshot};
+
+type Job = Box<dyn FnOnce(&mut Connection) + Send>;
+
+/// A thread owning one connection, running jobs in the order sent.
+struct Worker {
+    jobs: Mutex<Option<mpsc::UnboundedSender<Job>>>,
+    thread: Mutex<Option<std::thread::JoinHandle<()>>>,
+}
+
+impl Worker {
+    /// Start a thread and open its connection there.
+    async fn spawn<F>(name: String, open: F) -> anyhow::Result<Self>
+    where
+        F: FnOnce() -> anyhow::Result<Connection> + Send + 'static,
+    {
+        let (jobs, mut rx) = mpsc::unbounded_channel::<Job>();
+        let (opened, opened_rx) = oneshot::channel();
+        let thread = std::thread::Builder::new().name(name).spawn(move || {
+            let mut con = match open() {
+                Ok(con) => {
+                    let _ = opened.send(Ok(()));
+                    con
+                }
+                Err(err) => {
+                    let _ = opened.send(Err(err));
+                    return;
+                }
+            };
+            while let Some(job) = rx.blocking_recv() {
+                job(&mut con);
+            }
+        })?;
+        let worker = Self {
+            jobs: Mutex::new(Some(jobs)),
+            thread: Mutex::new(Some(thread)),
+        };
+        match opened_rx.await {
+            Ok(res) => res.map(|()| worker),
+            Err(_) => anyhow::bail!("connection thread died while opening"),
+        }
+    }
+
+    /// Run `f` on the connection, re-raising a panic on the caller.
+    async fn run<F, R>(&self, f: F) -> anyhow::Result<R>
+    where
+        F: FnOnce(&mut Connection) -> anyhow::Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (done, done_rx) = oneshot::channel::<Result<anyhow::Result<R>, Box<dyn Any + Send>>>();
+        let job: Job = Box::new(move |con| {
+            let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(con)));
+            let _ = done.send(res);
+        });
+        match &*self.jobs.lock().unwrap() {
+            Some(jobs) if jobs.send(job).is_ok() => {}
+            _ => anyhow::bail!("database is closed"),
+        }
+        match done_rx.await {
+            Ok(Ok(res)) => res,
+            Ok(Err(panic)) => std::panic::resume_unwind(panic),
+            Err(_) => anyhow::bail!("connection thread is gone"),
+        }
+    }
+
+    /// Stop taking jobs, and wait for the ones already sent.
+    async fn stop(&self) -> anyhow::Result<()> {
+        self.jobs.lock().unwrap().take();
+        let thread = self.thread.lock().unwrap().take();
+        if let Some(thread) = thread {
+            // the thread finishes once the queue is drained
+            tokio::task::spawn_blocking(move || thread.join())
+                .await?
+                .map_err(|_| anyhow::anyhow!("connection thread panicked"))?;
+        }
+        Ok(())
+    }
+}
+
+fn journal_mode(mode: &SqliteJournalMode) -> &'static str {
+    match mode {
+        SqliteJournalMode::Delete => "DELETE",
+        SqliteJournalMode::Truncate => "TRUNCATE",
+        SqliteJournalMode::Persist => "PERSIST",
+        SqliteJournalMode::Memory => "MEMORY",
+        SqliteJournalMode::Wal => "WAL",
+        SqliteJournalMode::Off => "OFF",
+    }
+}
+
+fn synchronous(synchronous: &SqliteSynchronous) -> &'static str {
+    match synchronous {
+        SqliteSynchronous::Off => "OFF",
+        SqliteSynchronous::Normal => "NORMAL",
+        SqliteSynchronous::Full => "FULL",
+        SqliteSynchronous::Extra => "EXTRA",
+    }
+}
+
+/// Open a connection and apply the same settings [`crate::Db`] does.
+fn open_connection(
+    path: &Path,
+    flags: OpenFlags,
+    config: &DbConfig,
+    key: Option<&crate::key_provider::DbKey>,
+) -> anyhow::Result<Connection> {
+    let con = Connection::open_with_flags(path, flags)?;
+    if let Some(key) = key {
+        let cmd = key.statement("PRAGMA key = ", ";");
+        // safety: the connection was just opened and nothing else has it
+        unsafe { exec_secret_on(con.handle(), "PRAGMA key", &cmd) }.map_err(anyhow::Error::msg)?;
+        for pragma in cipher_pragmas(config) {
+            con.execute_batch(&pragma)?;
+        }
+        // SQLCipher only notices a wrong key on the first real read
+        if let Err(err) = con.query_row("SELECT count(*) FROM sqlite_master", NO_PARAMS, |_| Ok(()))
+        {
+            let sqlcipher = con
+                .query_row("PRAGMA cipher_version;", NO_PARAMS, |_| Ok(()))
+                .optional()?
+                .is_some();
+            // stock sqlite ignores the key, so the file really is bad
+            return Err(if sqlcipher {
+                DbError::BadEncryptionKey.into()
+            } else {
+                err.into()
+            });
+        }
+    }
+    con.busy_timeout(config.busy_timeout)?;
+    con.set_prepared_statement_cache_capacity(config.statement_cache_capacity);
+    con.execute_batch(&format!(
+        "PRAGMA foreign_keys = {};
+        PRAGMA synchronous = {};",
+        if config.foreign_keys { "ON" } else { "OFF" },
+        synchronous(&config.synchronous)
+    ))?;
+    if let Some(cache_size) = config.cache_size {
+        con.execute_batch(&format!("PRAGMA cache_size = {};", cache_size))?;
+    }
+    if let Some(mmap_size) = config.mmap_size {
+        con.execute_batch(&format!("PRAGMA mmap_size = {};", mmap_size))?;
+    }
+    if let Some(pages) = config.wal_autocheckpoint {
+        con.execute_batch(&format!("PRAGMA wal_autocheckpoint = {};", pages))?;
+    }
+    Ok(con)
+}
+
+/// See [`crate::vacuum::apply_auto_vacuum`].
+fn apply_auto_vacuum(con: &Connection, mode: AutoVacuum) -> anyhow::Result<()> {
+    con.execute_batch(&format!("PRAGMA auto_vacuum = {};", mode.as_str()))?;
+    let current: i64 = con.query_row("PRAGMA auto_vacuum;", NO_PARAMS, |row| row.get(0))?;
+    if AutoVacuum::from_pragma(current) != mode {
+        con.execute_batch("VACUUM;")?;
+    }
+    Ok(())
+}
+
+/// Apply pending migrations, keeping the same `_sqlx_migrations`
+/// history sqlx does so either engine can open the file afterwards.
+fn migrate(con: &mut Connection) -> anyhow::Result<()> {
+    con.execute_batch(
+        "CREATE TABLE IF NOT EXISTS _sqlx_migrations (
+            version BIGINT PRIMARY KEY,
+            description TEXT NOT NULL,
+            installed_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            success BOOLEAN NOT NULL,
+            checksum BLOB NOT NULL,
+            execution_time BIGINT NOT NULL
+        );",
+    )?;
+    let current: i64 = con.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM _sqlx_migrations",
+        NO_PARAMS,
+        |row| row.get(0),
+    )?;
+    let latest = MIGRATOR.iter().last().map_or(0, |m| m.version);
+    if current > latest {
+        anyhow::bail!(
+            "database is at schema version {} but this build only knows up to {}",
+            current,
+            latest
+        );
+    }
+    for migration in MIGRATOR.iter() {
+        let checksum: Option<Vec<u8>> = con
+            .query_row(
+                "SELECT checksum FROM _sqlx_migrations WHERE version = ?1",
+                params![migration.version],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match checksum {
+            Some(checksum) if checksum == *migration.checksum => continue,
+            Some(_) => anyhow::bail!(
+                "migration {} was changed after it was applied",
+                migration.version
+            ),
+            None => {}
+        }
+        let start = Instant::now();
+        let tx = con.transaction()?;
+        tx.execute_batch(&migration.sql)?;
+        tx.commit()?;
+        con.execute(
+            "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+            VALUES (?1, ?2, TRUE, ?3, ?4)",
+            params![
+                migration.version,
+                &*migration.description,
+                &*migration.checksum,
+                start.elapsed().as_nanos() as i64
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn entry_params(entry: &Entry) -> [Value; 6] {
+    [
+        Value::Blob(entry.hash.get_raw_39().to_vec()),
+        Value::Integer(i64::from(entry.dht_loc())),
+        Value::Integer(entry.created_at.0),
+        Value::Text(entry.entry_type.to_string()),
+        Value::Integer(i64::from(entry.size_bytes())),
+        Value::Blob(entry.content.clone()),
+    ]
+}
+
+fn entry_from_row(row: &rusqlite::Row) -> anyhow::Result<Entry> {
+    Ok(Entry {
+        hash: EntryHash::from_raw_39(&row.get::<_, Vec<u8>>("hash")?)?,
+        created_at: Timestamp(row.get("created_at")?),
+        entry_type: row.get::<_, String>("entry_type")?.parse()?,
+        content: row.get("content")?,
+    })
+}
+
+struct Inner {
+    write: Worker,
+    read: Vec<Worker>,
+    next_read: AtomicUsize,
+    changes: Changes,
+}
+
+/// Handle to an entry database on rusqlite, with the same async entry
+/// API as [`crate::Db`] and the same file format.
+///
+/// Writes go to one connection thread and reads are spread over
+/// [`DbConfig::read_connections`] more, so sqlite never blocks an
+/// executor thread. There's no retry policy: with one writer thread
+/// nothing in the process competes for the write lock.
+/// Cheap to clone, all clones share the same threads.
+#[derive(Clone)]
+pub struct RusqliteDb {
+    inner: Arc<Inner>,
+}
+
+impl RusqliteDb {
+    /// Open (or create) the database at `path`,
+    /// see [`crate::Db::open`].
+    pub async fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        Self::open_with(path, DbConfig::default()).await
+    }
+
+    /// Open (or create) the database at `path` with `config`.
+    pub async fn open_with<P: AsRef<Path>>(path: P, config: DbConfig) -> anyhow::Result<Self> {
+        let path: PathBuf = path.as_ref().to_path_buf();
+        let key = match &config.encryption {
+            Some(Encryption::SqlCipher(source)) => Some(source.db_key(None).await?),
+            Some(Encryption::None) => None,
+            None => anyhow::bail!("no encryption configured, use Encryption::None for plaintext"),
+        };
+
+        // the writer must come first, it is the one allowed to create the file
+        let changes = Changes::new(config.subscription_capacity);
+        let write = Worker::spawn("rusqlite-write".to_string(), {
+            let (path, config, key) = (path.clone(), config.clone(), key.clone());
+            let changes = changes.clone();
+            move || {
+                let mut con = open_connection(&path, OpenFlags::default(), &config, key.as_ref())?;
+                con.execute_batch(&format!(
+                    "PRAGMA journal_mode = {};",
+                    journal_mode(&config.journal_mode)
+                ))?;
+                if let Some(mode) = config.auto_vacuum {
+                    apply_auto_vacuum(&con, mode)?;
+                }
+                migrate(&mut con)?;
+                // safety: the connection is open, and only ever this
+                // thread's
+                unsafe { track_changes(con.handle(), &changes) }.map_err(anyhow::Error::msg)?;
+                con.execute_batch(WATCH_TRIGGERS)?;
+                Ok(con)
+            }
+        })
+        .await?;
+
+        let mut read = Vec::new();
+        for i in 0..config.max_read_connections.max(1) {
+            let (path, config, key) = (path.clone(), config.clone(), key.clone());
+            let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+            read.push(
+                Worker::spawn(format!("rusqlite-read-{}", i), move || {
+                    open_connection(&path, flags, &config, key.as_ref())
+                })
+                .await?,
+            );
+        }
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                write,
+                read,
+                next_read: AtomicUsize::new(0),
+                changes,
+            }),
+        })
+    }
+
+    /// Run `f` on the next read connection in turn.
+    async fn read<F, R>(&self, f: F) -> anyhow::Result<R>
+    where
+        F: FnOnce(&mut Connection) -> anyhow::Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let i = self.inner.next_read.fetch_add(1, Ordering::Relaxed) % self.inner.read.len();
+        self.inner.read[i].run(f).await
+    }
+
+    /// Run `f` on the write connection, then run the commit hooks on
+    /// whatever it committed.
+    async fn write<F, R>(&self, f: F) -> anyhow::Result<R>
+    where
+        F: FnOnce(&mut Connection) -> anyhow::Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let res = self.inner.write.run(f).await;
+        self.inner.changes.publish();
+        res
+    }
+
+    /// Call `hook` with a summary of every transaction committed from
+    /// here on that wrote something, see [`crate::Db::on_commit`].
+    pub fn on_commit<F>(&self, hook: F)
+    where
+        F: Fn(&CommitSummary) + Send + Sync + 'static,
+    {
+        self.inner.changes.on_commit(Arc::new(hook))
+    }
+
+    /// Shut the database down, see [`crate::Db::close`].
+    ///
+    /// Work already queued finishes first. Clones send to the same
+    /// stopped workers, so anything they ask for afterwards fails.
+    pub async fn close(self) -> anyhow::Result<()> {
+        for worker in &self.inner.read {
+            worker.stop().await?;
+        }
+        let res = self
+            .inner
+            .write
+            .run(|con| {
+                con.query_row("PRAGMA wal_checkpoint(TRUNCATE);", NO_PARAMS, |_| Ok(()))?;
+                Ok(())
+            })
+            .await;
+        self.inner.write.stop().await?;
+        res
+    }
+
+    /// Insert a single entry in its own transaction.
+    pub async fn insert_entry(&self, entry: &Entry) -> anyhow::Result<WriteOutcome> {
+        let params = entry_params(entry);
+        let inserted = self
+            .write(move |con| Ok(con.prepare_cached(INSERT_ENTRY)?.execute(&params)?))
+            .await?;
+        Ok(WriteOutcome::inserted(inserted as u64, 1))
+    }
+
+    /// Insert an entry unless one with the same hash is already held,
+    /// in which case it's counted as ignored.
+    pub async fn upsert_entry(&self, entry: &Entry) -> anyhow::Result<WriteOutcome> {
+        let params = entry_params(entry);
+        let inserted = self
+            .write(move |con| {
+                let sql = format!("{} ON CONFLICT (hash) DO NOTHING", INSERT_ENTRY);
+                Ok(con.prepare_cached(&sql)?.execute(&params)?)
+            })
+            .await?;
+        Ok(WriteOutcome::inserted(inserted as u64, 1))
+    }
+
+    /// Insert many entries in a single transaction.
+    /// Nothing is inserted if any of them fails.
+    pub async fn insert_entries(&self, entries: &[Entry]) -> anyhow::Result<WriteOutcome> {
+        let rows: Vec<_> = entries.iter().map(entry_params).collect();
+        let inserted = self
+            .write(move |con| {
+                // rolled back on drop if an insert fails
+                let tx = con.transaction()?;
+                let mut inserted = 0;
+                {
+                    let mut insert = tx.prepare_cached(INSERT_ENTRY)?;
+                    for params in &rows {
+                        inserted += insert.execute(params)? as u64;
+                    }
+                }
+                tx.commit()?;
+                Ok(inserted)
+            })
+            .await?;
+        Ok(WriteOutcome::inserted(inserted, entries.len() as u64))
+    }
+
+    /// Delete an entry, and with it any headers creating it.
+    /// One that isn't held is counted as ignored.
+    pub async fn delete_entry(&self, hash: &EntryHash) -> anyhow::Result<WriteOutcome> {
+        let hash = hash.get_raw_39().to_vec();
+        let deleted = self
+            .write(move |con| {
+                Ok(con
+                    .prepare_cached("DELETE FROM entries WHERE hash = ?1")?
+                    .execute(params![hash])?)
+            })
+            .await?;
+        Ok(WriteOutcome::deleted(deleted as u64, 1))
+    }
+
+    /// True if we hold the entry with `hash`.
+    pub async fn entry_exists(&self, hash: &EntryHash) -> anyhow::Result<bool> {
+        let hash = hash.get_raw_39().to_vec();
+        self.read(move |con| {
+            Ok(con
+                .prepare_cached("SELECT 1 FROM entries WHERE hash = ?1;")?
+                .exists(params![hash])?)
+        })
+        .await
+    }
+
+    /// Fetch the entry with `hash`.
+    pub async fn get_entry(&self, hash: &EntryHash) -> anyhow::Result<Option<Entry>> {
+        let hash = hash.get_raw_39().to_vec();
+        self.read(move |con| {
+            let sql = format!("SELECT {} FROM entries WHERE hash = ?1;", ENTRY_COLUMNS);
+            let mut statement = con.prepare_cached(&sql)?;
+            let mut rows = statement.query(params![hash])?;
+            match rows.next()? {
+                Some(row) => Ok(Some(entry_from_row(row)?)),
+                None => Ok(None),
+            }
+        })
+        .await
+    }
+
+    /// Fetch all entries matching `filter`.
+    pub async fn filter_entries(&self, filter: &EntryFilter) -> anyhow::Result<Vec<Entry>> {
+        let query = filter.select(ENTRY_COLUMNS, None);
+        self.read(move |con| {
+            let mut statement = con.prepare_cached(&query.sql)?;
+            let mut rows = statement.query(query.values())?;
+            let mut out = Vec::new();
+            while let Some(row) = rows.next()? {
+                out.push(entry_from_row(row)?);
+            }
+            Ok(out)
+        })
+        .await
+    }
+
+    /// Fetch all entries within the given (inclusive) location
+    /// and creation time ranges.
+    /// The location range wraps if `dht_loc_start > dht_loc_end`.
+    pub async fn query_entries(
+        &self,
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+    ) -> anyhow::Result<Vec<Entry>> {
+        self.filter_entries(
+            &EntryFilter::new()
+                .loc_range(dht_loc_start, dht_loc_end)
+                .time_range(created_at_start, created_at_end),
+        )
+        .await
+    }
+
+    /// Count the entries within the given (inclusive) location
+    /// and creation time ranges, without fetching them.
+    /// The location range wraps if `dht_loc_start > dht_loc_end`.
+    pub async fn count_entries(
+        &self,
+        dht_loc_start: u32,
+        dht_loc_end: u32,
+        created_at_start: Timestamp,
+        created_at_end: Timestamp,
+    ) -> anyhow::Result<u64> {
+        let query = EntryFilter::new()
+            .loc_range(dht_loc_start, dht_loc_end)
+            .time_range(created_at_start, created_at_end)
+            .select("count(*)", None);
+        self.read(move |con| {
+            let count: i64 = con
+                .prepare_cached(&query.sql)?
+                .query_row(query.values(), |row| row.get(0))?;
+            Ok(count as u64)
+        })
+        .await
+    }
+}
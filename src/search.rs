@@ -0,0 +1,74 @@
+//! Full-text search over the content of held entries, through the
+//! `entry_search` FTS5 table the entry triggers keep up to date.
+
+use crate::retry::with_retry;
+use crate::{Db, DbRead, Entry};
+
+const SEARCH_ENTRIES: &str = "SELECT entries.*
+    FROM (
+        SELECT ids.hash AS found, entry_search.rank AS rank
+        FROM entry_search
+        JOIN entry_search_ids AS ids ON ids.id = entry_search.rowid
+        WHERE entry_search MATCH ?1
+        ORDER BY rank
+        LIMIT ?2
+    )
+    JOIN entries ON entries.hash = found
+    ORDER BY rank;";
+
+/// `text` as an FTS5 query matching every word of it, each quoted so
+/// nothing in it is taken for query syntax. `None` if it has no words.
+fn match_all_words(text: &str) -> Option<String> {
+    let words: Vec<String> = text
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+    if words.is_empty() {
+        None
+    } else {
+        Some(words.join(" "))
+    }
+}
+
+impl Db {
+    /// Up to `limit` entries whose content holds every word of `text`,
+    /// best match first, see [`DbRead::search`].
+    pub async fn search(&self, text: &str, limit: u32) -> anyhow::Result<Vec<Entry>> {
+        self.reader().search(text, limit).await
+    }
+}
+
+impl DbRead {
+    /// Up to `limit` entries whose content holds every word of `text`,
+    /// best match first by bm25.
+    ///
+    /// Content is searched as text: words match case- and
+    /// accent-insensitively, and for MessagePack content the strings
+    /// in it are what can be found. Punctuation in `text` only splits
+    /// words, it's never taken for FTS5 query syntax.
+    pub async fn search(&self, text: &str, limit: u32) -> anyhow::Result<Vec<Entry>> {
+        let query = match match_all_words(text) {
+            Some(query) => query,
+            None => return Ok(Vec::new()),
+        };
+        self.explain.check(&self.pool, SEARCH_ENTRIES).await;
+        let out = with_retry(
+            self.trace
+                .op("search")
+                .statement(SEARCH_ENTRIES, &[&query, &limit]),
+            &self.retry,
+            &self.permits,
+            || async {
+                let mut con = self.pool.acquire().await?;
+                self.statements.record(&mut con, SEARCH_ENTRIES);
+                sqlx::query_as::<_, Entry>(SEARCH_ENTRIES)
+                    .bind(&query)
+                    .bind(limit)
+                    .fetch_all(&mut con)
+                    .await
+            },
+        )
+        .await?;
+        Ok(out)
+    }
+}
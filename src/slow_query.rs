@@ -0,0 +1,123 @@
+//! Logging the operations that take longer than
+//! [`crate::DbConfig::slow_query_threshold`], so a query plan gone bad
+//! in the field shows up in the logs.
+
+use crate::explain::query_plan;
+use crate::filter::FilterSql;
+use crate::Value;
+use sqlx::encode::{Encode, IsNull};
+use sqlx::sqlite::{SqliteArgumentValue, SqlitePool};
+use sqlx::Sqlite;
+use std::time::Duration;
+
+/// A bound parameter as it may appear in the logs: its type and size,
+/// never its value.
+pub(crate) trait Redact {
+    fn redact(&self) -> String;
+}
+
+impl<'q, T: Encode<'q, Sqlite>> Redact for T {
+    fn redact(&self) -> String {
+        let mut values = Vec::new();
+        if let IsNull::Yes = self.encode_by_ref(&mut values) {
+            return "null".to_string();
+        }
+        values
+            .iter()
+            .map(|value| match value {
+                SqliteArgumentValue::Null => "null".to_string(),
+                SqliteArgumentValue::Text(text) => format!("text({})", text.len()),
+                SqliteArgumentValue::Blob(blob) => format!("blob({})", blob.len()),
+                SqliteArgumentValue::Double(_) => "real".to_string(),
+                SqliteArgumentValue::Int(_) | SqliteArgumentValue::Int64(_) => {
+                    "integer".to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl Redact for FilterSql {
+    fn redact(&self) -> String {
+        self.values()
+            .iter()
+            .map(|value| match value {
+                Value::Null => "null".to_string(),
+                Value::Integer(_) => "integer".to_string(),
+                Value::Real(_) => "real".to_string(),
+                Value::Text(text) => format!("text({})", text.len()),
+                Value::Blob(blob) => format!("blob({})", blob.len()),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// The statement an operation runs, for the log if it's slow.
+pub(crate) struct Statement<'s> {
+    pub(crate) sql: &'s str,
+    pub(crate) params: &'s [&'s (dyn Redact + Sync)],
+}
+
+/// When an operation counts as slow, and where to explain it.
+#[derive(Debug, Clone)]
+pub(crate) struct SlowQueries {
+    pub(crate) threshold: Duration,
+    pub(crate) pool: Option<SqlitePool>,
+}
+
+impl SlowQueries {
+    /// Log `op` at WARN, with its statement and that statement's plan
+    /// where they're known.
+    pub(crate) async fn log(
+        &self,
+        kind: &'static str,
+        side: &'static str,
+        op: &'static str,
+        elapsed: Duration,
+        statement: Option<&Statement<'_>>,
+    ) {
+        let elapsed_ms = elapsed.as_millis() as u64;
+        let statement = match statement {
+            Some(statement) => statement,
+            None => {
+                tracing::warn!(
+                    db_kind = kind,
+                    side,
+                    op,
+                    elapsed_ms,
+                    "slow database operation"
+                );
+                return;
+            }
+        };
+        let params = statement
+            .params
+            .iter()
+            .map(|param| param.redact())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let plan = match &self.pool {
+            Some(pool) => match pool.acquire().await {
+                Ok(mut con) => query_plan(&mut con, statement.sql).await,
+                Err(err) => Err(err),
+            },
+            None => Ok(Vec::new()),
+        };
+        let plan = match plan {
+            Ok(plan) => plan.join("; "),
+            Err(err) => format!("unavailable: {}", err),
+        };
+        tracing::warn!(
+            db_kind = kind,
+            side,
+            op,
+            elapsed_ms,
+            sql = statement.sql,
+            params = %params,
+            plan = %plan,
+            "slow database operation"
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! A rotation of timestamped snapshots of the database file, taken
+//! with [`Db::backup_to`], and falling back to the newest one that opens
+//! when the file itself turns out to be corrupt.
+//!
+//! Each snapshot is recorded in the `snapshots` table, but the directory
+//! is what counts: at open time a corrupt file can't say what it had.
+
+use crate::backup::{main_file, remove_if_exists, with_suffix};
+use crate::error::is_not_a_database;
+use crate::migrations::applied;
+use crate::{Db, DbConfig, DbError, DbKind, Timestamp};
+use chrono::prelude::*;
+use sqlx::sqlite::SqliteConnectOptions;
+use std::path::{Path, PathBuf};
+
+/// Where [`Db::snapshot`] keeps its snapshots and how many it keeps,
+/// see [`crate::DbConfig::snapshots`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotPolicy {
+    pub(crate) dir: PathBuf,
+    pub(crate) keep: usize,
+    pub(crate) max_total_bytes: Option<u64>,
+    pub(crate) restore_on_corruption: bool,
+}
+
+impl SnapshotPolicy {
+    /// Keep snapshots in `dir`, the newest 3 of them.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            keep: 3,
+            max_total_bytes: None,
+            restore_on_corruption: true,
+        }
+    }
+
+    /// Keep the newest `count` snapshots, at least 1.
+    pub fn keep(mut self, count: usize) -> Self {
+        self.keep = count.max(1);
+        self
+    }
+
+    /// Also drop the oldest snapshots while all of them together take
+    /// more than `bytes`. The newest is kept whatever its size.
+    pub fn max_total_bytes(mut self, bytes: u64) -> Self {
+        self.max_total_bytes = Some(bytes);
+        self
+    }
+
+    /// Whether a database file that's corrupt at open is swapped for
+    /// the newest snapshot that opens, on by default.
+    /// The corrupt file is kept next to it with a `.corrupt-<time>` suffix.
+    pub fn restore_on_corruption(mut self, restore: bool) -> Self {
+        self.restore_on_corruption = restore;
+        self
+    }
+}
+
+/// A snapshot on record, from [`Db::snapshots`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    pub path: PathBuf,
+    pub taken_at: Timestamp,
+    pub size_bytes: u64,
+    /// The latest migration applied when it was taken.
+    pub schema_version: i64,
+}
+
+fn file_name(file: &Path) -> String {
+    file.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// `<file name>.<utc time>.snapshot`, sorting in the order taken.
+fn snapshot_name(file: &Path, taken_at: DateTime<Utc>) -> String {
+    format!(
+        "{}.{}.snapshot",
+        file_name(file),
+        taken_at.format("%Y%m%dT%H%M%S%.6fZ")
+    )
+}
+
+/// Snapshots of `file` in `dir` with their sizes, newest first.
+async fn snapshot_files(dir: &Path, file: &Path) -> anyhow::Result<Vec<(PathBuf, u64)>> {
+    let prefix = format!("{}.", file_name(file));
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut found = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(&prefix) && name.ends_with(".snapshot") {
+            found.push((entry.path(), entry.metadata().await?.len()));
+        }
+    }
+    // all in one directory, so the paths sort like the names
+    found.sort_by(|a, b| b.0.cmp(&a.0));
+    Ok(found)
+}
+
+/// The file a [`Db::open`] url or path names, `None` for in-memory ones.
+/// Mirrors how sqlx reads it.
+pub(crate) fn url_file(url: &str) -> Option<PathBuf> {
+    let path = url
+        .trim_start_matches("sqlite://")
+        .trim_start_matches("sqlite:");
+    let path = path.split('?').next().unwrap_or_default();
+    if path.is_empty() || path == ":memory:" || path.starts_with("file:") {
+        None
+    } else {
+        Some(path.into())
+    }
+}
+
+fn is_corrupt(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<DbError>(), Some(DbError::Corrupt(_)))
+        || err
+            .downcast_ref::<sqlx::Error>()
+            .is_some_and(is_not_a_database)
+}
+
+async fn rename_with_journal(from: &Path, to: &Path) -> std::io::Result<()> {
+    tokio::fs::rename(from, to).await?;
+    for suffix in ["-wal", "-shm"].iter() {
+        match tokio::fs::rename(with_suffix(from, suffix), with_suffix(to, suffix)).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+async fn remove_with_journal(file: &Path) -> std::io::Result<()> {
+    for suffix in ["", "-wal", "-shm"].iter() {
+        remove_if_exists(&with_suffix(file, suffix)).await?;
+    }
+    Ok(())
+}
+
+/// Open as usual, but if `file` turns out to be corrupt and the config
+/// says so, move it aside and try each snapshot in turn, newest first.
+/// If none of them opens either, the corrupt file is put back.
+pub(crate) async fn open_or_restore(
+    file: Option<&Path>,
+    options: SqliteConnectOptions,
+    kind: Option<DbKind>,
+    config: DbConfig,
+) -> anyhow::Result<Db> {
+    let err = match Db::open_options(options.clone(), file, kind.clone(), config.clone()).await {
+        Ok(db) => return Ok(db),
+        Err(err) => err,
+    };
+    let (policy, file) = match (&config.snapshots, file) {
+        (Some(policy), Some(file)) if policy.restore_on_corruption && is_corrupt(&err) => {
+            (policy, file)
+        }
+        _ => return Err(err),
+    };
+    let snapshots = snapshot_files(&policy.dir, file).await?;
+    if snapshots.is_empty() {
+        return Err(err);
+    }
+
+    let aside = with_suffix(
+        file,
+        &format!(".corrupt-{}", Utc::now().format("%Y%m%dT%H%M%S%.6fZ")),
+    );
+    rename_with_journal(file, &aside).await?;
+    for (snapshot, _) in snapshots {
+        tokio::fs::copy(&snapshot, file).await?;
+        match Db::open_options(options.clone(), Some(file), kind.clone(), config.clone()).await {
+            Ok(db) => {
+                tracing::error!(
+                    file = %file.display(),
+                    error = %err,
+                    snapshot = %snapshot.display(),
+                    kept_as = %aside.display(),
+                    "database was corrupt, restored the latest snapshot that opens"
+                );
+                return Ok(db);
+            }
+            Err(_) => remove_with_journal(file).await?,
+        }
+    }
+    rename_with_journal(&aside, file).await?;
+    Err(err)
+}
+
+impl Db {
+    /// Take a snapshot into the directory of [`crate::DbConfig::snapshots`],
+    /// record it, then drop the oldest ones the policy no longer keeps.
+    ///
+    /// Writes wait while the snapshot is taken, see [`Db::backup_to`].
+    pub async fn snapshot(&self) -> anyhow::Result<SnapshotInfo> {
+        let policy = match &self.config.snapshots {
+            Some(policy) => policy,
+            None => anyhow::bail!("no snapshot policy configured"),
+        };
+        let file = match main_file(self).await? {
+            Some(file) => file,
+            None => anyhow::bail!("an in-memory database has no file to snapshot"),
+        };
+        tokio::fs::create_dir_all(&policy.dir).await?;
+        let now = Utc::now();
+        let path = policy.dir.join(snapshot_name(&file, now));
+        self.backup_to(&path, None).await?;
+
+        let info = SnapshotInfo {
+            size_bytes: tokio::fs::metadata(&path).await?.len(),
+            taken_at: now.into(),
+            schema_version: applied(&mut *self.write.pool.acquire().await?)
+                .await?
+                .map_or(0, |(_, version)| version),
+            path,
+        };
+        {
+            let _permit = self.write.permits.acquire().await?;
+            sqlx::query(
+                "INSERT INTO snapshots (file, taken_at, size_bytes, schema_version)
+                VALUES (?1, ?2, ?3, ?4);",
+            )
+            .bind(&*info.path.to_string_lossy())
+            .bind(info.taken_at)
+            .bind(info.size_bytes as i64)
+            .bind(info.schema_version)
+            .execute(&self.write.pool)
+            .await?;
+        }
+
+        let mut total = 0;
+        for (i, (path, len)) in snapshot_files(&policy.dir, &file)
+            .await?
+            .into_iter()
+            .enumerate()
+        {
+            total += len;
+            let too_big = policy.max_total_bytes.is_some_and(|max| total > max);
+            if i >= policy.keep || (i > 0 && too_big) {
+                remove_if_exists(&path).await?;
+                let _permit = self.write.permits.acquire().await?;
+                sqlx::query("DELETE FROM snapshots WHERE file = ?1;")
+                    .bind(&*path.to_string_lossy())
+                    .execute(&self.write.pool)
+                    .await?;
+            }
+        }
+        Ok(info)
+    }
+
+    /// The snapshots on record whose files are still there, newest first.
+    pub async fn snapshots(&self) -> anyhow::Result<Vec<SnapshotInfo>> {
+        let rows: Vec<(String, Timestamp, i64, i64)> = sqlx::query_as(
+            "SELECT file, taken_at, size_bytes, schema_version FROM snapshots
+            ORDER BY taken_at DESC;",
+        )
+        .fetch_all(&self.reader().pool)
+        .await?;
+        let mut snapshots = Vec::new();
+        for (file, taken_at, size_bytes, schema_version) in rows {
+            let path = PathBuf::from(file);
+            if tokio::fs::metadata(&path).await.is_ok() {
+                snapshots.push(SnapshotInfo {
+                    path,
+                    taken_at,
+                    size_bytes: size_bytes as u64,
+                    schema_version,
+                });
+            }
+        }
+        Ok(snapshots)
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{Db, DbRead, DbWrite};
+use sqlx::{Connection, SqliteConnection};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// How the prepared statements of the hot queries (inserting an entry,
+/// fetching one by hash, filters and range queries) fared against each
+/// connection's statement cache, see
+/// [`crate::DbConfig::statement_cache_capacity`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatementCacheMetrics {
+    /// Statements each connection keeps prepared.
+    pub capacity: usize,
+    /// Runs that found their statement already prepared.
+    pub hits: u64,
+    /// Runs that had to prepare their statement.
+    pub misses: u64,
+    /// Statements dropped from a full cache to make room.
+    pub evictions: u64,
+}
+
+impl StatementCacheMetrics {
+    /// Fraction of runs that were hits, 0 before any.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            runs => self.hits as f64 / runs as f64,
+        }
+    }
+
+    fn merge(self, other: Self) -> Self {
+        Self {
+            capacity: self.capacity.max(other.capacity),
+            hits: self.hits + other.hits,
+            misses: self.misses + other.misses,
+            evictions: self.evictions + other.evictions,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    metrics: StatementCacheMetrics,
+    /// Per connection, the tracked statements sqlx should be holding,
+    /// least recently used first.
+    connections: HashMap<usize, VecDeque<String>>,
+}
+
+/// Counts statement cache hits for one side of the database.
+///
+/// sqlx keeps each connection's cache to itself, so this mirrors it:
+/// the same LRU order over the statements we track, trimmed whenever
+/// the real cache is found to be smaller, e.g. for a fresh connection.
+/// Clones share the same counts.
+#[derive(Debug, Clone)]
+pub(crate) struct StatementCache(Arc<Mutex<Inner>>);
+
+impl StatementCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self(Arc::new(Mutex::new(Inner {
+            metrics: StatementCacheMetrics {
+                capacity,
+                ..Default::default()
+            },
+            connections: HashMap::new(),
+        })))
+    }
+
+    /// Note that `sql` is about to run as a persistent statement on `con`.
+    pub(crate) fn record(&self, con: &mut SqliteConnection, sql: &str) {
+        let cached = con.cached_statements_size();
+        let mut inner = self.0.lock().unwrap();
+        let capacity = inner.metrics.capacity;
+        let key = con.as_raw_handle() as usize;
+        let mirror = inner.connections.entry(key).or_default();
+        while mirror.len() > cached {
+            mirror.pop_front();
+        }
+        let hit = match mirror.iter().position(|seen| seen == sql) {
+            Some(i) => {
+                let seen = mirror.remove(i).unwrap();
+                mirror.push_back(seen);
+                true
+            }
+            None if capacity == 0 => false,
+            None => {
+                mirror.push_back(sql.to_string());
+                if mirror.len() > capacity {
+                    mirror.pop_front();
+                }
+                false
+            }
+        };
+        let metrics = &mut inner.metrics;
+        if hit {
+            metrics.hits += 1;
+        } else {
+            metrics.misses += 1;
+            // sqlx evicts whatever is oldest, tracked or not
+            if capacity > 0 && cached >= capacity {
+                metrics.evictions += 1;
+            }
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> StatementCacheMetrics {
+        self.0.lock().unwrap().metrics
+    }
+}
+
+impl Db {
+    /// Statement cache use by the hot queries so far, readers and
+    /// writer together.
+    pub fn statement_cache_metrics(&self) -> StatementCacheMetrics {
+        self.reader()
+            .statement_cache_metrics()
+            .merge(self.writer().statement_cache_metrics())
+    }
+}
+
+impl DbRead {
+    /// Statement cache use by the hot queries on the read pool so far.
+    pub fn statement_cache_metrics(&self) -> StatementCacheMetrics {
+        self.statements.metrics()
+    }
+}
+
+impl DbWrite {
+    /// Statement cache use by the hot queries on the writer so far.
+    pub fn statement_cache_metrics(&self) -> StatementCacheMetrics {
+        self.statements.metrics()
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{Db, DbRead};
+use sqlx::{Sqlite, Transaction};
+
+/// Size of the database and what takes up its pages, from
+/// [`Db::stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbStats {
+    pub page_size: u64,
+    /// Pages in the main database, in use or free.
+    pub page_count: u64,
+    /// Pages no longer in use, waiting to be reused or vacuumed.
+    pub freelist_count: u64,
+    /// Bytes of the main database file, 0 for one in memory.
+    pub file_bytes: u64,
+    /// Bytes of its `-wal` file, 0 if there isn't one.
+    pub wal_bytes: u64,
+    /// Every table, by name, the FTS5 tables as their shadow tables.
+    pub tables: Vec<TableStats>,
+}
+
+impl DbStats {
+    /// What the database takes on disk, WAL included.
+    pub fn disk_bytes(&self) -> u64 {
+        self.file_bytes + self.wal_bytes
+    }
+
+    /// Bytes of the pages in use.
+    pub fn used_bytes(&self) -> u64 {
+        self.page_count.saturating_sub(self.freelist_count) * self.page_size
+    }
+
+    pub fn table(&self, name: &str) -> Option<&TableStats> {
+        self.tables.iter().find(|table| table.name == name)
+    }
+}
+
+/// One table in [`DbStats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableStats {
+    pub name: String,
+    pub rows: u64,
+    /// Bytes of the pages holding it and its indexes, `None` where
+    /// sqlite was built without the `dbstat` table.
+    pub bytes: Option<u64>,
+}
+
+impl Db {
+    /// Page, file and per-table sizes, see [`DbRead::stats`].
+    pub async fn stats(&self) -> anyhow::Result<DbStats> {
+        self.reader().stats().await
+    }
+}
+
+impl DbRead {
+    /// Page, file and per-table sizes, all from one read transaction
+    /// apart from the file sizes.
+    ///
+    /// Counting rows scans every table, so this is one for a timer,
+    /// not for every write. The counts correct those in
+    /// [`crate::MetricsSnapshot::tables`].
+    pub async fn stats(&self) -> anyhow::Result<DbStats> {
+        let _permit = self.permits.acquire().await?;
+        let mut tx = self.pool.begin().await?;
+        let page_size = pragma(&mut tx, "page_size").await?;
+        let page_count = pragma(&mut tx, "page_count").await?;
+        let freelist_count = pragma(&mut tx, "freelist_count").await?;
+
+        let names = table_names(&mut tx).await?;
+        let bytes: Option<Vec<(String, i64)>> = sqlx::query_as(
+            "SELECT master.tbl_name, sum(stat.pgsize)
+            FROM dbstat AS stat
+            JOIN sqlite_master AS master ON master.name = stat.name
+            GROUP BY master.tbl_name;",
+        )
+        .fetch_all(&mut tx)
+        .await
+        .ok();
+        let mut tables = Vec::with_capacity(names.len());
+        for name in names {
+            let rows = count_rows(&mut tx, &name).await?;
+            let bytes = bytes.as_ref().map(|bytes| {
+                bytes
+                    .iter()
+                    .find(|(table, _)| *table == name)
+                    .map_or(0, |(_, bytes)| *bytes as u64)
+            });
+            tables.push(TableStats { name, rows, bytes });
+        }
+        let file: String =
+            sqlx::query_scalar("SELECT file FROM pragma_database_list WHERE name = 'main';")
+                .fetch_one(&mut tx)
+                .await?;
+        tx.rollback().await?;
+        self.row_counts.reconcile(
+            tables
+                .iter()
+                .map(|table| (table.name.clone(), table.rows))
+                .collect(),
+        );
+
+        // an in-memory database has no file, and no WAL
+        let (file_bytes, wal_bytes) = if file.is_empty() {
+            (0, 0)
+        } else {
+            (
+                file_len(&file).await?,
+                file_len(&format!("{}-wal", file)).await?,
+            )
+        };
+        Ok(DbStats {
+            page_size,
+            page_count,
+            freelist_count,
+            file_bytes,
+            wal_bytes,
+            tables,
+        })
+    }
+}
+
+/// Every table in the main database, by name. Virtual tables are left
+/// out, they're counted through their shadow tables.
+pub(crate) async fn table_names(
+    tx: &mut Transaction<'static, Sqlite>,
+) -> sqlx::Result<Vec<String>> {
+    sqlx::query_scalar(
+        "SELECT name FROM sqlite_master
+        WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+            AND sql NOT LIKE 'CREATE VIRTUAL TABLE%'
+        ORDER BY name;",
+    )
+    .fetch_all(tx)
+    .await
+}
+
+/// `count(*)` of `table`, which scans it.
+pub(crate) async fn count_rows(
+    tx: &mut Transaction<'static, Sqlite>,
+    table: &str,
+) -> sqlx::Result<u64> {
+    let rows: i64 = sqlx::query_scalar(&format!(
+        "SELECT count(*) FROM \"{}\";",
+        table.replace('"', "\"\"")
+    ))
+    .fetch_one(tx)
+    .await?;
+    Ok(rows as u64)
+}
+
+async fn pragma(tx: &mut Transaction<'static, Sqlite>, pragma: &str) -> sqlx::Result<u64> {
+    let value: i64 = sqlx::query_scalar(&format!("PRAGMA {};", pragma))
+        .fetch_one(tx)
+        .await?;
+    Ok(value as u64)
+}
+
+/// 0 for a file that doesn't exist, e.g. a WAL before the first write.
+async fn file_len(path: &str) -> std::io::Result<u64> {
+    match tokio::fs::metadata(path).await {
+        Ok(meta) => Ok(meta.len()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(err) => Err(err),
+    }
+}
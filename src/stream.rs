@@ -0,0 +1,81 @@
+use crate::db::ENTRY_COLUMNS;
+use crate::interrupt::{reset_statements, Interrupt, InterruptOnDrop};
+use crate::{Db, DbRead, Entry, EntryFilter};
+use futures::channel::mpsc;
+use futures::{SinkExt, Stream, StreamExt, TryStreamExt};
+
+/// Rows read ahead of the consumer.
+const STREAM_BUFFER: usize = 64;
+
+impl Db {
+    /// Like [`Db::filter_entries`], but yields rows as they are read
+    /// instead of collecting them.
+    pub fn stream_entries(
+        &self,
+        filter: &EntryFilter,
+    ) -> impl Stream<Item = anyhow::Result<Entry>> {
+        self.reader().stream_entries(filter)
+    }
+}
+
+impl DbRead {
+    /// Like [`DbRead::filter_entries`], but yields rows as they are read
+    /// instead of collecting them.
+    ///
+    /// The rows come from a single read transaction, so they are a
+    /// consistent snapshot however slowly they're consumed. That pins
+    /// a reader connection, and the WAL can't be checkpointed past the
+    /// snapshot, until the stream ends or is dropped. Dropping it
+    /// interrupts the read even mid-statement.
+    /// Busy errors are not retried, rows may already have been yielded.
+    pub fn stream_entries(
+        &self,
+        filter: &EntryFilter,
+    ) -> impl Stream<Item = anyhow::Result<Entry>> {
+        let query = filter.select(ENTRY_COLUMNS, None);
+        let (mut send, recv) = mpsc::channel(STREAM_BUFFER);
+        let pool = self.pool.clone();
+        let permits = self.permits.clone();
+        let explain = self.explain.clone();
+        let interrupt = Interrupt::default();
+        let on_drop = InterruptOnDrop(interrupt.clone());
+        // sqlx row streams borrow their connection, so read on a task
+        // that owns it and hand rows over a bounded channel
+        tokio::task::spawn(async move {
+            let res: anyhow::Result<()> = async {
+                // held until the stream ends, like the connection
+                let _permit = permits.acquire().await?;
+                let mut tx = pool.begin().await?;
+                let armed = interrupt.arm(&mut tx);
+                let res: anyhow::Result<()> = async {
+                    explain.check_on(&mut tx, &query.sql).await;
+                    let mut rows =
+                        sqlx::query_as_with::<_, Entry, _>(&query.sql, query.arguments())
+                            .fetch(&mut tx);
+                    while let Some(entry) = rows.try_next().await? {
+                        if send.send(Ok(entry)).await.is_err() {
+                            // the consumer went away, rolling back ends the read
+                            break;
+                        }
+                    }
+                    Ok(())
+                }
+                .await;
+                // before the connection goes back to the pool, and with
+                // nothing left running for an interrupt to hit
+                drop(armed);
+                reset_statements(&mut tx);
+                res
+            }
+            .await;
+            if let Err(err) = res {
+                let _ = send.send(Err(err)).await;
+            }
+        });
+        recv.map(move |entry| {
+            // owned by the stream, so dropped with it
+            let _ = &on_drop;
+            entry
+        })
+    }
+}
@@ -0,0 +1,88 @@
+use chrono::prelude::*;
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, Sqlite, Type};
+use std::convert::TryFrom;
+
+/// Microseconds since the unix epoch, stored as an `INTEGER`.
+///
+/// Integers compare the same whoever wrote them, unlike formatted
+/// text, and make for smaller indexes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub i64);
+
+impl Timestamp {
+    /// The current time, to the microsecond.
+    pub fn now() -> Self {
+        Utc::now().into()
+    }
+
+    /// Microseconds since the unix epoch.
+    pub fn as_micros(&self) -> i64 {
+        self.0
+    }
+
+    /// As a chrono time, failing if it's outside the range chrono can
+    /// represent.
+    pub fn to_date_time(&self) -> anyhow::Result<DateTime<Utc>> {
+        let secs = self.0.div_euclid(1_000_000);
+        let nanos = self.0.rem_euclid(1_000_000) as u32 * 1000;
+        match Utc.timestamp_opt(secs, nanos) {
+            chrono::LocalResult::Single(time) => Ok(time),
+            _ => anyhow::bail!("timestamp {}µs is out of range", self.0),
+        }
+    }
+}
+
+/// Truncates to the microsecond.
+impl From<DateTime<Utc>> for Timestamp {
+    fn from(time: DateTime<Utc>) -> Self {
+        Self(time.timestamp() * 1_000_000 + time.timestamp_subsec_micros() as i64)
+    }
+}
+
+impl TryFrom<Timestamp> for DateTime<Utc> {
+    type Error = anyhow::Error;
+
+    fn try_from(timestamp: Timestamp) -> anyhow::Result<Self> {
+        timestamp.to_date_time()
+    }
+}
+
+impl std::ops::Add<chrono::Duration> for Timestamp {
+    type Output = Self;
+
+    /// Panics if the duration overflows microseconds, as chrono does.
+    fn add(self, rhs: chrono::Duration) -> Self {
+        Self(
+            self.0
+                + rhs
+                    .num_microseconds()
+                    .expect("duration overflows microseconds"),
+        )
+    }
+}
+
+impl Type<Sqlite> for Timestamp {
+    fn type_info() -> SqliteTypeInfo {
+        <i64 as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <i64 as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for Timestamp {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        args.push(SqliteArgumentValue::Int64(self.0));
+        IsNull::No
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for Timestamp {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        Ok(Self(<i64 as Decode<Sqlite>>::decode(value)?))
+    }
+}
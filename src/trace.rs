@@ -0,0 +1,252 @@
+//! `tracing` spans around every operation on the database, so a
+//! subscriber can see where time goes inside this layer.
+//!
+//! Each span is a `db_op` at DEBUG level, with `db_kind`, `side`
+//! ("read" or "write") and `op`, the statement or transaction it
+//! stands for, and on completion `rows`, `attempts` and `elapsed_us`.
+//! With the `otel` feature they also carry the OpenTelemetry database
+//! attributes, see [`crate::otel_layer`].
+
+use crate::metrics::Metrics;
+use crate::slow_query::{Redact, SlowQueries, Statement};
+use crate::{DbConfig, WriteOutcome};
+use sqlx::sqlite::{SqlitePool, SqliteQueryResult};
+use std::future::Future;
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::Span;
+
+/// How much [`crate::Db`] reports to `tracing`, set with
+/// [`crate::DbConfig::tracing`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TraceVerbosity {
+    /// No spans at all.
+    Off,
+    /// A span for opening, for each transaction and for each built-in
+    /// query, the default.
+    Operations,
+    /// Also an event for every attempt that failed busy and was retried.
+    Attempts,
+}
+
+/// How many rows an operation returned or touched, where that's known.
+pub(crate) trait RowCount {
+    fn row_count(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl RowCount for SqliteQueryResult {
+    fn row_count(&self) -> Option<u64> {
+        Some(self.rows_affected())
+    }
+}
+
+impl<T> RowCount for Vec<T> {
+    fn row_count(&self) -> Option<u64> {
+        Some(self.len() as u64)
+    }
+}
+
+impl<T> RowCount for Option<T> {
+    fn row_count(&self) -> Option<u64> {
+        Some(self.is_some() as u64)
+    }
+}
+
+impl RowCount for u64 {
+    fn row_count(&self) -> Option<u64> {
+        Some(*self)
+    }
+}
+
+impl RowCount for WriteOutcome {
+    fn row_count(&self) -> Option<u64> {
+        Some(self.inserted + self.updated + self.deleted)
+    }
+}
+
+impl RowCount for u32 {}
+impl RowCount for bool {}
+impl RowCount for i64 {}
+impl RowCount for (i64,) {}
+impl RowCount for () {}
+
+/// Makes the spans for one side of a database, and records each
+/// operation in its [`Metrics`].
+#[derive(Debug, Clone)]
+pub(crate) struct Tracer {
+    kind: &'static str,
+    side: &'static str,
+    verbosity: TraceVerbosity,
+    metrics: Metrics,
+    slow: Option<SlowQueries>,
+}
+
+impl Tracer {
+    /// `kind` is the [`crate::DbKind::name`], or "path" for a database
+    /// opened by path.
+    pub(crate) fn new(kind: &'static str, side: &'static str, config: &DbConfig) -> Self {
+        Self {
+            kind,
+            side,
+            verbosity: config.tracing,
+            metrics: Metrics::new(kind, side),
+            slow: config.slow_query_threshold.map(|threshold| SlowQueries {
+                threshold,
+                pool: None,
+            }),
+        }
+    }
+
+    /// Explain slow statements on a connection from `pool`.
+    pub(crate) fn explaining_on(mut self, pool: &SqlitePool) -> Self {
+        if let Some(slow) = &mut self.slow {
+            slow.pool = Some(pool.clone());
+        }
+        self
+    }
+
+    pub(crate) fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    pub(crate) fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Start timing `op`.
+    pub(crate) fn op(&self, op: &'static str) -> TracedOp<'static> {
+        let span = if self.verbosity == TraceVerbosity::Off {
+            Span::none()
+        } else {
+            db_op_span(self.kind, self.side, op)
+        };
+        TracedOp {
+            span,
+            op,
+            kind: self.kind,
+            side: self.side,
+            metrics: self.metrics.clone(),
+            verbosity: self.verbosity,
+            slow: self.slow.clone(),
+            statement: None,
+            start: Instant::now(),
+            attempts: 1,
+        }
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+fn db_op_span(kind: &'static str, side: &'static str, op: &'static str) -> Span {
+    tracing::debug_span!(
+        "db_op",
+        db_kind = kind,
+        side,
+        op,
+        rows = Empty,
+        attempts = Empty,
+        elapsed_us = Empty,
+    )
+}
+
+/// Named for the op when exported, following the OpenTelemetry
+/// conventions for database client spans.
+#[cfg(feature = "otel")]
+fn db_op_span(kind: &'static str, side: &'static str, op: &'static str) -> Span {
+    tracing::debug_span!(
+        "db_op",
+        db_kind = kind,
+        side,
+        op,
+        rows = Empty,
+        attempts = Empty,
+        elapsed_us = Empty,
+        otel.name = op,
+        otel.kind = "client",
+        db.system = "sqlite",
+        db.name = kind,
+        db.operation = op,
+        db.statement = Empty,
+    )
+}
+
+/// One operation being timed, see [`Tracer::op`].
+pub(crate) struct TracedOp<'s> {
+    pub(crate) span: Span,
+    op: &'static str,
+    kind: &'static str,
+    side: &'static str,
+    metrics: Metrics,
+    verbosity: TraceVerbosity,
+    slow: Option<SlowQueries>,
+    statement: Option<Statement<'s>>,
+    start: Instant,
+    attempts: u32,
+}
+
+impl<'s> TracedOp<'s> {
+    /// The statement it runs and its parameters, logged if it's slow.
+    pub(crate) fn statement(
+        self,
+        sql: &'s str,
+        params: &'s [&'s (dyn Redact + Sync)],
+    ) -> TracedOp<'s> {
+        #[cfg(feature = "otel")]
+        self.span.record("db.statement", &sql);
+        TracedOp {
+            statement: Some(Statement { sql, params }),
+            ..self
+        }
+    }
+
+    /// Note that an attempt failed with `err` and is being retried.
+    pub(crate) fn retrying(&mut self, err: &dyn std::fmt::Display) {
+        if self.verbosity >= TraceVerbosity::Attempts {
+            tracing::debug!(parent: &self.span, attempt = self.attempts, error = %err, "busy, retrying");
+        }
+        self.attempts += 1;
+    }
+
+    /// Record how it went, with the rows it returned or touched.
+    pub(crate) fn finish<T: RowCount>(
+        self,
+        res: &anyhow::Result<T>,
+    ) -> impl Future<Output = ()> + 's {
+        self.finish_with(res.as_ref().ok().and_then(RowCount::row_count), res.is_ok())
+    }
+
+    /// Record how it went, where the rows aren't known.
+    pub(crate) fn finish_unknown<T>(
+        self,
+        res: &anyhow::Result<T>,
+    ) -> impl Future<Output = ()> + 's {
+        self.finish_with(None, res.is_ok())
+    }
+
+    async fn finish_with(self, rows: Option<u64>, ok: bool) {
+        let elapsed = self.start.elapsed();
+        let slow = self.slow.as_ref().filter(|slow| elapsed >= slow.threshold);
+        self.metrics
+            .record(self.op, elapsed, rows, ok, slow.is_some());
+        if !self.span.is_none() {
+            let elapsed_us = elapsed.as_micros() as u64;
+            if let Some(rows) = rows {
+                self.span.record("rows", &rows);
+            }
+            self.span.record("attempts", &self.attempts);
+            self.span.record("elapsed_us", &elapsed_us);
+            tracing::debug!(parent: &self.span, ok, elapsed_us, "db op finished");
+        }
+        if let Some(slow) = slow {
+            slow.log(
+                self.kind,
+                self.side,
+                self.op,
+                elapsed,
+                self.statement.as_ref(),
+            )
+            .await;
+        }
+    }
+}
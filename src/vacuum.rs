@@ -0,0 +1,125 @@
+use crate::{Db, DbWrite};
+use sqlx::SqliteConnection;
+
+/// Mode for `PRAGMA auto_vacuum`.
+/// See <https://www.sqlite.org/pragma.html#pragma_auto_vacuum>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoVacuum {
+    /// Freed pages stay on the freelist until a [`Db::vacuum`].
+    None,
+    /// Freed pages are moved to the end of the file and truncated
+    /// away at every commit.
+    Full,
+    /// Freed pages stay on the freelist until
+    /// [`Db::incremental_vacuum`] releases them.
+    Incremental,
+}
+
+impl AutoVacuum {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "NONE",
+            Self::Full => "FULL",
+            Self::Incremental => "INCREMENTAL",
+        }
+    }
+
+    pub(crate) fn from_pragma(value: i64) -> Self {
+        match value {
+            1 => Self::Full,
+            2 => Self::Incremental,
+            _ => Self::None,
+        }
+    }
+}
+
+/// Switch the database to `mode`.
+///
+/// sqlite only changes modes by itself before the first table is
+/// created, an existing database is rebuilt with a `VACUUM` to
+/// switch over.
+pub(crate) async fn apply_auto_vacuum(
+    con: &mut SqliteConnection,
+    mode: AutoVacuum,
+) -> sqlx::Result<()> {
+    let pragma = format!("PRAGMA auto_vacuum = {};", mode.as_str());
+    sqlx::query(&pragma).execute(&mut *con).await?;
+    let current: i64 = sqlx::query_scalar("PRAGMA auto_vacuum;")
+        .fetch_one(&mut *con)
+        .await?;
+    if AutoVacuum::from_pragma(current) != mode {
+        sqlx::query("VACUUM;").execute(&mut *con).await?;
+    }
+    Ok(())
+}
+
+impl Db {
+    /// Rebuild the database file, releasing every free page back to
+    /// the filesystem. Returns how many pages the file shrank by.
+    ///
+    /// This rewrites the whole file, blocking writes until it's done
+    /// and needing as much free disk again while it runs.
+    pub async fn vacuum(&self) -> anyhow::Result<u64> {
+        self.write.vacuum().await
+    }
+
+    /// Release up to `pages` free pages from the end of the file,
+    /// or all of them if `pages` is 0. Returns how many were released.
+    ///
+    /// Needs [`crate::DbConfig::auto_vacuum`] set to
+    /// [`AutoVacuum::Incremental`], otherwise it does nothing.
+    /// Cheap enough to run from a timer after purges, in small steps
+    /// to keep the write lock short.
+    pub async fn incremental_vacuum(&self, pages: u32) -> anyhow::Result<u64> {
+        self.write.incremental_vacuum(pages).await
+    }
+
+    /// Pages no longer in use, waiting to be reused or vacuumed.
+    pub async fn freelist_count(&self) -> anyhow::Result<u64> {
+        self.write.freelist_count().await
+    }
+}
+
+impl DbWrite {
+    /// Run a `VACUUM` through the writer, see [`Db::vacuum`].
+    pub async fn vacuum(&self) -> anyhow::Result<u64> {
+        let _permit = self.permits.acquire().await?;
+        let mut con = self.pool.acquire().await?;
+        let before = page_count(&mut con).await?;
+        sqlx::query("VACUUM;").execute(&mut *con).await?;
+        let after = page_count(&mut con).await?;
+        Ok(before.saturating_sub(after))
+    }
+
+    /// Run a `PRAGMA incremental_vacuum` through the writer,
+    /// see [`Db::incremental_vacuum`].
+    pub async fn incremental_vacuum(&self, pages: u32) -> anyhow::Result<u64> {
+        let _permit = self.permits.acquire().await?;
+        let mut con = self.pool.acquire().await?;
+        let before = freelist_count(&mut con).await?;
+        sqlx::query(&format!("PRAGMA incremental_vacuum({});", pages))
+            .execute(&mut *con)
+            .await?;
+        let after = freelist_count(&mut con).await?;
+        Ok(before.saturating_sub(after))
+    }
+
+    /// Pages no longer in use, see [`Db::freelist_count`].
+    pub async fn freelist_count(&self) -> anyhow::Result<u64> {
+        Ok(freelist_count(&mut *self.pool.acquire().await?).await?)
+    }
+}
+
+async fn page_count(con: &mut SqliteConnection) -> sqlx::Result<u64> {
+    let pages: i64 = sqlx::query_scalar("PRAGMA page_count;")
+        .fetch_one(con)
+        .await?;
+    Ok(pages as u64)
+}
+
+async fn freelist_count(con: &mut SqliteConnection) -> sqlx::Result<u64> {
+    let pages: i64 = sqlx::query_scalar("PRAGMA freelist_count;")
+        .fetch_one(con)
+        .await?;
+    Ok(pages as u64)
+}
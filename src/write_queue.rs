@@ -0,0 +1,224 @@
+use crate::{DbWrite, DhtOp, Entry, Header, Link, Writer};
+use futures::channel::{mpsc, oneshot};
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{Instrument, Span};
+
+/// A write for the [`DbWriter`] queue.
+#[derive(Debug, Clone)]
+pub enum WriteOp {
+    InsertEntry(Entry),
+    InsertHeader(Header),
+    InsertOp(DhtOp),
+    InsertLink(Link),
+}
+
+enum Request {
+    /// With somewhere to send the outcome if the submitter is waiting,
+    /// and the span it was submitted from.
+    Write(
+        Box<WriteOp>,
+        Option<oneshot::Sender<anyhow::Result<()>>>,
+        Span,
+    ),
+    Flush(oneshot::Sender<anyhow::Result<()>>),
+}
+
+/// Most queued writes committed in one transaction,
+/// unless coalescing says otherwise.
+const MAX_BATCH: usize = 256;
+
+/// Handle to the task that serializes queued writes, see
+/// [`crate::Db::write_queue`]. Cheap to clone.
+///
+/// Writes queued while a batch is being committed are picked up
+/// together, and share the next transaction. With
+/// [`crate::DbConfig::write_coalescing`] the task also holds each batch
+/// back for a while so more writes can join it.
+#[derive(Clone)]
+pub struct DbWriter {
+    tx: mpsc::Sender<Request>,
+}
+
+impl DbWriter {
+    /// Spawn the task draining the queue into `write`,
+    /// coalescing for up to `(flush_interval, max_rows)` if given.
+    pub(crate) fn spawn(
+        write: DbWrite,
+        coalescing: Option<(Duration, usize)>,
+    ) -> (Self, tokio::task::JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel(MAX_BATCH);
+        let task = tokio::task::spawn(write_queue_task(write, rx, coalescing));
+        (Self { tx }, task)
+    }
+
+    async fn send(&self, request: Request) -> anyhow::Result<()> {
+        self.tx
+            .clone()
+            .send(request)
+            .await
+            .map_err(|_| anyhow::anyhow!("write queue closed"))
+    }
+
+    /// Queue `op` and wait for the transaction it lands in to commit.
+    ///
+    /// Each op succeeds or fails on its own,
+    /// a failing op doesn't take the rest of its batch with it.
+    pub async fn submit(&self, op: WriteOp) -> anyhow::Result<()> {
+        let (reply, done) = oneshot::channel();
+        self.send(Request::Write(Box::new(op), Some(reply), Span::current()))
+            .await?;
+        done.await
+            .map_err(|_| anyhow::anyhow!("write queue closed"))?
+    }
+
+    /// Queue `op` without waiting for it to be written.
+    ///
+    /// It isn't durable until a later [`DbWriter::flush`] returns,
+    /// which is also where it failing gets reported.
+    pub async fn buffer(&self, op: WriteOp) -> anyhow::Result<()> {
+        self.send(Request::Write(Box::new(op), None, Span::current()))
+            .await
+    }
+
+    /// Commit everything queued so far right away, without waiting
+    /// out the flush interval.
+    ///
+    /// Fails if any buffered write failed since the last flush.
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        let (reply, done) = oneshot::channel();
+        self.send(Request::Flush(reply)).await?;
+        done.await
+            .map_err(|_| anyhow::anyhow!("write queue closed"))?
+    }
+}
+
+/// Buffered writes that failed since the last flush.
+#[derive(Default)]
+struct Failures {
+    count: usize,
+    first: Option<String>,
+}
+
+impl Failures {
+    fn record(&mut self, err: &anyhow::Error) {
+        self.count += 1;
+        self.first.get_or_insert_with(|| format!("{:#}", err));
+    }
+
+    /// Report and forget them.
+    fn take(&mut self) -> anyhow::Result<()> {
+        let failures = std::mem::take(self);
+        match failures.first {
+            None => Ok(()),
+            Some(first) => Err(anyhow::anyhow!(
+                "{} buffered writes failed since the last flush, the first with: {}",
+                failures.count,
+                first
+            )),
+        }
+    }
+}
+
+async fn write_queue_task(
+    write: DbWrite,
+    mut rx: mpsc::Receiver<Request>,
+    coalescing: Option<(Duration, usize)>,
+) {
+    let max_rows = coalescing.map_or(MAX_BATCH, |(_, max_rows)| max_rows.max(1));
+    let mut failures = Failures::default();
+    while let Some(first) = rx.next().await {
+        let deadline = coalescing.map(|(flush_interval, _)| Instant::now() + flush_interval);
+        let mut writes = Vec::new();
+        let mut flushes = Vec::new();
+        let mut submitted_from = Vec::new();
+        let mut next = Some(first);
+        while let Some(request) = next.take() {
+            match request {
+                Request::Write(op, reply, span) => {
+                    writes.push((*op, reply));
+                    submitted_from.push(span);
+                }
+                Request::Flush(reply) => flushes.push(reply),
+            }
+            if writes.len() >= max_rows || !flushes.is_empty() {
+                break;
+            }
+            next = match deadline {
+                // only what's already waiting
+                None => rx.try_next().ok().flatten(),
+                Some(deadline) => tokio::time::timeout_at(deadline, rx.next())
+                    .await
+                    .ok()
+                    .flatten(),
+            };
+        }
+
+        // the batch serves every submitter, so rather than a child of
+        // any one it's linked to them all
+        let traced = write.trace.op("write_batch");
+        for span in submitted_from {
+            traced.span.follows_from(span.id());
+        }
+        let (ops, replies): (Vec<_>, Vec<_>) = writes.into_iter().unzip();
+        let res = apply(&write, &ops).instrument(traced.span.clone()).await;
+        traced.finish(&res).await;
+        let results = match res {
+            Ok(results) => results,
+            Err(err) => {
+                let err = format!("{:#}", err);
+                ops.iter()
+                    .map(|_| Err(anyhow::anyhow!("write batch failed: {}", err)))
+                    .collect()
+            }
+        };
+        for (reply, res) in replies.into_iter().zip(results) {
+            match reply {
+                // the submitter may have given up waiting
+                Some(reply) => {
+                    let _ = reply.send(res);
+                }
+                None => {
+                    if let Err(err) = &res {
+                        failures.record(err);
+                    }
+                }
+            }
+        }
+        for reply in flushes {
+            let _ = reply.send(failures.take());
+        }
+    }
+}
+
+/// Apply `ops` in one transaction, each behind a savepoint so it can
+/// be undone alone. Only fails if the transaction itself does.
+async fn apply(write: &DbWrite, ops: &[WriteOp]) -> anyhow::Result<Vec<anyhow::Result<()>>> {
+    if ops.is_empty() {
+        return Ok(Vec::new());
+    }
+    let _permit = write.permits.acquire().await?;
+    let mut writer = Writer {
+        tx: write.pool.begin().await?,
+        changes: write.changes.clone(),
+    };
+    let mut results = Vec::with_capacity(ops.len());
+    for op in ops {
+        writer.savepoint("write_op").await?;
+        let res = match op {
+            WriteOp::InsertEntry(entry) => writer.insert_entry(entry).await,
+            WriteOp::InsertHeader(header) => writer.insert_header(header).await,
+            WriteOp::InsertOp(op) => writer.insert_op(op).await,
+            WriteOp::InsertLink(link) => writer.insert_link(link).await,
+        };
+        if res.is_err() {
+            writer.rollback_to("write_op").await?;
+        } else {
+            writer.release("write_op").await?;
+        }
+        results.push(res);
+    }
+    writer.tx.commit().await?;
+    Ok(results)
+}
@@ -0,0 +1,235 @@
+use crate::changes::Changes;
+use crate::db::{INSERT_ENTRY, INSERT_HEADER};
+use crate::dht_op::INSERT_OP;
+use crate::index::check_identifier;
+use crate::interrupt::reset_statements;
+use crate::link::INSERT_LINK;
+use crate::retry::is_busy;
+use crate::{Db, DbWrite, DhtLocation, DhtOp, Entry, Header, Link};
+use futures::future::BoxFuture;
+use sqlx::{Executor, Sqlite, Transaction};
+use std::time::Instant;
+use tracing::Instrument;
+
+/// One write transaction across every table, handed to the closure
+/// passed to [`Db::write`].
+///
+/// Nothing is visible to readers until the closure returns `Ok`.
+pub struct Writer {
+    pub(crate) tx: Transaction<'static, Sqlite>,
+    /// Told of savepoints, so an entry rolled back to one isn't
+    /// announced.
+    pub(crate) changes: Changes,
+}
+
+impl Writer {
+    /// Open a savepoint called `name` within the transaction.
+    ///
+    /// Savepoints nest, and rolling back to one only undoes what was
+    /// written since it was opened. Both [`Writer::release`] and
+    /// [`Writer::rollback_to`] close every savepoint opened after it too.
+    pub async fn savepoint(&mut self, name: &str) -> anyhow::Result<()> {
+        check_identifier(name)?;
+        (&mut self.tx)
+            .execute(format!("SAVEPOINT {};", name).as_str())
+            .await?;
+        self.changes.savepoint(name);
+        Ok(())
+    }
+
+    /// Keep what was written since the savepoint `name` was opened,
+    /// as part of the enclosing transaction, and close it.
+    pub async fn release(&mut self, name: &str) -> anyhow::Result<()> {
+        check_identifier(name)?;
+        (&mut self.tx)
+            .execute(format!("RELEASE {};", name).as_str())
+            .await?;
+        self.changes.close_savepoint(name, false);
+        Ok(())
+    }
+
+    /// Undo what was written since the savepoint `name` was opened,
+    /// and close it.
+    pub async fn rollback_to(&mut self, name: &str) -> anyhow::Result<()> {
+        check_identifier(name)?;
+        // ROLLBACK TO leaves the savepoint open to be written to again
+        (&mut self.tx)
+            .execute(format!("ROLLBACK TO {0}; RELEASE {0};", name).as_str())
+            .await?;
+        self.changes.close_savepoint(name, true);
+        Ok(())
+    }
+
+    /// Insert an entry, failing if it's already held.
+    pub async fn insert_entry(&mut self, entry: &Entry) -> anyhow::Result<()> {
+        sqlx::query(INSERT_ENTRY)
+            .bind(&entry.hash)
+            .bind(DhtLocation(entry.dht_loc()))
+            .bind(entry.created_at)
+            .bind(entry.entry_type)
+            .bind(entry.size_bytes())
+            .bind(&entry.content)
+            .execute(&mut self.tx)
+            .await?;
+        Ok(())
+    }
+
+    /// Insert a header, failing if the referenced entry isn't stored,
+    /// including earlier in this transaction.
+    pub async fn insert_header(&mut self, header: &Header) -> anyhow::Result<()> {
+        sqlx::query(INSERT_HEADER)
+            .bind(&header.hash)
+            .bind(&header.author)
+            .bind(header.seq)
+            .bind(&header.prev_hash)
+            .bind(&header.entry_hash)
+            .bind(header.header_type)
+            .bind(header.timestamp)
+            .execute(&mut self.tx)
+            .await?;
+        Ok(())
+    }
+
+    /// Insert an op, merging into the stored one as [`DbWrite::insert_op`] does.
+    pub async fn insert_op(&mut self, op: &DhtOp) -> anyhow::Result<()> {
+        sqlx::query(INSERT_OP)
+            .bind(&op.op_hash)
+            .bind(op.op_type)
+            .bind(DhtLocation(op.basis_loc))
+            .bind(op.authored_timestamp)
+            .bind(op.when_integrated)
+            .bind(op.validation_status)
+            .bind(&op.dependency)
+            .execute(&mut self.tx)
+            .await?;
+        Ok(())
+    }
+
+    /// Insert a link.
+    pub async fn insert_link(&mut self, link: &Link) -> anyhow::Result<()> {
+        sqlx::query(INSERT_LINK)
+            .bind(&link.create_header)
+            .bind(&link.base_hash)
+            .bind(&link.target_hash)
+            .bind(&link.tag)
+            .bind(link.zome_index)
+            .bind(link.link_type)
+            .bind(&link.delete_header)
+            .execute(&mut self.tx)
+            .await?;
+        Ok(())
+    }
+}
+
+impl Db {
+    /// Run `f` in a single write transaction,
+    /// committing if it returns `Ok` and rolling back if it returns `Err`.
+    ///
+    /// ```no_run
+    /// # use spike_sqlx::*;
+    /// # async fn f(db: Db) -> anyhow::Result<()> {
+    /// let entry = Entry::rand();
+    /// let header = Header::rand(entry.hash.clone());
+    /// db.write(move |writer| {
+    ///     Box::pin(async move {
+    ///         writer.insert_entry(&entry).await?;
+    ///         writer.insert_header(&header).await
+    ///     })
+    /// })
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn write<F, R>(&self, f: F) -> anyhow::Result<R>
+    where
+        F: for<'w> FnOnce(&'w mut Writer) -> BoxFuture<'w, anyhow::Result<R>>,
+    {
+        self.write.write(f).await
+    }
+
+    /// Like [`Db::write`], but if the transaction fails because the
+    /// database is busy or locked `f` is run again on a fresh one,
+    /// as [`DbConfig::retry_policy`](crate::DbConfig::retry_policy) allows.
+    pub async fn with_retrying_txn<F, R>(&self, f: F) -> anyhow::Result<R>
+    where
+        F: for<'w> FnMut(&'w mut Writer) -> BoxFuture<'w, anyhow::Result<R>>,
+    {
+        self.write.with_retrying_txn(f).await
+    }
+}
+
+impl DbWrite {
+    /// Run `f` in a single write transaction,
+    /// committing if it returns `Ok` and rolling back if it returns `Err`.
+    ///
+    /// Not retried, see [`DbWrite::with_retrying_txn`] for that.
+    pub async fn write<F, R>(&self, f: F) -> anyhow::Result<R>
+    where
+        F: for<'w> FnOnce(&'w mut Writer) -> BoxFuture<'w, anyhow::Result<R>>,
+    {
+        let op = self.trace.op("write_txn");
+        let span = op.span.clone();
+        let res = async {
+            let _permit = self.permits.acquire().await?;
+            let mut writer = Writer {
+                tx: self.pool.begin().await?,
+                changes: self.changes.clone(),
+            };
+            match f(&mut writer).await {
+                Ok(out) => {
+                    writer.tx.commit().await?;
+                    Ok(out)
+                }
+                Err(err) => {
+                    // a statement that failed busy part way through, e.g.
+                    // in a trigger, holds on to its read snapshot until
+                    // reset, and every retry would then fail against it
+                    reset_statements(&mut writer.tx);
+                    writer.tx.rollback().await?;
+                    Err(err)
+                }
+            }
+        }
+        .instrument(span)
+        .await;
+        op.finish_unknown(&res).await;
+        res
+    }
+
+    /// Run `f` in a write transaction, rolling back and running it again
+    /// on a fresh transaction whenever that fails with the database busy
+    /// or locked, up to the retry policy.
+    ///
+    /// Only the database side of `f` is undone between attempts,
+    /// anything else it does has to be safe to repeat.
+    pub async fn with_retrying_txn<F, R>(&self, mut f: F) -> anyhow::Result<R>
+    where
+        F: for<'w> FnMut(&'w mut Writer) -> BoxFuture<'w, anyhow::Result<R>>,
+    {
+        let mut op = self.trace.op("retrying_txn");
+        let span = op.span.clone();
+        // each attempt is a write_txn span of its own inside this one
+        let res = async {
+            let start = Instant::now();
+            let mut attempt = 1;
+            loop {
+                match self.write(&mut f).await {
+                    Err(err)
+                        if err.downcast_ref::<sqlx::Error>().is_some_and(is_busy)
+                            && attempt < self.retry.max_attempts
+                            && start.elapsed() < self.retry.max_elapsed =>
+                    {
+                        op.retrying(&err);
+                        tokio::time::sleep(self.retry.backoff(attempt - 1)).await;
+                        attempt += 1;
+                    }
+                    res => return res,
+                }
+            }
+        }
+        .instrument(span)
+        .await;
+        op.finish_unknown(&res).await;
+        res
+    }
+}
@@ -0,0 +1,36 @@
+use futures::future::join_all;
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn actor_serves_from_other_tasks() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    let actor = db.spawn_actor();
+
+    let entries: Vec<Entry> = (0..10).map(|_| Entry::rand()).collect();
+    // handles are Send, so they can go to spawned tasks
+    let inserts = entries.iter().cloned().map(|entry| {
+        let actor = actor.clone();
+        tokio::spawn(async move { actor.insert(entry).await })
+    });
+    for res in join_all(inserts).await {
+        assert_eq!(res.unwrap().unwrap().inserted, 1);
+    }
+    assert!(actor.insert(entries[0].clone()).await.is_err());
+
+    let (start, end) = (Timestamp(0), Timestamp::now());
+    assert_eq!(actor.count(0, u32::MAX, start, end).await.unwrap(), 10);
+    let mut fetched = actor.query(0, u32::MAX, start, end).await.unwrap();
+    fetched.sort_by(|a, b| a.hash.cmp(&b.hash));
+    let mut expected = entries;
+    expected.sort_by(|a, b| a.hash.cmp(&b.hash));
+    assert_eq!(
+        fetched.iter().map(|e| &e.hash).collect::<Vec<_>>(),
+        expected.iter().map(|e| &e.hash).collect::<Vec<_>>()
+    );
+
+    // the same database as the handle it was spawned from
+    assert_eq!(db.count_entries(0, u32::MAX, start, end).await.unwrap(), 10);
+
+    db.close().await.unwrap();
+    assert!(actor.count(0, u32::MAX, start, end).await.is_err());
+}
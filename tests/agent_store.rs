@@ -0,0 +1,62 @@
+use chrono::prelude::*;
+use spike_sqlx::*;
+
+fn agents(infos: Vec<AgentInfo>) -> Vec<AgentPubKey> {
+    let mut out: Vec<_> = infos.into_iter().map(|info| info.agent).collect();
+    out.sort();
+    out
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn agent_store_upsert_cover_and_prune() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let low = AgentInfo {
+        storage_arc_start: 0,
+        storage_arc_end: 100,
+        ..AgentInfo::rand()
+    };
+    // wraps past u32::MAX
+    let wrapping = AgentInfo {
+        storage_arc_start: u32::MAX - 10,
+        storage_arc_end: 10,
+        ..AgentInfo::rand()
+    };
+    db.put_agent_info(&low).await.unwrap();
+    db.put_agent_info(&wrapping).await.unwrap();
+
+    let mut both = vec![low.agent.clone(), wrapping.agent.clone()];
+    both.sort();
+    assert_eq!(agents(db.agents_covering(5).await.unwrap()), both);
+    assert_eq!(
+        agents(db.agents_covering(50).await.unwrap()),
+        vec![low.agent.clone()]
+    );
+    assert_eq!(
+        agents(db.agents_covering(u32::MAX).await.unwrap()),
+        vec![wrapping.agent.clone()]
+    );
+    assert!(db.agents_covering(1000).await.unwrap().is_empty());
+
+    // a newer info replaces the old one
+    let moved = AgentInfo {
+        agent: low.agent.clone(),
+        storage_arc_start: 900,
+        storage_arc_end: 1100,
+        expires_at: Utc::now() - chrono::Duration::seconds(1),
+        ..AgentInfo::rand()
+    };
+    db.put_agent_info(&moved).await.unwrap();
+    let stored = db.get_agent_info(&low.agent).await.unwrap().unwrap();
+    assert_eq!(stored.agent_info, moved.agent_info);
+    assert_eq!(
+        agents(db.agents_covering(1000).await.unwrap()),
+        vec![low.agent.clone()]
+    );
+
+    assert_eq!(db.prune_agent_info(Utc::now()).await.unwrap().deleted, 1);
+    assert!(db.get_agent_info(&low.agent).await.unwrap().is_none());
+    assert!(db.get_agent_info(&wrapping.agent).await.unwrap().is_some());
+
+    db.close().await.unwrap();
+}
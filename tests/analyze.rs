@@ -0,0 +1,53 @@
+mod common;
+
+use spike_sqlx::*;
+use std::path::Path;
+use std::time::Duration;
+
+/// Statistics rows `ANALYZE` left for the entries table.
+async fn entry_stats(path: &Path) -> usize {
+    let backend = SqlxBackend::open(path).await.unwrap();
+    let tables = backend
+        .query(
+            "SELECT name FROM sqlite_master WHERE name = 'sqlite_stat1'",
+            &[],
+        )
+        .await
+        .unwrap();
+    if tables.is_empty() {
+        return 0;
+    }
+    backend
+        .query("SELECT * FROM sqlite_stat1 WHERE tbl = 'entries'", &[])
+        .await
+        .unwrap()
+        .len()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn planner_statistics() {
+    let dir = common::temp_dir();
+    let config = DbConfig::new().encryption(Encryption::None);
+    let entries: Vec<_> = (0..100).map(|_| Entry::rand()).collect();
+
+    // by hand
+    let manual = dir.path().join("manual.sqlite3");
+    let db = Db::open_with(&manual, config.clone().optimize_on_close(false))
+        .await
+        .unwrap();
+    db.insert_entries(&entries).await.unwrap();
+    db.analyze().await.unwrap();
+    db.optimize().await.unwrap();
+    db.close().await.unwrap();
+    assert!(entry_stats(&manual).await > 0);
+
+    // on a timer
+    let background = dir.path().join("background.sqlite3");
+    let db = Db::open_with(&background, config.analyze_every(Duration::from_millis(10)))
+        .await
+        .unwrap();
+    db.insert_entries(&entries).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    db.close().await.unwrap();
+    assert!(entry_stats(&background).await > 0);
+}
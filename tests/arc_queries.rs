@@ -0,0 +1,103 @@
+use chrono::prelude::*;
+use proptest::prelude::*;
+use spike_sqlx::*;
+
+/// Locations clustered around the wrap point, where off-by-ones hide.
+fn loc() -> impl Strategy<Value = u32> {
+    prop_oneof![
+        any::<u32>(),
+        0u32..16,
+        (u32::MAX - 16)..=u32::MAX,
+        (u32::MAX / 2 - 8)..(u32::MAX / 2 + 8),
+    ]
+}
+
+/// The arc as the queries define it, checked in plain rust.
+fn in_arc(loc: u32, start: u32, end: u32) -> bool {
+    if start <= end {
+        loc >= start && loc <= end
+    } else {
+        loc >= start || loc <= end
+    }
+}
+
+fn run<F: std::future::Future>(f: F) -> F::Output {
+    tokio::runtime::Runtime::new().unwrap().block_on(f)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn query_entries_matches_filter(
+        hashes in proptest::collection::vec(any::<[u8; 32]>(), 0..32),
+        start in loc(),
+        end in loc(),
+    ) {
+        let (mut got, mut expected) = run(async {
+            let db = Db::open("sqlite::memory:").await.unwrap();
+            let mut expected = Vec::new();
+            for hash in hashes.iter() {
+                let entry = Entry {
+                    hash: EntryHash::from_raw_32(*hash),
+                    ..Entry::rand()
+                };
+                // a repeated hash is the same entry
+                if db.upsert_entry(&entry).await.unwrap().inserted == 0 {
+                    continue;
+                }
+                if in_arc(entry.dht_loc(), start, end) {
+                    expected.push(entry.hash);
+                }
+            }
+            let got: Vec<_> = db
+                .query_entries(start, end, Timestamp(0), Timestamp::now())
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.hash)
+                .collect();
+            db.close().await.unwrap();
+            (got, expected)
+        });
+        got.sort();
+        expected.sort();
+        prop_assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn query_ops_matches_filter(
+        locs in proptest::collection::vec(loc(), 0..32),
+        start in loc(),
+        end in loc(),
+    ) {
+        let (mut got, mut expected) = run(async {
+            let db = Db::open("sqlite::memory:").await.unwrap();
+            let mut expected = Vec::new();
+            for (i, basis_loc) in locs.iter().enumerate() {
+                let op = DhtOp {
+                    op_hash: OpHash::with_data(&(i as u32).to_be_bytes()),
+                    basis_loc: *basis_loc,
+                    when_integrated: Some(Utc::now()),
+                    ..DhtOp::rand()
+                };
+                db.insert_op(&op).await.unwrap();
+                if in_arc(op.basis_loc, start, end) {
+                    expected.push(op.op_hash);
+                }
+            }
+            let got: Vec<_> = db
+                .query_ops(None, start, end, Utc.ymd(1970, 1, 1).and_hms(0, 0, 0), Utc::now())
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|op| op.op_hash)
+                .collect();
+            db.close().await.unwrap();
+            (got, expected)
+        });
+        got.sort();
+        expected.sort();
+        prop_assert_eq!(got, expected);
+    }
+}
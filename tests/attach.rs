@@ -0,0 +1,71 @@
+mod common;
+
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cached_entries_not_yet_held() {
+    let dir = common::temp_dir();
+    let dna = DnaHash(vec![7; 39]);
+    let config = DbConfig::new().encryption(Encryption::SqlCipher(KeySource::Derived(
+        KeyDerivation::new(SecretKey::new([9; 32])),
+    )));
+
+    let cache = Db::open_kind(dir.path(), DbKind::Cache(dna.clone()), config.clone())
+        .await
+        .unwrap();
+    let dht = Db::open_kind(dir.path(), DbKind::Dht(dna.clone()), config)
+        .await
+        .unwrap();
+
+    let cached: Vec<Entry> = (0..10).map(|_| Entry::rand()).collect();
+    cache.insert_entries(&cached).await.unwrap();
+    dht.insert_entries(&cached[..4]).await.unwrap();
+
+    dht.attach(DbKind::Cache(dna.clone()), "cache")
+        .await
+        .unwrap();
+    assert_eq!(dht.attached(), vec!["cache".to_string()]);
+    let mut missing: Vec<EntryHash> = cached[4..].iter().map(|e| e.hash.clone()).collect();
+    missing.sort();
+    assert_eq!(
+        dht.entries_missing_from_main("cache").await.unwrap(),
+        missing
+    );
+
+    // the writer has it attached too, and still writes to its own file
+    dht.insert_entries(&cached[4..]).await.unwrap();
+    assert!(dht
+        .entries_missing_from_main("cache")
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(cache.entry_exists(&cached[9].hash).await.unwrap());
+
+    assert!(dht
+        .attach(DbKind::Cache(dna.clone()), "cache")
+        .await
+        .is_err());
+    assert!(dht.attach(DbKind::Cache(dna), "main").await.is_err());
+    assert!(dht
+        .attach(DbKind::Cache(DnaHash(vec![8; 39])), "other")
+        .await
+        .is_err());
+
+    dht.detach("cache").unwrap();
+    assert!(dht.attached().is_empty());
+    assert!(dht.entries_missing_from_main("cache").await.is_err());
+    assert!(dht.detach("cache").is_err());
+
+    dht.close().await.unwrap();
+    cache.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn attach_needs_a_kind() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    assert!(db
+        .attach(DbKind::Cache(DnaHash(vec![7; 39])), "cache")
+        .await
+        .is_err());
+    db.close().await.unwrap();
+}
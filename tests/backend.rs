@@ -0,0 +1,72 @@
+mod common;
+
+use spike_sqlx::*;
+
+/// The same workload, which every backend has to agree on.
+async fn round_trip<B: DbBackend>() {
+    let dir = common::temp_dir();
+    let backend = B::open(&dir.path().join("db.sqlite3")).await.unwrap();
+
+    backend
+        .execute(
+            "CREATE TABLE t (i INTEGER, r REAL, s TEXT, b BLOB, n INTEGER NULL)",
+            &[],
+        )
+        .await
+        .unwrap();
+    let row = vec![
+        Value::Integer(-7),
+        Value::Real(0.5),
+        Value::Text("seven".to_string()),
+        Value::Blob(vec![0, 7]),
+        Value::Null,
+    ];
+    assert_eq!(
+        backend
+            .execute("INSERT INTO t VALUES (?, ?, ?, ?, ?)", &row)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        backend
+            .query("SELECT * FROM t WHERE i = ?", &[Value::Integer(-7)])
+            .await
+            .unwrap(),
+        vec![row.clone()]
+    );
+
+    let insert = |i| Statement::new("INSERT INTO t (i) VALUES (?)", vec![Value::Integer(i)]);
+    assert_eq!(
+        backend
+            .transaction(vec![insert(1), insert(2)])
+            .await
+            .unwrap(),
+        2
+    );
+    // the bad statement takes the good one with it
+    assert!(backend
+        .transaction(vec![
+            insert(3),
+            Statement::new("INSERT INTO nope VALUES (1)", vec![])
+        ])
+        .await
+        .is_err());
+    assert_eq!(
+        backend.query("SELECT count(*) FROM t", &[]).await.unwrap(),
+        vec![vec![Value::Integer(3)]]
+    );
+
+    drop(backend);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sqlx_backend() {
+    round_trip::<SqlxBackend>().await;
+}
+
+#[cfg(feature = "rusqlite-backend")]
+#[tokio::test(flavor = "multi_thread")]
+async fn rusqlite_backend() {
+    round_trip::<RusqliteBackend>().await;
+}
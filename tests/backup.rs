@@ -0,0 +1,63 @@
+mod common;
+
+use spike_sqlx::*;
+use sqlx::{Connection, SqliteConnection};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backup_and_restore() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let backup = dir.path().join("backup.sqlite3");
+
+    let db = Db::open(&path).await.unwrap();
+    let before: Vec<Entry> = (0..50).map(|_| Entry::rand()).collect();
+    db.insert_entries(&before).await.unwrap();
+    db.backup_to(&backup, None).await.unwrap();
+    assert!(db.backup_to(&backup, None).await.is_err());
+
+    let after = Entry::rand();
+    db.insert_entry(&after).await.unwrap();
+
+    let db = db.restore_from(&backup).await.unwrap();
+    assert!(db.entry_exists(&before[7].hash).await.unwrap());
+    assert!(!db.entry_exists(&after.hash).await.unwrap());
+    // and it's writable again
+    db.insert_entry(&after).await.unwrap();
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn restore_refuses_newer_schema() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let backup = dir.path().join("backup.sqlite3");
+
+    let db = Db::open(&path).await.unwrap();
+    let held = Entry::rand();
+    db.insert_entry(&held).await.unwrap();
+    db.backup_to(&backup, None).await.unwrap();
+
+    // as if a newer build had migrated the backup
+    let mut con = SqliteConnection::connect(&format!("sqlite://{}", backup.display()))
+        .await
+        .unwrap();
+    sqlx::query(
+        "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+        VALUES (99999, 'from the future', TRUE, x'', 0);",
+    )
+    .execute(&mut con)
+    .await
+    .unwrap();
+    con.close().await.unwrap();
+
+    let err = db.restore_from(&backup).await.err().unwrap();
+    assert!(err.to_string().contains("schema version"), "{}", err);
+
+    // the file was left alone
+    let db = Db::open(&path).await.unwrap();
+    assert!(db.entry_exists(&held.hash).await.unwrap());
+    db.close().await.unwrap();
+
+    let memory = Db::open("sqlite::memory:").await.unwrap();
+    assert!(memory.restore_from(&backup).await.is_err());
+}
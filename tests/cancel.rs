@@ -0,0 +1,89 @@
+mod common;
+
+use futures::StreamExt;
+use spike_sqlx::*;
+use std::time::{Duration, Instant};
+
+/// Counts to a hundred million, taking seconds.
+const SLOW_SCAN: &str =
+    "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100000000)
+    SELECT count(*) FROM n;";
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cancelled_scans_release_their_reader() {
+    let dir = common::temp_dir();
+    // without WAL a reader still holding its lock blocks the writer's
+    // commit, and with one reader connection the next read waits on it
+    let config = DbConfig::new()
+        .journal_mode(SqliteJournalMode::Delete)
+        .busy_timeout(Duration::from_millis(200))
+        .retry_policy(RetryPolicy::none())
+        .read_connections(1, 1)
+        .permit_timeout(Duration::from_millis(500));
+    let db = Db::open_with(dir.path().join("db.sqlite3"), config)
+        .await
+        .unwrap();
+
+    let entries: Vec<_> = (0..1000u32)
+        .map(|i| {
+            let mut content = vec![0; 16 * 1024];
+            content[..4].copy_from_slice(&i.to_le_bytes());
+            Entry::from_content(content)
+        })
+        .collect();
+    db.insert_entries(&entries).await.unwrap();
+    let mut held = entries.len() as u64;
+    let all = EntryFilter::new().order(EntryOrder::CreatedAt);
+
+    // give up on the scan at different points part way through
+    for micros in (0..2000).step_by(100) {
+        let scan = db.filter_entries(&all);
+        let _ = tokio::time::timeout(Duration::from_micros(micros), scan).await;
+
+        db.insert_entry(&Entry::rand()).await.unwrap();
+        held += 1;
+        // nor is the interrupt left behind for the connection's next user
+        let count = db
+            .count_entries(0, u32::MAX, Timestamp(0), Timestamp::now())
+            .await
+            .unwrap();
+        assert_eq!(count, held);
+    }
+
+    // a statement that would run for many seconds, given up on once
+    // it's well under way, hands the one reader straight back; were it
+    // still running the next read would wait out the permit timeout
+    let failed = |db: &Db| {
+        db.metrics_snapshot()
+            .op("read_txn")
+            .map_or(0, |op| op.errors)
+    };
+    let before = failed(&db);
+    let scan = db.read(|reader| Box::pin(async move { reader.query(SLOW_SCAN, &[]).await }));
+    assert!(tokio::time::timeout(Duration::from_secs(1), scan)
+        .await
+        .is_err());
+    let started = Instant::now();
+    let count = db
+        .count_entries(0, u32::MAX, Timestamp(0), Timestamp::now())
+        .await
+        .unwrap();
+    assert_eq!(count, held);
+    assert!(started.elapsed() < Duration::from_millis(500));
+    // the statement ended interrupted, not by running to the end
+    assert_eq!(failed(&db), before + 1);
+
+    // same for a stream dropped before it's done
+    let mut stream = Box::pin(db.stream_entries(&EntryFilter::new()));
+    stream.next().await.unwrap().unwrap();
+    drop(stream);
+    db.insert_entry(&Entry::rand()).await.unwrap();
+    held += 1;
+    let count = db
+        .count_entries(0, u32::MAX, Timestamp(0), Timestamp::now())
+        .await
+        .unwrap();
+    assert_eq!(count, held);
+
+    db.close().await.unwrap();
+}
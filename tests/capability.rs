@@ -0,0 +1,142 @@
+use spike_sqlx::*;
+
+fn grant(access: CapAccess, functions: &[(&str, &str)]) -> CapGrant {
+    CapGrant {
+        header_hash: HeaderHash::rand(),
+        tag: "test".to_string(),
+        access,
+        functions: functions
+            .iter()
+            .map(|(zome, function)| GrantedFunction::new(zome, function))
+            .collect(),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn grants_check_access() {
+    let db = Db::open_with("sqlite::memory:", DbConfig::new().explain_queries(true))
+        .await
+        .unwrap();
+
+    let read = GrantedFunction::new("posts", "read");
+    let write = GrantedFunction::new("posts", "write");
+    let admin = GrantedFunction::new("posts", "admin");
+    let (alice, bob) = (AgentPubKey::rand(), AgentPubKey::rand());
+    let (transferable, assigned) = (CapSecret::rand(), CapSecret::rand());
+
+    let public = grant(CapAccess::Unrestricted, &[("posts", "read")]);
+    db.insert_cap_grant(&public).await.unwrap();
+    let shared = grant(
+        CapAccess::Transferable {
+            secret: transferable,
+        },
+        &[("posts", "write")],
+    );
+    db.insert_cap_grant(&shared).await.unwrap();
+    let alice_only = grant(
+        CapAccess::Assigned {
+            secret: assigned,
+            assignees: vec![alice.clone()],
+        },
+        &[("posts", "write"), ("posts", "admin")],
+    );
+    db.insert_cap_grant(&alice_only).await.unwrap();
+
+    // unrestricted needs no secret
+    assert_eq!(
+        db.valid_grant_for(&read, &bob, None).await.unwrap(),
+        Some(public.header_hash.clone())
+    );
+    // transferable needs its secret, from anyone
+    assert_eq!(
+        db.valid_grant_for(&write, &bob, Some(&transferable))
+            .await
+            .unwrap(),
+        Some(shared.header_hash.clone())
+    );
+    assert_eq!(db.valid_grant_for(&write, &bob, None).await.unwrap(), None);
+    assert_eq!(
+        db.valid_grant_for(&write, &bob, Some(&CapSecret::rand()))
+            .await
+            .unwrap(),
+        None
+    );
+    // assigned needs its secret and an assignee
+    assert_eq!(
+        db.valid_grant_for(&admin, &alice, Some(&assigned))
+            .await
+            .unwrap(),
+        Some(alice_only.header_hash.clone())
+    );
+    assert_eq!(
+        db.valid_grant_for(&admin, &bob, Some(&assigned))
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        db.valid_grant_for(&admin, &alice, Some(&transferable))
+            .await
+            .unwrap(),
+        None
+    );
+    // a secret only opens the functions its grant lists
+    assert_eq!(
+        db.valid_grant_for(&admin, &bob, Some(&transferable))
+            .await
+            .unwrap(),
+        None
+    );
+
+    // revoking takes the functions and assignees with it
+    assert!(db.delete_cap_grant(&alice_only.header_hash).await.unwrap());
+    assert!(!db.delete_cap_grant(&alice_only.header_hash).await.unwrap());
+    assert_eq!(
+        db.valid_grant_for(&admin, &alice, Some(&assigned))
+            .await
+            .unwrap(),
+        None
+    );
+
+    let check = db
+        .explained_queries()
+        .into_iter()
+        .find(|query| query.sql.contains("FROM cap_grant_functions"))
+        .unwrap();
+    assert!(!check.full_scan, "{:#?}", check);
+
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn claims_by_grantor() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let grantor = AgentPubKey::rand();
+    for tag in &["b", "a"] {
+        let claim = CapClaim {
+            secret: CapSecret::rand(),
+            tag: tag.to_string(),
+            grantor: grantor.clone(),
+        };
+        db.insert_cap_claim(&claim).await.unwrap();
+        // the secret identifies the claim
+        assert_eq!(db.insert_cap_claim(&claim).await.unwrap().inserted, 0);
+    }
+    db.insert_cap_claim(&CapClaim {
+        secret: CapSecret::rand(),
+        tag: "a".to_string(),
+        grantor: AgentPubKey::rand(),
+    })
+    .await
+    .unwrap();
+
+    let claims = db.cap_claims(&grantor).await.unwrap();
+    assert_eq!(
+        claims.iter().map(|c| c.tag.as_str()).collect::<Vec<_>>(),
+        vec!["a", "b"]
+    );
+    assert!(claims.iter().all(|c| c.grantor == grantor));
+
+    db.close().await.unwrap();
+}
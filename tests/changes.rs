@@ -0,0 +1,103 @@
+use spike_sqlx::*;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+
+fn agent() -> Entry {
+    let mut entry = Entry::rand();
+    entry.entry_type = EntryType::Agent;
+    entry
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn committed_entries_are_announced() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    let mut agents = db.subscribe(EntryFilter::new().entry_type(EntryType::Agent));
+    let mut everything = db.subscribe(EntryFilter::new());
+
+    let first = agent();
+    db.insert_entry(&first).await.unwrap();
+    let event = agents.recv().await.unwrap();
+    assert_eq!(event.hash, first.hash);
+    assert_eq!(event.dht_loc, first.dht_loc());
+    assert_eq!(event.entry_type, EntryType::Agent);
+    assert_eq!(event.created_at, first.created_at);
+    // already visible to readers
+    assert!(db.get_entry(&event.hash).await.unwrap().is_some());
+
+    // however it's written, in commit order
+    let app = Entry::rand();
+    let batch = vec![agent(), agent()];
+    db.insert_entries(&batch).await.unwrap();
+    db.write_queue()
+        .submit(WriteOp::InsertEntry(app.clone()))
+        .await
+        .unwrap();
+    let kept = agent();
+    let undone = agent();
+    let (k, u) = (kept.clone(), undone.clone());
+    db.write(move |writer| {
+        Box::pin(async move {
+            writer.insert_entry(&k).await?;
+            writer.savepoint("maybe").await?;
+            writer.insert_entry(&u).await?;
+            writer.rollback_to("maybe").await
+        })
+    })
+    .await
+    .unwrap();
+    // nothing of a transaction that rolled back
+    let failed = agent();
+    db.write(move |writer| {
+        Box::pin(async move {
+            writer.insert_entry(&failed).await?;
+            Err::<(), _>(anyhow::anyhow!("changed my mind"))
+        })
+    })
+    .await
+    .unwrap_err();
+
+    for expected in [&batch[0], &batch[1], &kept] {
+        assert_eq!(agents.recv().await.unwrap().hash, expected.hash);
+    }
+    assert_eq!(agents.try_recv().unwrap_err(), TryRecvError::Empty);
+    let all: Vec<_> = (0..5)
+        .map(|_| everything.try_recv().unwrap().hash)
+        .collect();
+    assert_eq!(
+        all,
+        vec![
+            first.hash.clone(),
+            batch[0].hash.clone(),
+            batch[1].hash.clone(),
+            app.hash.clone(),
+            kept.hash.clone(),
+        ]
+    );
+    assert!(!all.contains(&undone.hash));
+
+    // a location range, wrapping past the end of the space
+    let entry = Entry::rand();
+    let loc = entry.dht_loc();
+    let mut around = db.subscribe(EntryFilter::new().loc_range(loc, loc.wrapping_sub(1)));
+    let mut elsewhere =
+        db.subscribe(EntryFilter::new().loc_range(loc.wrapping_add(1), loc.wrapping_sub(1)));
+    db.insert_entry(&entry).await.unwrap();
+    assert_eq!(around.recv().await.unwrap().hash, entry.hash);
+    assert_eq!(elsewhere.try_recv().unwrap_err(), TryRecvError::Empty);
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn slow_subscribers_lag() {
+    let config = DbConfig::new().subscription_capacity(2);
+    let db = Db::open_with("sqlite::memory:", config).await.unwrap();
+    let mut rx = db.subscribe(EntryFilter::new());
+    let entries: Vec<_> = (0..3).map(|_| Entry::rand()).collect();
+    db.insert_entries(&entries).await.unwrap();
+    assert_eq!(rx.recv().await.unwrap_err(), RecvError::Lagged(1));
+    assert_eq!(rx.recv().await.unwrap().hash, entries[1].hash);
+
+    // a dropped receiver doesn't hold anything up
+    drop(rx);
+    db.insert_entry(&Entry::rand()).await.unwrap();
+    db.close().await.unwrap();
+}
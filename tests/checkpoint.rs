@@ -0,0 +1,53 @@
+mod common;
+
+use spike_sqlx::*;
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn large_wal_is_truncated_in_the_background() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let wal = dir.path().join("db.sqlite3-wal");
+    let max_bytes = 256 * 1024;
+    // only the size check gets to checkpoint
+    let config = DbConfig::new()
+        .wal_autocheckpoint(0)
+        .checkpoint_wal_above(max_bytes, Duration::from_millis(10));
+    let db = Db::open_with(&path, config).await.unwrap();
+    let before = db.checkpoint_metrics();
+
+    let entries: Vec<_> = (0..64u32)
+        .map(|i| {
+            let mut content = vec![0; 16 * 1024];
+            content[..4].copy_from_slice(&i.to_le_bytes());
+            Entry::from_content(content)
+        })
+        .collect();
+    db.insert_entries(&entries).await.unwrap();
+    assert!(std::fs::metadata(&wal).unwrap().len() > max_bytes);
+
+    // the wal is truncated a moment before the checkpoint is counted
+    let mut waited = Duration::from_millis(0);
+    while std::fs::metadata(&wal).unwrap().len() > 0
+        || db.checkpoint_metrics().checkpoints == before.checkpoints
+    {
+        assert!(
+            waited < Duration::from_secs(5),
+            "the wal was never truncated"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        waited += Duration::from_millis(10);
+    }
+    let metrics = db.checkpoint_metrics();
+    assert!(metrics.checkpoints > before.checkpoints);
+    // a 16KB entry spans at least four pages
+    assert!(metrics.pages_checkpointed >= 64 * 4);
+    assert!(!metrics.last.unwrap().busy);
+
+    // manual checkpoints count too
+    let result = db.checkpoint(CheckpointMode::Passive).await.unwrap();
+    assert_eq!(db.checkpoint_metrics().last, Some(result));
+    assert_eq!(db.checkpoint_metrics().checkpoints, metrics.checkpoints + 1);
+
+    db.close().await.unwrap();
+}
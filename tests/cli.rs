@@ -0,0 +1,152 @@
+mod common;
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+/// Run the binary with `args` against the database at `path`, keyed
+/// from `key_file`, failing the test if it fails.
+fn run(path: &Path, key_file: &Path, args: &[&str]) -> String {
+    let output = spike_sqlx(path, key_file, args).output().unwrap();
+    check(&output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn spike_sqlx(path: &Path, key_file: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_spike-sqlx"));
+    command
+        .args(args)
+        .arg("--path")
+        .arg(path)
+        .arg("--key-file")
+        .arg(key_file);
+    command
+}
+
+fn check(output: &Output) {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn init_insert_query_inspect() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let key_file = dir.path().join("db.key");
+
+    let out = run(&path, &key_file, &["init"]);
+    assert!(out.contains("wrote a new key"), "{}", out);
+    assert!(key_file.exists());
+    // a second init keeps the key it has
+    let out = run(&path, &key_file, &["init"]);
+    assert!(!out.contains("wrote a new key"), "{}", out);
+
+    let out = run(
+        &path,
+        &key_file,
+        &["insert", "--count", "25", "--batch", "10"],
+    );
+    assert!(out.starts_with("inserted 25 entries"), "{}", out);
+    let out = run(&path, &key_file, &["query"]);
+    assert!(out.ends_with("25 entries\n"), "{}", out);
+    let out = run(&path, &key_file, &["query", "--limit", "3"]);
+    assert!(out.ends_with("3 entries\n"), "{}", out);
+
+    let out = run(&path, &key_file, &["inspect"]);
+    assert!(out.contains("entries, 25 rows"), "{}", out);
+    assert!(out.contains("journal_mode = wal"), "{}", out);
+}
+
+#[test]
+fn repl_runs_piped_sql() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let key_file = dir.path().join("db.key");
+    run(&path, &key_file, &["init"]);
+    run(&path, &key_file, &["insert", "--count", "4"]);
+
+    let mut repl = spike_sqlx(&path, &key_file, &["repl"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    repl.stdin
+        .take()
+        .unwrap()
+        .write_all(b".tables\nSELECT count(*)\n  AS n FROM entries;\nSELECT nonsense;\n.quit\n")
+        .unwrap();
+    let output = repl.wait_with_output().unwrap();
+    check(&output);
+    let out = String::from_utf8(output.stdout).unwrap();
+    assert!(out.contains("\nentries\n"), "{}", out);
+    assert!(out.ends_with("n\n-\n4\n(1 row)\n"), "{}", out);
+    // a bad statement is reported and the prompt carries on
+    let err = String::from_utf8(output.stderr).unwrap();
+    assert!(err.contains("no such column: nonsense"), "{}", err);
+}
+
+#[test]
+fn bench_reports_throughput() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let key_file = dir.path().join("db.key");
+    run(&path, &key_file, &["init"]);
+    let out = run(
+        &path,
+        &key_file,
+        &[
+            "bench",
+            "--seconds",
+            "1",
+            "--concurrency",
+            "2",
+            "--seed",
+            "10",
+        ],
+    );
+    assert!(out.contains("per second"), "{}", out);
+}
+
+#[test]
+fn key_rotate_needs_sqlcipher() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let key_file = dir.path().join("db.key");
+    let new_key_file = dir.path().join("new.key");
+    let backup = dir.path().join("backup.sqlite3");
+    run(&path, &key_file, &["init"]);
+    run(&path, &key_file, &["insert", "--count", "5"]);
+    let sqlcipher = run(&path, &key_file, &["inspect"]).contains("cipher_version");
+
+    let output = spike_sqlx(&path, &key_file, &["key-rotate"])
+        .arg("--new-key-file")
+        .arg(&new_key_file)
+        .arg("--backup")
+        .arg(&backup)
+        .output()
+        .unwrap();
+    if sqlcipher {
+        check(&output);
+        let out = String::from_utf8(output.stdout).unwrap();
+        assert!(out.contains("is now under the new key"), "{}", out);
+        let out = run(&path, &new_key_file, &["query"]);
+        assert!(out.ends_with("5 entries\n"), "{}", out);
+        let output = spike_sqlx(&path, &key_file, &["query"]).output().unwrap();
+        assert!(!output.status.success());
+        return;
+    }
+
+    // plain sqlite never encrypted the file, so there's nothing to
+    // rotate and nothing is touched
+    assert!(!output.status.success());
+    let err = String::from_utf8(output.stderr).unwrap();
+    assert!(err.contains("SQLCipher"), "{}", err);
+    assert!(!new_key_file.exists());
+    assert!(!backup.exists());
+    let out = run(&path, &key_file, &["query"]);
+    assert!(out.ends_with("5 entries\n"), "{}", out);
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use spike_sqlx::*;
+use std::time::Duration;
+
+/// Far longer than failing should take.
+const WAIT: Duration = Duration::from_secs(5);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clones_fail_once_closed() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let db = Db::open(&path).await.unwrap();
+    let entry = Entry::rand();
+    db.insert_entry(&entry).await.unwrap();
+    let clone = db.clone();
+    clone.ping().await.unwrap();
+
+    db.close().await.unwrap();
+    // the final checkpoint emptied the wal
+    let wal = dir.path().join("db.sqlite3-wal");
+    assert_eq!(std::fs::metadata(&wal).map_or(0, |meta| meta.len()), 0);
+
+    // errors straight away rather than waiting on a connection
+    let read = tokio::time::timeout(WAIT, clone.get_entry(&entry.hash));
+    assert!(read.await.unwrap().is_err());
+    let later = Entry::rand();
+    let write = tokio::time::timeout(WAIT, clone.insert_entry(&later));
+    assert!(write.await.unwrap().is_err());
+    let op = WriteOp::InsertEntry(Entry::rand());
+    let queued = tokio::time::timeout(WAIT, clone.write_queue().submit(op));
+    assert!(queued.await.unwrap().is_err());
+    let ping = tokio::time::timeout(WAIT, clone.ping());
+    assert!(ping.await.unwrap().is_err());
+
+    // everything written before the close is there on reopening, and
+    // nothing after
+    let db = Db::open(&path).await.unwrap();
+    assert!(db.entry_exists(&entry.hash).await.unwrap());
+    assert!(!db.entry_exists(&later.hash).await.unwrap());
+    db.close().await.unwrap();
+}
@@ -0,0 +1,84 @@
+use spike_sqlx::*;
+use std::sync::{Arc, Mutex};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn committed_writes_are_summarized() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    let summaries = Arc::new(Mutex::new(Vec::new()));
+    let seen = summaries.clone();
+    db.on_commit(move |summary| seen.lock().unwrap().push(summary.clone()));
+    let take = || std::mem::take(&mut *summaries.lock().unwrap());
+
+    let entry = Entry::rand();
+    db.insert_entry(&entry).await.unwrap();
+    let summary = &take()[0];
+    let entries = summary.table("entries").unwrap();
+    assert_eq!(
+        (entries.inserted, entries.updated, entries.deleted),
+        (1, 0, 0)
+    );
+    // the search index's tables are written by its trigger
+    assert_eq!(summary.table("entry_search_docsize").unwrap().inserted, 1);
+    assert!(summary.op_hashes.is_empty());
+
+    // ops by hash, each once
+    let op = DhtOp::rand();
+    db.insert_op(&op).await.unwrap();
+    db.integrate_op(&op.op_hash, ValidationStatus::Valid)
+        .await
+        .unwrap();
+    let summaries = take();
+    assert_eq!(summaries.len(), 2);
+    assert_eq!(summaries[0].op_hashes, vec![op.op_hash.clone()]);
+    assert_eq!(summaries[0].table("dht_ops").unwrap().inserted, 1);
+    assert_eq!(summaries[1].op_hashes, vec![op.op_hash.clone()]);
+    assert_eq!(summaries[1].table("dht_ops").unwrap().updated, 1);
+
+    // only what survived savepoints, nothing of a rollback or a read
+    let (kept, undone) = (Entry::rand(), Entry::rand());
+    db.write(move |writer| {
+        Box::pin(async move {
+            writer.insert_entry(&kept).await?;
+            writer.savepoint("maybe").await?;
+            writer.insert_entry(&undone).await?;
+            writer.rollback_to("maybe").await
+        })
+    })
+    .await
+    .unwrap();
+    db.write(move |writer| {
+        Box::pin(async move {
+            writer.insert_entry(&Entry::rand()).await?;
+            Err::<(), _>(anyhow::anyhow!("changed my mind"))
+        })
+    })
+    .await
+    .unwrap_err();
+    db.get_entry(&entry.hash).await.unwrap();
+    let summaries = take();
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0].table("entries").unwrap().inserted, 1);
+
+    // however it's written
+    let queued = DhtOp::rand();
+    db.write_queue()
+        .submit(WriteOp::InsertOp(queued.clone()))
+        .await
+        .unwrap();
+    assert_eq!(take()[0].op_hashes, vec![queued.op_hash]);
+    db.delete_entry(&entry.hash).await.unwrap();
+    let summary = &take()[0];
+    assert_eq!(summary.table("entries").unwrap().deleted, 1);
+    assert_eq!(summary.table("entry_search_ids").unwrap().deleted, 1);
+    assert_eq!(summary.table("entry_search_docsize").unwrap().deleted, 1);
+
+    // the FTS5 shadow tables are in there, as they are in the stats,
+    // but not the virtual table they're behind
+    let stats = db.stats().await.unwrap();
+    for changes in &summary.tables {
+        assert!(stats.table(&changes.table).is_some(), "{}", changes.table);
+    }
+    assert!(summary.table("entry_search_data").is_some());
+    assert!(summary.table("entry_search").is_none());
+    db.close().await.unwrap();
+}
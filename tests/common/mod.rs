@@ -0,0 +1,12 @@
+//! Shared by the integration tests, each pulls it in with `mod common;`.
+
+use tempfile::TempDir;
+
+/// A fresh directory for one test's files, removed when it's dropped,
+/// so a test that panics doesn't leave it behind for the next run.
+pub fn temp_dir() -> TempDir {
+    tempfile::Builder::new()
+        .prefix("spike-sqlx-")
+        .tempdir()
+        .unwrap()
+}
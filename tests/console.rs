@@ -0,0 +1,58 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sql_runs_as_typed() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    let entry = Entry::rand();
+    db.insert_entry(&entry).await.unwrap();
+
+    // every statement, each with what it did
+    let outputs = db
+        .run_sql(
+            "CREATE TABLE notes (id INTEGER PRIMARY KEY, note TEXT);
+            INSERT INTO notes (note) VALUES ('a'), (NULL);
+            SELECT id, note FROM notes ORDER BY id;
+            SELECT count(*) AS n FROM notes WHERE note IS NULL;",
+        )
+        .await
+        .unwrap();
+    assert_eq!(outputs.len(), 4);
+    assert_eq!(outputs[0].rows_changed, 0);
+    assert_eq!(outputs[1].rows_changed, 2);
+    assert_eq!(outputs[2].columns, vec!["id", "note"]);
+    assert_eq!(
+        outputs[2].rows,
+        vec![
+            vec![Value::Integer(1), Value::Text("a".to_string())],
+            vec![Value::Integer(2), Value::Null],
+        ]
+    );
+    // not the count left over from the insert
+    assert_eq!(outputs[2].rows_changed, 0);
+    assert_eq!(outputs[3].rows, vec![vec![Value::Integer(1)]]);
+
+    assert_eq!(
+        outputs[2].to_string(),
+        "id | note\n---+-----\n1  | a\n2  | NULL\n(2 rows)\n"
+    );
+    assert_eq!(outputs[1].to_string(), "2 rows changed\n");
+
+    // blobs as literals, whole hashes included
+    let outputs = db.run_sql("SELECT hash FROM entries;").await.unwrap();
+    let hex: String = entry
+        .hash
+        .get_raw_39()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert!(outputs[0].to_string().contains(&format!("x'{}'", hex)));
+
+    // those before a failing statement have run
+    assert!(db
+        .run_sql("DELETE FROM notes WHERE id = 1; SELECT * FROM missing;")
+        .await
+        .is_err());
+    let outputs = db.run_sql("SELECT count(*) FROM notes;").await.unwrap();
+    assert_eq!(outputs[0].rows, vec![vec![Value::Integer(1)]]);
+    db.close().await.unwrap();
+}
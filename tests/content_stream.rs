@@ -0,0 +1,70 @@
+mod common;
+
+use futures::{stream, StreamExt, TryStreamExt};
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn large_content_in_chunks() {
+    let dir = common::temp_dir();
+    let db = Db::open(dir.path().join("db.sqlite3")).await.unwrap();
+
+    let content: Vec<u8> = (0..3 * 1024 * 1024u32).map(|i| i as u8).collect();
+    let hash = EntryHash::with_data(&content);
+    let entry_type = EntryType::App {
+        zome_index: 1,
+        entry_def_index: 2,
+    };
+    let chunks: Vec<anyhow::Result<Vec<u8>>> = content
+        .chunks(100_000)
+        .map(|chunk| Ok(chunk.to_vec()))
+        .collect();
+    let outcome = db
+        .insert_content_stream(
+            &hash,
+            entry_type,
+            Timestamp::now(),
+            content.len() as u32,
+            stream::iter(chunks),
+        )
+        .await
+        .unwrap();
+    assert_eq!(outcome.inserted, 1);
+
+    let read: Vec<Vec<u8>> = db.read_content_stream(&hash).try_collect().await.unwrap();
+    assert_eq!(read.len(), 48);
+    assert!(read.iter().all(|chunk| chunk.len() <= CONTENT_CHUNK));
+    assert_eq!(read.concat(), content);
+    let entry = db.get_entry(&hash).await.unwrap().unwrap();
+    assert_eq!(entry.entry_type, entry_type);
+    assert_eq!(entry.size_bytes(), content.len() as u32);
+
+    // an empty entry streams nothing, a missing one fails
+    let empty = Entry::from_content(Vec::new());
+    db.insert_entry(&empty).await.unwrap();
+    let read: Vec<Vec<u8>> = db
+        .read_content_stream(&empty.hash)
+        .try_collect()
+        .await
+        .unwrap();
+    assert!(read.is_empty());
+    let mut missing = db.read_content_stream(&EntryHash::rand());
+    assert!(missing.next().await.unwrap().is_err());
+
+    // short or long, nothing is kept
+    for size in &[10u32, 2] {
+        let hash = EntryHash::rand();
+        let res = db
+            .insert_content_stream(
+                &hash,
+                entry_type,
+                Timestamp::now(),
+                *size,
+                stream::iter(vec![Ok(vec![0; 3]), Ok(vec![0; 3])]),
+            )
+            .await;
+        assert!(res.is_err());
+        assert!(!db.entry_exists(&hash).await.unwrap());
+    }
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,46 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn count_and_exists() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let created_at = Timestamp::now();
+    let mut locs = Vec::new();
+    for _ in 0..4 {
+        let entry = Entry {
+            created_at,
+            ..Entry::rand()
+        };
+        db.insert_entry(&entry).await.unwrap();
+        locs.push(entry.dht_loc());
+    }
+    locs.sort_unstable();
+
+    let start = Timestamp(0);
+    let end = Timestamp::now();
+    assert_eq!(db.count_entries(0, u32::MAX, start, end).await.unwrap(), 4);
+    assert_eq!(
+        db.count_entries(locs[1], locs[2], start, end)
+            .await
+            .unwrap(),
+        2
+    );
+    // wraps round from u32::MAX to 0
+    assert_eq!(
+        db.count_entries(locs[3], locs[0], start, end)
+            .await
+            .unwrap(),
+        2
+    );
+    assert_eq!(
+        db.count_entries(0, u32::MAX, start, start).await.unwrap(),
+        0
+    );
+
+    let entry = Entry::rand();
+    assert!(!db.entry_exists(&entry.hash).await.unwrap());
+    db.insert_entry(&entry).await.unwrap();
+    assert!(db.entry_exists(&entry.hash).await.unwrap());
+
+    db.close().await.unwrap();
+}
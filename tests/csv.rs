@@ -0,0 +1,71 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn csv_dumps_tables() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    let entries: Vec<Entry> = (0..10).map(|_| Entry::rand()).collect();
+    db.insert_entries(&entries).await.unwrap();
+    let mut op = DhtOp::rand();
+    op.basis_loc = 1234;
+    db.insert_op(&op).await.unwrap();
+
+    let mut out = Vec::new();
+    assert_eq!(
+        db.export_csv(CsvTable::Entries, &mut out).await.unwrap(),
+        10
+    );
+    let text = String::from_utf8(out).unwrap();
+    let mut lines = text.split("\r\n");
+    assert_eq!(
+        lines.next().unwrap(),
+        "hash,dht_loc,created_at,content,entry_type,size_bytes"
+    );
+    let first: Vec<&str> = lines.next().unwrap().split(',').collect();
+    assert_eq!(first[0], hex(entries[0].hash.get_raw_39()));
+    assert_eq!(first[1], entries[0].dht_loc().to_string());
+    assert_eq!(first[2], entries[0].created_at.0.to_string());
+
+    // only the columns asked for, in that order, NULL as empty
+    let mut out = Vec::new();
+    let export = CsvTable::DhtOps.columns(vec!["basis_loc", "when_integrated", "op_type"]);
+    assert_eq!(db.export_csv(export, &mut out).await.unwrap(), 1);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "basis_loc,when_integrated,op_type\r\n1234,,StoreEntry\r\n"
+    );
+
+    let mut out = Vec::new();
+    let export = CsvTable::Entries.columns(vec!["hash; DROP TABLE entries"]);
+    assert!(db.export_csv(export, &mut out).await.is_err());
+    assert!(out.is_empty());
+
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn csv_escapes_awkward_text() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    db.insert_cap_grant(&CapGrant {
+        header_hash: HeaderHash::rand(),
+        tag: "said \"hi\", twice\nthen left".to_string(),
+        access: CapAccess::Unrestricted,
+        functions: vec![GrantedFunction::new("posts", "read")],
+    })
+    .await
+    .unwrap();
+
+    let mut out = Vec::new();
+    db.export_csv(CsvTable::CapGrants.columns(vec!["tag", "access"]), &mut out)
+        .await
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "tag,access\r\n\"said \"\"hi\"\", twice\nthen left\",Unrestricted\r\n"
+    );
+
+    db.close().await.unwrap();
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,90 @@
+use chrono::prelude::*;
+use spike_sqlx::*;
+use sqlx::Connection;
+
+/// Either side of the i32 sign boundary, and both ends of the space.
+const EDGES: [u32; 4] = [0, i32::MAX as u32, i32::MAX as u32 + 1, u32::MAX];
+
+#[tokio::test(flavor = "multi_thread")]
+async fn locations_bind_as_non_negative_integers() {
+    let mut con = sqlx::SqliteConnection::connect("sqlite::memory:")
+        .await
+        .unwrap();
+    for loc in EDGES.iter() {
+        let (raw, kind, back): (i64, String, DhtLocation) =
+            sqlx::query_as("SELECT ?1, typeof(?1), ?1")
+                .bind(DhtLocation(*loc))
+                .fetch_one(&mut con)
+                .await
+                .unwrap();
+        assert_eq!(raw, i64::from(*loc));
+        assert_eq!(kind, "integer");
+        assert_eq!(back, DhtLocation(*loc));
+    }
+    // out of range values are refused rather than wrapped
+    for sql in &["SELECT -1", "SELECT 4294967296"] {
+        assert!(sqlx::query_as::<_, (DhtLocation,)>(sql)
+            .fetch_one(&mut con)
+            .await
+            .is_err());
+    }
+    con.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn arc_queries_span_the_sign_boundary() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    for loc in EDGES.iter() {
+        let op = DhtOp {
+            basis_loc: *loc,
+            when_integrated: Some(Utc::now()),
+            ..DhtOp::rand()
+        };
+        db.insert_op(&op).await.unwrap();
+        assert_eq!(
+            db.get_op(&op.op_hash).await.unwrap().unwrap().basis_loc,
+            *loc
+        );
+    }
+
+    let start = Utc.ymd(1970, 1, 1).and_hms(0, 0, 0);
+    let locs = |ops: Vec<DhtOp>| {
+        let mut locs: Vec<u32> = ops.into_iter().map(|op| op.basis_loc).collect();
+        locs.sort_unstable();
+        locs
+    };
+    assert_eq!(
+        locs(
+            db.query_ops(
+                None,
+                i32::MAX as u32,
+                i32::MAX as u32 + 1,
+                start,
+                Utc::now()
+            )
+            .await
+            .unwrap()
+        ),
+        vec![i32::MAX as u32, i32::MAX as u32 + 1]
+    );
+    assert_eq!(
+        locs(
+            db.query_ops(None, i32::MAX as u32 + 1, u32::MAX, start, Utc::now())
+                .await
+                .unwrap()
+        ),
+        vec![i32::MAX as u32 + 1, u32::MAX]
+    );
+    // wraps from the top of the space back round to 0
+    assert_eq!(
+        locs(
+            db.query_ops(None, u32::MAX, 0, start, Utc::now())
+                .await
+                .unwrap()
+        ),
+        vec![0, u32::MAX]
+    );
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,245 @@
+use chrono::prelude::*;
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ops_integrate_then_show_up_in_queries() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let op = DhtOp {
+        basis_loc: 100,
+        dependency: Some(OpHash::rand()),
+        ..DhtOp::rand()
+    };
+    db.insert_op(&op).await.unwrap();
+    let other = DhtOp {
+        basis_loc: 200,
+        ..DhtOp::rand()
+    };
+    db.insert_op(&other).await.unwrap();
+
+    let fetched = db.get_op(&op.op_hash).await.unwrap().unwrap();
+    assert_eq!(fetched.op_type, DhtOpType::StoreEntry);
+    assert_eq!(fetched.dependency, op.dependency);
+    assert_eq!(fetched.validation_status, ValidationStatus::Pending);
+    assert!(db.get_op(&OpHash::rand()).await.unwrap().is_none());
+
+    let start = Utc.ymd(1970, 1, 1).and_hms(0, 0, 0);
+    let end = Utc::now();
+    assert!(db
+        .query_ops(None, 0, u32::MAX, start, end)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(db.ops_pending_integration().await.unwrap().len(), 2);
+
+    db.integrate_op(&op.op_hash, ValidationStatus::Valid)
+        .await
+        .unwrap();
+    assert!(db
+        .integrate_op(&OpHash::rand(), ValidationStatus::Valid)
+        .await
+        .is_err());
+
+    let pending = db.ops_pending_integration().await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].op_hash, other.op_hash);
+
+    let held = db.query_ops(None, 0, 150, start, Utc::now()).await.unwrap();
+    assert_eq!(held.len(), 1);
+    assert_eq!(held[0].op_hash, op.op_hash);
+    assert_eq!(held[0].validation_status, ValidationStatus::Valid);
+    assert!(held[0].when_integrated.is_some());
+
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn validation_status_updates() {
+    let db = Db::open_with("sqlite::memory:", DbConfig::new().explain_queries(true))
+        .await
+        .unwrap();
+
+    let mut ops = Vec::new();
+    for minutes in 0..4 {
+        let op = DhtOp {
+            authored_timestamp: Utc::now() - chrono::Duration::minutes(10 - minutes),
+            ..DhtOp::rand()
+        };
+        db.insert_op(&op).await.unwrap();
+        ops.push(op);
+    }
+    let awaiting = db.ops_awaiting_validation().await.unwrap();
+    let hashes: Vec<_> = awaiting.into_iter().map(|op| op.op_hash).collect();
+    let expected: Vec<_> = ops.iter().map(|op| op.op_hash.clone()).collect();
+    assert_eq!(hashes, expected);
+
+    db.set_validation_status(&ops[0].op_hash, ValidationStatus::Abandoned)
+        .await
+        .unwrap();
+    assert!(db
+        .set_validation_status(&OpHash::rand(), ValidationStatus::Valid)
+        .await
+        .is_err());
+    let batch = vec![
+        ops[1].op_hash.clone(),
+        ops[2].op_hash.clone(),
+        OpHash::rand(),
+    ];
+    assert_eq!(
+        db.set_validation_statuses(&batch, ValidationStatus::Rejected)
+            .await
+            .unwrap(),
+        2
+    );
+
+    let awaiting = db.ops_awaiting_validation().await.unwrap();
+    assert_eq!(awaiting.len(), 1);
+    assert_eq!(awaiting[0].op_hash, ops[3].op_hash);
+    let fetched = db.get_op(&ops[1].op_hash).await.unwrap().unwrap();
+    assert_eq!(fetched.validation_status, ValidationStatus::Rejected);
+    // validated, but still up to integration
+    assert_eq!(db.ops_pending_integration().await.unwrap().len(), 4);
+
+    let rejected = db
+        .ops_with_status(ValidationStatus::Rejected)
+        .await
+        .unwrap();
+    assert_eq!(rejected.len(), 2);
+    assert!(rejected
+        .windows(2)
+        .all(|w| w[0].authored_timestamp <= w[1].authored_timestamp));
+    assert!(db
+        .ops_with_status(ValidationStatus::Valid)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let plan = db
+        .explained_queries()
+        .into_iter()
+        .find(|query| query.sql.contains("validation_status = 0"))
+        .unwrap();
+    assert!(!plan.full_scan, "{:#?}", plan);
+
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn query_ops_by_type() {
+    let db = Db::open_with("sqlite::memory:", DbConfig::new().explain_queries(true))
+        .await
+        .unwrap();
+
+    let types = [
+        DhtOpType::StoreEntry,
+        DhtOpType::RegisterAddLink,
+        DhtOpType::RegisterAddLink,
+        DhtOpType::RegisterAgentActivity,
+    ];
+    for op_type in types.iter() {
+        let op = DhtOp {
+            op_type: *op_type,
+            basis_loc: 100,
+            when_integrated: Some(Utc::now()),
+            ..DhtOp::rand()
+        };
+        db.insert_op(&op).await.unwrap();
+    }
+
+    let start = Utc.ymd(1970, 1, 1).and_hms(0, 0, 0);
+    let links = db
+        .query_ops(Some(DhtOpType::RegisterAddLink), 0, 200, start, Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(links.len(), 2);
+    assert!(links
+        .iter()
+        .all(|op| op.op_type == DhtOpType::RegisterAddLink));
+    // wrapping arcs filter by type too
+    assert_eq!(
+        db.query_ops(
+            Some(DhtOpType::StoreEntry),
+            u32::MAX - 10,
+            200,
+            start,
+            Utc::now()
+        )
+        .await
+        .unwrap()
+        .len(),
+        1
+    );
+    assert!(db
+        .query_ops(
+            Some(DhtOpType::StoreElement),
+            0,
+            u32::MAX,
+            start,
+            Utc::now()
+        )
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        db.query_ops(None, 0, 200, start, Utc::now())
+            .await
+            .unwrap()
+            .len(),
+        4
+    );
+
+    let plan = db
+        .explained_queries()
+        .into_iter()
+        .find(|query| query.sql.contains("op_type = ?") && !query.sql.contains(" OR "))
+        .unwrap();
+    assert!(
+        plan.plan.iter().any(|d| d.contains("dht_ops_type_idx")),
+        "{:#?}",
+        plan
+    );
+
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn batch_status_update_past_the_parameter_limit() {
+    let db = Db::open_with("sqlite::memory:", DbConfig::new().explain_queries(true))
+        .await
+        .unwrap();
+    let mut batch = Vec::new();
+    for _ in 0..1500 {
+        let op = DhtOp::rand();
+        db.insert_op(&op).await.unwrap();
+        batch.push(op.op_hash);
+    }
+    // repeats and misses are fine
+    batch.push(batch[0].clone());
+    batch.push(OpHash::rand());
+    assert_eq!(
+        db.set_validation_statuses(&batch, ValidationStatus::Valid)
+            .await
+            .unwrap(),
+        1500
+    );
+    assert!(db.ops_awaiting_validation().await.unwrap().is_empty());
+    assert_eq!(
+        db.set_validation_statuses(&[], ValidationStatus::Valid)
+            .await
+            .unwrap(),
+        0
+    );
+
+    let plan = db
+        .explained_queries()
+        .into_iter()
+        .find(|query| query.sql.contains("json_each"))
+        .unwrap();
+    assert!(
+        plan.plan.iter().any(|d| d.contains("dht_ops USING")),
+        "{:#?}",
+        plan
+    );
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use chrono::prelude::*;
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dump_round_trips() {
+    let dir = common::temp_dir();
+
+    let from = Db::open("sqlite::memory:").await.unwrap();
+    let entries: Vec<Entry> = (0..50).map(|_| Entry::rand()).collect();
+    from.insert_entries(&entries).await.unwrap();
+    let header = Header::rand(entries[0].hash.clone());
+    from.insert_header(&header).await.unwrap();
+    let mut op = DhtOp::rand();
+    op.when_integrated = Some(Utc::now());
+    op.validation_status = ValidationStatus::Valid;
+    from.insert_op(&op).await.unwrap();
+    from.insert_receipt(&ValidationReceipt::rand(op.op_hash.clone()))
+        .await
+        .unwrap();
+    let link = Link::rand(entries[1].hash.clone(), b"tag".to_vec());
+    from.insert_link(&link).await.unwrap();
+    from.put_agent_info(&AgentInfo::rand()).await.unwrap();
+    let grant = CapGrant {
+        header_hash: HeaderHash::rand(),
+        tag: "test".to_string(),
+        access: CapAccess::Assigned {
+            secret: CapSecret::rand(),
+            assignees: vec![AgentPubKey::rand()],
+        },
+        functions: vec![GrantedFunction::new("posts", "read")],
+    };
+    from.insert_cap_grant(&grant).await.unwrap();
+
+    let info = from.dump(dir.path().join("a.dump")).await.unwrap();
+    assert!(info.schema_version > 0);
+    let rows = |name: &str| info.tables.iter().find(|t| t.name == name).unwrap().rows;
+    assert_eq!(rows("entries"), 50);
+    assert_eq!(rows("validation_receipts"), 1);
+    assert_eq!(rows("cap_grant_assignees"), 1);
+    assert_eq!(rows("cap_claims"), 0);
+    assert!(info.tables.iter().all(|t| t.name != "snapshots"));
+    // never over an existing file
+    assert!(from.dump(dir.path().join("a.dump")).await.is_err());
+
+    let to = Db::open("sqlite::memory:").await.unwrap();
+    let outcome = to.load(dir.path().join("a.dump")).await.unwrap();
+    let total: u64 = info.tables.iter().map(|t| t.rows).sum();
+    assert_eq!(outcome.inserted, total);
+    let fetched = to.get_entry(&entries[7].hash).await.unwrap().unwrap();
+    assert_eq!(fetched.content, entries[7].content);
+    assert_eq!(fetched.created_at, entries[7].created_at);
+    let loaded = to.get_op(&op.op_hash).await.unwrap().unwrap();
+    assert_eq!(loaded.when_integrated, op.when_integrated);
+    assert_eq!(to.count_receipts(&op.op_hash).await.unwrap(), 1);
+    assert_eq!(to.get_links(&link.base_hash, b"").await.unwrap().len(), 1);
+
+    // the same data dumps to the same bytes
+    to.dump(dir.path().join("b.dump")).await.unwrap();
+    assert_eq!(
+        std::fs::read(dir.path().join("a.dump")).unwrap(),
+        std::fs::read(dir.path().join("b.dump")).unwrap()
+    );
+
+    let outcome = to.load(dir.path().join("a.dump")).await.unwrap();
+    assert_eq!(outcome.ignored, total);
+    assert!(!outcome.changed());
+
+    from.close().await.unwrap();
+    to.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn broken_dumps_load_nothing() {
+    let dir = common::temp_dir();
+
+    let from = Db::open("sqlite::memory:").await.unwrap();
+    let entries: Vec<Entry> = (0..10).map(|_| Entry::rand()).collect();
+    from.insert_entries(&entries).await.unwrap();
+    from.insert_op(&DhtOp::rand()).await.unwrap();
+    from.dump(dir.path().join("full.dump")).await.unwrap();
+    let bytes = std::fs::read(dir.path().join("full.dump")).unwrap();
+
+    let to = Db::open("sqlite::memory:").await.unwrap();
+    std::fs::write(dir.path().join("short.dump"), &bytes[..bytes.len() - 5]).unwrap();
+    let err = to.load(dir.path().join("short.dump")).await.unwrap_err();
+    assert!(err.to_string().contains("dht_ops row 1"), "{}", err);
+    assert!(!to.entry_exists(&entries[0].hash).await.unwrap());
+
+    let mut long = bytes.clone();
+    long.extend_from_slice(&bytes[bytes.len() - 20..]);
+    std::fs::write(dir.path().join("long.dump"), long).unwrap();
+    assert!(to.load(dir.path().join("long.dump")).await.is_err());
+
+    std::fs::write(dir.path().join("other.dump"), b"not a dump at all").unwrap();
+    assert!(to.load(dir.path().join("other.dump")).await.is_err());
+    assert!(!to.entry_exists(&entries[0].hash).await.unwrap());
+
+    from.close().await.unwrap();
+    to.close().await.unwrap();
+}
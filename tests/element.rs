@@ -0,0 +1,124 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_element_joins_entry() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let entry = Entry::rand();
+    db.insert_entry(&entry).await.unwrap();
+    let header = Header::rand(entry.hash.clone());
+    db.insert_header(&header).await.unwrap();
+
+    let element = db.get_element(&header.hash).await.unwrap().unwrap();
+    assert_eq!(element.header.author, header.author);
+    assert_eq!(element.header.seq, header.seq);
+    assert_eq!(element.header.prev_hash, header.prev_hash);
+    assert_eq!(element.header.header_type, HeaderType::Create);
+    assert_eq!(element.header.timestamp, header.timestamp);
+    let fetched = element.entry.unwrap();
+    assert_eq!(fetched.hash, entry.hash);
+    assert_eq!(fetched.dht_loc(), entry.dht_loc());
+
+    // headers without an entry still come back, just on their own
+    let dna = Header {
+        seq: 0,
+        prev_hash: None,
+        entry_hash: None,
+        header_type: HeaderType::Dna,
+        ..Header::rand(EntryHash::rand())
+    };
+    db.insert_header(&dna).await.unwrap();
+    let element = db.get_element(&dna.hash).await.unwrap().unwrap();
+    assert_eq!(element.header.header_type, HeaderType::Dna);
+    assert!(element.entry.is_none());
+
+    assert!(db.get_element(&HeaderHash::rand()).await.unwrap().is_none());
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn query_by_author_in_chain_order() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let author = AgentPubKey::rand();
+    // written out of order, and interleaved with another agent's chain
+    for seq in &[3u32, 0, 2, 1, 4] {
+        let entry = Entry::rand();
+        db.insert_entry(&entry).await.unwrap();
+        let header = Header {
+            author: author.clone(),
+            seq: *seq,
+            ..Header::rand(entry.hash)
+        };
+        db.insert_header(&header).await.unwrap();
+        let other = Header {
+            author: AgentPubKey::rand(),
+            seq: *seq,
+            entry_hash: None,
+            ..Header::rand(EntryHash::rand())
+        };
+        db.insert_header(&other).await.unwrap();
+    }
+
+    let chain = db.query_by_author(&author, 1, 3).await.unwrap();
+    assert_eq!(
+        chain.iter().map(|e| e.header.seq).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+    assert!(chain.iter().all(|e| e.header.author == author));
+    assert!(chain.iter().all(|e| e.entry.is_some()));
+
+    assert_eq!(
+        db.query_by_author(&author, 0, u32::MAX)
+            .await
+            .unwrap()
+            .len(),
+        5
+    );
+    assert!(db
+        .query_by_author(&AgentPubKey::rand(), 0, u32::MAX)
+        .await
+        .unwrap()
+        .is_empty());
+
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn chain_head_is_highest_seq() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let author = AgentPubKey::rand();
+    assert!(db.chain_head(&author).await.unwrap().is_none());
+
+    let mut headers = Vec::new();
+    for seq in &[0u32, 2, 1] {
+        let header = Header {
+            author: author.clone(),
+            seq: *seq,
+            entry_hash: None,
+            ..Header::rand(EntryHash::rand())
+        };
+        db.insert_header(&header).await.unwrap();
+        headers.push(header);
+    }
+    assert_eq!(
+        db.chain_head(&author).await.unwrap(),
+        Some((2, headers[1].hash.clone()))
+    );
+
+    // a second header at a taken position is a fork, and refused
+    let fork = Header {
+        author: author.clone(),
+        seq: 2,
+        entry_hash: None,
+        ..Header::rand(EntryHash::rand())
+    };
+    assert!(db.insert_header(&fork).await.is_err());
+    assert_eq!(
+        db.chain_head(&author).await.unwrap(),
+        Some((2, headers[1].hash.clone()))
+    );
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use spike_sqlx::*;
+
+fn keyed(key: [u8; 32]) -> DbConfig {
+    DbConfig::new().encryption(Encryption::SqlCipher(KeySource::Raw(SecretKey::new(key))))
+}
+
+async fn journal_mode(db: &Db) -> String {
+    db.inspect()
+        .await
+        .unwrap()
+        .pragmas
+        .into_iter()
+        .find(|(name, _)| name == "journal_mode")
+        .unwrap()
+        .1
+}
+
+// sqlx's own pragmas read the file on every new connection, so the
+// key has to be in place before them or reopening fails
+#[tokio::test(flavor = "multi_thread")]
+async fn reopens_encrypted_wal_database() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let db = Db::open_with(&path, keyed([3; 32])).await.unwrap();
+    let entry = Entry::rand();
+    db.insert_entry(&entry).await.unwrap();
+    assert_eq!(journal_mode(&db).await, "wal");
+    db.close().await.unwrap();
+
+    let db = Db::open_with(&path, keyed([3; 32])).await.unwrap();
+    assert!(db.entry_exists(&entry.hash).await.unwrap());
+    assert_eq!(journal_mode(&db).await, "wal");
+    db.insert_entry(&Entry::rand()).await.unwrap();
+    db.close().await.unwrap();
+}
+
+// and with the wal still there, from a handle that wasn't closed
+#[tokio::test(flavor = "multi_thread")]
+async fn second_handle_opens_encrypted_wal_database() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let db = Db::open_with(&path, keyed([3; 32])).await.unwrap();
+    let entry = Entry::rand();
+    db.insert_entry(&entry).await.unwrap();
+
+    let other = Db::open_with(&path, keyed([3; 32])).await.unwrap();
+    assert!(other.entry_exists(&entry.hash).await.unwrap());
+    other.close().await.unwrap();
+    db.close().await.unwrap();
+}
+
+// the cipher settings go in with the key as the file is opened, so a
+// database created with a non-default page size opens again with it
+#[tokio::test(flavor = "multi_thread")]
+async fn cipher_page_size_survives_reopen() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let config = keyed([4; 32]).cipher_page_size(8192);
+    let db = Db::open_with(&path, config.clone()).await.unwrap();
+    let entry = Entry::rand();
+    db.insert_entry(&entry).await.unwrap();
+    let sqlcipher = db.cipher_version().await.unwrap().is_some();
+    db.close().await.unwrap();
+
+    let db = Db::open_with(&path, config).await.unwrap();
+    assert!(db.entry_exists(&entry.hash).await.unwrap());
+    db.close().await.unwrap();
+
+    if sqlcipher {
+        // the page size is part of the format, the default can't read it
+        let err = Db::open_with(&path, keyed([4; 32])).await.err().unwrap();
+        assert!(err.downcast_ref::<DbError>().is_some(), "{}", err);
+    }
+}
+
+// only a key SQLCipher can't read the file with is a bad key, stock
+// sqlite ignores keys so there it's down to the file
+#[tokio::test(flavor = "multi_thread")]
+async fn garbage_file_is_corrupt_not_a_bad_key() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    std::fs::write(&path, vec![0x5a; 8192]).unwrap();
+    let memory = Db::open("sqlite::memory:").await.unwrap();
+    let sqlcipher = memory.cipher_version().await.unwrap().is_some();
+    memory.close().await.unwrap();
+
+    let err = Db::open_with(&path, keyed([5; 32])).await.err().unwrap();
+    match err.downcast_ref::<DbError>() {
+        // SQLCipher can't tell a file it can't decrypt from one that
+        // isn't a database at all
+        Some(DbError::BadEncryptionKey) if sqlcipher => {}
+        Some(DbError::Corrupt(_)) if !sqlcipher => {}
+        _ => panic!("unexpected error: {}", err),
+    }
+}
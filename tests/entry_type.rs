@@ -0,0 +1,83 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn entries_filter_by_type() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let post = EntryType::App {
+        zome_index: 1,
+        entry_def_index: 2,
+    };
+    let types = [EntryType::Agent, post, EntryType::CapGrant, post];
+    for entry_type in &types {
+        let entry = Entry {
+            entry_type: *entry_type,
+            ..Entry::rand()
+        };
+        db.insert_entry(&entry).await.unwrap();
+        assert_eq!(
+            db.get_entry(&entry.hash).await.unwrap().unwrap().entry_type,
+            *entry_type
+        );
+    }
+
+    let posts = db
+        .filter_entries(&EntryFilter::new().entry_type(post))
+        .await
+        .unwrap();
+    assert_eq!(posts.len(), 2);
+    assert!(posts.iter().all(|e| e.entry_type == post));
+    assert!(db
+        .filter_entries(&EntryFilter::new().entry_type(EntryType::CapClaim))
+        .await
+        .unwrap()
+        .is_empty());
+
+    // the indices are part of the type
+    let other = EntryType::App {
+        zome_index: 2,
+        entry_def_index: 1,
+    };
+    assert!(db
+        .filter_entries(&EntryFilter::new().entry_type(other))
+        .await
+        .unwrap()
+        .is_empty());
+
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn bytes_held_by_loc_range() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let mut entries: Vec<_> = (1..=4)
+        .map(|i| Entry::from_content(vec![i as u8; i * 10]))
+        .collect();
+    db.insert_entries(&entries).await.unwrap();
+    entries.sort_by_key(Entry::dht_loc);
+
+    assert_eq!(db.bytes_held(&EntryFilter::new()).await.unwrap(), 100);
+    let (first, last) = (entries[0].dht_loc(), entries[3].dht_loc());
+    assert_eq!(
+        db.bytes_held(&EntryFilter::new().loc_range(entries[1].dht_loc(), entries[2].dht_loc()))
+            .await
+            .unwrap(),
+        (entries[1].size_bytes() + entries[2].size_bytes()) as u64
+    );
+    // wraps round from u32::MAX to 0
+    assert_eq!(
+        db.bytes_held(&EntryFilter::new().loc_range(last, first))
+            .await
+            .unwrap(),
+        (entries[0].size_bytes() + entries[3].size_bytes()) as u64
+    );
+    assert_eq!(
+        db.bytes_held(&EntryFilter::new().entry_type(EntryType::Agent))
+            .await
+            .unwrap(),
+        0
+    );
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,49 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn explain_flags_full_scans() {
+    let db = Db::open_with("sqlite::memory:", DbConfig::new().explain_queries(true))
+        .await
+        .unwrap();
+    let entry = Entry::rand();
+    db.insert_entry(&entry).await.unwrap();
+
+    db.query_entries(0, 1000, Timestamp(0), Timestamp::now())
+        .await
+        .unwrap();
+    db.get_entry(&entry.hash).await.unwrap();
+    db.get_entries(std::slice::from_ref(&entry.hash))
+        .await
+        .unwrap();
+    // twice, but only explained once
+    db.filter_entries(&EntryFilter::new()).await.unwrap();
+    db.filter_entries(&EntryFilter::new()).await.unwrap();
+
+    let explained = db.explained_queries();
+    assert_eq!(explained.len(), 4, "{:#?}", explained);
+    let find = |needle: &str| {
+        explained
+            .iter()
+            .find(|query| query.sql.contains(needle))
+            .unwrap()
+    };
+    assert!(explained.iter().all(|query| !query.plan.is_empty()));
+    assert!(!find("WHERE hash = ").full_scan);
+    let range = find("dht_loc >= ");
+    assert!(!range.full_scan);
+    assert!(range.plan.iter().any(|d| d.contains("entries_query_idx")));
+    // no filter has to read everything
+    let unfiltered = explained
+        .iter()
+        .find(|query| !query.sql.contains("WHERE") && !query.sql.contains("JOIN"))
+        .unwrap();
+    assert!(unfiltered.full_scan);
+
+    db.close().await.unwrap();
+
+    // off by default
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    db.get_entry(&entry.hash).await.unwrap();
+    assert!(db.explained_queries().is_empty());
+    db.close().await.unwrap();
+}
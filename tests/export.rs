@@ -0,0 +1,76 @@
+mod common;
+
+use spike_sqlx::*;
+use sqlx::{Connection, SqliteConnection};
+use std::path::Path;
+
+fn keyed() -> DbConfig {
+    DbConfig::new().encryption(Encryption::SqlCipher(KeySource::Raw(SecretKey::new(
+        [7; 32],
+    ))))
+}
+
+/// Every table's rows, leaving out sqlite's own statistics, which
+/// opening the copy may refresh.
+async fn row_counts(db: &Db) -> Vec<(String, u64)> {
+    db.stats()
+        .await
+        .unwrap()
+        .tables
+        .into_iter()
+        .filter(|table| !table.name.starts_with("sqlite_"))
+        .map(|table| (table.name, table.rows))
+        .collect()
+}
+
+async fn round_trip(dir: &Path, config: DbConfig) {
+    let path = dir.join("db.sqlite3");
+    let plaintext = dir.join("plain.sqlite3");
+    let imported = dir.join("imported.sqlite3");
+
+    let db = Db::open_with(&path, config.clone()).await.unwrap();
+    let entries: Vec<Entry> = (0..100).map(|_| Entry::rand()).collect();
+    db.insert_entries(&entries).await.unwrap();
+    db.export_plaintext(&plaintext).await.unwrap();
+    assert!(db.export_plaintext(&plaintext).await.is_err());
+
+    // readable by any sqlite, no key needed
+    let mut con = SqliteConnection::connect(&format!("sqlite://{}", plaintext.display()))
+        .await
+        .unwrap();
+    let (count,): (i64,) = sqlx::query_as("SELECT count(*) FROM entries;")
+        .fetch_one(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(count, 100);
+    con.close().await.unwrap();
+
+    let copy = Db::import_plaintext(&plaintext, &imported, config)
+        .await
+        .unwrap();
+    assert_eq!(row_counts(&copy).await, row_counts(&db).await);
+    for entry in &entries {
+        let got = copy.get_entry(&entry.hash).await.unwrap().unwrap();
+        assert_eq!(got.created_at, entry.created_at);
+        assert_eq!(got.entry_type, entry.entry_type);
+        assert_eq!(got.content, entry.content);
+    }
+    // and it's a working database, not just a copy of the rows
+    copy.insert_entry(&Entry::rand()).await.unwrap();
+    copy.close().await.unwrap();
+    db.close().await.unwrap();
+}
+
+// through sqlcipher_export, or VACUUM INTO where sqlite is plain
+#[tokio::test(flavor = "multi_thread")]
+async fn keyed_round_trip() {
+    let dir = common::temp_dir();
+    round_trip(dir.path(), keyed()).await;
+}
+
+// always VACUUM INTO
+#[tokio::test(flavor = "multi_thread")]
+async fn plaintext_round_trip() {
+    let dir = common::temp_dir();
+    round_trip(dir.path(), DbConfig::new().encryption(Encryption::None)).await;
+}
@@ -0,0 +1,81 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn filters_compose() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let author = AgentPubKey::from_raw_32([0xaa; 32]);
+    let h = |i: u8| EntryHash::from_raw_32([i; 32]);
+    let t0 = Timestamp::now();
+    let mut entries = Vec::new();
+    for i in 0..10u32 {
+        let entry = Entry {
+            hash: h(i as u8),
+            created_at: t0 + chrono::Duration::seconds(i as i64),
+            ..Entry::rand()
+        };
+        db.insert_entry(&entry).await.unwrap();
+        // every other entry is ours
+        if i % 2 == 0 {
+            let header = Header {
+                author: author.clone(),
+                ..Header::rand(entry.hash.clone())
+            };
+            db.insert_header(&header).await.unwrap();
+        }
+        entries.push(entry);
+    }
+    let hashes = |entries: Vec<Entry>| entries.into_iter().map(|e| e.hash).collect::<Vec<_>>();
+    let mut by_loc = entries.clone();
+    by_loc.sort_by_key(|e| e.dht_loc());
+
+    assert_eq!(
+        db.filter_entries(&EntryFilter::new()).await.unwrap().len(),
+        10
+    );
+    assert_eq!(
+        hashes(
+            db.filter_entries(
+                &EntryFilter::new()
+                    .loc_range(by_loc[2].dht_loc(), by_loc[4].dht_loc())
+                    .order(EntryOrder::DhtLoc)
+            )
+            .await
+            .unwrap()
+        ),
+        hashes(by_loc[2..5].to_vec())
+    );
+    assert_eq!(
+        hashes(
+            db.filter_entries(
+                &EntryFilter::new()
+                    .time_range(t0, t0 + chrono::Duration::seconds(5))
+                    .author(author.clone())
+                    .order(EntryOrder::CreatedAtDesc)
+            )
+            .await
+            .unwrap()
+        ),
+        vec![h(4), h(2), h(0)]
+    );
+    assert_eq!(
+        hashes(
+            db.filter_entries(
+                &EntryFilter::new()
+                    .author(author.clone())
+                    .order(EntryOrder::CreatedAt)
+                    .limit(2)
+            )
+            .await
+            .unwrap()
+        ),
+        vec![h(0), h(2)]
+    );
+    assert!(db
+        .filter_entries(&EntryFilter::new().author(AgentPubKey::rand()))
+        .await
+        .unwrap()
+        .is_empty());
+
+    db.close().await.unwrap();
+}
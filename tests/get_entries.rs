@@ -0,0 +1,46 @@
+mod common;
+
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_by_hash_and_batch() {
+    // on disk, so readers really are read-only connections
+    let dir = common::temp_dir();
+    let db = Db::open(dir.path().join("db.sqlite3")).await.unwrap();
+
+    let mut held = Vec::new();
+    for i in 0..1500u32 {
+        let entry = Entry {
+            hash: EntryHash::with_data(&i.to_be_bytes()),
+            ..Entry::rand()
+        };
+        db.insert_entry(&entry).await.unwrap();
+        held.push(entry.hash);
+    }
+
+    let entry = db.get_entry(&held[7]).await.unwrap().unwrap();
+    assert_eq!(entry.hash, held[7]);
+    assert!(db.get_entry(&EntryHash::rand()).await.unwrap().is_none());
+
+    // more hashes than fit in one statement, with repeats and misses
+    let mut wanted: Vec<EntryHash> = held.iter().step_by(2).cloned().collect();
+    wanted.push(held[0].clone());
+    wanted.push(EntryHash::rand());
+    let mut got: Vec<_> = db
+        .get_entries(&wanted)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.hash)
+        .collect();
+    got.sort();
+    let mut expected: Vec<_> = held.iter().step_by(2).cloned().collect();
+    expected.sort();
+    assert_eq!(got, expected);
+
+    assert!(db.get_entries(&[]).await.unwrap().is_empty());
+    // the scratch table doesn't outlive the call
+    assert_eq!(db.get_entries(&held[..1]).await.unwrap().len(), 1);
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,34 @@
+use spike_sqlx::*;
+
+#[test]
+fn hashes_check_their_kind() {
+    let entry = EntryHash::with_data(b"hello");
+    assert_eq!(entry.get_raw_39().len(), HOLO_HASH_LEN);
+    assert_eq!(&entry.get_raw_39()[..3], &EntryHash::PREFIX);
+    assert_eq!(EntryHash::from_raw_39(entry.get_raw_39()).unwrap(), entry);
+    assert_eq!(EntryHash::with_data(b"hello"), entry);
+
+    // same bytes, wrong kind
+    assert!(HeaderHash::from_raw_39(entry.get_raw_39()).is_err());
+    assert!(EntryHash::from_raw_39(&entry.get_raw_39()[..32]).is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn entries_are_stored_at_their_hash_loc() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let entry = Entry::rand();
+    let loc = entry.hash.get_loc();
+    assert_eq!(entry.dht_loc(), loc);
+    assert_eq!(&loc.to_le_bytes()[..], &entry.hash.get_raw_39()[35..]);
+    db.insert_entry(&entry).await.unwrap();
+
+    let found = db
+        .filter_entries(&EntryFilter::new().loc_range(loc, loc))
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].hash, entry.hash);
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,50 @@
+#![cfg(unix)]
+
+mod common;
+
+use spike_sqlx::*;
+
+/// Swap a good copy in at `path`, leaving connections already open on
+/// a file that's no longer a database, as if it had been lost under
+/// them. New connections open the copy.
+fn break_open_connections(path: &std::path::Path) {
+    let copy = path.with_extension("copy");
+    std::fs::copy(path, &copy).unwrap();
+    // in place, so it's the file the open connections have
+    std::fs::write(path, vec![0xff; 4096]).unwrap();
+    std::fs::rename(&copy, path).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn broken_connections_are_replaced() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    // the rollback journal has every read check the file for changes,
+    // and with one reader there's only the broken one to hand out
+    let config = DbConfig::new()
+        .journal_mode(SqliteJournalMode::Delete)
+        .read_connections(1, 1);
+    let db = Db::open_with(&path, config.clone()).await.unwrap();
+    let entry = Entry::rand();
+    db.insert_entry(&entry).await.unwrap();
+    assert!(db.entry_exists(&entry.hash).await.unwrap());
+
+    break_open_connections(&path);
+    let got = db.get_entry(&entry.hash).await.unwrap().unwrap();
+    assert_eq!(got.content, entry.content);
+    // the writer too
+    let later = Entry::rand();
+    db.insert_entry(&later).await.unwrap();
+    assert!(db.entry_exists(&later.hash).await.unwrap());
+    db.close().await.unwrap();
+
+    // without the check the broken one is handed out as it is
+    let db = Db::open_with(&path, config.health_check_on_acquire(false))
+        .await
+        .unwrap();
+    assert!(db.entry_exists(&entry.hash).await.unwrap());
+    break_open_connections(&path);
+    assert!(db.get_entry(&entry.hash).await.is_err());
+    assert!(db.insert_entry(&Entry::rand()).await.is_err());
+    let _ = db.close().await;
+}
@@ -0,0 +1,79 @@
+use chrono::prelude::*;
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn histogram_buckets_count_and_xor() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let t0 = Utc.ymd(2021, 3, 1).and_hms(12, 0, 0);
+    let ops = [
+        (OpHash::rand(), 10, t0),
+        (
+            OpHash::rand(),
+            20,
+            t0 + chrono::Duration::milliseconds(59_999),
+        ),
+        (OpHash::rand(), 30, t0 + chrono::Duration::minutes(1)),
+        (OpHash::rand(), 40, t0 + chrono::Duration::minutes(3)),
+        // outside the arc
+        (OpHash::rand(), 1000, t0),
+    ];
+    let xor = |hashes: &[&OpHash]| {
+        let mut out = vec![0; HOLO_HASH_LEN];
+        for hash in hashes {
+            for (o, b) in out.iter_mut().zip(hash.get_raw_39()) {
+                *o ^= b;
+            }
+        }
+        out
+    };
+    for (op_hash, basis_loc, authored_timestamp) in ops.iter() {
+        let op = DhtOp {
+            op_hash: op_hash.clone(),
+            basis_loc: *basis_loc,
+            authored_timestamp: *authored_timestamp,
+            when_integrated: Some(Utc::now()),
+            ..DhtOp::rand()
+        };
+        db.insert_op(&op).await.unwrap();
+    }
+    // not integrated, so not held for gossip yet
+    db.insert_op(&DhtOp {
+        basis_loc: 10,
+        authored_timestamp: t0,
+        ..DhtOp::rand()
+    })
+    .await
+    .unwrap();
+
+    let histogram = db
+        .op_histogram(0, 100, chrono::Duration::minutes(1))
+        .await
+        .unwrap();
+    assert_eq!(
+        histogram,
+        vec![
+            HistogramBucket {
+                start: t0,
+                count: 2,
+                xor_hash: xor(&[&ops[0].0, &ops[1].0]),
+            },
+            HistogramBucket {
+                start: t0 + chrono::Duration::minutes(1),
+                count: 1,
+                xor_hash: xor(&[&ops[2].0]),
+            },
+            HistogramBucket {
+                start: t0 + chrono::Duration::minutes(3),
+                count: 1,
+                xor_hash: xor(&[&ops[3].0]),
+            },
+        ]
+    );
+
+    assert!(db
+        .op_histogram(0, 100, chrono::Duration::zero())
+        .await
+        .is_err());
+    db.close().await.unwrap();
+}
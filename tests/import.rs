@@ -0,0 +1,39 @@
+use futures::stream;
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn import_commits_in_chunks() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let held = Entry::rand();
+    db.insert_entry(&held).await.unwrap();
+
+    let mut entries: Vec<Entry> = (0..2500).map(|_| Entry::rand()).collect();
+    entries.insert(1234, held);
+    let mut reported = Vec::new();
+    let done = db
+        .import(stream::iter(entries.clone()), 1000, |progress| {
+            reported.push(progress)
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(
+        done,
+        ImportProgress {
+            received: 2501,
+            inserted: 2500,
+            chunks: 3,
+        }
+    );
+    let received: Vec<u64> = reported.iter().map(|p| p.received).collect();
+    assert_eq!(received, vec![1000, 2000, 2501]);
+    assert_eq!(
+        db.filter_entries(&EntryFilter::new()).await.unwrap().len(),
+        2501
+    );
+
+    assert!(db.import(stream::iter(entries), 0, |_| ()).await.is_err());
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn runtime_indexes() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+
+    let db = Db::open(&path).await.unwrap();
+    let before = db.list_indexes().await.unwrap();
+    assert!(before.iter().all(|index| index.from_migration));
+    assert!(before.iter().any(|index| index.name == "entries_query_idx"
+        && index.columns == vec!["dht_loc".to_string(), "created_at".to_string()]));
+
+    let spec = IndexSpec::new("entries_dht_loc_idx", "entries").column("dht_loc");
+    db.create_index(&spec).await.unwrap();
+    // already there is fine
+    db.create_index(&spec).await.unwrap();
+
+    let created = db
+        .list_indexes()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|index| index.name == "entries_dht_loc_idx")
+        .unwrap();
+    assert_eq!(created.table, "entries");
+    assert_eq!(created.columns, vec!["dht_loc".to_string()]);
+    assert!(!created.from_migration);
+
+    for bad in &[
+        IndexSpec::new("no_columns", "entries"),
+        IndexSpec::new("sqlite_mine", "entries").column("dht_loc"),
+        IndexSpec::new("x\"; DROP TABLE entries; --", "entries").column("dht_loc"),
+        IndexSpec::new("bad_column", "entries").column("dht_loc DESC"),
+    ] {
+        assert!(db.create_index(bad).await.is_err(), "{:?}", bad);
+    }
+    assert!(db.drop_index("entries_query_idx").await.is_err());
+
+    // runtime indexes survive a reopen, schema validation ignores them
+    db.close().await.unwrap();
+    let db = Db::open(&path).await.unwrap();
+    db.drop_index("entries_dht_loc_idx").await.unwrap();
+    assert_eq!(db.list_indexes().await.unwrap(), before);
+    db.close().await.unwrap();
+}
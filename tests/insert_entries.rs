@@ -0,0 +1,33 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn insert_entries_in_batches() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    // several statements' worth
+    let entries: Vec<Entry> = (0..1000).map(|_| Entry::rand()).collect();
+    db.insert_entries(&entries).await.unwrap();
+    db.insert_entries(&[]).await.unwrap();
+
+    let hashes: Vec<EntryHash> = entries.iter().map(|e| e.hash.clone()).collect();
+    let mut fetched: Vec<EntryHash> = db
+        .get_entries(&hashes)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|e| e.hash)
+        .collect();
+    fetched.sort();
+    let mut expected = hashes.clone();
+    expected.sort();
+    assert_eq!(fetched, expected);
+
+    // a duplicate anywhere rolls back the whole batch
+    let fresh: Vec<Entry> = (0..500).map(|_| Entry::rand()).collect();
+    let mut batch = fresh.clone();
+    batch.push(entries[0].clone());
+    assert!(db.insert_entries(&batch).await.is_err());
+    assert!(!db.entry_exists(&fresh[0].hash).await.unwrap());
+
+    db.close().await.unwrap();
+}
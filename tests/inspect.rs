@@ -0,0 +1,40 @@
+mod common;
+
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn inspection_covers_schema_sizes_and_pragmas() {
+    let dir = common::temp_dir();
+    let db = Db::open(dir.path().join("db.sqlite3")).await.unwrap();
+    for _ in 0..3 {
+        db.insert_entry(&Entry::rand()).await.unwrap();
+    }
+
+    let inspection = db.inspect().await.unwrap();
+    assert_eq!(inspection.schema_version, Some(inspection.latest_version));
+    assert!(inspection.latest_version > 0);
+    assert_eq!(inspection.stats.table("entries").unwrap().rows, 3);
+    assert!(inspection.stats.file_bytes > 0);
+
+    let entries = inspection
+        .objects
+        .iter()
+        .find(|object| object.kind == "table" && object.name == "entries")
+        .unwrap();
+    assert!(entries.sql.contains("CREATE TABLE"));
+    assert!(inspection.indexes("entries").count() > 0);
+    assert!(inspection
+        .indexes("entries")
+        .all(|index| index.table == "entries"));
+
+    assert_eq!(inspection.pragma("journal_mode"), Some("wal"));
+    assert_eq!(inspection.pragma("foreign_keys"), Some("1"));
+    // plain sqlite has no cipher_version to report
+    assert_eq!(inspection.pragma("cipher_version"), None);
+
+    let report = inspection.to_string();
+    assert!(report.starts_with(&format!("schema version {}", inspection.latest_version)));
+    assert!(report.contains("\nentries, 3 rows"));
+    assert!(report.contains("  journal_mode = wal\n"));
+    db.close().await.unwrap();
+}
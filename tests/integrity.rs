@@ -0,0 +1,78 @@
+mod common;
+
+use spike_sqlx::*;
+use std::io::{Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn damage_is_found() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let db = Db::open(&path).await.unwrap();
+    let entries: Vec<_> = (0..200).map(|_| Entry::rand()).collect();
+    db.insert_entries(&entries).await.unwrap();
+    for quick in [true, false] {
+        let report = db.integrity_check(quick).await.unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+        assert_eq!(report.quick, quick);
+    }
+
+    // a leaf page of one of the entries indexes, nothing opening reads
+    let rows = db
+        .read(|reader| {
+            Box::pin(async move {
+                reader
+                    .query(
+                        "SELECT d.pageno FROM dbstat d JOIN sqlite_master m ON m.name = d.name
+                        WHERE m.type = 'index' AND m.tbl_name = 'entries'
+                            AND d.pagetype = 'leaf'
+                        LIMIT 1;",
+                        &[],
+                    )
+                    .await
+            })
+        })
+        .await
+        .unwrap();
+    let page = match rows[0][0] {
+        Value::Integer(page) => page as u64,
+        ref other => panic!("{:?}", other),
+    };
+    let page_size = db.stats().await.unwrap().page_size;
+    db.close().await.unwrap();
+    let mut file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+    file.seek(SeekFrom::Start((page - 1) * page_size + 8))
+        .unwrap();
+    file.write_all(&vec![0xa5; page_size as usize - 8]).unwrap();
+    drop(file);
+
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let reported = failures.clone();
+    let config =
+        DbConfig::new().integrity_check_every(Duration::from_millis(20), false, move |failure| {
+            reported.lock().unwrap().push(failure)
+        });
+    let db = Db::open_with(&path, config).await.unwrap();
+    match db.integrity_check(false).await {
+        Ok(report) => assert!(!report.is_ok()),
+        Err(err) => assert!(
+            matches!(err.downcast_ref::<DbError>(), Some(DbError::Corrupt(_))),
+            "{:?}",
+            err
+        ),
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let failures = std::mem::take(&mut *failures.lock().unwrap());
+    assert!(!failures.is_empty());
+    match &failures[0] {
+        IntegrityFailure::Problems(report) => assert!(!report.quick && !report.is_ok()),
+        IntegrityFailure::Error(err) => assert!(
+            matches!(err.downcast_ref::<DbError>(), Some(DbError::Corrupt(_))),
+            "{:?}",
+            err
+        ),
+    }
+    // optimizing on close may trip over the damage too
+    let _ = db.close().await;
+}
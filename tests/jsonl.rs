@@ -0,0 +1,70 @@
+use chrono::prelude::*;
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn jsonl_dump_round_trips() {
+    let from = Db::open("sqlite::memory:").await.unwrap();
+    let entries: Vec<Entry> = (0..700).map(|_| Entry::rand()).collect();
+    from.insert_entries(&entries).await.unwrap();
+    let mut integrated = DhtOp::rand();
+    integrated.when_integrated = Some(Utc::now());
+    integrated.validation_status = ValidationStatus::Valid;
+    integrated.dependency = Some(OpHash::rand());
+    let pending = DhtOp::rand();
+    from.insert_op(&integrated).await.unwrap();
+    from.insert_op(&pending).await.unwrap();
+
+    let mut dump = Vec::new();
+    assert_eq!(
+        from.export_jsonl(&mut dump, &JsonlFilter::new())
+            .await
+            .unwrap(),
+        702
+    );
+    let text = String::from_utf8(dump.clone()).unwrap();
+    let first: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+    assert_eq!(first["table"], "entries");
+    assert!(text.lines().last().unwrap().contains("\"dht_ops\""));
+
+    let to = Db::open("sqlite::memory:").await.unwrap();
+    let outcome = to.import_jsonl(&dump[..]).await.unwrap();
+    assert_eq!(outcome.inserted, 702);
+    let fetched = to.get_entry(&entries[5].hash).await.unwrap().unwrap();
+    assert_eq!(fetched.content, entries[5].content);
+    assert_eq!(fetched.created_at, entries[5].created_at);
+    let op = to.get_op(&integrated.op_hash).await.unwrap().unwrap();
+    assert_eq!(op.validation_status, ValidationStatus::Valid);
+    assert_eq!(op.dependency, integrated.dependency);
+    assert_eq!(op.when_integrated, integrated.when_integrated);
+
+    // loading it again changes nothing
+    let outcome = to.import_jsonl(&dump[..]).await.unwrap();
+    assert_eq!(outcome.ignored, 702);
+    assert!(!outcome.changed());
+
+    from.close().await.unwrap();
+    to.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn jsonl_filter_and_bad_lines() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    let entries: Vec<Entry> = (0..20).map(|_| Entry::rand()).collect();
+    db.insert_entries(&entries).await.unwrap();
+    db.insert_op(&DhtOp::rand()).await.unwrap();
+
+    let mut dump = Vec::new();
+    let filter = JsonlFilter::new()
+        .entries(EntryFilter::new().limit(3))
+        .without_ops();
+    assert_eq!(db.export_jsonl(&mut dump, &filter).await.unwrap(), 3);
+    let mut ops = Vec::new();
+    let filter = JsonlFilter::new().without_entries();
+    assert_eq!(db.export_jsonl(&mut ops, &filter).await.unwrap(), 1);
+
+    let bad = b"\n{\"table\":\"entries\",\"hash\":\"not base64!\"}\n";
+    let err = db.import_jsonl(&bad[..]).await.unwrap_err();
+    assert!(err.to_string().starts_with("line 2:"), "{}", err);
+
+    db.close().await.unwrap();
+}
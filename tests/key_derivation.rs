@@ -0,0 +1,42 @@
+use spike_sqlx::*;
+
+fn kinds() -> Vec<DbKind> {
+    let dna_a = DnaHash(vec![1; 39]);
+    let dna_b = DnaHash(vec![2; 39]);
+    let agent_a = AgentPubKey::from_raw_32([3; 32]);
+    let agent_b = AgentPubKey::from_raw_32([4; 32]);
+    vec![
+        DbKind::Authored(dna_a.clone(), agent_a.clone()),
+        DbKind::Authored(dna_a.clone(), agent_b.clone()),
+        DbKind::Authored(dna_b.clone(), agent_a.clone()),
+        DbKind::Dht(dna_a.clone()),
+        DbKind::Dht(dna_b.clone()),
+        DbKind::Cache(dna_a),
+        DbKind::Cache(dna_b),
+        DbKind::Conductor,
+        // the agent is fixed length, but the dna length still counts
+        DbKind::Authored(DnaHash(vec![1, 0x84]), agent_a.clone()),
+        DbKind::Authored(DnaHash(vec![1]), agent_a),
+    ]
+}
+
+#[test]
+fn distinct_kinds_never_share_a_key() {
+    let kd = KeyDerivation::new(SecretKey::new([42; 32]));
+    let keys: Vec<_> = kinds().iter().map(|k| *kd.derive(k).as_bytes()).collect();
+    for (i, a) in keys.iter().enumerate() {
+        for b in &keys[i + 1..] {
+            assert_ne!(a, b);
+        }
+    }
+}
+
+#[test]
+fn derivation_is_deterministic_per_master() {
+    let kd = KeyDerivation::new(SecretKey::new([42; 32]));
+    let other = KeyDerivation::new(SecretKey::new([43; 32]));
+    for kind in kinds() {
+        assert_eq!(kd.derive(&kind).as_bytes(), kd.derive(&kind).as_bytes());
+        assert_ne!(kd.derive(&kind).as_bytes(), other.derive(&kind).as_bytes());
+    }
+}
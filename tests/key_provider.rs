@@ -0,0 +1,70 @@
+mod common;
+
+use spike_sqlx::*;
+
+const HEX: &str = "000102030405060708090A0B0C0D0E0F101112131415161718191a1b1c1d1e1f";
+
+fn expected() -> [u8; 32] {
+    let mut key = [0; 32];
+    for (i, b) in key.iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    key
+}
+
+#[tokio::test]
+async fn env_provider_reads_hex() {
+    std::env::set_var("SPIKE_SQLX_TEST_KEY", format!("{}\n", HEX));
+    let key = EnvKeyProvider::new("SPIKE_SQLX_TEST_KEY")
+        .get_key(None)
+        .await
+        .unwrap();
+    assert_eq!(key.as_bytes(), &expected());
+
+    assert!(EnvKeyProvider::new("SPIKE_SQLX_TEST_KEY_UNSET")
+        .get_key(None)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn file_provider_reads_raw_and_hex() {
+    let dir = common::temp_dir();
+
+    let raw = dir.path().join("raw.key");
+    std::fs::write(&raw, expected()).unwrap();
+    let key = FileKeyProvider::new(&raw).get_key(None).await.unwrap();
+    assert_eq!(key.as_bytes(), &expected());
+
+    let hex = dir.path().join("hex.key");
+    std::fs::write(&hex, HEX).unwrap();
+    let key = FileKeyProvider::new(&hex).get_key(None).await.unwrap();
+    assert_eq!(key.as_bytes(), &expected());
+
+    std::fs::write(&hex, "not a key").unwrap();
+    assert!(FileKeyProvider::new(&hex).get_key(None).await.is_err());
+}
+
+// sqlx's sqlite driver blocks in place, which needs the threaded runtime
+#[tokio::test(flavor = "multi_thread")]
+async fn custom_provider_opens_db() {
+    let source = KeySource::provider(StaticKeyProvider::new(SecretKey::new(expected())));
+    let config = DbConfig::new().encryption(Encryption::SqlCipher(source));
+    let db = Db::open_with("sqlite::memory:", config).await.unwrap();
+    db.insert_entry(&Entry::rand()).await.unwrap();
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn passphrase_opens_db() {
+    let open = |pass: &str| {
+        let config = DbConfig::new().encryption(Encryption::SqlCipher(KeySource::passphrase(pass)));
+        Db::open_with("sqlite::memory:", config)
+    };
+    // quotes must be escaped, not end the literal
+    let db = open("it's a 'secret'; DROP TABLE entries").await.unwrap();
+    db.insert_entry(&Entry::rand()).await.unwrap();
+    db.close().await.unwrap();
+
+    assert!(open("").await.is_err());
+}
@@ -0,0 +1,52 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_links_by_tag_prefix() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let base = EntryHash::rand();
+    let tags: &[&[u8]] = &[b"", b"a", b"ab", b"abc", b"b", &[0xff], &[0xff, 0x00]];
+    let mut links = Vec::new();
+    for tag in tags {
+        let link = Link::rand(base.clone(), tag.to_vec());
+        db.insert_link(&link).await.unwrap();
+        links.push(link);
+    }
+    // same tag on another base never shows up
+    db.insert_link(&Link::rand(EntryHash::rand(), b"ab".to_vec()))
+        .await
+        .unwrap();
+
+    let tags_of = |links: Vec<Link>| links.into_iter().map(|l| l.tag).collect::<Vec<_>>();
+
+    let all = db.get_links(&base, b"").await.unwrap();
+    assert_eq!(all.len(), tags.len());
+    assert_eq!(
+        tags_of(db.get_links(&base, b"a").await.unwrap()),
+        vec![b"a".to_vec(), b"ab".to_vec(), b"abc".to_vec()]
+    );
+    assert_eq!(
+        tags_of(db.get_links(&base, b"ab").await.unwrap()),
+        vec![b"ab".to_vec(), b"abc".to_vec()]
+    );
+    assert_eq!(
+        tags_of(db.get_links(&base, &[0xff]).await.unwrap()),
+        vec![vec![0xff], vec![0xff, 0x00]]
+    );
+    assert!(db.get_links(&base, b"c").await.unwrap().is_empty());
+
+    // deleted links are no longer returned
+    db.delete_link(&links[2].create_header, &HeaderHash::rand())
+        .await
+        .unwrap();
+    assert_eq!(
+        tags_of(db.get_links(&base, b"ab").await.unwrap()),
+        vec![b"abc".to_vec()]
+    );
+    assert!(db
+        .delete_link(&HeaderHash::rand(), &HeaderHash::rand())
+        .await
+        .is_err());
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,477 @@
+mod common;
+
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+use spike_sqlx::lmdb::*;
+use spike_sqlx::*;
+use std::convert::TryInto;
+
+// ---- a data.mdb written by LMDB itself ----
+
+type Records = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Written by [`write_fixture`], from [`fixture`]'s records.
+fn fixture_dir() -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/lmdb")
+}
+
+/// `stores` in `mdb_dump`'s format, for `mdb_load` to write.
+fn dump(stores: &[(&str, Records)]) -> String {
+    let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{:02x}", b)).collect() };
+    let mut dump = String::new();
+    for (name, records) in stores {
+        dump.push_str("VERSION=3\nformat=bytevalue\ntype=btree\n");
+        dump.push_str(&format!("database={}\nHEADER=END\n", name));
+        for (key, value) in records {
+            dump.push_str(&format!(" {}\n {}\n", hex(key), hex(value)));
+        }
+        dump.push_str("DATA=END\n");
+    }
+    dump
+}
+
+/// Rewrite the checked in fixture with LMDB's own `mdb_load`, which
+/// has to be on the PATH:
+///
+/// ```shell
+/// cargo test --test lmdb_import -- --ignored write_fixture
+/// ```
+#[test]
+#[ignore]
+fn write_fixture() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let dir = fixture_dir();
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let (_, stores) = fixture();
+    let mut load = Command::new("mdb_load")
+        .arg(&dir)
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("mdb_load from LMDB on the PATH");
+    load.stdin
+        .take()
+        .unwrap()
+        .write_all(dump(&stores).as_bytes())
+        .unwrap();
+    assert!(load.wait().unwrap().success());
+    // only data.mdb is read
+    std::fs::remove_file(dir.join("lock.mdb")).unwrap();
+}
+
+// ---- records as holochain_state wrote them ----
+
+fn rkv_blob<T: Serialize>(value: &T) -> Vec<u8> {
+    let bytes = rmp_serde::to_vec_named(value).unwrap();
+    let mut out = vec![9];
+    out.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    out.extend_from_slice(&bytes);
+    out
+}
+
+fn bytes(hash: &[u8]) -> ByteBuf {
+    ByteBuf::from(hash.to_vec())
+}
+
+#[derive(Serialize)]
+struct Time(i64, u32);
+
+#[derive(Serialize)]
+struct AppEntryType {
+    id: u8,
+    zome_id: u8,
+    visibility: Visibility,
+}
+
+#[derive(Serialize)]
+enum Visibility {
+    Public,
+}
+
+#[derive(Serialize)]
+#[allow(dead_code)]
+enum LegacyEntryType {
+    AgentPubKey,
+    App(AppEntryType),
+}
+
+#[derive(Serialize)]
+struct Dna {
+    author: ByteBuf,
+    timestamp: Time,
+    hash: ByteBuf,
+}
+
+#[derive(Serialize)]
+struct Create {
+    author: ByteBuf,
+    timestamp: Time,
+    header_seq: u32,
+    prev_header: ByteBuf,
+    entry_type: LegacyEntryType,
+    entry_hash: ByteBuf,
+}
+
+#[derive(Serialize)]
+struct CreateLink {
+    author: ByteBuf,
+    timestamp: Time,
+    header_seq: u32,
+    prev_header: ByteBuf,
+    base_address: ByteBuf,
+    target_address: ByteBuf,
+    zome_id: u8,
+    tag: ByteBuf,
+}
+
+// variants in holochain's order, they're encoded by index
+#[derive(Serialize)]
+#[allow(dead_code)]
+enum LegacyHeader {
+    Dna(Dna),
+    AgentValidationPkg(()),
+    InitZomesComplete(()),
+    CreateLink(CreateLink),
+    DeleteLink(()),
+    OpenChain(()),
+    CloseChain(()),
+    Create(Create),
+}
+
+#[derive(Serialize)]
+#[allow(dead_code)]
+enum LegacyEntry {
+    Agent(ByteBuf),
+    App(ByteBuf),
+}
+
+#[derive(Serialize)]
+#[allow(dead_code)]
+enum OpLight {
+    StoreElement(ByteBuf, Option<ByteBuf>, ByteBuf),
+    StoreEntry(ByteBuf, ByteBuf, ByteBuf),
+    RegisterAgentActivity(ByteBuf, ByteBuf),
+}
+
+#[derive(Serialize)]
+#[allow(dead_code)]
+enum Status {
+    Valid,
+    Rejected,
+}
+
+#[derive(Serialize)]
+struct Integrated {
+    validation_status: Status,
+    op: OpLight,
+    when_integrated: Time,
+}
+
+#[derive(Serialize)]
+struct IntegrationLimbo {
+    validation_status: Status,
+    op: OpLight,
+}
+
+#[derive(Serialize)]
+struct ValidationLimbo {
+    status: u8,
+    op: OpLight,
+    time_added: Time,
+    num_tries: u32,
+}
+
+struct Fixture {
+    app: Vec<Entry>,
+    agent: AgentPubKey,
+    agent_header: HeaderHash,
+    big: Entry,
+    orphan: Entry,
+    integrated: OpHash,
+    rejected: OpHash,
+    pending: OpHash,
+}
+
+/// The same every time, so the checked in fixture holds exactly these.
+fn fixture() -> (Fixture, Vec<(&'static str, Records)>) {
+    let seq = std::cell::Cell::new(0u32);
+    let raw = || {
+        seq.set(seq.get() + 1);
+        let mut raw = [0xa5; 32];
+        raw[..4].copy_from_slice(&seq.get().to_le_bytes());
+        raw
+    };
+    let agent = AgentPubKey::from_raw_32(raw());
+    let time = |secs| Time(1_600_000_000 + secs, 500_000_000);
+    let create = |seq, entry: &EntryHash, entry_type| {
+        (
+            HeaderHash::from_raw_32(raw()),
+            rkv_blob(&(
+                LegacyHeader::Create(Create {
+                    author: bytes(agent.get_raw_39()),
+                    timestamp: time(seq as i64),
+                    header_seq: seq,
+                    prev_header: bytes(HeaderHash::from_raw_32(raw()).get_raw_39()),
+                    entry_type,
+                    entry_hash: bytes(entry.get_raw_39()),
+                }),
+                ByteBuf::from(vec![0; 64]),
+            )),
+        )
+    };
+
+    let mut entries = Vec::new();
+    let mut headers = Vec::new();
+    let app: Vec<Entry> = (0..60)
+        .map(|i| Entry::from_content(format!("app entry {}", i).into_bytes()))
+        .collect();
+    for (i, entry) in app.iter().enumerate() {
+        entries.push((
+            entry.hash.get_raw_39().to_vec(),
+            rkv_blob(&LegacyEntry::App(bytes(&entry.content))),
+        ));
+        let app_type = AppEntryType {
+            id: 2,
+            zome_id: 1,
+            visibility: Visibility::Public,
+        };
+        headers.push(create(
+            i as u32 + 4,
+            &entry.hash,
+            LegacyEntryType::App(app_type),
+        ));
+    }
+    // too big for a page, so in overflow pages
+    let big = Entry::from_content(vec![7; 10_000]);
+    entries.push((
+        big.hash.get_raw_39().to_vec(),
+        rkv_blob(&LegacyEntry::App(bytes(&big.content))),
+    ));
+    headers.push(create(
+        100,
+        &big.hash,
+        LegacyEntryType::App(AppEntryType {
+            id: 0,
+            zome_id: 0,
+            visibility: Visibility::Public,
+        }),
+    ));
+    let agent_entry = EntryHash::from_raw_32(agent.get_raw_32().try_into().unwrap());
+    entries.push((
+        agent_entry.get_raw_39().to_vec(),
+        rkv_blob(&LegacyEntry::Agent(bytes(agent.get_raw_39()))),
+    ));
+    let agent_header = create(2, &agent_entry, LegacyEntryType::AgentPubKey);
+    let agent_header_hash = agent_header.0.clone();
+    headers.push(agent_header);
+    // no header creates it
+    let orphan = Entry::from_content(b"orphan".to_vec());
+    entries.push((
+        orphan.hash.get_raw_39().to_vec(),
+        rkv_blob(&LegacyEntry::App(bytes(&orphan.content))),
+    ));
+    headers.push((
+        HeaderHash::from_raw_32(raw()),
+        rkv_blob(&(
+            LegacyHeader::Dna(Dna {
+                author: bytes(agent.get_raw_39()),
+                timestamp: time(0),
+                hash: bytes(&[1; 39]),
+            }),
+            ByteBuf::from(vec![0; 64]),
+        )),
+    ));
+    headers.push((
+        HeaderHash::from_raw_32(raw()),
+        rkv_blob(&(
+            LegacyHeader::CreateLink(CreateLink {
+                author: bytes(agent.get_raw_39()),
+                timestamp: time(200),
+                header_seq: 200,
+                prev_header: bytes(HeaderHash::from_raw_32(raw()).get_raw_39()),
+                base_address: bytes(app[0].hash.get_raw_39()),
+                target_address: bytes(app[1].hash.get_raw_39()),
+                zome_id: 1,
+                tag: ByteBuf::new(),
+            }),
+            ByteBuf::from(vec![0; 64]),
+        )),
+    ));
+
+    let store_entry = |i: usize| {
+        OpLight::StoreEntry(
+            bytes(headers[i].0.get_raw_39()),
+            bytes(app[i].hash.get_raw_39()),
+            bytes(app[i].hash.get_raw_39()),
+        )
+    };
+    let (integrated, rejected, pending) = (
+        OpHash::from_raw_32(raw()),
+        OpHash::from_raw_32(raw()),
+        OpHash::from_raw_32(raw()),
+    );
+    let integrated_ops = vec![
+        (
+            integrated.get_raw_39().to_vec(),
+            rkv_blob(&Integrated {
+                validation_status: Status::Valid,
+                op: store_entry(0),
+                when_integrated: time(1000),
+            }),
+        ),
+        // its header isn't held
+        (
+            OpHash::from_raw_32(raw()).get_raw_39().to_vec(),
+            rkv_blob(&Integrated {
+                validation_status: Status::Valid,
+                op: OpLight::RegisterAgentActivity(
+                    bytes(HeaderHash::from_raw_32(raw()).get_raw_39()),
+                    bytes(agent.get_raw_39()),
+                ),
+                when_integrated: time(1000),
+            }),
+        ),
+    ];
+    let integration_limbo = vec![(
+        rejected.get_raw_39().to_vec(),
+        rkv_blob(&IntegrationLimbo {
+            validation_status: Status::Rejected,
+            op: store_entry(1),
+        }),
+    )];
+    let validation_limbo = vec![(
+        pending.get_raw_39().to_vec(),
+        rkv_blob(&ValidationLimbo {
+            status: 0,
+            op: OpLight::RegisterAgentActivity(
+                bytes(agent_header_hash.get_raw_39()),
+                bytes(agent.get_raw_39()),
+            ),
+            time_added: time(1000),
+            num_tries: 0,
+        }),
+    )];
+
+    let headers = headers
+        .into_iter()
+        .map(|(hash, value)| (hash.get_raw_39().to_vec(), value))
+        .collect();
+    let stores = vec![
+        ("element_vault_public_entries", entries),
+        ("element_vault_headers", headers),
+        ("integrated_dht_ops", integrated_ops),
+        ("integration_limbo", integration_limbo),
+        ("validation_limbo", validation_limbo),
+        // not imported
+        ("meta", vec![(b"key".to_vec(), b"value".to_vec())]),
+    ];
+    let fixture = Fixture {
+        app,
+        agent,
+        agent_header: agent_header_hash,
+        big,
+        orphan,
+        integrated,
+        rejected,
+        pending,
+    };
+    (fixture, stores)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn imports_an_environment() {
+    let (fixture, _) = fixture();
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    // a dry run reads it all and writes nothing
+    let import = LmdbImport::new(fixture_dir()).chunk_size(25);
+    let dry = db
+        .import_lmdb(&import.clone().dry_run(true), |_| ())
+        .await
+        .unwrap();
+    assert_eq!((dry.entries, dry.headers, dry.ops), (63, 64, 4));
+    // the orphan entry and the op without its header
+    assert_eq!(dry.skipped, 2);
+    assert_eq!(dry.written, 0);
+    assert!(!db.entry_exists(&fixture.app[0].hash).await.unwrap());
+
+    let mut reported = Vec::new();
+    let done = db
+        .import_lmdb(&import, |progress| reported.push(progress))
+        .await
+        .unwrap();
+    assert_eq!(reported.last(), Some(&done));
+    assert_eq!(done.chunks, reported.len() as u64);
+    // 3 chunks of entries, 3 of headers, one for each op store
+    assert_eq!(done.chunks, 9);
+    // 62 entries, 64 headers, 3 ops
+    assert_eq!(done.written, 129);
+
+    let entry = db.get_entry(&fixture.app[3].hash).await.unwrap().unwrap();
+    assert_eq!(entry.content, fixture.app[3].content);
+    assert_eq!(
+        entry.entry_type,
+        EntryType::App {
+            zome_index: 1,
+            entry_def_index: 2
+        }
+    );
+    assert_eq!(entry.created_at, Timestamp(1_600_000_007_500_000));
+    let big = db.get_entry(&fixture.big.hash).await.unwrap().unwrap();
+    assert_eq!(big.content, fixture.big.content);
+    assert!(!db.entry_exists(&fixture.orphan.hash).await.unwrap());
+    let agent = EntryHash::from_raw_32(fixture.agent.get_raw_32().try_into().unwrap());
+    let agent = db.get_entry(&agent).await.unwrap().unwrap();
+    assert_eq!(agent.entry_type, EntryType::Agent);
+    assert_eq!(agent.content, fixture.agent.get_raw_39());
+
+    let op = db.get_op(&fixture.integrated).await.unwrap().unwrap();
+    assert_eq!(op.op_type, DhtOpType::StoreEntry);
+    assert_eq!(op.basis_loc, fixture.app[0].dht_loc());
+    assert_eq!(op.validation_status, ValidationStatus::Valid);
+    assert!(op.when_integrated.is_some());
+    let op = db.get_op(&fixture.rejected).await.unwrap().unwrap();
+    assert_eq!(op.validation_status, ValidationStatus::Rejected);
+    assert!(op.when_integrated.is_none());
+    let op = db.get_op(&fixture.pending).await.unwrap().unwrap();
+    assert_eq!(op.op_type, DhtOpType::RegisterAgentActivity);
+    assert_eq!(op.validation_status, ValidationStatus::Pending);
+    assert_eq!(
+        op.authored_timestamp.timestamp(),
+        1_600_000_002,
+        "authored when {:?} was",
+        fixture.agent_header
+    );
+
+    // again, and nothing new is written but the ops merging
+    let again = db.import_lmdb(&import, |_| ()).await.unwrap();
+    assert_eq!(again.written, 3);
+
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_what_isnt_an_environment() {
+    let dir = common::temp_dir();
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    assert!(db
+        .import_lmdb(&LmdbImport::new(dir.path()), |_| ())
+        .await
+        .is_err());
+    std::fs::write(dir.path().join("data.mdb"), vec![0; 8192]).unwrap();
+    let err = db
+        .import_lmdb(&LmdbImport::new(dir.path()), |_| ())
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string().contains("not an LMDB environment"),
+        "{}",
+        err
+    );
+
+    db.close().await.unwrap();
+}
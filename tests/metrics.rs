@@ -0,0 +1,75 @@
+use spike_sqlx::*;
+
+#[test]
+fn histogram_buckets() {
+    let mut histogram = Histogram::new(&[1, 10, 100]);
+    assert_eq!(histogram.quantile(0.5), None);
+    for value in [0, 1, 5, 10, 50, 500] {
+        histogram.observe(value);
+    }
+    assert_eq!(histogram.counts, vec![2, 2, 1, 1]);
+    assert_eq!(
+        (histogram.count, histogram.sum, histogram.max),
+        (6, 566, 500)
+    );
+    assert!((histogram.mean() - 566.0 / 6.0).abs() < 1e-9);
+    assert_eq!(histogram.quantile(0.0), Some(1));
+    assert_eq!(histogram.quantile(0.5), Some(10));
+    assert_eq!(histogram.quantile(0.8), Some(100));
+    // past the last bound, the largest seen
+    assert_eq!(histogram.quantile(1.0), Some(500));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn operations_are_measured() {
+    // recorded whether or not there are spans
+    let config = DbConfig::new().tracing(TraceVerbosity::Off);
+    let db = Db::open_with("sqlite::memory:", config).await.unwrap();
+    let entries: Vec<Entry> = (0..4).map(|_| Entry::rand()).collect();
+    db.insert_entries(&entries).await.unwrap();
+    for entry in &entries[..3] {
+        db.get_entry(&entry.hash).await.unwrap().unwrap();
+    }
+    assert!(db.get_entry(&Entry::rand().hash).await.unwrap().is_none());
+    db.read(|reader| Box::pin(async move { reader.query("SELECT nope;", &[]).await }))
+        .await
+        .unwrap_err();
+
+    let snapshot = db.metrics_snapshot();
+    assert_eq!(snapshot.db_kind, "path");
+    assert_eq!(snapshot.op("open").unwrap().side, "write");
+
+    let inserted = snapshot.op("insert_entry_batch").unwrap();
+    assert_eq!(inserted.side, "write");
+    assert_eq!(inserted.calls(), 1);
+    assert_eq!(inserted.rows_written.sum, 4);
+    assert_eq!(inserted.rows_returned.count, 0);
+
+    let got = snapshot.op("get_entry").unwrap();
+    assert_eq!(got.side, "read");
+    assert_eq!(got.calls(), 4);
+    assert_eq!((got.rows_returned.count, got.rows_returned.sum), (4, 3));
+    assert_eq!(got.latency_us.counts.iter().sum::<u64>(), 4);
+    assert!(got.latency_us.max > 0);
+    assert_eq!(got.errors, 0);
+    assert!(snapshot.throughput("get_entry") > 0.0);
+    assert_eq!(snapshot.throughput("search"), 0.0);
+
+    assert_eq!(snapshot.op("read_txn").unwrap().errors, 1);
+    // each side on its own
+    assert!(db.reader().metrics_snapshot().op("open").is_none());
+    assert!(db.writer().metrics_snapshot().op("get_entry").is_none());
+
+    let text = snapshot.to_prometheus();
+    assert!(text.contains("# TYPE spike_sqlx_op_duration_seconds histogram\n"));
+    assert!(text.contains(
+        "spike_sqlx_op_duration_seconds_count{db_kind=\"path\",side=\"read\",op=\"get_entry\"} 4\n"
+    ));
+    assert!(text.contains(
+        "spike_sqlx_op_rows_written_bucket{db_kind=\"path\",side=\"write\",op=\"insert_entry_batch\",le=\"5\"} 1\n"
+    ));
+    assert!(text.contains(
+        "spike_sqlx_op_errors_total{db_kind=\"path\",side=\"read\",op=\"read_txn\"} 1\n"
+    ));
+    db.close().await.unwrap();
+}
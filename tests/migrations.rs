@@ -0,0 +1,120 @@
+mod common;
+
+use chrono::prelude::*;
+use spike_sqlx::*;
+use sqlx::Connection;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn migrations_apply_once() {
+    let dir = common::temp_dir();
+    let url = format!("sqlite://{}", dir.path().join("db.sqlite3").display());
+
+    let db = Db::open(&url).await.unwrap();
+    // open already migrated
+    assert_eq!(db.migrate().await.unwrap(), 0);
+    db.insert_entry(&Entry::rand()).await.unwrap();
+    db.close().await.unwrap();
+
+    let db = Db::open(&url).await.unwrap();
+    assert_eq!(db.migrate().await.unwrap(), 0);
+    db.close().await.unwrap();
+
+    // stock sqlite ignores the test key, so a plain connection can write
+    let mut con = sqlx::SqliteConnection::connect(&url).await.unwrap();
+    sqlx::query(
+        "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+        VALUES (9999, 'from the future', 1, x'', 0)",
+    )
+    .execute(&mut con)
+    .await
+    .unwrap();
+    con.close().await.unwrap();
+
+    assert!(Db::open(&url).await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn schema_drift_fails_open() {
+    let dir = common::temp_dir();
+    let url = format!("sqlite://{}", dir.path().join("db.sqlite3").display());
+
+    Db::open(&url).await.unwrap().close().await.unwrap();
+
+    let mut con = sqlx::SqliteConnection::connect(&url).await.unwrap();
+    // an extra index is tolerated, a missing one is not
+    for sql in &[
+        "CREATE INDEX extra_idx ON entries (created_at)",
+        "DROP INDEX entries_query_idx",
+    ] {
+        sqlx::query(sql).execute(&mut con).await.unwrap();
+    }
+    con.close().await.unwrap();
+
+    let err = match Db::open(&url).await {
+        Ok(_) => panic!("opened a database with a missing index"),
+        Err(err) => err,
+    };
+    match err.downcast_ref::<DbError>() {
+        Some(DbError::SchemaMismatch(msg)) => assert!(msg.contains("entries_query_idx"), "{}", msg),
+        other => panic!("expected a schema mismatch, got {:?}", other),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn text_timestamps_migrate_to_micros() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let url = format!("sqlite://{}", path.display());
+
+    // laid out as before versioning, so every migration runs over it
+    let opts = sqlx::sqlite::SqliteConnectOptions::new()
+        .filename(&path)
+        .create_if_missing(true);
+    let mut con = sqlx::SqliteConnection::connect_with(&opts).await.unwrap();
+    sqlx::query(
+        "CREATE TABLE entries (hash BLOB PRIMARY KEY, dht_loc INT NOT NULL, created_at TEXT NOT NULL);
+        CREATE TABLE headers (hash BLOB PRIMARY KEY, entry_hash BLOB NULL
+            REFERENCES entries(hash) ON DELETE CASCADE);",
+    )
+    .execute(&mut con)
+    .await
+    .unwrap();
+    let entry = EntryHash::rand();
+    let header = HeaderHash::rand();
+    sqlx::query("INSERT INTO entries VALUES (?1, 0, '2021-03-01 12:00:00.123456789')")
+        .bind(&entry)
+        .execute(&mut con)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO headers VALUES (?1, ?2)")
+        .bind(&header)
+        .bind(&entry)
+        .execute(&mut con)
+        .await
+        .unwrap();
+    // all legacy headers land at the same chain position
+    sqlx::query("INSERT INTO headers VALUES (?1, NULL)")
+        .bind(HeaderHash::rand())
+        .execute(&mut con)
+        .await
+        .unwrap();
+    con.close().await.unwrap();
+
+    let db = Db::open(&url).await.unwrap();
+    let fetched = db.get_entry(&entry).await.unwrap().unwrap();
+    assert_eq!(
+        fetched.created_at,
+        Timestamp::from(Utc.ymd(2021, 3, 1).and_hms_micro(12, 0, 0, 123_456))
+    );
+    db.close().await.unwrap();
+
+    // rebuilding entries didn't take the headers with it
+    let mut con = sqlx::SqliteConnection::connect(&url).await.unwrap();
+    let (headers,): (i64,) = sqlx::query_as("SELECT count(*) FROM headers WHERE entry_hash = ?1")
+        .bind(&entry)
+        .fetch_one(&mut con)
+        .await
+        .unwrap();
+    assert_eq!(headers, 1);
+    con.close().await.unwrap();
+}
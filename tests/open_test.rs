@@ -0,0 +1,27 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn handles_with_the_same_name_share_a_database() {
+    let name = format!("open-test-{}", std::process::id());
+    let a = Db::open_test(&name).await.unwrap();
+    let b = Db::open_test(&name).await.unwrap();
+    let other = Db::open_test(&format!("{}-other", name)).await.unwrap();
+
+    let entry = Entry::rand();
+    a.insert_entry(&entry).await.unwrap();
+    // through b's read pool, not just its writer
+    assert!(b.entry_exists(&entry.hash).await.unwrap());
+    assert!(!other.entry_exists(&entry.hash).await.unwrap());
+
+    // separate memory: handles don't
+    let memory = Db::open("sqlite::memory:").await.unwrap();
+    assert!(!memory.entry_exists(&entry.hash).await.unwrap());
+
+    a.close().await.unwrap();
+    assert!(b.entry_exists(&entry.hash).await.unwrap());
+    b.close().await.unwrap();
+    let reopened = Db::open_test(&name).await.unwrap();
+    assert!(!reopened.entry_exists(&entry.hash).await.unwrap());
+
+    assert!(Db::open_test("no?such&name").await.is_err());
+}
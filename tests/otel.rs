@@ -0,0 +1,107 @@
+#![cfg(feature = "otel")]
+
+use opentelemetry::api::{self, Provider, TraceContextExt};
+use opentelemetry::exporter::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry::sdk;
+use spike_sqlx::*;
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Keeps every span exported.
+#[derive(Debug, Clone, Default)]
+struct Exported(Arc<Mutex<Vec<Arc<SpanData>>>>);
+
+impl SpanExporter for Exported {
+    fn export(&self, batch: Vec<Arc<SpanData>>) -> ExportResult {
+        self.0.lock().unwrap().extend(batch);
+        ExportResult::Success
+    }
+
+    fn shutdown(&self) {}
+}
+
+impl Exported {
+    fn named(&self, name: &str) -> Vec<Arc<SpanData>> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|span| span.name == name)
+            .cloned()
+            .collect()
+    }
+}
+
+fn attribute(span: &SpanData, key: &str) -> Option<api::Value> {
+    span.attributes
+        .iter()
+        .find(|(k, _)| k.as_str() == key)
+        .map(|(_, v)| v.clone())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn db_ops_join_the_callers_trace() {
+    let exported = Exported::default();
+    let provider = sdk::Provider::builder()
+        .with_simple_exporter(exported.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry().with(otel_layer(provider.get_tracer("test")));
+    // global, the ops finish on whichever thread runs them
+    tracing::subscriber::set_global_default(subscriber).unwrap();
+
+    // as if from an incoming request
+    let trace_id = api::TraceId::from_u128(0x5eed);
+    let remote = api::Context::current().with_remote_span_context(api::SpanContext::new(
+        trace_id,
+        api::SpanId::from_u64(7),
+        api::TRACE_FLAG_SAMPLED,
+        true,
+    ));
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    let entry = Entry::rand();
+    let held = db.clone();
+    let queued = entry.clone();
+    in_trace_context(&remote, async move {
+        // handed on as the request's own trace
+        assert_eq!(
+            current_trace_context().span().span_context().trace_id(),
+            trace_id
+        );
+        held.get_entry(&queued.hash).await.unwrap();
+        held.write_queue()
+            .submit(WriteOp::InsertEntry(queued))
+            .await
+            .unwrap();
+    })
+    .await;
+    db.close().await.unwrap();
+
+    let request = &exported.named("db_request")[0];
+    assert_eq!(request.span_context.trace_id(), trace_id);
+    assert_eq!(request.parent_span_id, api::SpanId::from_u64(7));
+
+    // a child of the request, with the database attributes
+    let got = &exported.named("get_entry")[0];
+    assert_eq!(got.span_context.trace_id(), trace_id);
+    assert_eq!(got.parent_span_id, request.span_context.span_id());
+    assert_eq!(got.span_kind, api::SpanKind::Client);
+    assert_eq!(
+        attribute(got, "db.system"),
+        Some(api::Value::String("sqlite".into()))
+    );
+    assert_eq!(
+        attribute(got, "db.operation"),
+        Some(api::Value::String("get_entry".into()))
+    );
+    match attribute(got, "db.statement") {
+        Some(api::Value::String(sql)) => assert!(sql.contains("FROM entries"), "{}", sql),
+        other => panic!("no statement: {:?}", other),
+    }
+
+    // the queued write is linked back to the request
+    let batch = &exported.named("write_batch")[0];
+    assert!(batch
+        .links
+        .iter()
+        .any(|link| link.span_context().span_id() == request.span_context.span_id()));
+}
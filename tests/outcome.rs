@@ -0,0 +1,61 @@
+use chrono::prelude::*;
+use spike_sqlx::*;
+
+fn outcome(inserted: u64, updated: u64, deleted: u64, ignored: u64) -> WriteOutcome {
+    WriteOutcome {
+        inserted,
+        updated,
+        deleted,
+        ignored,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writes_report_what_they_did() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let entries: Vec<Entry> = (0..3).map(|_| Entry::rand()).collect();
+    assert_eq!(
+        db.insert_entries(&entries).await.unwrap(),
+        outcome(3, 0, 0, 0)
+    );
+    assert_eq!(
+        db.insert_header(&Header::rand(entries[0].hash.clone()))
+            .await
+            .unwrap(),
+        outcome(1, 0, 0, 0)
+    );
+
+    // gossip accounting: only the first copy of an op is new
+    let op = DhtOp::rand();
+    assert_eq!(db.insert_op(&op).await.unwrap(), outcome(1, 0, 0, 0));
+    assert_eq!(db.insert_op(&op).await.unwrap(), outcome(0, 0, 0, 1));
+    let validated = DhtOp {
+        validation_status: ValidationStatus::Valid,
+        ..op.clone()
+    };
+    assert_eq!(db.insert_op(&validated).await.unwrap(), outcome(0, 1, 0, 0));
+    assert_eq!(db.insert_op(&validated).await.unwrap(), outcome(0, 0, 0, 1));
+
+    let receipt = ValidationReceipt::rand(op.op_hash.clone());
+    assert!(db.insert_receipt(&receipt).await.unwrap().changed());
+    assert!(!db.insert_receipt(&receipt).await.unwrap().changed());
+
+    let info = AgentInfo::rand();
+    assert_eq!(db.put_agent_info(&info).await.unwrap(), outcome(1, 0, 0, 0));
+    let renewed = AgentInfo {
+        expires_at: Utc::now() + chrono::Duration::hours(1),
+        ..info.clone()
+    };
+    assert_eq!(
+        db.put_agent_info(&renewed).await.unwrap(),
+        outcome(0, 1, 0, 0)
+    );
+
+    assert_eq!(
+        db.delete_op(&OpHash::rand()).await.unwrap(),
+        outcome(0, 0, 0, 1)
+    );
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,62 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pages_cover_the_range_in_order() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    // plenty of ties on created_at, dht_loc comes from the hash
+    let created_at = Timestamp::now();
+    let mut expected = Vec::new();
+    for i in 0..25u32 {
+        let entry = Entry {
+            hash: EntryHash::from_raw_32([(i * 7 % 25) as u8; 32]),
+            created_at: created_at + chrono::Duration::milliseconds((i % 2) as i64),
+            ..Entry::rand()
+        };
+        db.insert_entry(&entry).await.unwrap();
+        expected.push(entry);
+    }
+    expected.sort_by(|a, b| {
+        (a.dht_loc(), a.created_at, &a.hash).cmp(&(b.dht_loc(), b.created_at, &b.hash))
+    });
+
+    let start = Timestamp(0);
+    let end = created_at + chrono::Duration::seconds(1);
+    // the page overrides the filter's own limit
+    let filter = EntryFilter::new().time_range(start, end).limit(3);
+    let mut page = QueryPage::first(10);
+    let mut got = Vec::new();
+    let mut pages = 0;
+    loop {
+        let res = db.query_entries_page(&filter, &page).await.unwrap();
+        assert!(res.items.len() <= 10);
+        got.extend(res.items);
+        pages += 1;
+        match res.next {
+            // cursors survive being sent over the wire
+            Some(next) => page.cursor = Some(next.to_string().parse().unwrap()),
+            None => break,
+        }
+    }
+    assert_eq!(pages, 3);
+    assert_eq!(
+        got.iter().map(|e| e.hash.clone()).collect::<Vec<_>>(),
+        expected.iter().map(|e| e.hash.clone()).collect::<Vec<_>>()
+    );
+
+    // an exact fit doesn't leave an empty trailing page
+    let all = db
+        .query_entries_page(&filter, &QueryPage::first(25))
+        .await
+        .unwrap();
+    assert_eq!(all.items.len(), 25);
+    assert!(all.next.is_none());
+
+    assert!("nonsense".parse::<Cursor>().is_err());
+    assert!(db
+        .query_entries_page(&filter, &QueryPage::first(0))
+        .await
+        .is_err());
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,66 @@
+use spike_sqlx::*;
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn held_permits_time_out_others() {
+    let config = DbConfig::new()
+        .read_connections(1, 2)
+        .permit_timeout(Duration::from_millis(50));
+    let db = Db::open_with("sqlite::memory:", config).await.unwrap();
+    let entry = Entry::rand();
+
+    let write = db.writer().permit().await.unwrap();
+    let err = db.insert_entry(&entry).await.unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<DbError>(),
+            Some(DbError::PermitTimeout("write", _))
+        ),
+        "{:?}",
+        err
+    );
+    // reads don't wait on the writer
+    assert!(db.get_entry(&entry.hash).await.unwrap().is_none());
+    drop(write);
+    db.insert_entry(&entry).await.unwrap();
+
+    let reads = (
+        db.reader().permit().await.unwrap(),
+        db.reader().permit().await.unwrap(),
+    );
+    let err = db.get_entry(&entry.hash).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<DbError>(),
+        Some(DbError::PermitTimeout("read", _))
+    ));
+    drop(reads);
+    assert!(db.get_entry(&entry.hash).await.unwrap().is_some());
+
+    let metrics = db.writer().permit_metrics();
+    assert_eq!(metrics.timed_out, 1);
+    assert_eq!(db.reader().permit_metrics().timed_out, 1);
+
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn waits_are_measured() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let held = db.writer().permit().await.unwrap();
+    let before = db.writer().permit_metrics();
+    let waiting = {
+        let db = db.clone();
+        tokio::spawn(async move { db.insert_entry(&Entry::rand()).await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    drop(held);
+    waiting.await.unwrap().unwrap();
+
+    let after = db.writer().permit_metrics();
+    assert_eq!(after.acquired, before.acquired + 1);
+    assert!(after.max_wait >= Duration::from_millis(50), "{:?}", after);
+    assert!(after.mean_wait() <= after.max_wait);
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,133 @@
+use spike_sqlx::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+type Fields = HashMap<String, String>;
+
+struct FieldsVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldsVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+}
+
+/// Keeps the fields of every WARN event.
+#[derive(Clone, Default)]
+struct Warnings(Arc<Mutex<Vec<Fields>>>);
+
+impl Warnings {
+    fn slow_waits(&self) -> Vec<Fields> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|fields| fields["message"] == "slow wait for a database connection")
+            .cloned()
+            .collect()
+    }
+}
+
+impl Subscriber for Warnings {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= Level::WARN
+    }
+
+    fn new_span(&self, _: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::new();
+        event.record(&mut FieldsVisitor(&mut fields));
+        self.0.lock().unwrap().push(fields);
+    }
+
+    fn enter(&self, _: &Id) {}
+    fn exit(&self, _: &Id) {}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pool_use_and_waits() {
+    let warnings = Warnings::default();
+    // global, the waits happen on whichever thread runs them
+    tracing::subscriber::set_global_default(warnings.clone()).unwrap();
+
+    let config = DbConfig::new()
+        .read_connections(1, 2)
+        .permit_timeout(Duration::from_millis(200))
+        .slow_acquire_threshold(Some(Duration::from_millis(20)));
+    let db = Db::open_with("sqlite::memory:", config).await.unwrap();
+    let metrics = db.metrics_snapshot();
+    let read = metrics.pool("read").unwrap();
+    assert_eq!(read.max_connections, 2);
+    assert_eq!(read.in_use(), 0);
+    assert_eq!(metrics.pool("write").unwrap().max_connections, 1);
+
+    // a reader holds a connection while it runs
+    let held = db.clone();
+    let during = db
+        .read(move |_| {
+            let db = held.clone();
+            Box::pin(async move { Ok(db.metrics_snapshot()) })
+        })
+        .await
+        .unwrap();
+    let read = during.pool("read").unwrap();
+    assert_eq!(read.in_use(), 1);
+    assert_eq!(read.utilization(), 0.5);
+
+    // queued behind the writer for longer than the threshold
+    let write = db.writer().permit().await.unwrap();
+    let waiting = {
+        let db = db.clone();
+        tokio::spawn(async move { db.insert_entry(&Entry::rand()).await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    drop(write);
+    waiting.await.unwrap().unwrap();
+    // then for longer than the timeout
+    let write = db.writer().permit().await.unwrap();
+    assert!(db.insert_entry(&Entry::rand()).await.is_err());
+    drop(write);
+
+    let metrics = db.metrics_snapshot();
+    let writer = metrics.pool("write").unwrap();
+    assert_eq!(writer.waits.slow, 2);
+    assert_eq!(writer.waits.timed_out, 1);
+    assert_eq!(writer.acquire_wait_us.count, writer.waits.acquired + 1);
+    assert!(writer.acquire_wait_us.max >= 200_000);
+    assert_eq!(metrics.pool("read").unwrap().waits.slow, 0);
+
+    let logged = warnings.slow_waits();
+    assert_eq!(logged.len(), 2);
+    assert_eq!(logged[0]["side"], "write");
+    assert_eq!(logged[0]["timed_out"], "false");
+    assert_eq!(logged[1]["timed_out"], "true");
+
+    let text = metrics.to_prometheus();
+    assert!(text.contains("spike_sqlx_pool_max_connections{db_kind=\"path\",side=\"read\"} 2\n"));
+    assert!(text
+        .contains("spike_sqlx_pool_acquire_timeouts_total{db_kind=\"path\",side=\"write\"} 1\n"));
+    assert!(
+        text.contains("spike_sqlx_pool_slow_acquires_total{db_kind=\"path\",side=\"write\"} 2\n")
+    );
+    assert!(text
+        .contains("spike_sqlx_pool_acquire_wait_seconds_count{db_kind=\"path\",side=\"write\"}"));
+    assert!(
+        text.contains("spike_sqlx_pool_connections{db_kind=\"path\",side=\"read\",state=\"idle\"}")
+    );
+    db.close().await.unwrap();
+}
@@ -0,0 +1,149 @@
+#![cfg(feature = "postgres")]
+
+//! Needs a server: set `SPIKE_SQLX_POSTGRES_URL` to a database the tests
+//! may create scratch databases from, e.g.
+//! `postgres://postgres@127.0.0.1:5432/postgres`. Skipped otherwise.
+
+use chrono::prelude::*;
+use spike_sqlx::*;
+use sqlx::{Connection, Executor, PgConnection};
+
+/// A fresh database on the server, dropped again by [`drop_scratch`].
+async fn scratch(name: &str) -> Option<(String, String)> {
+    let server = match std::env::var("SPIKE_SQLX_POSTGRES_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            eprintln!("SPIKE_SQLX_POSTGRES_URL not set, skipping");
+            return None;
+        }
+    };
+    let db_name = format!("spike_sqlx_{}_{}", name, std::process::id());
+    let mut admin = PgConnection::connect(&server).await.unwrap();
+    admin
+        .execute(&*format!("DROP DATABASE IF EXISTS {}", db_name))
+        .await
+        .unwrap();
+    admin
+        .execute(&*format!("CREATE DATABASE {}", db_name))
+        .await
+        .unwrap();
+    admin.close().await.unwrap();
+    let (base, _) = server.rsplit_once('/').unwrap();
+    Some((server.clone(), format!("{}/{}", base, db_name)))
+}
+
+async fn drop_scratch(server: &str, url: &str) {
+    let (_, db_name) = url.rsplit_once('/').unwrap();
+    let mut admin = PgConnection::connect(server).await.unwrap();
+    admin
+        .execute(&*format!("DROP DATABASE {}", db_name))
+        .await
+        .unwrap();
+    admin.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn entry_api_on_postgres() {
+    let (server, url) = match scratch("entries").await {
+        Some(urls) => urls,
+        None => return,
+    };
+
+    let db = PgDb::open(&url).await.unwrap();
+    assert_eq!(db.migrate().await.unwrap(), 0);
+    let entries: Vec<_> = (0..10u8).map(|i| Entry::from_content(vec![i])).collect();
+    assert_eq!(db.insert_entries(&entries).await.unwrap().inserted, 10);
+    assert_eq!(db.upsert_entry(&entries[0]).await.unwrap().ignored, 1);
+    assert!(db.insert_entry(&entries[0]).await.is_err());
+    assert!(db.entry_exists(&entries[1].hash).await.unwrap());
+    let fetched = db.get_entry(&entries[2].hash).await.unwrap().unwrap();
+    assert_eq!(fetched.content, entries[2].content);
+    assert_eq!(fetched.entry_type, entries[2].entry_type);
+    assert_eq!(fetched.created_at, entries[2].created_at);
+    assert_eq!(db.delete_entry(&entries[3].hash).await.unwrap().deleted, 1);
+    assert!(db.get_entry(&entries[3].hash).await.unwrap().is_none());
+    assert_eq!(
+        db.count_entries(0, u32::MAX, Timestamp(0), Timestamp::now())
+            .await
+            .unwrap(),
+        9
+    );
+
+    // locations past i32::MAX survive the BIGINT round trip,
+    // and a wrapping range covers both ends
+    let high = entries
+        .iter()
+        .filter(|e| e.hash != entries[3].hash && e.dht_loc() > u32::MAX / 2)
+        .count();
+    let wrapped = db
+        .query_entries(u32::MAX / 2 + 1, 0, Timestamp(0), Timestamp::now())
+        .await
+        .unwrap();
+    assert_eq!(wrapped.len(), high);
+
+    let ordered = db
+        .filter_entries(&EntryFilter::new().order(EntryOrder::DhtLoc).limit(3))
+        .await
+        .unwrap();
+    assert_eq!(ordered.len(), 3);
+    assert!(ordered.windows(2).all(|w| w[0].dht_loc() <= w[1].dht_loc()));
+
+    // author filters go through headers
+    let header = Header::rand(entries[4].hash.clone());
+    assert_eq!(db.insert_header(&header).await.unwrap().inserted, 1);
+    let authored = db
+        .filter_entries(&EntryFilter::new().author(header.author.clone()))
+        .await
+        .unwrap();
+    assert_eq!(authored.len(), 1);
+    assert_eq!(authored[0].hash, entries[4].hash);
+
+    db.close().await;
+    drop_scratch(&server, &url).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn op_upserts_merge_on_postgres() {
+    let (server, url) = match scratch("ops").await {
+        Some(urls) => urls,
+        None => return,
+    };
+
+    let db = PgDb::open(&url).await.unwrap();
+    let mut op = DhtOp::rand();
+    op.basis_loc = u32::MAX;
+    op.dependency = Some(OpHash::rand());
+    assert_eq!(db.insert_op(&op).await.unwrap().inserted, 1);
+    let fetched = db.get_op(&op.op_hash).await.unwrap().unwrap();
+    assert_eq!(fetched.basis_loc, u32::MAX);
+    assert_eq!(fetched.op_type, op.op_type);
+    assert_eq!(fetched.dependency, op.dependency);
+    assert_eq!(fetched.validation_status, ValidationStatus::Pending);
+
+    // the same op again changes nothing
+    assert_eq!(db.insert_op(&op).await.unwrap().ignored, 1);
+
+    // a validated, integrated copy fills in the outcome
+    let mut later = op.clone();
+    later.validation_status = ValidationStatus::Valid;
+    later.when_integrated = Some(Utc::now());
+    assert_eq!(db.insert_op(&later).await.unwrap().updated, 1);
+    let fetched = db.get_op(&op.op_hash).await.unwrap().unwrap();
+    assert_eq!(fetched.validation_status, ValidationStatus::Valid);
+    assert!(fetched.when_integrated.is_some());
+
+    // and a pending copy doesn't undo it
+    assert_eq!(db.insert_op(&op).await.unwrap().ignored, 1);
+    db.set_validation_status(&op.op_hash, ValidationStatus::Rejected)
+        .await
+        .unwrap();
+    let fetched = db.get_op(&op.op_hash).await.unwrap().unwrap();
+    assert_eq!(fetched.validation_status, ValidationStatus::Rejected);
+    assert!(db
+        .set_validation_status(&OpHash::rand(), ValidationStatus::Valid)
+        .await
+        .is_err());
+
+    db.close().await;
+    drop_scratch(&server, &url).await;
+}
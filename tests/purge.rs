@@ -0,0 +1,39 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn delete_and_purge() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let mut entries: Vec<Entry> = (0..4).map(|_| Entry::rand()).collect();
+    entries.sort_by_key(|entry| entry.dht_loc());
+    for entry in &entries {
+        db.insert_entry(entry).await.unwrap();
+    }
+    let middle = EntryFilter::new().loc_range(entries[1].dht_loc(), entries[2].dht_loc());
+    let header = Header::rand(entries[1].hash.clone());
+    db.insert_header(&header).await.unwrap();
+
+    assert_eq!(db.delete_entry(&entries[0].hash).await.unwrap().deleted, 1);
+    // not held any more
+    assert_eq!(db.delete_entry(&entries[0].hash).await.unwrap().ignored, 1);
+
+    let purged = db.purge(&middle).await.unwrap();
+    assert_eq!(purged.deleted, 2);
+    // the header went with its entry
+    assert!(db.get_element(&header.hash).await.unwrap().is_none());
+    let left = db.filter_entries(&EntryFilter::new()).await.unwrap();
+    assert_eq!(left.len(), 1);
+    assert_eq!(left[0].hash, entries[3].hash);
+    assert!(!db.purge(&middle).await.unwrap().changed());
+
+    let op = DhtOp::rand();
+    db.insert_op(&op).await.unwrap();
+    db.insert_receipt(&ValidationReceipt::rand(op.op_hash.clone()))
+        .await
+        .unwrap();
+    assert!(db.delete_op(&op.op_hash).await.unwrap().changed());
+    assert!(db.get_op(&op.op_hash).await.unwrap().is_none());
+    assert_eq!(db.count_receipts(&op.op_hash).await.unwrap(), 0);
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use spike_sqlx::*;
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reads_after_a_marker_see_the_write() {
+    let dir = common::temp_dir();
+    let db = Db::open_with(
+        dir.path().join("db.sqlite3"),
+        DbConfig::new().read_connections(2, 4),
+    )
+    .await
+    .unwrap();
+
+    let first = Entry::rand();
+    db.insert_entry(&first).await.unwrap();
+    let marker = db.commit_and_notify().await.unwrap();
+    let hash = first.hash.clone();
+    let seen = db
+        .read_after(marker, move |reader| {
+            let hash = hash.clone();
+            Box::pin(async move { reader.get_entry(&hash).await })
+        })
+        .await
+        .unwrap();
+    assert_eq!(seen.unwrap().hash, first.hash);
+
+    // waiting on a commit that hasn't happened yet
+    let next = CommitMarker(marker.0 + 1);
+    let second = Entry::rand();
+    let hash = second.hash.clone();
+    let waiting = tokio::spawn({
+        let db = db.clone();
+        async move {
+            db.read_after(next, move |reader| {
+                let hash = hash.clone();
+                Box::pin(async move { reader.get_entry(&hash).await })
+            })
+            .await
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    db.insert_entry(&second).await.unwrap();
+    assert_eq!(db.commit_and_notify().await.unwrap(), next);
+    let seen = waiting.await.unwrap().unwrap();
+    assert_eq!(seen.unwrap().hash, second.hash);
+
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn read_after_gives_up_on_a_commit_that_never_comes() {
+    let config = DbConfig::new().retry_policy(RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(5),
+        max_elapsed: Duration::from_secs(1),
+    });
+    let db = Db::open_with("sqlite::memory:", config).await.unwrap();
+    let marker = db.commit_and_notify().await.unwrap();
+
+    let err = db
+        .read_after(CommitMarker(marker.0 + 1), |reader| {
+            Box::pin(async move { reader.count_matching(&EntryFilter::new()).await })
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<DbError>(),
+        Some(DbError::StaleSnapshot(wanted, seen)) if *wanted == marker.0 + 1 && *seen == marker.0
+    ));
+}
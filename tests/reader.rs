@@ -0,0 +1,47 @@
+mod common;
+
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reads_share_a_snapshot() {
+    let dir = common::temp_dir();
+    let db = Db::open(dir.path().join("db.sqlite3")).await.unwrap();
+
+    let held = Entry::rand();
+    db.insert_entry(&held).await.unwrap();
+    let header = Header::rand(held.hash.clone());
+    db.insert_header(&header).await.unwrap();
+
+    let late = Entry::rand();
+    let (writer, header_hash, late_entry) = (db.clone(), header.hash.clone(), late.clone());
+    let (before, after, late_seen, element) = db
+        .read(move |reader| {
+            let (writer, header_hash, late) =
+                (writer.clone(), header_hash.clone(), late_entry.clone());
+            Box::pin(async move {
+                let before = reader.count_matching(&EntryFilter::new()).await?;
+                // committed while the read is still open
+                writer.insert_entry(&late).await?;
+                let after = reader.count_matching(&EntryFilter::new()).await?;
+                let late_seen = reader.entry_exists(&late.hash).await?;
+                let element = reader.get_element(&header_hash).await?;
+                Ok((before, after, late_seen, element))
+            })
+        })
+        .await
+        .unwrap();
+    assert_eq!((before, after, late_seen), (1, 1, false));
+    assert_eq!(element.unwrap().entry.unwrap().hash, held.hash);
+
+    // a fresh read sees it
+    let fetched = db
+        .read(move |reader| {
+            let hash = late.hash.clone();
+            Box::pin(async move { reader.get_entry(&hash).await })
+        })
+        .await
+        .unwrap();
+    assert!(fetched.is_some());
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,42 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn receipts_count_towards_threshold() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let op = DhtOp::rand();
+    let other = DhtOp::rand();
+    db.insert_op(&op).await.unwrap();
+    db.insert_op(&other).await.unwrap();
+
+    let mut needing = db.ops_needing_more_receipts(2).await.unwrap();
+    needing.sort();
+    let mut both = vec![op.op_hash.clone(), other.op_hash.clone()];
+    both.sort();
+    assert_eq!(needing, both);
+
+    let receipt = ValidationReceipt::rand(op.op_hash.clone());
+    db.insert_receipt(&receipt).await.unwrap();
+    // a repeat from the same signer doesn't count twice
+    db.insert_receipt(&receipt).await.unwrap();
+    assert_eq!(db.count_receipts(&op.op_hash).await.unwrap(), 1);
+
+    db.insert_receipt(&ValidationReceipt::rand(op.op_hash.clone()))
+        .await
+        .unwrap();
+    assert_eq!(db.count_receipts(&op.op_hash).await.unwrap(), 2);
+    assert_eq!(db.count_receipts(&other.op_hash).await.unwrap(), 0);
+    assert_eq!(
+        db.ops_needing_more_receipts(2).await.unwrap(),
+        vec![other.op_hash.clone()]
+    );
+    assert!(db.ops_needing_more_receipts(0).await.unwrap().is_empty());
+
+    // receipts only exist for ops we hold
+    assert!(db
+        .insert_receipt(&ValidationReceipt::rand(OpHash::rand()))
+        .await
+        .is_err());
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use spike_sqlx::*;
+
+fn keyed(key: [u8; 32]) -> DbConfig {
+    DbConfig::new().encryption(Encryption::SqlCipher(KeySource::provider(
+        StaticKeyProvider::new(SecretKey::new(key)),
+    )))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn plaintext_db_is_not_rekeyed() {
+    let dir = common::temp_dir();
+    let db = Db::open(dir.path().join("db.sqlite3")).await.unwrap();
+    let entry = Entry::rand();
+    db.insert_entry(&entry).await.unwrap();
+
+    assert!(db.rekey([2; 32]).await.is_err());
+    assert!(db.entry_exists(&entry.hash).await.unwrap());
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn same_key_is_refused() {
+    let dir = common::temp_dir();
+    let db = Db::open_with(dir.path().join("db.sqlite3"), keyed([1; 32]))
+        .await
+        .unwrap();
+    assert!(db.rekey([1; 32]).await.is_err());
+    db.ping().await.unwrap();
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rekey_locks_out_the_old_key() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let db = Db::open_with(&path, keyed([1; 32])).await.unwrap();
+    let entry = Entry::rand();
+    db.insert_entry(&entry).await.unwrap();
+
+    if db.cipher_version().await.unwrap().is_none() {
+        // plain sqlite would ignore the pragma and leave the file as
+        // it is, so nothing is done and the database carries on
+        let err = db.rekey([2; 32]).await.unwrap_err();
+        assert!(err.to_string().contains("SQLCipher"), "{}", err);
+        assert!(db.entry_exists(&entry.hash).await.unwrap());
+        db.insert_entry(&Entry::rand()).await.unwrap();
+        db.close().await.unwrap();
+        return;
+    }
+
+    db.rekey([2; 32]).await.unwrap();
+    // connections keyed before the rekey are replaced
+    assert!(db.entry_exists(&entry.hash).await.unwrap());
+    db.insert_entry(&Entry::rand()).await.unwrap();
+    db.close().await.unwrap();
+
+    let db = Db::open_with(&path, keyed([2; 32])).await.unwrap();
+    assert!(db.entry_exists(&entry.hash).await.unwrap());
+    db.close().await.unwrap();
+
+    let err = Db::open_with(&path, keyed([1; 32])).await.err().unwrap();
+    assert!(matches!(
+        err.downcast_ref::<DbError>(),
+        Some(DbError::BadEncryptionKey)
+    ));
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use spike_sqlx::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retries_the_whole_transaction_while_locked() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    // fail fast on the lock, so it's our retrying that waits it out
+    let config = DbConfig::new()
+        .busy_timeout(Duration::from_millis(0))
+        .retry_policy(RetryPolicy {
+            max_attempts: 100,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(20),
+            max_elapsed: Duration::from_secs(10),
+        });
+    let holder = Db::open_with(&path, config.clone()).await.unwrap();
+    let db = Db::open_with(&path, config).await.unwrap();
+
+    // another process holding the write lock for a while
+    let (locked_tx, locked_rx) = futures::channel::oneshot::channel();
+    let hold = tokio::spawn(async move {
+        holder
+            .write(move |writer| {
+                Box::pin(async move {
+                    writer.insert_entry(&Entry::rand()).await?;
+                    locked_tx.send(()).unwrap();
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    Ok(())
+                })
+            })
+            .await
+            .unwrap();
+        holder
+    });
+    locked_rx.await.unwrap();
+
+    let attempts = Arc::new(AtomicU32::new(0));
+    let entry = Entry::rand();
+    let (counter, e) = (attempts.clone(), entry.clone());
+    db.with_retrying_txn(move |writer| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        let e = e.clone();
+        Box::pin(async move { writer.insert_entry(&e).await })
+    })
+    .await
+    .unwrap();
+    assert!(attempts.load(Ordering::SeqCst) > 1);
+    assert!(db.entry_exists(&entry.hash).await.unwrap());
+
+    // other errors aren't retried
+    attempts.store(0, Ordering::SeqCst);
+    let (counter, e) = (attempts.clone(), entry.clone());
+    let res = db
+        .with_retrying_txn(move |writer| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let e = e.clone();
+            Box::pin(async move { writer.insert_entry(&e).await })
+        })
+        .await;
+    assert!(res.is_err());
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+    hold.await.unwrap().close().await.unwrap();
+    db.close().await.unwrap();
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use spike_sqlx::*;
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn row_counts_follow_writes() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let config = DbConfig::new().count_table_rows_every(Duration::from_secs(3600));
+    let db = Db::open_with(&path, config).await.unwrap();
+    // the first count runs as the database opens
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(db.metrics_snapshot().table_rows("entries"), Some(0));
+    let entries: Vec<_> = (0..5).map(|_| Entry::rand()).collect();
+    db.insert_entries(&entries).await.unwrap();
+    assert_eq!(db.metrics_snapshot().table_rows("entries"), Some(5));
+
+    // kept up to date by each commit, not by counting
+    db.insert_entry(&Entry::rand()).await.unwrap();
+    db.delete_entry(&entries[0].hash).await.unwrap();
+    db.insert_op(&DhtOp::rand()).await.unwrap();
+    let metrics = db.metrics_snapshot();
+    assert_eq!(metrics.table_rows("entries"), Some(5));
+    assert_eq!(metrics.table_rows("dht_ops"), Some(1));
+    let entries_rows = metrics
+        .tables
+        .iter()
+        .find(|rows| rows.table == "entries")
+        .unwrap();
+    assert!(entries_rows.since_counted >= Duration::from_millis(100));
+
+    // written through another handle, which the commit hooks never see
+    let other = Db::open(&path).await.unwrap();
+    other.insert_entry(&Entry::rand()).await.unwrap();
+    other.close().await.unwrap();
+    assert_eq!(db.metrics_snapshot().table_rows("entries"), Some(5));
+    let counted = db.count_table_rows().await.unwrap();
+    let entries_rows = counted.iter().find(|rows| rows.table == "entries").unwrap();
+    assert_eq!(entries_rows.rows, 6);
+    assert!(entries_rows.since_counted < Duration::from_millis(100));
+    assert!(db
+        .metrics_snapshot()
+        .to_prometheus()
+        .contains("spike_sqlx_table_rows{db_kind=\"path\",table=\"entries\"} 6\n"));
+    db.close().await.unwrap();
+}
@@ -0,0 +1,73 @@
+#![cfg(feature = "rusqlite-backend")]
+
+mod common;
+
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rusqlite_engine_shares_the_file_format() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+
+    let db = RusqliteDb::open(&path).await.unwrap();
+    let entries: Vec<_> = (0..10u8).map(|i| Entry::from_content(vec![i])).collect();
+    assert_eq!(db.insert_entries(&entries).await.unwrap().inserted, 10);
+    assert_eq!(db.upsert_entry(&entries[0]).await.unwrap().ignored, 1);
+    assert!(db.insert_entry(&entries[0]).await.is_err());
+    assert!(db.entry_exists(&entries[1].hash).await.unwrap());
+    let fetched = db.get_entry(&entries[2].hash).await.unwrap().unwrap();
+    assert_eq!(fetched.content, entries[2].content);
+    assert_eq!(fetched.entry_type, entries[2].entry_type);
+    assert_eq!(db.delete_entry(&entries[3].hash).await.unwrap().deleted, 1);
+    assert_eq!(
+        db.count_entries(0, u32::MAX, Timestamp(0), Timestamp::now())
+            .await
+            .unwrap(),
+        9
+    );
+    let ordered = db
+        .filter_entries(&EntryFilter::new().order(EntryOrder::DhtLoc).limit(3))
+        .await
+        .unwrap();
+    assert_eq!(ordered.len(), 3);
+    assert!(ordered.windows(2).all(|w| w[0].dht_loc() <= w[1].dht_loc()));
+    db.clone().close().await.unwrap();
+    // clones fail once closed
+    assert!(db.entry_exists(&entries[1].hash).await.is_err());
+
+    // sqlx sees the same rows and agrees the schema is migrated
+    let sqlx_db = Db::open(&path).await.unwrap();
+    assert_eq!(sqlx_db.migrate().await.unwrap(), 0);
+    let fetched = sqlx_db.get_entry(&entries[4].hash).await.unwrap().unwrap();
+    assert_eq!(fetched.created_at, entries[4].created_at);
+    let later = Entry::rand();
+    sqlx_db.insert_entry(&later).await.unwrap();
+    sqlx_db.close().await.unwrap();
+
+    // and the other way round
+    let db = RusqliteDb::open(&path).await.unwrap();
+    assert!(db.entry_exists(&later.hash).await.unwrap());
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rusqlite_commits_are_summarized() {
+    let dir = common::temp_dir();
+    let db = RusqliteDb::open(dir.path().join("db.sqlite3"))
+        .await
+        .unwrap();
+    let summaries = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = summaries.clone();
+    db.on_commit(move |summary| seen.lock().unwrap().push(summary.clone()));
+
+    let entries: Vec<_> = (0..3).map(|_| Entry::rand()).collect();
+    db.insert_entries(&entries).await.unwrap();
+    // fails on the duplicate, so nothing commits
+    assert!(db.insert_entries(&entries[..1]).await.is_err());
+    db.delete_entry(&entries[0].hash).await.unwrap();
+    let summaries = std::mem::take(&mut *summaries.lock().unwrap());
+    assert_eq!(summaries.len(), 2);
+    assert_eq!(summaries[0].table("entries").unwrap().inserted, 3);
+    assert_eq!(summaries[1].table("entries").unwrap().deleted, 1);
+    db.close().await.unwrap();
+}
@@ -0,0 +1,62 @@
+use spike_sqlx::*;
+
+fn hashes(entries: Vec<Entry>) -> Vec<EntryHash> {
+    entries.into_iter().map(|e| e.hash).collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn search_held_entries() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    let texts = [
+        "The quick brown fox",
+        "a slow brown bear, then a brown dog: brown all round",
+        "Café au lait",
+        "nothing to see here",
+    ];
+    let entries: Vec<Entry> = texts
+        .iter()
+        .map(|text| Entry::from_content(text.as_bytes().to_vec()))
+        .collect();
+    db.insert_entries(&entries).await.unwrap();
+    // binary content never gets in the way
+    db.insert_entry(&Entry::from_content(vec![0xff, 0xfe, 0x00, 0x01]))
+        .await
+        .unwrap();
+    // strings in MessagePack content are found too
+    let stored = db.put_typed(&("recipe", "pancakes")).await.unwrap();
+
+    // the one with more of the word ranks first
+    assert_eq!(
+        hashes(db.search("brown", 10).await.unwrap()),
+        vec![entries[1].hash.clone(), entries[0].hash.clone()]
+    );
+    assert_eq!(
+        hashes(db.search("BROWN fox", 10).await.unwrap()),
+        vec![entries[0].hash.clone()]
+    );
+    assert_eq!(db.search("brown", 1).await.unwrap().len(), 1);
+    assert_eq!(
+        hashes(db.search("cafe", 10).await.unwrap()),
+        vec![entries[2].hash.clone()]
+    );
+    assert_eq!(
+        hashes(db.search("pancakes", 10).await.unwrap()),
+        vec![stored.hash.clone()]
+    );
+    // query syntax is just punctuation
+    assert_eq!(db.search("fox\" OR \"see", 10).await.unwrap().len(), 0);
+    assert_eq!(db.search("brown*", 10).await.unwrap().len(), 2);
+    assert!(db.search("  ", 10).await.unwrap().is_empty());
+    assert!(db.search("wolf", 10).await.unwrap().is_empty());
+
+    // gone from the index with the entry
+    db.delete_entry(&entries[0].hash).await.unwrap();
+    assert_eq!(
+        hashes(db.search("brown", 10).await.unwrap()),
+        vec![entries[1].hash.clone()]
+    );
+    db.purge(&EntryFilter::new()).await.unwrap();
+    assert!(db.search("brown", 10).await.unwrap().is_empty());
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,112 @@
+use spike_sqlx::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+type Fields = HashMap<String, String>;
+
+struct FieldsVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldsVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+}
+
+/// Keeps the fields of every WARN event.
+#[derive(Clone, Default)]
+struct Warnings(Arc<Mutex<Vec<Fields>>>);
+
+impl Warnings {
+    fn for_op(&self, op: &str) -> Vec<Fields> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|fields| fields.get("op").map(String::as_str) == Some(op))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Subscriber for Warnings {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= Level::WARN
+    }
+
+    fn new_span(&self, _: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::new();
+        event.record(&mut FieldsVisitor(&mut fields));
+        self.0.lock().unwrap().push(fields);
+    }
+
+    fn enter(&self, _: &Id) {}
+    fn exit(&self, _: &Id) {}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn slow_queries_are_logged_and_counted() {
+    let warnings = Warnings::default();
+    // global, the ops finish on whichever thread runs them
+    tracing::subscriber::set_global_default(warnings.clone()).unwrap();
+
+    // every op counts as slow
+    let config = DbConfig::new().slow_query_threshold(Some(Duration::from_secs(0)));
+    let db = Db::open_with("sqlite::memory:", config).await.unwrap();
+    let entry = Entry::rand();
+    db.insert_entry(&entry).await.unwrap();
+    db.get_entry(&entry.hash).await.unwrap().unwrap();
+    db.search("needle", 5).await.unwrap();
+
+    let got = warnings.for_op("get_entry");
+    assert_eq!(got.len(), 1);
+    let got = &got[0];
+    assert_eq!(got["message"], "slow database operation");
+    assert_eq!(got["side"], "read");
+    assert!(got["sql"].contains("FROM entries"), "{:?}", got);
+    // the hash shows as its size only
+    assert_eq!(got["params"], "blob(39)");
+    assert!(got["plan"].contains("entries"), "{:?}", got);
+    let hex: String = entry.hash.get_raw_39()[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert!(!format!("{:?}", got).contains(&hex));
+
+    let searched = &warnings.for_op("search")[0];
+    assert_eq!(searched["params"], "text(8), integer");
+    // ops with no one statement are still logged, without a plan
+    let inserted = &warnings.for_op("insert_entry")[0];
+    assert!(!inserted.contains_key("plan"));
+
+    let metrics = db.metrics_snapshot();
+    let get_entry = metrics.op("get_entry").unwrap();
+    assert_eq!(get_entry.slow, get_entry.calls());
+    assert!(metrics
+        .to_prometheus()
+        .contains("spike_sqlx_op_slow_total{db_kind=\"path\",side=\"read\",op=\"get_entry\"} 1\n"));
+    db.close().await.unwrap();
+
+    // nothing is slow once turned off
+    let config = DbConfig::new().slow_query_threshold(None);
+    let db = Db::open_with("sqlite::memory:", config).await.unwrap();
+    db.get_entry(&entry.hash).await.unwrap();
+    assert_eq!(warnings.for_op("get_entry").len(), 1);
+    assert_eq!(db.metrics_snapshot().op("get_entry").unwrap().slow, 0);
+    db.close().await.unwrap();
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn snapshots_rotate() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let snapshots = dir.path().join("snapshots");
+
+    let config = DbConfig::new().snapshots(SnapshotPolicy::new(&snapshots).keep(2));
+    let db = Db::open_with(&path, config).await.unwrap();
+    let mut taken = Vec::new();
+    for _ in 0..3 {
+        db.insert_entry(&Entry::rand()).await.unwrap();
+        taken.push(db.snapshot().await.unwrap());
+    }
+    let kept = db.snapshots().await.unwrap();
+    assert_eq!(kept, vec![taken[2].clone(), taken[1].clone()]);
+    assert_eq!(std::fs::read_dir(&snapshots).unwrap().count(), 2);
+    assert!(kept[0].size_bytes > 0);
+    assert!(kept[0].schema_version > 0);
+    db.close().await.unwrap();
+
+    // a size cap keeps only what fits, but always the newest
+    let config = DbConfig::new().snapshots(SnapshotPolicy::new(&snapshots).max_total_bytes(1));
+    let db = Db::open_with(&path, config).await.unwrap();
+    let newest = db.snapshot().await.unwrap();
+    assert_eq!(db.snapshots().await.unwrap(), vec![newest]);
+    db.close().await.unwrap();
+
+    let db = Db::open_with(&path, DbConfig::new()).await.unwrap();
+    assert!(db.snapshot().await.is_err());
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn corrupt_file_falls_back_to_a_snapshot() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+    let config = DbConfig::new().snapshots(SnapshotPolicy::new(dir.path().join("snapshots")));
+
+    let db = Db::open_with(&path, config.clone()).await.unwrap();
+    let saved = Entry::rand();
+    db.insert_entry(&saved).await.unwrap();
+    db.snapshot().await.unwrap();
+    let lost = Entry::rand();
+    db.insert_entry(&lost).await.unwrap();
+    db.close().await.unwrap();
+
+    std::fs::write(&path, vec![0x42; 8192]).unwrap();
+
+    let err = Db::open(&path).await.err().unwrap();
+    assert!(matches!(
+        err.downcast_ref::<DbError>(),
+        Some(DbError::Corrupt(_))
+    ));
+    let no_restore = config
+        .clone()
+        .snapshots(SnapshotPolicy::new(dir.path().join("snapshots")).restore_on_corruption(false));
+    assert!(Db::open_with(&path, no_restore).await.is_err());
+
+    let db = Db::open_with(&path, config).await.unwrap();
+    assert!(db.entry_exists(&saved.hash).await.unwrap());
+    assert!(!db.entry_exists(&lost.hash).await.unwrap());
+    db.close().await.unwrap();
+    let kept_aside = std::fs::read_dir(dir.path())
+        .unwrap()
+        .filter(|e| {
+            e.as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with("db.sqlite3.corrupt-")
+        })
+        .count();
+    assert_eq!(kept_aside, 1);
+}
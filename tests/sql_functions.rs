@@ -0,0 +1,135 @@
+use spike_sqlx::*;
+
+async fn query(db: &Db, sql: &'static str) -> anyhow::Result<Vec<Vec<Value>>> {
+    db.read(move |reader| Box::pin(async move { reader.query(sql, &[]).await }))
+        .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn builtin_functions() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    let entries: Vec<Entry> = (0..20)
+        .map(|i| Entry::from_content(format!("entry {}", i).into_bytes()))
+        .collect();
+    db.insert_entries(&entries).await.unwrap();
+
+    let mismatched = query(
+        &db,
+        "SELECT count(*) FROM entries
+        WHERE holo_dht_loc(hash) != dht_loc
+            OR holo_dht_loc(substr(hash, 4, 32)) != dht_loc
+            OR blake2b(content) != substr(hash, 4, 32);",
+    )
+    .await
+    .unwrap();
+    assert_eq!(mismatched, vec![vec![Value::Integer(0)]]);
+    assert_eq!(
+        query(&db, "SELECT holo_dht_loc(x'0102'), blake2b(NULL);")
+            .await
+            .unwrap(),
+        vec![vec![Value::Null, Value::Null]]
+    );
+
+    // the last arc wraps past u32::MAX
+    let arcs = query(
+        &db,
+        "SELECT arc_contains(5, 0, 10), arc_contains(50, 0, 10),
+            arc_contains(4294967290, 4294967280, 10), arc_contains(5, 4294967280, 10),
+            arc_contains(50, 4294967280, 10), arc_contains(NULL, 0, 10);",
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        arcs[0],
+        [1, 0, 1, 1, 0]
+            .iter()
+            .map(|b| Value::Integer(*b))
+            .chain(Some(Value::Null))
+            .collect::<Vec<_>>()
+    );
+
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn application_functions() {
+    let double = SqlFunction::new("double_it", 1, |args| match args[0].as_i64() {
+        Some(i) => Ok(Value::Integer(i * 2)),
+        None => anyhow::bail!("double_it needs an integer"),
+    })
+    .deterministic();
+    let shout = SqlFunction::new("shout", 1, |args| match &args[0] {
+        Value::Text(text) => Ok(Value::Text(text.to_uppercase())),
+        _ => Ok(Value::Null),
+    });
+    let boom = SqlFunction::new("boom", 0, |_| panic!("boom"));
+    let config = DbConfig::new()
+        .function(double)
+        .function(shout)
+        .function(boom);
+    let db = Db::open_with("sqlite::memory:", config).await.unwrap();
+
+    db.insert_entry(&Entry::rand()).await.unwrap();
+    assert_eq!(
+        query(
+            &db,
+            "SELECT double_it(21), shout('hi'), double_it(size_bytes + 1) FROM entries;"
+        )
+        .await
+        .unwrap(),
+        vec![vec![
+            Value::Integer(42),
+            Value::Text("HI".to_string()),
+            Value::Integer(2)
+        ]]
+    );
+
+    let failed = query(&db, "SELECT double_it('x');").await.unwrap_err();
+    assert!(
+        failed.to_string().contains("needs an integer"),
+        "{}",
+        failed
+    );
+    let panicked = query(&db, "SELECT boom();").await.unwrap_err();
+    assert!(panicked.to_string().contains("panicked"), "{}", panicked);
+    db.close().await.unwrap();
+
+    let bad = DbConfig::new().function(SqlFunction::new("no good", 0, |_| Ok(Value::Null)));
+    assert!(Db::open_with("sqlite::memory:", bad).await.is_err());
+    // the change tracking's own, in any case
+    let reserved = DbConfig::new().function(SqlFunction::new("SPIKE_SQLX_written", 1, |_| {
+        Ok(Value::Null)
+    }));
+    let err = Db::open_with("sqlite::memory:", reserved)
+        .await
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("reserved"), "{}", err);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn query_only_reads() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    db.insert_entry(&Entry::rand()).await.unwrap();
+
+    for sql in &[
+        "DELETE FROM entries;",
+        // a write after a read is caught before the read runs
+        "SELECT 1; DELETE FROM entries;",
+        "CREATE TABLE scratch (x);",
+    ] {
+        let err = query(&db, sql).await.unwrap_err();
+        assert!(
+            err.to_string().contains("only runs statements that read"),
+            "{}",
+            err
+        );
+    }
+    assert_eq!(
+        query(&db, "SELECT count(*) FROM entries; SELECT 2; -- done")
+            .await
+            .unwrap(),
+        vec![vec![Value::Integer(1)], vec![Value::Integer(2)]]
+    );
+    db.close().await.unwrap();
+}
@@ -0,0 +1,56 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hot_statements_are_reused() {
+    let config = DbConfig::new().read_connections(1, 1);
+    let db = Db::open_with("sqlite::memory:", config).await.unwrap();
+    assert_eq!(db.statement_cache_metrics().capacity, 100);
+
+    let entries: Vec<_> = (0..10).map(|_| Entry::rand()).collect();
+    for entry in &entries {
+        db.insert_entry(entry).await.unwrap();
+    }
+    for entry in &entries {
+        db.get_entry(&entry.hash).await.unwrap().unwrap();
+    }
+    for _ in 0..5 {
+        db.query_entries(0, u32::MAX, Timestamp(0), Timestamp::now())
+            .await
+            .unwrap();
+    }
+    // one connection a side, so each statement is prepared once
+    let write = db.writer().statement_cache_metrics();
+    assert_eq!((write.hits, write.misses), (9, 1));
+    let read = db.reader().statement_cache_metrics();
+    assert_eq!((read.hits, read.misses), (13, 2));
+    let metrics = db.statement_cache_metrics();
+    assert_eq!(
+        (metrics.hits, metrics.misses, metrics.evictions),
+        (22, 3, 0)
+    );
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_full_cache_evicts() {
+    let config = DbConfig::new()
+        .read_connections(1, 1)
+        .statement_cache_capacity(1);
+    let db = Db::open_with("sqlite::memory:", config).await.unwrap();
+    let entry = Entry::rand();
+    db.insert_entry(&entry).await.unwrap();
+
+    // two statements taking turns in a one slot cache never hit
+    for _ in 0..3 {
+        db.get_entry(&entry.hash).await.unwrap().unwrap();
+        db.query_entries(0, u32::MAX, Timestamp(0), Timestamp::now())
+            .await
+            .unwrap();
+    }
+    let read = db.reader().statement_cache_metrics();
+    assert_eq!(read.capacity, 1);
+    assert_eq!(read.hits, 0);
+    assert_eq!(read.misses, 6);
+    assert!(read.evictions >= 5);
+    db.close().await.unwrap();
+}
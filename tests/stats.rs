@@ -0,0 +1,55 @@
+mod common;
+
+use spike_sqlx::*;
+
+fn entries(n: u32) -> Vec<Entry> {
+    (0..n)
+        .map(|i| {
+            let mut content = vec![0; 4 * 1024];
+            content[..4].copy_from_slice(&i.to_le_bytes());
+            Entry::from_content(content)
+        })
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sizes_and_row_counts() {
+    let dir = common::temp_dir();
+    let db = Db::open(dir.path().join("db.sqlite3")).await.unwrap();
+    db.insert_entries(&entries(32)).await.unwrap();
+
+    let stats = db.stats().await.unwrap();
+    assert!(stats.page_size >= 512);
+    assert!(stats.page_count > 0);
+    assert_eq!(stats.freelist_count, 0);
+    // the inserts are still in the WAL
+    assert!(stats.wal_bytes > 0);
+    assert_eq!(stats.disk_bytes(), stats.file_bytes + stats.wal_bytes);
+    let held = stats.table("entries").unwrap();
+    assert_eq!(held.rows, 32);
+    // a 4KB entry takes at least one page
+    assert!(held.bytes.unwrap() >= 32 * stats.page_size);
+    assert_eq!(stats.table("headers").unwrap().rows, 0);
+    // the search index shows as its shadow tables
+    assert!(stats.table("entry_search").is_none());
+    assert!(stats.table("entry_search_data").is_some());
+    assert_eq!(stats.table("entry_search_ids").unwrap().rows, 32);
+    assert!(stats.used_bytes() <= stats.page_count * stats.page_size);
+
+    db.checkpoint(CheckpointMode::Truncate).await.unwrap();
+    db.purge(&EntryFilter::new()).await.unwrap();
+    db.checkpoint(CheckpointMode::Truncate).await.unwrap();
+    let stats = db.stats().await.unwrap();
+    assert_eq!(stats.wal_bytes, 0);
+    assert_eq!(stats.file_bytes, stats.page_count * stats.page_size);
+    assert_eq!(stats.table("entries").unwrap().rows, 0);
+    assert!(stats.freelist_count >= 32);
+    db.close().await.unwrap();
+
+    // nothing on disk for one in memory
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    let stats = db.stats().await.unwrap();
+    assert_eq!(stats.disk_bytes(), 0);
+    assert!(stats.table("entries").unwrap().bytes.is_some());
+    db.close().await.unwrap();
+}
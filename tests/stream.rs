@@ -0,0 +1,39 @@
+use futures::{StreamExt, TryStreamExt};
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stream_entries_yields_every_row() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    // more than the read-ahead buffer, so the reader has to wait on us
+    for _ in 0..200 {
+        db.insert_entry(&Entry::rand()).await.unwrap();
+    }
+
+    let start = Timestamp(0);
+    let end = Timestamp::now();
+    let mut streamed: Vec<_> = db
+        .stream_entries(&EntryFilter::new())
+        .map_ok(|entry| entry.hash)
+        .try_collect()
+        .await
+        .unwrap();
+    let mut queried: Vec<_> = db
+        .query_entries(0, u32::MAX, start, end)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.hash)
+        .collect();
+    streamed.sort();
+    queried.sort();
+    assert_eq!(streamed.len(), 200);
+    assert_eq!(streamed, queried);
+
+    // dropping a stream part way releases its reader
+    let mut stream = Box::pin(db.stream_entries(&EntryFilter::new()));
+    stream.next().await.unwrap().unwrap();
+    drop(stream);
+
+    db.close().await.unwrap();
+}
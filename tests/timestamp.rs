@@ -0,0 +1,24 @@
+use chrono::prelude::*;
+use spike_sqlx::Timestamp;
+use std::convert::TryFrom;
+
+#[test]
+fn converts_through_chrono() {
+    let time = Utc.ymd(2021, 3, 4).and_hms_micro(5, 6, 7, 890_123);
+    let timestamp = Timestamp::from(time);
+    assert_eq!(timestamp.to_date_time().unwrap(), time);
+
+    // before the epoch the microseconds still count forwards
+    let before = Timestamp(-1_500_000);
+    let time = before.to_date_time().unwrap();
+    assert_eq!(time.timestamp(), -2);
+    assert_eq!(time.timestamp_subsec_micros(), 500_000);
+    assert_eq!(Timestamp::from(time), before);
+}
+
+#[test]
+fn out_of_range_is_an_error() {
+    assert!(Timestamp(i64::MAX).to_date_time().is_err());
+    assert!(Timestamp(i64::MIN).to_date_time().is_err());
+    assert!(DateTime::<Utc>::try_from(Timestamp(i64::MAX)).is_err());
+}
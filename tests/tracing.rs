@@ -0,0 +1,127 @@
+mod common;
+
+use spike_sqlx::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+type Fields = HashMap<String, String>;
+
+struct FieldsVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldsVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(
+            field.name().to_string(),
+            format!("{:?}", value).replace('"', ""),
+        );
+    }
+}
+
+/// Keeps the fields of every `db_op` span, in the order they were opened.
+#[derive(Clone, Default)]
+struct Spans {
+    next_id: Arc<AtomicU64>,
+    spans: Arc<Mutex<Vec<(u64, Fields)>>>,
+}
+
+impl Spans {
+    fn ops(&self) -> Vec<Fields> {
+        self.spans
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, fields)| fields.clone())
+            .collect()
+    }
+
+    fn op(&self, name: &str) -> Fields {
+        self.ops()
+            .into_iter()
+            .rev()
+            .find(|fields| fields["op"] == name)
+            .unwrap_or_else(|| panic!("no {} span", name))
+    }
+}
+
+impl Subscriber for Spans {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        if span.metadata().name() == "db_op" {
+            let mut fields = Fields::new();
+            span.record(&mut FieldsVisitor(&mut fields));
+            self.spans.lock().unwrap().push((id, fields));
+        }
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        if let Some((_, fields)) = spans.iter_mut().find(|(id, _)| *id == span.into_u64()) {
+            values.record(&mut FieldsVisitor(fields));
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+    fn event(&self, _: &Event<'_>) {}
+    fn enter(&self, _: &Id) {}
+    fn exit(&self, _: &Id) {}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spans_per_operation() {
+    let spans = Spans::default();
+    // global, the spans are opened on whichever thread runs the op
+    tracing::subscriber::set_global_default(spans.clone()).unwrap();
+    let root = common::temp_dir();
+
+    let db = Db::open_kind(root.path(), DbKind::Conductor, DbConfig::new())
+        .await
+        .unwrap();
+    let open = spans.op("open");
+    assert_eq!(open["db_kind"], "conductor");
+    assert_eq!(open["side"], "write");
+    assert!(open.contains_key("elapsed_us"));
+
+    let entries: Vec<Entry> = (0..3).map(|_| Entry::rand()).collect();
+    db.insert_entries(&entries).await.unwrap();
+    let inserted = spans.op("insert_entry_batch");
+    assert_eq!(inserted["rows"], "3");
+    assert_eq!(inserted["attempts"], "1");
+
+    db.get_entry(&entries[0].hash).await.unwrap().unwrap();
+    let got = spans.op("get_entry");
+    assert_eq!(got["side"], "read");
+    assert_eq!(got["rows"], "1");
+
+    db.read(|reader| Box::pin(async move { reader.query("SELECT 1;", &[]).await }))
+        .await
+        .unwrap();
+    let read = spans.op("read_txn");
+    assert_eq!(read["side"], "read");
+    assert!(read.contains_key("elapsed_us"));
+
+    db.write(|writer| Box::pin(async move { writer.insert_entry(&Entry::rand()).await }))
+        .await
+        .unwrap();
+    assert_eq!(spans.op("write_txn")["side"], "write");
+    db.close().await.unwrap();
+
+    // nothing at all once turned off
+    let before = spans.ops().len();
+    let config = DbConfig::new().tracing(TraceVerbosity::Off);
+    let db = Db::open_kind(root.path(), DbKind::Conductor, config)
+        .await
+        .unwrap();
+    db.insert_entry(&Entry::rand()).await.unwrap();
+    db.get_entry(&entries[0].hash).await.unwrap().unwrap();
+    db.close().await.unwrap();
+    assert_eq!(spans.ops().len(), before);
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+use spike_sqlx::*;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Post {
+    title: String,
+    body: String,
+    tags: Vec<String>,
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn typed_entries_round_trip() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let post = Post {
+        title: "hello".to_string(),
+        body: "world".to_string(),
+        tags: vec!["a".to_string(), "b".to_string()],
+    };
+    let entry = db.put_typed(&post).await.unwrap();
+    assert_eq!(entry.hash, EntryHash::with_data(&entry.content));
+    // content addressed, so storing it again is harmless
+    assert_eq!(db.put_typed(&post).await.unwrap().hash, entry.hash);
+
+    let fetched: Post = db.get_typed(&entry.hash).await.unwrap().unwrap();
+    assert_eq!(fetched, post);
+    assert!(db
+        .get_typed::<Post>(&EntryHash::rand())
+        .await
+        .unwrap()
+        .is_none());
+    // wrong shape
+    assert!(db.get_typed::<u64>(&entry.hash).await.is_err());
+
+    // content comes back through every read path
+    let header = Header::rand(entry.hash.clone());
+    db.insert_header(&header).await.unwrap();
+    let element = db.get_element(&header.hash).await.unwrap().unwrap();
+    assert_eq!(element.entry.unwrap().content, entry.content);
+    let all = db.filter_entries(&EntryFilter::new()).await.unwrap();
+    assert_eq!(all[0].content, entry.content);
+    let batch = db.get_entries(std::slice::from_ref(&entry.hash)).await;
+    assert_eq!(batch.unwrap()[0].content, entry.content);
+
+    db.close().await.unwrap();
+}
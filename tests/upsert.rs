@@ -0,0 +1,41 @@
+use chrono::prelude::*;
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn duplicates_are_not_errors() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let entry = Entry::rand();
+    assert_eq!(db.upsert_entry(&entry).await.unwrap().inserted, 1);
+    assert_eq!(db.upsert_entry(&entry).await.unwrap().ignored, 1);
+    // the strict insert still refuses
+    assert!(db.insert_entry(&entry).await.is_err());
+
+    let op = DhtOp::rand();
+    db.insert_op(&op).await.unwrap();
+    db.insert_op(&op).await.unwrap();
+    db.integrate_op(&op.op_hash, ValidationStatus::Rejected)
+        .await
+        .unwrap();
+
+    // a copy without an outcome keeps the stored one
+    db.insert_op(&op).await.unwrap();
+    let fetched = db.get_op(&op.op_hash).await.unwrap().unwrap();
+    assert_eq!(fetched.validation_status, ValidationStatus::Rejected);
+    assert!(fetched.when_integrated.is_some());
+
+    // one with an outcome replaces it, other columns stay
+    let validated = DhtOp {
+        basis_loc: op.basis_loc.wrapping_add(1),
+        validation_status: ValidationStatus::Valid,
+        when_integrated: Some(Utc::now()),
+        ..op.clone()
+    };
+    db.insert_op(&validated).await.unwrap();
+    let fetched = db.get_op(&op.op_hash).await.unwrap().unwrap();
+    assert_eq!(fetched.validation_status, ValidationStatus::Valid);
+    assert_eq!(fetched.basis_loc, op.basis_loc);
+    assert_eq!(db.ops_pending_integration().await.unwrap().len(), 0);
+
+    db.close().await.unwrap();
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use spike_sqlx::*;
+
+fn entries(n: u32) -> Vec<Entry> {
+    (0..n)
+        .map(|i| {
+            let mut content = vec![0; 16 * 1024];
+            content[..4].copy_from_slice(&i.to_le_bytes());
+            Entry::from_content(content)
+        })
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn purged_space_is_reclaimed() {
+    let dir = common::temp_dir();
+    let path = dir.path().join("db.sqlite3");
+
+    // without auto_vacuum freed pages only go with a full vacuum
+    let db = Db::open(&path).await.unwrap();
+    db.insert_entries(&entries(64)).await.unwrap();
+    db.purge(&EntryFilter::new()).await.unwrap();
+    let free = db.freelist_count().await.unwrap();
+    // a 16KB entry spans at least four pages
+    assert!(free >= 64 * 4);
+    assert_eq!(db.incremental_vacuum(0).await.unwrap(), 0);
+    assert!(db.vacuum().await.unwrap() >= free);
+    assert_eq!(db.freelist_count().await.unwrap(), 0);
+    db.close().await.unwrap();
+
+    // the existing file is converted on open
+    let config = DbConfig::new().auto_vacuum(AutoVacuum::Incremental);
+    let db = Db::open_with(&path, config).await.unwrap();
+    let held = entries(64);
+    db.insert_entries(&held).await.unwrap();
+    let survivor = held[0].hash.clone();
+    for entry in &held[1..] {
+        db.delete_entry(&entry.hash).await.unwrap();
+    }
+    let free = db.freelist_count().await.unwrap();
+    assert!(free >= 63 * 4);
+    // in steps, as an embedder would on a timer
+    assert_eq!(db.incremental_vacuum(16).await.unwrap(), 16);
+    assert_eq!(db.freelist_count().await.unwrap(), free - 16);
+    assert_eq!(db.incremental_vacuum(0).await.unwrap(), free - 16);
+    assert_eq!(db.freelist_count().await.unwrap(), 0);
+    assert!(db.entry_exists(&survivor).await.unwrap());
+    db.close().await.unwrap();
+}
@@ -0,0 +1,87 @@
+use futures::future::join_all;
+use spike_sqlx::*;
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn queued_writes_fail_alone() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+    let queue = db.write_queue().clone();
+
+    let held = Entry::rand();
+    db.insert_entry(&held).await.unwrap();
+
+    let entries: Vec<Entry> = (0..200).map(|_| Entry::rand()).collect();
+    let mut ops: Vec<WriteOp> = entries.iter().cloned().map(WriteOp::InsertEntry).collect();
+    // a duplicate in the middle of the queue
+    ops.insert(100, WriteOp::InsertEntry(held.clone()));
+    ops.push(WriteOp::InsertHeader(Header::rand(entries[0].hash.clone())));
+
+    let results = join_all(ops.into_iter().map(|op| {
+        let queue = queue.clone();
+        async move { queue.submit(op).await }
+    }))
+    .await;
+    assert!(results[100].is_err());
+    assert_eq!(results.iter().filter(|res| res.is_ok()).count(), 201);
+
+    let hashes: Vec<EntryHash> = entries.iter().map(|e| e.hash.clone()).collect();
+    assert_eq!(db.get_entries(&hashes).await.unwrap().len(), 200);
+
+    db.close().await.unwrap();
+    assert!(queue
+        .submit(WriteOp::InsertEntry(Entry::rand()))
+        .await
+        .is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn coalesced_writes_wait_for_a_flush() {
+    let config = DbConfig::new().write_coalescing(Duration::from_secs(60), 3);
+    let db = Db::open_with("sqlite::memory:", config).await.unwrap();
+    let queue = db.write_queue();
+
+    let entries: Vec<Entry> = (0..5).map(|_| Entry::rand()).collect();
+    queue
+        .buffer(WriteOp::InsertEntry(entries[0].clone()))
+        .await
+        .unwrap();
+    queue
+        .buffer(WriteOp::InsertEntry(entries[1].clone()))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!db.entry_exists(&entries[0].hash).await.unwrap());
+    queue.flush().await.unwrap();
+    assert!(db.entry_exists(&entries[0].hash).await.unwrap());
+    assert!(db.entry_exists(&entries[1].hash).await.unwrap());
+
+    // buffered failures show up at the next flush, once
+    queue
+        .buffer(WriteOp::InsertEntry(entries[0].clone()))
+        .await
+        .unwrap();
+    assert!(queue.flush().await.is_err());
+    queue.flush().await.unwrap();
+
+    // a full batch doesn't wait out the interval
+    let submits = entries[2..]
+        .iter()
+        .map(|entry| queue.submit(WriteOp::InsertEntry(entry.clone())));
+    let results = tokio::time::timeout(Duration::from_secs(5), join_all(submits))
+        .await
+        .unwrap();
+    assert!(results.iter().all(|res| res.is_ok()));
+
+    db.close().await.unwrap();
+
+    let config = DbConfig::new().write_coalescing(Duration::from_millis(20), 1000);
+    let db = Db::open_with("sqlite::memory:", config).await.unwrap();
+    let entry = Entry::rand();
+    db.write_queue()
+        .buffer(WriteOp::InsertEntry(entry.clone()))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(db.entry_exists(&entry.hash).await.unwrap());
+    db.close().await.unwrap();
+}
@@ -0,0 +1,97 @@
+use spike_sqlx::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn write_commits_or_rolls_back_every_table() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let entry = Entry::rand();
+    let header = Header::rand(entry.hash.clone());
+    let op = DhtOp::rand();
+    let link = Link::rand(entry.hash.clone(), b"tag".to_vec());
+    let (e, h, o, l) = (entry.clone(), header.clone(), op.clone(), link.clone());
+    let out = db
+        .write(move |writer| {
+            Box::pin(async move {
+                writer.insert_entry(&e).await?;
+                writer.insert_header(&h).await?;
+                writer.insert_op(&o).await?;
+                writer.insert_link(&l).await?;
+                Ok(42)
+            })
+        })
+        .await
+        .unwrap();
+    assert_eq!(out, 42);
+    assert!(db.get_element(&header.hash).await.unwrap().is_some());
+    assert!(db.get_op(&op.op_hash).await.unwrap().is_some());
+    assert_eq!(db.get_links(&entry.hash, b"").await.unwrap().len(), 1);
+
+    // the header's entry is missing, so the whole element goes
+    let entry = Entry::rand();
+    let header = Header::rand(Entry::rand().hash);
+    let op = DhtOp::rand();
+    let (e, h, o) = (entry.clone(), header.clone(), op.clone());
+    let res = db
+        .write(move |writer| {
+            Box::pin(async move {
+                writer.insert_entry(&e).await?;
+                writer.insert_op(&o).await?;
+                writer.insert_header(&h).await
+            })
+        })
+        .await;
+    assert!(res.is_err());
+    assert!(!db.entry_exists(&entry.hash).await.unwrap());
+    assert!(db.get_op(&op.op_hash).await.unwrap().is_none());
+
+    // an error from the closure itself rolls back too
+    let entry = Entry::rand();
+    let e = entry.clone();
+    let res: anyhow::Result<()> = db
+        .write(move |writer| {
+            Box::pin(async move {
+                writer.insert_entry(&e).await?;
+                anyhow::bail!("changed my mind")
+            })
+        })
+        .await;
+    assert!(res.is_err());
+    assert!(!db.entry_exists(&entry.hash).await.unwrap());
+
+    db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn savepoints_undo_part_of_a_transaction() {
+    let db = Db::open("sqlite::memory:").await.unwrap();
+
+    let entries: Vec<Entry> = (0..3).map(|_| Entry::rand()).collect();
+    let e = entries.clone();
+    db.write(move |writer| {
+        Box::pin(async move {
+            writer.insert_entry(&e[0]).await?;
+
+            writer.savepoint("speculative").await?;
+            writer.insert_entry(&e[1]).await?;
+            writer.rollback_to("speculative").await?;
+
+            writer.savepoint("outer").await?;
+            writer.savepoint("inner").await?;
+            writer.insert_entry(&e[2]).await?;
+            // releases inner along with it
+            writer.release("outer").await?;
+
+            assert!(writer.savepoint("no; DROP TABLE entries").await.is_err());
+            assert!(writer.release("never_opened").await.is_err());
+            Ok(())
+        })
+    })
+    .await
+    .unwrap();
+
+    assert!(db.entry_exists(&entries[0].hash).await.unwrap());
+    assert!(!db.entry_exists(&entries[1].hash).await.unwrap());
+    assert!(db.entry_exists(&entries[2].hash).await.unwrap());
+
+    db.close().await.unwrap();
+}